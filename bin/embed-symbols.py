@@ -0,0 +1,118 @@
+#!/usr/bin/env python3
+
+# Writes the function symbols of a linked kernel ELF into its `.ksyms`
+# section so that `kern::backtrace` can print symbol names.
+#
+# usage: embed-symbols.py build/kernel.elf
+#
+# The table format must match `kern/src/backtrace.rs`: b"KSYM", a u32 count,
+# `count` entries of (u64 addr, u32 name_offset, u32 name_len) sorted by
+# address, then the names. All integers are little-endian.
+
+import re
+import struct
+import sys
+
+KSYMS_SIZE = 64 * 1024
+KSYMS_MAGIC = b"KSYM"
+
+SHT_SYMTAB = 2
+SHT_NOBITS = 8
+STT_FUNC = 2
+
+ESCAPES = {
+    "$SP$": "@", "$BP$": "*", "$RF$": "&", "$LT$": "<", "$GT$": ">",
+    "$LP$": "(", "$RP$": ")", "$C$": ",", "$u20$": " ", "$u27$": "'",
+    "$u5b$": "[", "$u5d$": "]", "$u7b$": "{", "$u7d$": "}", "$u7e$": "~",
+}
+
+def demangle(name):
+    """Demangles a legacy Rust symbol name, dropping the trailing hash."""
+    if not name.startswith("_ZN") or not name.endswith("E"):
+        return name
+
+    rest, parts = name[3:-1], []
+    while rest:
+        m = re.match(r"(\d+)", rest)
+        if not m:
+            return name
+        n = int(m.group(1))
+        start = len(m.group(1))
+        parts.append(rest[start:start + n])
+        rest = rest[start + n:]
+
+    if parts and re.fullmatch(r"h[0-9a-f]{16}", parts[-1]):
+        parts.pop()
+
+    path = "::".join(parts).replace("..", "::")
+    for esc, ch in ESCAPES.items():
+        path = path.replace(esc, ch)
+    return path.replace("_$", "$")
+
+def sections(elf):
+    shoff, = struct.unpack_from("<Q", elf, 0x28)
+    shentsize, shnum, shstrndx = struct.unpack_from("<HHH", elf, 0x3a)
+
+    headers = []
+    for i in range(shnum):
+        (name, kind, _flags, addr, offset, size, link, _info, _align,
+         _entsize) = struct.unpack_from("<IIQQQQIIQQ", elf, shoff + i * shentsize)
+        headers.append(dict(name=name, kind=kind, addr=addr, offset=offset,
+                            size=size, link=link))
+
+    strtab = headers[shstrndx]
+    for h in headers:
+        start = strtab["offset"] + h["name"]
+        h["name"] = elf[start:elf.index(b"\0", start)].decode()
+    return headers
+
+def functions(elf, headers):
+    symtab = next(h for h in headers if h["kind"] == SHT_SYMTAB)
+    strtab = headers[symtab["link"]]
+
+    syms = {}
+    for off in range(symtab["offset"], symtab["offset"] + symtab["size"], 24):
+        name, info, _other, _shndx, value, _size = struct.unpack_from("<IBBHQQ", elf, off)
+        if info & 0xf != STT_FUNC or value == 0:
+            continue
+        start = strtab["offset"] + name
+        raw = elf[start:elf.index(b"\0", start)].decode(errors="replace")
+        syms.setdefault(value, demangle(raw))
+    return sorted(syms.items())
+
+def build_table(syms):
+    header_len = 8 + 16 * len(syms)
+    entries, names = b"", b""
+    for addr, name in syms:
+        encoded = name.encode()
+        entries += struct.pack("<QII", addr, header_len + len(names), len(encoded))
+        names += encoded
+    return KSYMS_MAGIC + struct.pack("<I", len(syms)) + entries + names
+
+def main():
+    if len(sys.argv) != 2:
+        print("usage: %s <kernel.elf>" % sys.argv[0])
+        sys.exit(1)
+
+    path = sys.argv[1]
+    elf = bytearray(open(path, "rb").read())
+    headers = sections(elf)
+
+    ksyms = next((h for h in headers if h["name"] == ".ksyms"), None)
+    if ksyms is None or ksyms["kind"] == SHT_NOBITS or ksyms["size"] < KSYMS_SIZE:
+        print("[!] %s has no %d-byte .ksyms section" % (path, KSYMS_SIZE))
+        sys.exit(1)
+
+    syms = functions(elf, headers)
+    table = build_table(syms)
+    if len(table) > KSYMS_SIZE:
+        print("[!] symbol table needs %d bytes; raise KSYMS_SIZE in "
+              "kern/src/backtrace.rs and %s" % (len(table), sys.argv[0]))
+        sys.exit(1)
+
+    elf[ksyms["offset"]:ksyms["offset"] + len(table)] = table
+    open(path, "wb").write(elf)
+    print("+ Embedded %d symbols (%d bytes) in %s" % (len(syms), len(table), path))
+
+if __name__ == "__main__":
+    main()
@@ -13,6 +13,8 @@ TOP=$(git rev-parse --show-toplevel)
 BIN=$TOP/bin
 DEP=$TOP/.dep
 VER=nightly-2019-07-01
+# ttywrite uses async/await (rustc 1.39+); see lib/ttywrite/rust-toolchain
+TTYWRITE_VER=nightly-2020-02-01
 PROJ_PKG=(build-essential
      python3
      socat
@@ -43,6 +45,7 @@ fi
 
 rustup default $VER
 rustup component add rust-src llvm-tools-preview clippy
+rustup toolchain install $TTYWRITE_VER
 
 # install cargo xbuild
 mkdir -p $DEP
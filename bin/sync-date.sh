@@ -0,0 +1,8 @@
+#!/bin/sh
+# Sets the kernel's wall clock from the host by typing a `date` command at the
+# shell over the serial port.
+#
+# usage: sync-date.sh [tty]
+
+TTY=${1:-/dev/ttyUSB0}
+printf 'date %s\r' "$(date -u +%s)" | ttywrite -r "$TTY"
@@ -8,8 +8,11 @@
 mod init;
 
 use xmodem::Xmodem;
+use core::slice;
 use core::time::Duration;
 use pi;
+use pi::timer;
+use pi::uart::MiniUart;
 
 /// Start address of the binary to load and of the bootloader.
 const BINARY_START_ADDR: usize = 0x80000;
@@ -30,5 +33,22 @@ unsafe fn jump_to(addr: *mut u8) -> ! {
 }
 
 fn kmain() -> ! {
-    // FIXME: Implement the bootloader.
+    let mut uart = MiniUart::new();
+    uart.set_read_timeout(Duration::from_millis(750));
+    let binary = unsafe { slice::from_raw_parts_mut(BINARY_START, MAX_BINARY_SIZE) };
+
+    // Only jump into an image whose CRC-32 matches the sender's, if it sends
+    // one; anything else, including a timeout while waiting for a sender,
+    // starts over.
+    loop {
+        let received = Xmodem::builder()
+            .clock(timer::current_time)
+            .max_len(MAX_BINARY_SIZE)
+            .verify(true)
+            .receive(&mut uart, &mut binary[..]);
+
+        if received.is_ok() {
+            unsafe { jump_to(BINARY_START) }
+        }
+    }
 }
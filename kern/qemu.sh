@@ -4,6 +4,7 @@ TOP=$(git rev-parse --show-toplevel)
 $TOP/bin/qemu-system-aarch64 \
     -nographic \
     -M raspi3 \
+    -semihosting \
     -serial null -serial mon:stdio \
     -kernel \
     "$@"
@@ -0,0 +1,140 @@
+//! Thin wrappers around AArch64 instructions that have no Rust equivalent.
+
+/// Waits for an event, putting the core into a low-power state until then.
+#[cfg(not(test))]
+#[inline(always)]
+pub fn wfe() {
+    unsafe { asm!("wfe" :::: "volatile") }
+}
+
+/// Sends an event to all cores, waking any that are in `wfe`.
+#[cfg(not(test))]
+#[inline(always)]
+pub fn sev() {
+    unsafe { asm!("sev" :::: "volatile") }
+}
+
+/// Returns the affinity level 0 of the calling core, which is its core number
+/// on the Raspberry Pi 3.
+#[cfg(not(test))]
+#[inline(always)]
+pub fn affinity() -> usize {
+    let mpidr: u64;
+    unsafe { asm!("mrs $0, MPIDR_EL1" : "=r"(mpidr) ::: "volatile") }
+    (mpidr & 0b11) as usize
+}
+
+/// Cleans and invalidates the data cache line holding `addr` to the point of
+/// coherency, so that cores running with their caches off observe writes.
+#[inline(always)]
+pub fn clean_dcache_line<T>(addr: *const T) {
+    unsafe { asm!("dc civac, $0
+                   dsb sy" :: "r"(addr) : "memory" : "volatile") }
+}
+
+/// Returns the faulting virtual address of the current abort (`FAR_EL1`).
+#[inline(always)]
+pub fn far() -> usize {
+    let far: u64;
+    unsafe { asm!("mrs $0, FAR_EL1" : "=r"(far) ::: "volatile") }
+    far as usize
+}
+
+/// Makes an instruction written to `addr` visible to instruction fetch by
+/// cleaning it from the data cache and invalidating it in the instruction
+/// cache.
+#[inline(always)]
+pub fn sync_icache<T>(addr: *const T) {
+    unsafe { asm!("dc cvau, $0
+                   dsb ish
+                   ic ivau, $0
+                   dsb ish
+                   isb" :: "r"(addr) : "memory" : "volatile") }
+}
+
+/// Writes the debug control register, `MDSCR_EL1`.
+#[inline(always)]
+pub unsafe fn set_mdscr(val: u64) {
+    asm!("msr MDSCR_EL1, $0
+          isb" :: "r"(val) :: "volatile")
+}
+
+/// Clears the OS lock, which otherwise disables breakpoint, watchpoint and
+/// software step exceptions after reset.
+#[inline(always)]
+pub unsafe fn clear_os_lock() {
+    asm!("msr OSLAR_EL1, xzr
+          isb" :::: "volatile")
+}
+
+/// Executes `brk #0`, raising a breakpoint exception.
+#[inline(always)]
+pub fn brk() {
+    unsafe { asm!("brk #0" :::: "volatile") }
+}
+
+/// Returns the frame pointer (`x29`) of the calling function.
+#[cfg(not(test))]
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: u64;
+    unsafe { asm!("mov $0, x29" : "=r"(fp) ::: "volatile") }
+    fp as usize
+}
+
+/// Returns `true` if the calling core has its MMU (and so its data cache)
+/// enabled. Exclusive loads and stores only work on cacheable memory.
+#[cfg(not(test))]
+#[inline(always)]
+pub fn mmu_enabled() -> bool {
+    let sctlr: u64;
+    unsafe { asm!("mrs $0, SCTLR_EL1" : "=r"(sctlr) ::: "volatile") }
+    sctlr & 1 != 0
+}
+
+/// Returns the interrupt mask bits (`DAIF`).
+#[inline(always)]
+pub fn daif() -> u64 {
+    let daif: u64;
+    unsafe { asm!("mrs $0, DAIF" : "=r"(daif) ::: "volatile") }
+    daif
+}
+
+/// Restores interrupt mask bits previously returned by `daif()`.
+#[inline(always)]
+pub fn set_daif(daif: u64) {
+    unsafe { asm!("msr DAIF, $0" :: "r"(daif) : "memory" : "volatile") }
+}
+
+/// Masks IRQs on the calling core.
+#[inline(always)]
+pub fn mask_irq() {
+    unsafe { asm!("msr DAIFSet, #2" ::: "memory" : "volatile") }
+}
+
+/// Host stand-ins for the wrappers that host tests reach, through `Mutex`,
+/// `OnceCell` and the shell. Tests run as core 0 with its MMU on, and there
+/// is no event to wait for.
+#[cfg(test)]
+mod host {
+    pub fn wfe() {
+        std::thread::yield_now();
+    }
+
+    pub fn sev() {}
+
+    pub fn affinity() -> usize {
+        0
+    }
+
+    pub fn frame_pointer() -> usize {
+        0
+    }
+
+    pub fn mmu_enabled() -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+pub use self::host::*;
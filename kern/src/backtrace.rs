@@ -0,0 +1,172 @@
+//! Frame-pointer backtraces.
+//!
+//! The kernel is built with `-C force-frame-pointers=yes`, so every function
+//! pushes a frame record `{ caller's x29, lr }` and points `x29` at it. Walking
+//! that chain yields the return addresses of the current call stack.
+//!
+//! Return addresses are resolved to names with a symbol table that
+//! `bin/embed-symbols.py` writes into the `.ksyms` section after linking.
+//! Without it, addresses are printed bare and can be resolved with
+//! `aarch64-addr2line` or `nm`.
+
+use core::{fmt, ptr, slice, str};
+
+use crate::aarch64;
+use crate::console::kprintln;
+use crate::smp;
+use crate::traps::TrapFrame;
+
+/// The most frames printed in one backtrace.
+const MAX_DEPTH: usize = 32;
+
+/// Size of the space reserved for the symbol table; must match
+/// `KSYMS_SIZE` in `bin/embed-symbols.py`.
+const KSYMS_SIZE: usize = 64 * 1024;
+
+/// Marks a symbol table written by `bin/embed-symbols.py`.
+const KSYMS_MAGIC: &[u8; 4] = b"KSYM";
+
+/// The symbol table: `KSYMS_MAGIC`, a `u32` count, `count` entries of
+/// `{ addr: u64, name_offset: u32, name_len: u32 }` sorted by address, and the
+/// names. Offsets are from the start of the table. It's `mut` so that reads
+/// aren't constant-folded to the all-zero initializer, since the table is
+/// only filled in after linking.
+#[used]
+#[link_section = ".ksyms"]
+static mut KSYMS: [u8; KSYMS_SIZE] = [0; KSYMS_SIZE];
+
+/// Reads a little-endian `u32` at `offset` in the symbol table.
+fn read_u32(table: &[u8], offset: usize) -> Option<u32> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(table.get(offset..offset + 4)?);
+    Some(u32::from_le_bytes(bytes))
+}
+
+/// Reads a little-endian `u64` at `offset` in the symbol table.
+fn read_u64(table: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from(read_u32(table, offset)?) | u64::from(read_u32(table, offset + 4)?) << 32)
+}
+
+/// Returns the embedded symbol table, or `None` if none was embedded.
+fn symbol_table() -> Option<&'static [u8]> {
+    let table = unsafe { slice::from_raw_parts(KSYMS.as_ptr(), KSYMS_SIZE) };
+
+    if &table[..4] == KSYMS_MAGIC {
+        Some(table)
+    } else {
+        None
+    }
+}
+
+/// Returns the name of the function containing `addr` and `addr`'s offset
+/// into it.
+pub fn symbolize(addr: usize) -> Option<(&'static str, usize)> {
+    let table = symbol_table()?;
+    let count = read_u32(table, 4)? as usize;
+    let entry = |i: usize| read_u64(table, 8 + 16 * i);
+
+    // find the last symbol at or below `addr`
+    let (mut lo, mut hi) = (0, count);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if entry(mid)? as usize <= addr {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let i = lo.checked_sub(1)?;
+    let sym_addr = entry(i)? as usize;
+    let name_offset = read_u32(table, 8 + 16 * i + 8)? as usize;
+    let name_len = read_u32(table, 8 + 16 * i + 12)? as usize;
+    let name = str::from_utf8(table.get(name_offset..name_offset + name_len)?).ok()?;
+    Some((name, addr - sym_addr))
+}
+
+/// Calls `f` with the return address of each frame in the chain starting at
+/// frame pointer `fp`, stopping at a null or implausible frame pointer. Only
+/// frames on the calling core's stack, above its guard region, are followed.
+pub fn walk<F: FnMut(usize)>(mut fp: usize, mut f: F) {
+    let (bottom, top) = smp::stack_bounds(smp::core_id());
+    let bottom = bottom + smp::GUARD_SIZE;
+
+    for _ in 0..MAX_DEPTH {
+        if fp < bottom || fp + 16 > top || fp % 8 != 0 {
+            return;
+        }
+
+        let (next, lr) = unsafe {
+            let record = fp as *const usize;
+            (ptr::read(record), ptr::read(record.add(1)))
+        };
+
+        if lr == 0 {
+            return;
+        }
+
+        f(lr);
+
+        // the stack grows down, so callers' frames are at higher addresses
+        if next <= fp {
+            return;
+        }
+        fp = next;
+    }
+}
+
+/// Returns the address the calling function will return to. The caller must
+/// be `#[inline(never)]`, or this is its own caller's return address.
+#[inline(always)]
+pub fn return_address() -> usize {
+    let fp = aarch64::frame_pointer();
+    if fp == 0 {
+        return 0;
+    }
+
+    unsafe { ptr::read((fp as *const usize).add(1)) }
+}
+
+/// A code address, displayed with its symbol if one is known.
+pub struct Site(pub usize);
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match symbolize(self.0) {
+            Some((name, offset)) => write!(f, "{:#x} ({}+{:#x})", self.0, name, offset),
+            None => write!(f, "{:#x}", self.0),
+        }
+    }
+}
+
+/// Prints one frame of a backtrace.
+fn print_frame(depth: usize, addr: usize) {
+    match symbolize(addr) {
+        Some((name, offset)) => kprintln!("  {:2}: {:#018x} {}+{:#x}", depth, addr, name, offset),
+        None => kprintln!("  {:2}: {:#018x}", depth, addr),
+    }
+}
+
+/// Prints a backtrace of the caller's stack.
+#[inline(never)]
+pub fn print() {
+    kprintln!("backtrace:");
+    let mut depth = 0;
+    walk(aarch64::frame_pointer(), |addr| {
+        print_frame(depth, addr);
+        depth += 1;
+    });
+}
+
+/// Prints a backtrace of the code interrupted by the exception that saved
+/// `tf`, starting with the faulting instruction.
+pub fn print_trap(tf: &TrapFrame) {
+    kprintln!("backtrace of the interrupted code:");
+    print_frame(0, tf.elr as usize);
+
+    let mut depth = 1;
+    walk(tf.x[29] as usize, |addr| {
+        print_frame(depth, addr);
+        depth += 1;
+    });
+}
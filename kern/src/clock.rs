@@ -0,0 +1,130 @@
+//! Wall-clock time.
+//!
+//! The Raspberry Pi has no battery-backed real-time clock, so the wall clock
+//! is unset at boot. Once it's set (with the `date` shell command, or from the
+//! host with `bin/sync-date.sh`), the time is tracked as an offset from the
+//! monotonic system timer.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use pi::timer;
+
+/// The wall-clock time at which the system timer read zero, in microseconds
+/// since the Unix epoch, or `UNSET`.
+static EPOCH_OFFSET_US: AtomicU64 = AtomicU64::new(UNSET);
+
+const UNSET: u64 = u64::max_value();
+
+/// Sets the current wall-clock time to `now`, measured from the Unix epoch.
+pub fn set(now: Duration) {
+    let offset = (now.as_micros() as u64).saturating_sub(timer::current_time().as_micros() as u64);
+    EPOCH_OFFSET_US.store(offset, Ordering::Relaxed);
+}
+
+/// Returns the current wall-clock time since the Unix epoch, or `None` if it
+/// hasn't been set.
+pub fn now() -> Option<Duration> {
+    match EPOCH_OFFSET_US.load(Ordering::Relaxed) {
+        UNSET => None,
+        offset => Some(Duration::from_micros(offset) + timer::current_time()),
+    }
+}
+
+/// A UTC calendar date and time, with one-second resolution.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the date and time `secs` seconds after the Unix epoch.
+    pub fn from_unix(secs: u64) -> DateTime {
+        // ref: Howard Hinnant, "chrono-Compatible Low-Level Date Algorithms"
+        let days = secs / 86400;
+        let rem = secs % 86400;
+
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+
+    /// Returns the number of seconds between the Unix epoch and this time.
+    pub fn to_unix(&self) -> u64 {
+        let year = self.year as u64 - if self.month <= 2 { 1 } else { 0 };
+        let month = self.month as u64;
+        let era = year / 400;
+        let yoe = year - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as u64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        days * 86400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    /// Parses a time of the form `YYYY-MM-DDTHH:MM:SS` (a space may be used in
+    /// place of the `T`), no earlier than 1970.
+    pub fn parse(s: &str) -> Option<DateTime> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 19
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || (bytes[10] != b'T' && bytes[10] != b' ')
+            || bytes[13] != b':'
+            || bytes[16] != b':'
+        {
+            return None;
+        }
+
+        let field = |range: core::ops::Range<usize>| s.get(range)?.parse::<u32>().ok();
+        let dt = DateTime {
+            year: field(0..4)?,
+            month: field(5..7)? as u8,
+            day: field(8..10)? as u8,
+            hour: field(11..13)? as u8,
+            minute: field(14..16)? as u8,
+            second: field(17..19)? as u8,
+        };
+
+        let valid = dt.year >= 1970
+            && (1..=12).contains(&dt.month)
+            && (1..=31).contains(&dt.day)
+            && dt.hour < 24
+            && dt.minute < 60
+            && dt.second < 60;
+
+        if valid { Some(dt) } else { None }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
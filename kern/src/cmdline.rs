@@ -0,0 +1,179 @@
+//! Kernel command-line options.
+//!
+//! The firmware passes the contents of `cmdline.txt` (plus its own options)
+//! in the `CMDLINE` ATAG. Options are whitespace-separated `key=value` pairs;
+//! unrecognised keys and malformed values are ignored so that the firmware's
+//! own options don't get in the way.
+//!
+//! | key         | values                          | default |
+//! |-------------|---------------------------------|---------|
+//! | `console`   | `uart`, `hdmi` (also `serial0`, `ttyS0`, `tty1`) | `uart` |
+//! | `shell`     | `1`/`on`/`yes`, `0`/`off`/`no`  | `1`     |
+//! | `selftest`  | `1`/`on`/`yes`, `0`/`off`/`no`  | `0`     |
+//! | `gdb`       | `1`/`on`/`yes`, `0`/`off`/`no`  | `0`     |
+//! | `panic`     | seconds before rebooting, `0` to halt | see `panic_policy` |
+
+use core::time::Duration;
+
+use pi::atags::Atags;
+
+use crate::once::OnceCell;
+use crate::panic_policy::{self, PanicPolicy};
+
+/// The device used for the kernel console.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConsoleKind {
+    Uart,
+    Hdmi,
+}
+
+/// The options parsed from the kernel command line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Params {
+    pub console: ConsoleKind,
+    pub shell: bool,
+    /// Whether to run the self-tests at boot.
+    pub selftest: bool,
+    /// Whether to start the GDB stub and wait for a debugger at boot.
+    pub gdb: bool,
+    /// The `panic=` option, if given.
+    pub panic: Option<PanicPolicy>,
+}
+
+impl Params {
+    /// The options used when the command line doesn't say otherwise.
+    pub const DEFAULT: Params = Params {
+        console: ConsoleKind::Uart,
+        shell: true,
+        selftest: false,
+        gdb: false,
+        panic: None,
+    };
+
+    /// Parses `cmdline`, starting from `Params::DEFAULT`.
+    pub fn parse(cmdline: &str) -> Params {
+        let mut params = Params::DEFAULT;
+        for arg in cmdline.split_whitespace() {
+            let mut kv = arg.splitn(2, '=');
+            let (key, value) = match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+
+            params.apply(key, value);
+        }
+
+        params
+    }
+
+    /// Applies a single `key=value` option, ignoring it if it's invalid.
+    fn apply(&mut self, key: &str, value: &str) {
+        match key {
+            "console" => {
+                // the firmware passes Linux-style `console=ttyS0,115200`
+                self.console = match value.split(',').next() {
+                    Some("uart") | Some("serial0") | Some("ttyS0") => ConsoleKind::Uart,
+                    Some("hdmi") | Some("tty1") => ConsoleKind::Hdmi,
+                    _ => return,
+                }
+            }
+            "shell" => {
+                self.shell = match parse_bool(value) {
+                    Some(on) => on,
+                    None => return,
+                }
+            }
+            "selftest" => {
+                self.selftest = match parse_bool(value) {
+                    Some(on) => on,
+                    None => return,
+                }
+            }
+            "gdb" => {
+                self.gdb = match parse_bool(value) {
+                    Some(on) => on,
+                    None => return,
+                }
+            }
+            "panic" => {
+                self.panic = match value.parse::<u64>() {
+                    Ok(0) => Some(PanicPolicy::Halt),
+                    Ok(secs) => Some(PanicPolicy::Reboot(Duration::from_secs(secs))),
+                    Err(_) => return,
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses a boolean option value.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "on" | "yes" => Some(true),
+        "0" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+static PARAMS: OnceCell<Params> = OnceCell::new();
+
+/// Reads and parses the command line passed by the firmware and applies the
+/// options that take effect immediately.
+pub fn init() {
+    let params = Atags::cmdline().map(Params::parse).unwrap_or(Params::DEFAULT);
+    if let Some(policy) = params.panic {
+        panic_policy::set(policy);
+    }
+
+    let _ = PARAMS.set(params);
+}
+
+/// Returns the options parsed by `init`, or `Params::DEFAULT` before then.
+pub fn get() -> Params {
+    PARAMS.get().cloned().unwrap_or(Params::DEFAULT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConsoleKind, Params};
+    use crate::panic_policy::PanicPolicy;
+    use core::time::Duration;
+
+    #[test]
+    fn defaults_without_options() {
+        assert_eq!(Params::parse(""), Params::DEFAULT);
+        assert_eq!(Params::parse("  \t\n "), Params::DEFAULT);
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let params = Params::parse("console=hdmi shell=off selftest=1 gdb=yes panic=5");
+        assert_eq!(params.console, ConsoleKind::Hdmi);
+        assert!(!params.shell);
+        assert!(params.selftest);
+        assert!(params.gdb);
+        assert_eq!(params.panic, Some(PanicPolicy::Reboot(Duration::from_secs(5))));
+        assert_eq!(Params::parse("panic=0").panic, Some(PanicPolicy::Halt));
+    }
+
+    #[test]
+    fn understands_firmware_console_names() {
+        assert_eq!(Params::parse("console=tty1").console, ConsoleKind::Hdmi);
+        assert_eq!(Params::parse("console=hdmi console=ttyS0,115200").console, ConsoleKind::Uart);
+        assert_eq!(Params::parse("console=hdmi console=serial0").console, ConsoleKind::Uart);
+    }
+
+    #[test]
+    fn ignores_unknown_and_malformed_options() {
+        // the firmware's own options come first
+        let firmware = "bcm2708_fb.fbwidth=1824 dma.dmachans=0x7f35 8250.nr_uarts=1 quiet";
+        assert_eq!(Params::parse(firmware), Params::DEFAULT);
+
+        let params = Params::parse("shell=maybe selftest panic=soon console=vga gdb= loglevel=debug");
+        assert_eq!(params, Params::DEFAULT);
+
+        // a bad value leaves an earlier good one alone
+        assert!(Params::parse("selftest=on selftest=perhaps").selftest);
+    }
+}
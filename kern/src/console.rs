@@ -1,52 +1,63 @@
 use core::fmt;
+#[cfg(not(test))]
 use pi::uart::MiniUart;
+#[cfg(test)]
+use pi::mock::MiniUart;
+use pi::uart::LoopbackReport;
 use shim::io;
 
 use crate::mutex::Mutex;
+use crate::once::Lazy;
+use crate::watchdog::{self, Source};
 
 /// A global singleton allowing read/write access to the console.
 pub struct Console {
-    inner: Option<MiniUart>,
+    inner: MiniUart,
 }
 
 impl Console {
-    /// Creates a new instance of `Console`.
-    const fn new() -> Console {
-        Console { inner: None }
+    /// Creates a new instance of `Console`, initializing the UART.
+    fn new() -> Console {
+        Console { inner: MiniUart::new() }
     }
 
-    /// Initializes the console if it's not already initialized.
-    #[inline]
-    fn initialize(&mut self) {
-        unimplemented!()
+    /// Returns a mutable borrow to the inner `MiniUart`.
+    fn inner(&mut self) -> &mut MiniUart {
+        &mut self.inner
     }
 
-    /// Returns a mutable borrow to the inner `MiniUart`, initializing it as
-    /// needed.
-    fn inner(&mut self) -> &mut MiniUart {
-        unimplemented!()
+    /// Returns `true` if there is a byte ready to be read.
+    pub fn has_byte(&mut self) -> bool {
+        self.inner().has_byte()
     }
 
     /// Reads a byte from the UART device, blocking until a byte is available.
     pub fn read_byte(&mut self) -> u8 {
-        unimplemented!()
+        self.inner().read_byte()
     }
 
     /// Writes the byte `byte` to the UART device.
     pub fn write_byte(&mut self, byte: u8) {
-        unimplemented!()
+        self.inner().write_byte(byte)
+    }
+
+    /// Runs `MiniUart::loopback_test` with `len` bytes. TX must be jumpered
+    /// to RX, so nothing written meanwhile reaches the terminal.
+    pub fn loopback_test(&mut self, len: usize) -> LoopbackReport {
+        let seed = crate::rand::rand_u64() as u32;
+        self.inner().loopback_test(len, seed)
     }
 }
 
 impl io::Read for Console {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        unimplemented!()
+        io::Read::read(self.inner(), buf)
     }
 }
 
 impl io::Write for Console {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        unimplemented!()
+        io::Write::write(self.inner(), buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -56,27 +67,54 @@ impl io::Write for Console {
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        unimplemented!()
+        fmt::Write::write_str(self.inner(), s)
     }
 }
 
-/// Global `Console` singleton.
-pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
+/// Global `Console` singleton. The UART is initialized on first use.
+pub static CONSOLE: Lazy<Mutex<Console>> = Lazy::new(new_console);
+
+fn new_console() -> Mutex<Console> {
+    Mutex::new(Console::new())
+}
+
+/// A function that receives everything printed with `kprint!`, in addition
+/// to the UART.
+pub type Sink = fn(fmt::Arguments);
+
+/// The most sinks that can be registered.
+const MAX_SINKS: usize = 4;
+
+static SINKS: Mutex<[Option<Sink>; MAX_SINKS]> = Mutex::new([None; MAX_SINKS]);
+
+/// Registers `sink` to receive all further console output. Returns `false`
+/// if `MAX_SINKS` sinks are already registered.
+pub fn register_sink(sink: Sink) -> bool {
+    let mut sinks = SINKS.lock();
+    match sinks.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(sink);
+            true
+        }
+        None => false,
+    }
+}
 
 /// Internal function called by the `kprint[ln]!` macros.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    #[cfg(not(test))]
-    {
-        use core::fmt::Write;
-        let mut console = CONSOLE.lock();
-        console.write_fmt(args).unwrap();
-    }
+    use core::fmt::Write;
 
-    #[cfg(test)]
-    {
-        print!("{}", args);
+    watchdog::begin(Source::Console);
+    CONSOLE.with(|console| console.write_fmt(args)).unwrap();
+
+    // copied out so sinks can't deadlock by registering or printing
+    let sinks = *SINKS.lock();
+    for sink in sinks.iter().filter_map(|sink| *sink) {
+        sink(args);
     }
+
+    watchdog::end(Source::Console);
 }
 
 /// Like `println!`, but for kernel-space.
@@ -90,3 +128,77 @@ pub macro kprintln {
 pub macro kprint($($arg:tt)*) {
     _print(format_args!($($arg)*))
 }
+
+/// The mock UART behind `CONSOLE` in host tests.
+#[cfg(test)]
+pub mod mock {
+    use core::ops::Deref;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use pi::mock::MockHandle;
+
+    use super::CONSOLE;
+
+    static IN_USE: AtomicBool = AtomicBool::new(false);
+
+    /// A handle to the console's mock UART. Tests share `CONSOLE`, so only
+    /// one holds this at a time.
+    pub struct MockConsole {
+        handle: MockHandle,
+    }
+
+    /// Waits for other tests to finish with `CONSOLE`, discards whatever they
+    /// left in its mock UART, and returns a handle to script its input and
+    /// read its output with.
+    pub fn console() -> MockConsole {
+        while IN_USE.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            std::thread::yield_now();
+        }
+
+        let handle = CONSOLE.with(|console| {
+            while console.has_byte() {
+                console.read_byte();
+            }
+            console.inner().handle()
+        });
+
+        handle.take_output();
+        MockConsole { handle }
+    }
+
+    impl Deref for MockConsole {
+        type Target = MockHandle;
+
+        fn deref(&self) -> &MockHandle {
+            &self.handle
+        }
+    }
+
+    impl Drop for MockConsole {
+        fn drop(&mut self) {
+            IN_USE.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{kprint, kprintln, mock, CONSOLE};
+
+    #[test]
+    fn prints_to_the_uart() {
+        let console = mock::console();
+        kprint!("{}-{}", 1, 2);
+        kprintln!(" done");
+        assert_eq!(console.take_output(), b"1-2 done\r\n".to_vec());
+    }
+
+    #[test]
+    fn reads_typed_bytes() {
+        let console = mock::console();
+        console.push_input(b"hi");
+        let read = CONSOLE.with(|console| (console.has_byte(), console.read_byte(), console.read_byte()));
+        assert_eq!(read, (true, b'h', b'i'));
+        assert!(!CONSOLE.with(|console| console.has_byte()));
+    }
+}
@@ -0,0 +1,464 @@
+//! A minimal GDB remote serial protocol stub on the PL011.
+//!
+//! The stub is enabled with `gdb=1` on the kernel command line. The kernel
+//! then stops at a breakpoint early in `kinit` and waits for GDB to attach,
+//! while the console stays on the mini UART:
+//!
+//! ```text
+//! (gdb) target remote /dev/ttyUSB1      # or localhost:1234 under QEMU
+//! ```
+//!
+//! Supported packets are `?`, `g`/`G`, `p`/`P`, `m`/`M`, `c`, `s`, `Z0`/`z0`,
+//! `D`, `k` and `qSupported`; anything else gets the empty "unsupported" reply.
+//! Memory accesses aren't checked, so asking for an unmapped address faults.
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use pi::pl011::Pl011;
+use stack_vec::StackVec;
+
+use crate::aarch64;
+use crate::console::kprintln;
+use crate::mutex::Mutex;
+use crate::traps::TrapFrame;
+
+/// The largest packet we accept or send, advertised in `qSupported`.
+const PACKET_SIZE: usize = 1024;
+
+const MAX_BREAKPOINTS: usize = 16;
+
+/// The encoding of `brk #0`.
+const BRK_INSTRUCTION: u32 = 0xd420_0000;
+
+/// `SPSR_EL1` software step and debug mask bits.
+const SPSR_SS: u64 = 1 << 21;
+const SPSR_D: u64 = 1 << 9;
+
+/// `MDSCR_EL1` software step and kernel debug enable bits.
+const MDSCR_SS: u64 = 1 << 0;
+const MDSCR_KDE: u64 = 1 << 13;
+
+/// Register numbers in GDB's AArch64 layout; 0 to 30 are `x0` to `x30`.
+const REG_SP: usize = 31;
+const REG_PC: usize = 32;
+const REG_CPSR: usize = 33;
+
+/// A software breakpoint and the instruction it replaced.
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    addr: usize,
+    insn: u32,
+}
+
+struct Stub {
+    uart: Pl011,
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    /// Whether GDB is waiting for a stop reply to a `c` or `s` packet.
+    running: bool,
+}
+
+static STUB: Mutex<Option<Stub>> = Mutex::new(None);
+
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+
+/// Starts the stub on the PL011 and stops at a breakpoint until GDB attaches
+/// and continues.
+pub fn init() {
+    *STUB.lock() = Some(Stub {
+        uart: Pl011::new(),
+        breakpoints: [None; MAX_BREAKPOINTS],
+        running: false,
+    });
+
+    unsafe { aarch64::clear_os_lock() };
+    ATTACHED.store(true, Ordering::Release);
+
+    kprintln!("gdb: waiting for a debugger on the PL011");
+    aarch64::brk();
+}
+
+/// Returns `true` if breakpoint and step exceptions should go to the stub.
+pub fn attached() -> bool {
+    ATTACHED.load(Ordering::Acquire)
+}
+
+/// Handles a `brk` exception. A `brk` that isn't one of GDB's breakpoints is
+/// stepped over, so that resuming doesn't trap on it again.
+pub fn handle_breakpoint(tf: &mut TrapFrame) {
+    let mut stub = STUB.lock();
+    if let Some(stub) = stub.as_mut() {
+        if stub.breakpoint(tf.elr as usize).is_none() {
+            tf.elr += 4;
+        }
+
+        stub.stop(tf);
+    }
+}
+
+/// Handles a software step exception.
+pub fn handle_step(tf: &mut TrapFrame) {
+    if let Some(stub) = STUB.lock().as_mut() {
+        stub.stop(tf);
+    }
+}
+
+/// A reply packet being built.
+struct Reply<'a>(StackVec<'a, u8>);
+
+impl<'a> Reply<'a> {
+    /// Appends `s`. Output beyond the packet size is dropped.
+    fn str(&mut self, s: &str) {
+        self.0.extend(s.bytes());
+    }
+
+    /// Appends `bytes` as pairs of hex digits.
+    fn hex(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            let _ = self.0.push(HEX[(b >> 4) as usize]);
+            let _ = self.0.push(HEX[(b & 0xF) as usize]);
+        }
+    }
+}
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+/// Returns the value of the hex digit `c`.
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a big-endian hex number such as an address or length.
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+
+    s.iter().try_fold(0u64, |acc, &c| Some(acc << 4 | hex_digit(c)? as u64))
+}
+
+/// Decodes pairs of hex digits into `out`, returning the number of bytes
+/// written.
+fn decode_hex(s: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    for (pair, byte) in s.chunks(2).zip(out.iter_mut()) {
+        if pair.len() != 2 {
+            return None;
+        }
+
+        *byte = hex_digit(pair[0])? << 4 | hex_digit(pair[1])?;
+        len += 1;
+    }
+
+    Some(len)
+}
+
+/// Splits `s` at the first `sep`.
+fn split(s: &[u8], sep: u8) -> Option<(&[u8], &[u8])> {
+    let i = s.iter().position(|&c| c == sep)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+/// Parses `addr,len`.
+fn parse_range(s: &[u8]) -> Option<(usize, usize)> {
+    let (addr, len) = split(s, b',')?;
+    Some((parse_hex(addr)? as usize, parse_hex(len)? as usize))
+}
+
+/// Returns the value of register `reg` in `tf` and its size in bytes.
+fn read_reg(tf: &TrapFrame, reg: usize) -> Option<(u64, usize)> {
+    match reg {
+        0..=30 => Some((tf.x[reg], 8)),
+        // the stack pointer before the exception, which pushed `tf`
+        REG_SP => Some((tf as *const TrapFrame as u64 + mem::size_of::<TrapFrame>() as u64, 8)),
+        REG_PC => Some((tf.elr, 8)),
+        REG_CPSR => Some((tf.spsr & 0xFFFF_FFFF, 4)),
+        _ => None,
+    }
+}
+
+/// Sets register `reg` in `tf`. Writes to `sp` are ignored since the frame
+/// lives on that stack.
+fn write_reg(tf: &mut TrapFrame, reg: usize, val: u64) {
+    match reg {
+        0..=30 => tf.x[reg] = val,
+        REG_PC => tf.elr = val,
+        REG_CPSR => tf.spsr = val & 0xFFFF_FFFF,
+        _ => {}
+    }
+}
+
+impl Stub {
+    /// Returns the index of the breakpoint at `addr`, if any.
+    fn breakpoint(&self, addr: usize) -> Option<usize> {
+        self.breakpoints.iter().position(|bp| bp.map(|bp| bp.addr) == Some(addr))
+    }
+
+    /// Reports the stop to GDB (if it's waiting for one) and services its
+    /// requests until it resumes execution.
+    fn stop(&mut self, tf: &mut TrapFrame) {
+        if self.running {
+            self.send(b"S05");
+        }
+
+        let mut packet = [0u8; PACKET_SIZE];
+        let mut storage = [0u8; PACKET_SIZE];
+        loop {
+            let len = self.recv(&mut packet);
+            let mut reply = Reply(StackVec::new(&mut storage));
+            if self.execute(&packet[..len], tf, &mut reply) {
+                return;
+            }
+
+            self.send(reply.0.as_slice());
+        }
+    }
+
+    /// Executes the command in `packet`, writing its reply to `reply`.
+    /// Returns `true` if execution should resume, in which case `reply` is
+    /// not sent.
+    fn execute(&mut self, packet: &[u8], tf: &mut TrapFrame, reply: &mut Reply) -> bool {
+        let (cmd, args) = match packet.split_first() {
+            Some((&cmd, args)) => (cmd, args),
+            None => return false,
+        };
+
+        let ok = match cmd {
+            b'?' => {
+                reply.str("S05");
+                true
+            }
+            b'g' => {
+                for reg in 0..=REG_CPSR {
+                    let (val, size) = read_reg(tf, reg).unwrap();
+                    reply.hex(&val.to_le_bytes()[..size]);
+                }
+                true
+            }
+            b'G' => {
+                let mut offset = 0;
+                for reg in 0..=REG_CPSR {
+                    let size = read_reg(tf, reg).unwrap().1;
+                    let mut bytes = [0u8; 8];
+                    match args.get(offset..offset + 2 * size) {
+                        Some(hex) if decode_hex(hex, &mut bytes).is_some() => {
+                            write_reg(tf, reg, u64::from_le_bytes(bytes))
+                        }
+                        _ => break,
+                    }
+                    offset += 2 * size;
+                }
+                reply.str("OK");
+                true
+            }
+            b'p' => match parse_hex(args).and_then(|reg| read_reg(tf, reg as usize)) {
+                Some((val, size)) => {
+                    reply.hex(&val.to_le_bytes()[..size]);
+                    true
+                }
+                None => false,
+            },
+            b'P' => {
+                let mut bytes = [0u8; 8];
+                let reg = split(args, b'=').and_then(|(reg, val)| {
+                    decode_hex(val, &mut bytes)?;
+                    parse_hex(reg)
+                });
+                match reg {
+                    Some(reg) => {
+                        write_reg(tf, reg as usize, u64::from_le_bytes(bytes));
+                        reply.str("OK");
+                        true
+                    }
+                    None => false,
+                }
+            }
+            b'm' => match parse_range(args) {
+                Some((addr, len)) => {
+                    for i in 0..len.min((PACKET_SIZE - 4) / 2) {
+                        let byte = unsafe { ptr::read_volatile((addr + i) as *const u8) };
+                        reply.hex(&[byte]);
+                    }
+                    true
+                }
+                None => false,
+            },
+            b'M' => match split(args, b':').and_then(|(range, data)| Some((parse_range(range)?, data))) {
+                Some(((addr, len), data)) if data.len() == 2 * len => {
+                    for (i, pair) in data.chunks(2).enumerate() {
+                        let mut byte = [0u8];
+                        decode_hex(pair, &mut byte);
+                        unsafe { ptr::write_volatile((addr + i) as *mut u8, byte[0]) };
+                    }
+
+                    for line in (addr & !0xF..addr + len).step_by(16) {
+                        aarch64::sync_icache(line as *const u8);
+                    }
+
+                    reply.str("OK");
+                    true
+                }
+                _ => false,
+            },
+            b'Z' | b'z' => match split(args, b',').and_then(|(kind, rest)| Some((kind, parse_range(rest)?))) {
+                Some((b"0", (addr, _))) => {
+                    let ok = if cmd == b'Z' {
+                        self.insert_breakpoint(addr)
+                    } else {
+                        self.remove_breakpoint(addr)
+                    };
+
+                    if ok {
+                        reply.str("OK");
+                    }
+                    ok
+                }
+                // other breakpoint and watchpoint types are unsupported
+                _ => return false,
+            },
+            b'c' | b's' => {
+                if let Some(addr) = parse_hex(args) {
+                    tf.elr = addr;
+                }
+
+                self.resume(tf, cmd == b's');
+                return true;
+            }
+            b'D' | b'k' => {
+                if cmd == b'D' {
+                    self.send(b"OK");
+                }
+
+                self.detach(tf);
+                return true;
+            }
+            b'q' if args.starts_with(b"Supported") => {
+                reply.str("PacketSize=400");
+                true
+            }
+            _ => return false,
+        };
+
+        if !ok {
+            reply.str("E01");
+        }
+
+        false
+    }
+
+    /// Replaces the instruction at `addr` with `brk #0`.
+    fn insert_breakpoint(&mut self, addr: usize) -> bool {
+        if self.breakpoint(addr).is_some() {
+            return true;
+        }
+
+        let slot = match self.breakpoints.iter().position(|bp| bp.is_none()) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        let insn = unsafe { ptr::read_volatile(addr as *const u32) };
+        unsafe { ptr::write_volatile(addr as *mut u32, BRK_INSTRUCTION) };
+        aarch64::sync_icache(addr as *const u32);
+
+        self.breakpoints[slot] = Some(Breakpoint { addr, insn });
+        true
+    }
+
+    /// Restores the instruction replaced by the breakpoint at `addr`.
+    fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        let slot = match self.breakpoint(addr) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        let bp = self.breakpoints[slot].take().unwrap();
+        unsafe { ptr::write_volatile(bp.addr as *mut u32, bp.insn) };
+        aarch64::sync_icache(bp.addr as *const u32);
+        true
+    }
+
+    /// Sets up `tf` to resume, stepping a single instruction if `step`.
+    fn resume(&mut self, tf: &mut TrapFrame, step: bool) {
+        self.running = true;
+        if step {
+            tf.spsr = (tf.spsr | SPSR_SS) & !SPSR_D;
+            unsafe { aarch64::set_mdscr(MDSCR_KDE | MDSCR_SS) };
+        } else {
+            tf.spsr = (tf.spsr & !SPSR_SS) | SPSR_D;
+            unsafe { aarch64::set_mdscr(0) };
+        }
+    }
+
+    /// Removes all breakpoints and resumes without the debugger.
+    fn detach(&mut self, tf: &mut TrapFrame) {
+        for slot in 0..MAX_BREAKPOINTS {
+            if let Some(bp) = self.breakpoints[slot] {
+                self.remove_breakpoint(bp.addr);
+            }
+        }
+
+        self.resume(tf, false);
+        self.running = false;
+        ATTACHED.store(false, Ordering::Release);
+    }
+
+    /// Receives a packet into `buf`, acknowledging it, and returns its length.
+    /// Packets with a bad checksum are rejected and retransmitted by GDB.
+    fn recv(&mut self, buf: &mut [u8]) -> usize {
+        loop {
+            while self.uart.read_byte() != b'$' {}
+
+            let mut len = 0;
+            let mut sum = 0u8;
+            loop {
+                let byte = self.uart.read_byte();
+                if byte == b'#' {
+                    break;
+                }
+
+                sum = sum.wrapping_add(byte);
+                if len < buf.len() {
+                    buf[len] = byte;
+                    len += 1;
+                }
+            }
+
+            let checksum = [self.uart.read_byte(), self.uart.read_byte()];
+            if parse_hex(&checksum) == Some(sum as u64) {
+                self.uart.write_byte(b'+');
+                return len;
+            }
+
+            self.uart.write_byte(b'-');
+        }
+    }
+
+    /// Sends `data` as a packet, retransmitting until GDB acknowledges it.
+    fn send(&mut self, data: &[u8]) {
+        loop {
+            let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+            self.uart.write_byte(b'$');
+            for &b in data {
+                self.uart.write_byte(b);
+            }
+            self.uart.write_byte(b'#');
+            self.uart.write_byte(HEX[(sum >> 4) as usize]);
+            self.uart.write_byte(HEX[(sum & 0xF) as usize]);
+
+            loop {
+                match self.uart.read_byte() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
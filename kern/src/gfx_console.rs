@@ -0,0 +1,317 @@
+//! A text console on the HDMI framebuffer.
+//!
+//! With `console=hdmi` on the command line, `init` sets up a framebuffer at
+//! the display's resolution and registers the console as a `console` sink, so
+//! everything printed with `kprint!`, including the shell, shows up on an
+//! attached monitor as well as on the UART.
+//!
+//! Text is drawn in an 8x8 bitmap font on a grid of `cols` by `rows`
+//! character cells. Output wraps at the right edge, scrolls at the bottom, and
+//! the cursor is an underline in the next cell to be written. `\n`, `\r`,
+//! `\t` and backspace are interpreted; other control characters are ignored.
+
+use core::fmt::{self, Write};
+
+use pi::framebuffer::Framebuffer;
+
+use crate::console;
+use crate::mutex::Mutex;
+
+/// The size of a character cell, in pixels.
+const GLYPH_WIDTH: usize = 8;
+const GLYPH_HEIGHT: usize = 8;
+
+/// The largest grid kept, enough for 1920x1080. Larger displays only use
+/// their top-left corner.
+const MAX_COLS: usize = 240;
+const MAX_ROWS: usize = 135;
+
+/// The resolution used when the display doesn't report one.
+const DEFAULT_SIZE: (u32, u32) = (640, 480);
+
+const FOREGROUND: u32 = 0x00AA_AAAA;
+const BACKGROUND: u32 = 0x0000_0000;
+
+/// Tab stops are every `TAB_WIDTH` columns.
+const TAB_WIDTH: usize = 8;
+
+/// The first character in `FONT`.
+const FONT_FIRST: u8 = 0x20;
+
+/// Drawn for bytes that start a non-ASCII UTF-8 sequence.
+const REPLACEMENT: u8 = b'?';
+
+/// Glyphs for the printable ASCII characters, `' '` to `'~'`. Each byte is a
+/// row, top first; bit `x` of a row is the pixel in column `x`.
+static FONT: [[u8; GLYPH_HEIGHT]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+/// A character grid drawn on a framebuffer.
+pub struct GfxConsole {
+    fb: Framebuffer,
+    /// The character in each cell, used to redraw cells under the cursor.
+    cells: [[u8; MAX_COLS]; MAX_ROWS],
+    cols: usize,
+    rows: usize,
+    /// The cursor position. `col == cols` means the next character wraps.
+    col: usize,
+    row: usize,
+}
+
+impl GfxConsole {
+    /// Sets up a framebuffer at the display's resolution and returns a
+    /// cleared console on it, or `None` if the firmware refuses.
+    pub fn new() -> Option<GfxConsole> {
+        let (width, height) = Framebuffer::display_size().unwrap_or(DEFAULT_SIZE);
+        let fb = Framebuffer::new(width, height)?;
+        let cols = (fb.width() / GLYPH_WIDTH).min(MAX_COLS);
+        let rows = (fb.height() / GLYPH_HEIGHT).min(MAX_ROWS);
+        if cols == 0 || rows == 0 {
+            return None;
+        }
+
+        let mut console = GfxConsole { fb, cells: [[b' '; MAX_COLS]; MAX_ROWS], cols, rows, col: 0, row: 0 };
+        console.clear();
+        Some(console)
+    }
+
+    /// Returns the size of the grid, in characters.
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Blanks the screen and moves the cursor to the top left.
+    pub fn clear(&mut self) {
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = b' ';
+            }
+        }
+
+        let (width, height) = (self.fb.width(), self.fb.height());
+        self.fb.fill_rect(0, 0, width, height, BACKGROUND);
+        self.fb.flush(0, 0, width, height);
+        self.col = 0;
+        self.row = 0;
+        self.draw_cursor();
+    }
+
+    /// Writes the byte `byte`, interpreting control characters.
+    pub fn write_byte(&mut self, byte: u8) {
+        self.erase_cursor();
+        match byte {
+            b'\n' => self.newline(),
+            b'\r' => self.col = 0,
+            b'\t' => {
+                let stop = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.col < stop.min(self.cols) {
+                    self.put(b' ');
+                }
+            }
+            0x08 | 0x7F => self.col = self.col.saturating_sub(1),
+            0x20..=0x7E => self.put(byte),
+            // UTF-8 continuation bytes
+            0x80..=0xBF => {}
+            0xC0..=0xFF => self.put(REPLACEMENT),
+            _ => {}
+        }
+        self.draw_cursor();
+    }
+
+    /// Draws `byte` at the cursor and advances it, wrapping first if the
+    /// previous character filled the line.
+    fn put(&mut self, byte: u8) {
+        if self.col == self.cols {
+            self.newline();
+        }
+
+        self.cells[self.row][self.col] = byte;
+        self.draw_cell(self.col, self.row);
+        self.col += 1;
+    }
+
+    fn newline(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// Moves every line up by one and blanks the last.
+    fn scroll(&mut self) {
+        for row in 1..self.rows {
+            self.cells[row - 1] = self.cells[row];
+        }
+        self.cells[self.rows - 1] = [b' '; MAX_COLS];
+
+        let (width, height) = (self.fb.width(), self.fb.height());
+        self.fb.scroll_up(GLYPH_HEIGHT, BACKGROUND);
+        self.fb.flush(0, 0, width, height);
+    }
+
+    /// Draws the character stored for cell (`col`, `row`).
+    fn draw_cell(&mut self, col: usize, row: usize) {
+        let byte = self.cells[row][col];
+        let glyph = match byte.checked_sub(FONT_FIRST).and_then(|i| FONT.get(i as usize)) {
+            Some(glyph) => glyph,
+            None => &FONT[(REPLACEMENT - FONT_FIRST) as usize],
+        };
+
+        let (x0, y0) = (col * GLYPH_WIDTH, row * GLYPH_HEIGHT);
+        for (y, bits) in glyph.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                let color = if bits & (1 << x) != 0 { FOREGROUND } else { BACKGROUND };
+                self.fb.set_pixel(x0 + x, y0 + y, color);
+            }
+        }
+
+        self.fb.flush(x0, y0, GLYPH_WIDTH, GLYPH_HEIGHT);
+    }
+
+    /// Underlines the cell under the cursor. Nothing is drawn while a wrap is
+    /// pending.
+    fn draw_cursor(&mut self) {
+        if self.col < self.cols {
+            let (x, y) = (self.col * GLYPH_WIDTH, (self.row + 1) * GLYPH_HEIGHT - 1);
+            self.fb.fill_rect(x, y, GLYPH_WIDTH, 1, FOREGROUND);
+            self.fb.flush(x, y, GLYPH_WIDTH, 1);
+        }
+    }
+
+    fn erase_cursor(&mut self) {
+        if self.col < self.cols {
+            self.draw_cell(self.col, self.row);
+        }
+    }
+}
+
+impl fmt::Write for GfxConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+/// The HDMI console, once `init` has set it up.
+pub static GFX_CONSOLE: Mutex<Option<GfxConsole>> = Mutex::new(None);
+
+/// Sets up the HDMI console and starts mirroring console output to it.
+/// Returns `false` if there's no framebuffer or the console sinks are full.
+pub fn init() -> bool {
+    let console = match GfxConsole::new() {
+        Some(console) => console,
+        None => return false,
+    };
+
+    *GFX_CONSOLE.lock() = Some(console);
+    console::register_sink(print)
+}
+
+/// The console sink: draws `args` if the console is set up.
+fn print(args: fmt::Arguments) {
+    GFX_CONSOLE.with(|console| {
+        if let Some(console) = console.as_mut() {
+            let _ = console.write_fmt(args);
+        }
+    });
+}
@@ -1,5 +1,11 @@
-use core::mem::zeroed;
-use core::ptr::write_volatile;
+//! Early boot.
+//!
+//! `init.s` runs first on every core: core 0 continues while cores 1-3 wait
+//! in the firmware's spin table. Each core drops from EL3 or EL2 to EL1,
+//! masks interrupts, enables SIMD/FP, installs the exception vectors and
+//! switches to its own boot stack (see `smp::stack_bounds`). Core 0 then
+//! zeroes BSS and calls `kinit`; cores released by `smp::start_cores` call
+//! `kinit_secondary`.
 
 mod panic;
 mod oom;
@@ -8,23 +14,33 @@ use crate::kmain;
 
 global_asm!(include_str!("init/init.s"));
 
-unsafe fn zeros_bss() {
-    extern "C" {
-        static mut __bss_beg: u64;
-        static mut __bss_end: u64;
+/// Core 0's entry point. BSS is zeroed and this core runs at EL1 on its boot
+/// stack with interrupts masked and the MMU off.
+#[no_mangle]
+unsafe fn kinit() -> ! {
+    crate::vm::init();
+    crate::cmdline::init();
+    if crate::cmdline::get().console == crate::cmdline::ConsoleKind::Hdmi {
+        crate::gfx_console::init();
     }
-
-    let mut iter: *mut u64 = &mut __bss_beg;
-    let end: *mut u64 = &mut __bss_end;
-
-    while iter < end {
-        write_volatile(iter, zeroed());
-        iter = iter.add(1);
+    crate::rand::init();
+    if crate::cmdline::get().gdb {
+        crate::gdb::init();
     }
+    crate::smp::start_cores();
+    if crate::cmdline::get().selftest {
+        let failed = crate::selftest::run_all();
+        // let automated QEMU runs report the result through the exit status
+        if crate::qemu::is_qemu() {
+            crate::qemu::exit(if failed == 0 { 0 } else { 1 });
+        }
+    }
+    kmain();
 }
 
+/// The entry point of cores 1-3 once `smp::start_cores` releases them.
 #[no_mangle]
-unsafe fn kinit() -> ! {
-    zeros_bss();
-    kmain();
+unsafe fn kinit_secondary() -> ! {
+    crate::vm::enable();
+    crate::smp::park();
 }
\ No newline at end of file
@@ -5,21 +5,51 @@
 
 .section .text.init
 
+// size of each core's boot stack; must match `smp::STACK_SIZE`
+.equ STACK_SIZE, 0x10000
+
+// size of the unmapped guard region at the bottom of each stack; must match
+// `smp::GUARD_SIZE`
+.equ GUARD_SIZE, 0x1000
+
+// size of each core's emergency stack, used to handle stack overflows
+.equ EMERGENCY_STACK_SIZE, 0x1000
+
+// address of core 0's spin-table slot; core N's slot is 8 * N bytes above it
+.equ SPIN_TABLE_BASE, 0xd8
+
 .global _start
 _start:
-    // read cpu affinity, start core 0, halt rest
-    mrs     x1, MPIDR_EL1
-    and     x1, x1, #3
-    cbz     x1, setup
+    // read cpu affinity, start core 0, park the rest
+    mrs     x6, MPIDR_EL1
+    and     x6, x6, #3
+    cbz     x6, setup
+
+park:
+    // core affinity != 0, wait until the kernel writes an entry address into
+    // this core's spin-table slot and jump to it
+    wfe
+    mov     x2, #SPIN_TABLE_BASE
+    ldr     x2, [x2, x6, lsl #3]
+    cbz     x2, park
+    br      x2
 
 halt:
-    // core affinity != 0, halt it
     wfe
     b       halt
 
+// entry point for secondary cores released by `smp::start_cores`
+.global _start_secondary
+_start_secondary:
+    mrs     x6, MPIDR_EL1
+    and     x6, x6, #3
+
 setup:
-    // store the desired EL1 stack pointer in x1
+    // store the desired EL1 stack pointer in x1: each core gets `STACK_SIZE`
+    // bytes below the previous core's, starting from `_start` for core 0
     adr     x1, _start
+    mov     x2, #STACK_SIZE
+    msub    x1, x2, x6, x1
 
     // read the current exception level into x0 (ref: C5.2.1)
     mrs     x0, CurrentEL
@@ -43,9 +73,10 @@ switch_to_el2:
     eret
 
 switch_to_el1:
-    // switch to EL1 if we're not already in EL1. otherwise continue with start
+    // switch to EL1 if we're not already in EL1. otherwise continue with the
+    // EL1 set-up
     cmp     x0, 0b01    // EL1
-    beq     set_stack
+    beq     el1_setup
 
     // set the stack-pointer for EL1
     msr     SP_EL1, x1
@@ -57,70 +88,278 @@ switch_to_el1:
     msr     CNTHCTL_EL2, x0
     msr     CNTVOFF_EL2, xzr
 
-    // enable AArch64 in EL1 (A53: 4.3.36)
+    // enable AArch64 in EL1 and clear every other HCR bit, so no trap or
+    // stage 2 translation the firmware left configured applies (A53: 4.3.36)
     mov     x0, #(1 << 31)      // Enable AArch64 for EL1
     orr     x0, x0, #(1 << 1)   // RES1 on A-53
     msr     HCR_EL2, x0
-    mrs     x0, HCR_EL2
 
-    // enable floating point and SVE (SIMD) (A53: 4.3.38, 4.3.34)
-    msr     CPTR_EL2, xzr     // don't trap accessing SVE registers
+    // reads of MIDR_EL1 and MPIDR_EL1 at EL1 return VPIDR_EL2 and VMPIDR_EL2,
+    // which newer firmware doesn't initialize; copy the real values so that
+    // `smp::core_id()` is right (ref: D13.2.105, D13.2.106)
+    mrs     x0, MIDR_EL1
+    msr     VPIDR_EL2, x0
+    mrs     x0, MPIDR_EL1
+    msr     VMPIDR_EL2, x0
+
+    // don't trap accessing SIMD/FP registers (A53: 4.3.34)
+    msr     CPTR_EL2, xzr
+
+    // change execution level to EL1h with DAIF masked (ref: C5.2.19)
+    mov     x2, #0x3c5
+    msr     SPSR_EL2, x2
+    adr     x2, el1_setup
+    msr     ELR_EL2, x2
+    eret
+
+el1_setup:
+    // the firmware may have entered EL1 directly, so everything EL1 needs is
+    // set up here rather than from EL2. mask interrupts and use SP_EL1
+    msr     DAIFSet, #0xf
+    msr     SPSel, #1
+
+    // enable floating point and SIMD (A53: 4.3.38)
     mrs     x0, CPACR_EL1
     orr     x0, x0, #(0b11 << 20)
     msr     CPACR_EL1, x0
 
-    // Set SCTLR to known state (RES1: 11, 20, 22, 23, 28, 29) (A53: 4.3.30)
+    // generate an event every 2^11 ticks of the generic timer, about 107us at
+    // 19.2MHz, so that loops polling a device with `wfe` wake up to check it
+    // without interrupts (ref: D7.5.4)
+    mrs     x0, CNTKCTL_EL1
+    bic     x0, x0, #(0b1111 << 4)
+    mov     x2, #(10 << 4)      // EVNTI: trigger on bit 10
+    orr     x2, x2, #(1 << 2)   // EVNTEN
+    orr     x0, x0, x2
+    msr     CNTKCTL_EL1, x0
+
+    // Set SCTLR to known state, MMU and caches off (RES1: 11, 20, 22, 23, 28,
+    // 29) (A53: 4.3.30)
     mov     x2, #0x0800
     movk    x2, #0x30d0, lsl #16
     msr     SCTLR_EL1, x2
 
     // set up exception handlers
-    // FIXME: load `_vectors` addr into appropriate register (guide: 10.4)
-
-    // change execution level to EL1 (ref: C5.2.19)
-    mov     x2, #0x3c5
-    msr     SPSR_EL2, x2
-    // FIXME: Return to EL1 at `set_stack`.
+    adr     x2, _vectors
+    msr     VBAR_EL1, x2
+    isb
 
 set_stack:
     // set the current stack pointer
     mov     sp, x1
 
-// zero_bss:
-//     // load the start address and number of bytes in BSS section
-//     ldr     x1, =__bss_start
-//     ldr     x2, =__bss_length
+    // secondary cores start after core 0 has zeroed BSS
+    cbnz    x6, go_secondary
 
-// zero_bss_loop:
-//     // zero out the BSS section, 64-bits at a time
-//     cbz     x2, go_kmain
-//     str     xzr, [x1], #8
-//     sub     x2, x2, #8
-//     cbnz    x2, zero_bss_loop
+zero_bss:
+    // zero the BSS section, 64 bits at a time, before any Rust code runs;
+    // `layout.ld` aligns both ends to 8 bytes
+    ldr     x1, =__bss_beg
+    ldr     x2, =__bss_end
+
+zero_bss_loop:
+    cmp     x1, x2
+    b.hs    go_kmain
+    str     xzr, [x1], #8
+    b       zero_bss_loop
 
 go_kmain:
-    // jump to kmain, which shouldn't return. halt if it does
+    // jump to kinit (core 0) or kinit_secondary, which shouldn't return. halt
+    // if they do
     bl      kinit
     b       halt
 
+go_secondary:
+    bl      kinit_secondary
+    b       halt
+
 context_save:
-    // FIXME: Save the remaining context to the stack.
+    // save x0-x27; x28, x29 and lr were saved by the `HANDLER` stub
+    stp     x26, x27, [SP, #-16]!
+    stp     x24, x25, [SP, #-16]!
+    stp     x22, x23, [SP, #-16]!
+    stp     x20, x21, [SP, #-16]!
+    stp     x18, x19, [SP, #-16]!
+    stp     x16, x17, [SP, #-16]!
+    stp     x14, x15, [SP, #-16]!
+    stp     x12, x13, [SP, #-16]!
+    stp     x10, x11, [SP, #-16]!
+    stp     x8, x9, [SP, #-16]!
+    stp     x6, x7, [SP, #-16]!
+    stp     x4, x5, [SP, #-16]!
+    stp     x2, x3, [SP, #-16]!
+    stp     x0, x1, [SP, #-16]!
+
+    // save the SIMD/FP registers
+    stp     q30, q31, [SP, #-32]!
+    stp     q28, q29, [SP, #-32]!
+    stp     q26, q27, [SP, #-32]!
+    stp     q24, q25, [SP, #-32]!
+    stp     q22, q23, [SP, #-32]!
+    stp     q20, q21, [SP, #-32]!
+    stp     q18, q19, [SP, #-32]!
+    stp     q16, q17, [SP, #-32]!
+    stp     q14, q15, [SP, #-32]!
+    stp     q12, q13, [SP, #-32]!
+    stp     q10, q11, [SP, #-32]!
+    stp     q8, q9, [SP, #-32]!
+    stp     q6, q7, [SP, #-32]!
+    stp     q4, q5, [SP, #-32]!
+    stp     q2, q3, [SP, #-32]!
+    stp     q0, q1, [SP, #-32]!
+
+    // save the special registers: ELR, SPSR, SP_EL0 and TPIDR_EL0
+    mrs     x1, SP_EL0
+    mrs     x2, TPIDR_EL0
+    stp     x1, x2, [SP, #-16]!
+    mrs     x1, ELR_EL1
+    mrs     x2, SPSR_EL1
+    stp     x1, x2, [SP, #-16]!
+
+    // call `handle_exception(info, esr, tf)`, preserving our return address
+    mov     x28, lr
+    mov     x0, x29
+    mrs     x1, ESR_EL1
+    mov     x2, SP
+    bl      handle_exception
+    mov     lr, x28
 
 .global context_restore
 context_restore:
-    // FIXME: Restore the context from the stack.
+    // restore the special registers
+    ldp     x1, x2, [SP], #16
+    msr     ELR_EL1, x1
+    msr     SPSR_EL1, x2
+    ldp     x1, x2, [SP], #16
+    msr     SP_EL0, x1
+    msr     TPIDR_EL0, x2
+
+    // restore the SIMD/FP registers
+    ldp     q0, q1, [SP], #32
+    ldp     q2, q3, [SP], #32
+    ldp     q4, q5, [SP], #32
+    ldp     q6, q7, [SP], #32
+    ldp     q8, q9, [SP], #32
+    ldp     q10, q11, [SP], #32
+    ldp     q12, q13, [SP], #32
+    ldp     q14, q15, [SP], #32
+    ldp     q16, q17, [SP], #32
+    ldp     q18, q19, [SP], #32
+    ldp     q20, q21, [SP], #32
+    ldp     q22, q23, [SP], #32
+    ldp     q24, q25, [SP], #32
+    ldp     q26, q27, [SP], #32
+    ldp     q28, q29, [SP], #32
+    ldp     q30, q31, [SP], #32
+
+    // restore x0-x27; the `HANDLER` stub restores x28, x29 and lr
+    ldp     x0, x1, [SP], #16
+    ldp     x2, x3, [SP], #16
+    ldp     x4, x5, [SP], #16
+    ldp     x6, x7, [SP], #16
+    ldp     x8, x9, [SP], #16
+    ldp     x10, x11, [SP], #16
+    ldp     x12, x13, [SP], #16
+    ldp     x14, x15, [SP], #16
+    ldp     x16, x17, [SP], #16
+    ldp     x18, x19, [SP], #16
+    ldp     x20, x21, [SP], #16
+    ldp     x22, x23, [SP], #16
+    ldp     x24, x25, [SP], #16
+    ldp     x26, x27, [SP], #16
 
     ret
 
-// #define HANDLER(source, kind) \
-//     .align 7; \
-//     stp     lr, x0, [SP, #-16]!; \
-//     mov     x0, ##source; \
-//     movk    x0, ##kind, LSL #16; \
-//     bl      context_save; \
-//     ldp     lr, x0, [SP], #16; \
-//     eret
+// Saves lr, x28 and x29, stores the exception `Info` (`source | kind << 16`)
+// in x29 and calls `context_save`, which builds a `TrapFrame` and calls
+// `handle_exception`.
+.macro HANDLER source, kind
+    .align 7
+    HANDLER_BODY \source, \kind
+.endm
+
+.macro HANDLER_BODY source, kind
+    stp     lr, xzr, [SP, #-16]!
+    stp     x28, x29, [SP, #-16]!
+    mov     x29, #\source
+    movk    x29, #\kind, LSL #16
+    bl      context_save
+    ldp     x28, x29, [SP], #16
+    ldp     lr, xzr, [SP], #16
+    eret
+.endm
 
 .align 11
 _vectors:
-    // FIXME: Setup the 16 exception vectors.
+    // current EL, SP_EL0
+    HANDLER 0, 0
+    HANDLER 0, 1
+    HANDLER 0, 2
+    HANDLER 0, 3
+
+    // current EL, SP_ELx
+    .align 7
+    // a data abort on a guard page near SP is a stack overflow; handle it on
+    // the emergency stack so that saving the context doesn't fault again.
+    // anything else, including svc and brk near the bottom of the stack,
+    // takes the normal path
+    msr     TPIDR_EL1, x0
+    mrs     x0, ESR_EL1
+    lsr     x0, x0, #26
+    cmp     x0, #0x25               // EC: data abort from the current EL
+    b.ne    1f
+    mrs     x0, FAR_EL1
+    and     x0, x0, #(STACK_SIZE - 1)
+    cmp     x0, #GUARD_SIZE         // FAR in the guard page of some stack
+    b.hs    1f
+    mrs     x0, FAR_EL1
+    sub     x0, SP, x0
+    add     x0, x0, #GUARD_SIZE
+    cmp     x0, #(3 * GUARD_SIZE)   // SP - FAR in (-GUARD_SIZE, 2 * GUARD_SIZE)
+    b.hs    1f
+    mrs     x0, TPIDR_EL1
+    b       emergency_sync
+1:  mrs     x0, TPIDR_EL1
+    HANDLER_BODY 1, 0
+    HANDLER 1, 1
+    HANDLER 1, 2
+    HANDLER 1, 3
+
+    // lower EL, AArch64
+    HANDLER 2, 0
+    HANDLER 2, 1
+    HANDLER 2, 2
+    HANDLER 2, 3
+
+    // lower EL, AArch32
+    HANDLER 3, 0
+    HANDLER 3, 1
+    HANDLER 3, 2
+    HANDLER 3, 3
+
+emergency_sync:
+    // switch to this core's emergency stack; the interrupted stack is not
+    // needed again since overflows aren't recoverable
+    msr     TPIDR_EL1, x0
+    mrs     x0, MPIDR_EL1
+    and     x0, x0, #3
+    add     x0, x0, #1
+    lsl     x0, x0, #12             // (core + 1) * EMERGENCY_STACK_SIZE
+    mov     SP, x0
+    adr     x0, emergency_stacks
+    add     SP, SP, x0
+    mrs     x0, TPIDR_EL1
+
+    stp     lr, xzr, [SP, #-16]!
+    stp     x28, x29, [SP, #-16]!
+    mov     x29, #1
+    movk    x29, #0, LSL #16
+    bl      context_save
+
+    // `handle_exception` returned, but the interrupted SP is lost
+    b       halt
+
+.section .bss
+.balign 16
+emergency_stacks:
+    .space  4 * EMERGENCY_STACK_SIZE
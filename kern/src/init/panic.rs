@@ -1,6 +1,37 @@
 use core::panic::PanicInfo;
 
+use pi::pm::Watchdog;
+
+use crate::aarch64;
+use crate::backtrace;
+use crate::console::{kprintln, CONSOLE};
+use crate::gfx_console::GFX_CONSOLE;
+use crate::panic_policy::{self, PanicPolicy};
+use crate::smp;
+use crate::watchdog;
+
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    // a panicked kernel is wedged, so let the watchdog reset it if it's on
+    watchdog::note_panic();
+
+    // the panicking code may have been printing; it will never resume
+    if CONSOLE.owner() == Some(smp::core_id()) {
+        unsafe { CONSOLE.force_unlock() };
+    }
+    if GFX_CONSOLE.owner() == Some(smp::core_id()) {
+        unsafe { GFX_CONSOLE.force_unlock() };
+    }
+
+    kprintln!("kernel panic: {}", info);
+    backtrace::print();
+
+    if let PanicPolicy::Reboot(delay) = panic_policy::get() {
+        kprintln!("rebooting in {}ms", delay.as_millis());
+        Watchdog::new().start(delay);
+    }
+
+    loop {
+        aarch64::wfe();
+    }
 }
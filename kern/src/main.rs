@@ -10,16 +10,90 @@
 #[cfg(not(test))]
 mod init;
 
+pub mod aarch64;
+pub mod backtrace;
+pub mod clock;
+pub mod cmdline;
 pub mod console;
+pub mod gdb;
+pub mod gfx_console;
 pub mod mutex;
+pub mod once;
+pub mod panic_policy;
+pub mod qemu;
+pub mod rand;
+pub mod selftest;
 pub mod shell;
+pub mod smp;
+pub mod traps;
+pub mod vm;
+pub mod watchdog;
 
 use console::kprintln;
 
 // FIXME: You need to add dependencies here to
 // test your drivers (Phase 2). Add them as needed.
 
+/// Returns the board name for a new-style revision code, if it's known.
+fn board_name(revision: u32) -> Option<&'static str> {
+    if revision & (1 << 23) == 0 {
+        return None;
+    }
+
+    Some(match (revision >> 4) & 0xFF {
+        0x04 => "Raspberry Pi 2 Model B",
+        0x08 => "Raspberry Pi 3 Model B",
+        0x0a => "Compute Module 3",
+        0x0d => "Raspberry Pi 3 Model B+",
+        0x0e => "Raspberry Pi 3 Model A+",
+        0x10 => "Compute Module 3+",
+        0x11 => "Raspberry Pi 4 Model B",
+        _ => return None,
+    })
+}
+
+/// Prints the board model, serial number, memory, clock rates and firmware
+/// version, so that a log says which hardware produced it. Values the firmware
+/// doesn't report are left out.
+fn print_banner() {
+    use pi::mailbox::{Clock, Mailbox};
+
+    let mut mbox = Mailbox::new();
+    kprintln!("oxidation kernel v{}", env!("CARGO_PKG_VERSION"));
+
+    if let Some(revision) = mbox.board_revision() {
+        let name = board_name(revision).unwrap_or("unknown board");
+        kprintln!("board:    {} (revision {:#08x})", name, revision);
+        if revision & (1 << 23) != 0 {
+            kprintln!("ram:      {} MiB", 256u32 << ((revision >> 20) & 0x7));
+        }
+    }
+
+    if let Some(serial) = mbox.board_serial() {
+        kprintln!("serial:   {:016x}", serial);
+    }
+
+    if let Some((base, size)) = mbox.arm_memory() {
+        kprintln!("arm ram:  {} MiB at {:#x}", size >> 20, base);
+    }
+
+    if let (Some(arm), Some(core)) = (mbox.clock_rate(Clock::Arm), mbox.clock_rate(Clock::Core)) {
+        kprintln!("clocks:   arm {} MHz, core {} MHz", arm / 1_000_000, core / 1_000_000);
+    }
+
+    if let Some(firmware) = mbox.firmware_revision() {
+        kprintln!("firmware: {}", firmware);
+    }
+}
+
 fn kmain() -> ! {
-    // FIXME: Start the shell.
-    unimplemented!()
+    print_banner();
+
+    if cmdline::get().shell {
+        shell::shell("> ");
+    }
+
+    loop {
+        aarch64::wfe();
+    }
 }
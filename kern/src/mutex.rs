@@ -1,13 +1,27 @@
 use core::fmt;
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::sync::atomic::{spin_loop_hint, AtomicBool, AtomicUsize, Ordering};
 use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::{self, ManuallyDrop};
 use core::ops::{DerefMut, Deref, Drop};
+use core::time::Duration;
+
+use pi::timer;
+
+use crate::aarch64;
+use crate::backtrace;
+use crate::smp;
+
+/// The `owner` of an unlocked mutex.
+const NO_OWNER: usize = usize::max_value();
 
 #[repr(align(32))]
 pub struct Mutex<T> {
     data: UnsafeCell<T>,
     lock: AtomicBool,
-    owner: AtomicUsize
+    owner: AtomicUsize,
+    /// The return address of the call that took the lock.
+    site: AtomicUsize,
 }
 
 unsafe impl<T: Send> Send for Mutex<T> { }
@@ -24,41 +38,220 @@ impl<T> Mutex<T> {
     pub const fn new(val: T) -> Mutex<T> {
         Mutex {
             lock: AtomicBool::new(false),
-            owner: AtomicUsize::new(usize::max_value()),
+            owner: AtomicUsize::new(NO_OWNER),
+            site: AtomicUsize::new(0),
             data: UnsafeCell::new(val)
         }
     }
 }
 
 impl<T> Mutex<T> {
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
+    /// Attempts to acquire the lock without spinning.
+    ///
+    /// With the MMU on, the lock is taken with an exclusive load/store pair
+    /// (`ldaxrb`/`stxrb`) with acquire ordering. Exclusives don't work on the
+    /// device memory that all accesses go to while the MMU is off, but then
+    /// only core 0 is running, so a plain load and store suffice.
+    #[inline(never)]
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        let this = 0;
-        if !self.lock.load(Ordering::Relaxed) || self.owner.load(Ordering::Relaxed) == this {
+        self.acquire(backtrace::return_address())
+    }
+
+    /// Acquires the lock, spinning until it's available.
+    ///
+    /// In debug builds, panics if the calling core already holds the lock,
+    /// which would otherwise hang forever, naming both call sites.
+    #[inline(never)]
+    pub fn lock(&self) -> MutexGuard<T> {
+        self.lock_at(backtrace::return_address())
+    }
+
+    /// Runs `f` with the lock held and releases it before returning `f`'s
+    /// result.
+    ///
+    /// Use this to hold a lock for exactly one operation, rather than keeping
+    /// a guard alive across something that blocks:
+    ///
+    /// ```rust,ignore
+    /// let byte = CONSOLE.with(|console| console.read_byte());
+    /// ```
+    #[inline(never)]
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let mut guard = self.lock_at(backtrace::return_address());
+        f(&mut guard)
+    }
+
+    /// Acquires the lock, recording `site` as where it was taken.
+    fn lock_at(&self, site: usize) -> MutexGuard<T> {
+        loop {
+            if let Some(guard) = self.acquire(site) {
+                return guard;
+            }
+
+            if cfg!(debug_assertions) {
+                self.check_deadlock(site);
+            }
+
+            self.wait();
+        }
+    }
+
+    /// Acquires the lock, spinning for at most `timeout`. Returns `None` if
+    /// the lock wasn't acquired in time.
+    #[inline(never)]
+    pub fn try_lock_for(&self, timeout: Duration) -> Option<MutexGuard<T>> {
+        let site = backtrace::return_address();
+        let start = timer::current_time();
+        loop {
+            if let Some(guard) = self.acquire(site) {
+                return Some(guard);
+            }
+
+            if cfg!(debug_assertions) {
+                self.check_deadlock(site);
+            }
+
+            if timer::current_time() - start >= timeout {
+                return None;
+            }
+
+            spin_loop_hint();
+        }
+    }
+
+    /// Takes the lock if it's free, recording `site` as where it was taken.
+    fn acquire(&self, site: usize) -> Option<MutexGuard<T>> {
+        let acquired = if aarch64::mmu_enabled() {
+            self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+        } else if !self.lock.load(Ordering::Relaxed) {
             self.lock.store(true, Ordering::Relaxed);
-            self.owner.store(this, Ordering::Relaxed);
+            true
+        } else {
+            false
+        };
+
+        if acquired {
+            self.owner.store(smp::core_id(), Ordering::Relaxed);
+            self.site.store(site, Ordering::Relaxed);
             Some(MutexGuard { lock: &self })
         } else {
             None
         }
     }
 
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
-    #[inline(never)]
-    pub fn lock(&self) -> MutexGuard<T> {
-        // Wait until we can "aquire" the lock, then "acquire" it.
-        loop {
-            match self.try_lock() {
-                Some(guard) => return guard,
-                None => continue
-            }
+    /// Waits with plain loads until the lock looks free, so the cache line
+    /// isn't bounced between cores by failed exclusive stores.
+    fn wait(&self) {
+        while self.lock.load(Ordering::Relaxed) {
+            spin_loop_hint();
         }
     }
 
+    /// Panics if the calling core holds the lock it's trying to take at
+    /// `site`.
+    fn check_deadlock(&self, site: usize) {
+        let core = smp::core_id();
+        if self.owner.load(Ordering::Relaxed) == core {
+            panic!(
+                "deadlock: core {} tried to lock a Mutex it already holds\n  \
+                 held since: {}\n  locked again at: {}",
+                core,
+                backtrace::Site(self.site.load(Ordering::Relaxed)),
+                backtrace::Site(site)
+            );
+        }
+    }
+
+    /// Returns the core holding the lock, or `None` if it's unlocked.
+    pub fn owner(&self) -> Option<usize> {
+        match self.owner.load(Ordering::Relaxed) {
+            NO_OWNER => None,
+            core => Some(core),
+        }
+    }
+
+    /// Releases the lock even though no guard is being dropped.
+    ///
+    /// This is for the panic handler, which must be able to print even if the
+    /// panicking code held `CONSOLE`. The caller must ensure the guard is never
+    /// used again.
+    pub unsafe fn force_unlock(&self) {
+        self.unlock();
+    }
+
+    /// Releases the lock, publishing all writes made while it was held.
     fn unlock(&self) {
-        self.lock.store(false, Ordering::Relaxed);
+        release(&self.lock, &self.owner);
+    }
+}
+
+/// Releases the lock made of `lock` and `owner`. Shared by `MutexGuard` and
+/// `MappedMutexGuard`, which doesn't know the `Mutex`'s type.
+fn release(lock: &AtomicBool, owner: &AtomicUsize) {
+    owner.store(NO_OWNER, Ordering::Relaxed);
+    lock.store(false, Ordering::Release);
+}
+
+impl<'a, T: 'a> MutexGuard<'a, T> {
+    /// Narrows `guard` to the part of the data `f` returns, keeping the lock
+    /// held until the returned guard is dropped.
+    ///
+    /// This is an associated function, called as `MutexGuard::map(guard, f)`,
+    /// so it doesn't shadow a `map` method of `T`.
+    pub fn map<U: ?Sized, F>(guard: Self, f: F) -> MappedMutexGuard<'a, U>
+        where F: FnOnce(&mut T) -> &mut U
+    {
+        let lock = guard.lock;
+        // if `f` panics, `guard` is dropped and the lock released
+        let data = f(unsafe { &mut *lock.data.get() }) as *mut U;
+        mem::forget(guard);
+
+        MappedMutexGuard { data, lock: &lock.lock, owner: &lock.owner, _marker: PhantomData }
+    }
+}
+
+/// A guard for part of the data protected by a `Mutex`, made with
+/// `MutexGuard::map`. The lock is released when it's dropped.
+pub struct MappedMutexGuard<'a, U: ?Sized + 'a> {
+    data: *mut U,
+    lock: &'a AtomicBool,
+    owner: &'a AtomicUsize,
+    _marker: PhantomData<&'a mut U>,
+}
+
+impl<'a, U: ?Sized> !Send for MappedMutexGuard<'a, U> { }
+unsafe impl<'a, U: ?Sized + Sync> Sync for MappedMutexGuard<'a, U> { }
+
+impl<'a, U: ?Sized + 'a> MappedMutexGuard<'a, U> {
+    /// Narrows `guard` further, like `MutexGuard::map`.
+    pub fn map<V: ?Sized, F>(guard: Self, f: F) -> MappedMutexGuard<'a, V>
+        where F: FnOnce(&mut U) -> &mut V
+    {
+        let (lock, owner) = (guard.lock, guard.owner);
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        mem::forget(guard);
+
+        MappedMutexGuard { data, lock, owner, _marker: PhantomData }
+    }
+}
+
+impl<'a, U: ?Sized + 'a> Deref for MappedMutexGuard<'a, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, U: ?Sized + 'a> DerefMut for MappedMutexGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, U: ?Sized + 'a> Drop for MappedMutexGuard<'a, U> {
+    fn drop(&mut self) {
+        release(self.lock, self.owner)
     }
 }
 
@@ -90,3 +283,238 @@ impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
         }
     }
 }
+
+/// A `Mutex` that masks IRQs on the locking core while it's held.
+///
+/// Data shared with an interrupt handler must use this: if the handler
+/// interrupts a core holding a plain `Mutex` and tries to take the same lock,
+/// it spins forever. The previous mask state is restored when the guard is
+/// dropped, so these locks nest.
+pub struct IrqSafeMutex<T> {
+    inner: Mutex<T>,
+}
+
+pub struct IrqSafeMutexGuard<'a, T: 'a> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    daif: u64,
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(val: T) -> IrqSafeMutex<T> {
+        IrqSafeMutex { inner: Mutex::new(val) }
+    }
+
+    /// Masks IRQs and attempts to acquire the lock without spinning. IRQs are
+    /// unmasked again if the lock isn't acquired.
+    pub fn try_lock(&self) -> Option<IrqSafeMutexGuard<T>> {
+        let daif = aarch64::daif();
+        aarch64::mask_irq();
+
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqSafeMutexGuard { guard: ManuallyDrop::new(guard), daif }),
+            None => {
+                aarch64::set_daif(daif);
+                None
+            }
+        }
+    }
+
+    /// Masks IRQs and acquires the lock, spinning until it's available. IRQs
+    /// are left enabled while spinning so that a waiting core still services
+    /// its interrupts.
+    pub fn lock(&self) -> IrqSafeMutexGuard<T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+
+            while self.inner.lock.load(Ordering::Relaxed) {
+                spin_loop_hint();
+            }
+        }
+    }
+}
+
+impl<'a, T: 'a> Deref for IrqSafeMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: 'a> DerefMut for IrqSafeMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: 'a> Drop for IrqSafeMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        // unlock before unmasking, so an IRQ can't arrive while it's held
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        aarch64::set_daif(self.daif);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for IrqSafeMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("IrqSafeMutex").field("data", &&*guard).finish(),
+            None => f.debug_struct("IrqSafeMutex").field("data", &"<locked>").finish()
+        }
+    }
+}
+
+/// A reader-writer spinlock: any number of readers or one writer.
+///
+/// By default readers can always join other readers, so a steady stream of
+/// readers can starve a writer. Locks created with `with_writer_preference`
+/// instead hold new readers back while a writer is waiting.
+///
+/// Unlike `Mutex`, this always uses exclusive loads and stores, so it must
+/// not be used before the MMU is enabled.
+pub struct RwLock<T> {
+    data: UnsafeCell<T>,
+    state: AtomicUsize,
+    prefer_writers: bool,
+}
+
+/// `RwLock::state` bits: a writer holds the lock, a writer is waiting, and
+/// the reader count in the remaining bits.
+const WRITER: usize = 1;
+const WRITER_WAITING: usize = 1 << 1;
+const READER: usize = 1 << 2;
+
+unsafe impl<T: Send> Send for RwLock<T> { }
+unsafe impl<T: Send + Sync> Sync for RwLock<T> { }
+
+pub struct RwLockReadGuard<'a, T: 'a> {
+    lock: &'a RwLock<T>
+}
+
+pub struct RwLockWriteGuard<'a, T: 'a> {
+    lock: &'a RwLock<T>
+}
+
+impl<'a, T> !Send for RwLockReadGuard<'a, T> { }
+impl<'a, T> !Send for RwLockWriteGuard<'a, T> { }
+unsafe impl<'a, T: Sync> Sync for RwLockReadGuard<'a, T> { }
+unsafe impl<'a, T: Sync> Sync for RwLockWriteGuard<'a, T> { }
+
+impl<T> RwLock<T> {
+    /// Creates a lock that prefers readers.
+    pub const fn new(val: T) -> RwLock<T> {
+        RwLock {
+            data: UnsafeCell::new(val),
+            state: AtomicUsize::new(0),
+            prefer_writers: false,
+        }
+    }
+
+    /// Creates a lock that makes new readers wait while a writer is waiting.
+    pub const fn with_writer_preference(val: T) -> RwLock<T> {
+        RwLock {
+            data: UnsafeCell::new(val),
+            state: AtomicUsize::new(0),
+            prefer_writers: true,
+        }
+    }
+
+    /// Attempts to acquire a shared read lock without spinning.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        let blocked = if self.prefer_writers { WRITER | WRITER_WAITING } else { WRITER };
+
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & blocked == 0 {
+            match self.state.compare_exchange_weak(state, state + READER, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self }),
+                Err(current) => state = current,
+            }
+        }
+
+        None
+    }
+
+    /// Acquires a shared read lock, spinning until it's available.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        loop {
+            match self.try_read() {
+                Some(guard) => return guard,
+                None => spin_loop_hint(),
+            }
+        }
+    }
+
+    /// Attempts to acquire the exclusive write lock without spinning.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & !WRITER_WAITING != 0 {
+            return None;
+        }
+
+        // clears `WRITER_WAITING`; any other waiting writers set it again
+        match self.state.compare_exchange(state, WRITER, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Some(RwLockWriteGuard { lock: self }),
+            Err(_) => None,
+        }
+    }
+
+    /// Acquires the exclusive write lock, spinning until it's available.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+
+            if self.prefer_writers {
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+
+            spin_loop_hint();
+        }
+    }
+}
+
+impl<'a, T: 'a> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { & *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+impl<'a, T: 'a> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { & *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: 'a> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_and(!WRITER, Ordering::Release);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("RwLock").field("data", &&*guard).finish(),
+            None => f.debug_struct("RwLock").field("data", &"<locked>").finish()
+        }
+    }
+}
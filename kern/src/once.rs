@@ -0,0 +1,207 @@
+//! One-time initialization of globals.
+//!
+//! `OnceCell` holds a value that is set exactly once, explicitly, by whoever
+//! initializes the subsystem. `Lazy` is a `OnceCell` paired with the function
+//! that initializes it on first use, like `CONSOLE`.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem;
+use core::ops::Deref;
+use core::sync::atomic::{spin_loop_hint, AtomicU8, Ordering};
+
+use crate::aarch64;
+
+/// `OnceCell::state` values.
+const EMPTY: u8 = 0;
+const RUNNING: u8 = 1;
+const READY: u8 = 2;
+const POISONED: u8 = 3;
+
+/// A cell that can be written to only once.
+pub struct OnceCell<T> {
+    value: UnsafeCell<Option<T>>,
+    state: AtomicU8,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> { }
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> { }
+
+impl<T> OnceCell<T> {
+    /// Creates an empty cell.
+    pub const fn new() -> OnceCell<T> {
+        OnceCell {
+            value: UnsafeCell::new(None),
+            state: AtomicU8::new(EMPTY),
+        }
+    }
+
+    /// Claims the right to initialize the cell. Like `Mutex::try_lock`, this
+    /// falls back to a plain load and store while the MMU is off.
+    fn claim(&self) -> bool {
+        if aarch64::mmu_enabled() {
+            self.state.compare_exchange(EMPTY, RUNNING, Ordering::Acquire, Ordering::Acquire).is_ok()
+        } else if self.state.load(Ordering::Relaxed) == EMPTY {
+            self.state.store(RUNNING, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Waits for a concurrent initialization to finish.
+    fn wait(&self) {
+        while self.state.load(Ordering::Acquire) == RUNNING {
+            spin_loop_hint();
+        }
+    }
+
+    /// Returns the value, or `None` if the cell hasn't been initialized.
+    pub fn get(&self) -> Option<&T> {
+        match self.state.load(Ordering::Acquire) {
+            READY => unsafe { (*self.value.get()).as_ref() },
+            _ => None,
+        }
+    }
+
+    /// Initializes the cell with `val`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(val)` if the cell was already initialized.
+    pub fn set(&self, val: T) -> Result<(), T> {
+        if !self.claim() {
+            return Err(val);
+        }
+
+        unsafe { *self.value.get() = Some(val) };
+        self.state.store(READY, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns the value, initializing the cell with `f()` first if it's
+    /// empty. If another core is initializing the cell, waits for it. `f`
+    /// must not access this cell, or it waits for itself forever.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` panicked and unwound in an earlier call, which poisons
+    /// the cell. Kernel panics don't unwind: the panicking core never
+    /// finishes `f`, and other cores calling this wait until the panic
+    /// policy halts or resets the board.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        if let Some(val) = self.get() {
+            return val;
+        }
+
+        if self.claim() {
+            let poison = Poison(&self.state);
+            unsafe { *self.value.get() = Some(f()) };
+            mem::forget(poison);
+            self.state.store(READY, Ordering::Release);
+        } else {
+            self.wait();
+        }
+
+        match self.get() {
+            Some(val) => val,
+            None => panic!("OnceCell poisoned: its initializer panicked"),
+        }
+    }
+}
+
+/// Poisons the cell whose state it holds if dropped, which only happens when
+/// the initializer unwinds.
+struct Poison<'a>(&'a AtomicU8);
+
+impl Drop for Poison<'_> {
+    fn drop(&mut self) {
+        self.0.store(POISONED, Ordering::Release);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(val) => f.debug_tuple("OnceCell").field(val).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+/// A value initialized by `init` the first time it's dereferenced.
+pub struct Lazy<T> {
+    cell: OnceCell<T>,
+    init: fn() -> T,
+}
+
+impl<T> Lazy<T> {
+    /// Creates a value that will be initialized by `init`.
+    pub const fn new(init: fn() -> T) -> Lazy<T> {
+        Lazy { cell: OnceCell::new(), init }
+    }
+
+    /// Initializes the value if needed and returns it.
+    pub fn force(this: &Lazy<T>) -> &T {
+        this.cell.get_or_init(this.init)
+    }
+}
+
+impl<T> Deref for Lazy<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.cell.get() {
+            Some(val) => f.debug_tuple("Lazy").field(val).finish(),
+            None => f.write_str("Lazy(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lazy, OnceCell};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn set_once() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get_or_init(|| 3), &1);
+        assert_eq!(format!("{:?}", cell), "OnceCell(1)");
+    }
+
+    #[test]
+    fn lazy_initializes_on_first_use() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn init() -> usize {
+            CALLS.fetch_add(1, Ordering::SeqCst) + 10
+        }
+
+        let lazy = Lazy::new(init);
+        assert_eq!(format!("{:?}", lazy), "Lazy(<uninit>)");
+        assert_eq!(*lazy, 10);
+        assert_eq!(*lazy, 10);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn panicking_init_poisons() {
+        let cell = OnceCell::<usize>::new();
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!("init failed")))).is_err());
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(1), Err(1));
+
+        // later callers panic rather than wait forever
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| *cell.get_or_init(|| 2))).is_err());
+    }
+}
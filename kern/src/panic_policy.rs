@@ -0,0 +1,43 @@
+//! What the panic handler does once it has printed its diagnostics.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+/// The action taken after a kernel panic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Halt the core forever, leaving the board in its crashed state.
+    Halt,
+    /// Arm the hardware watchdog to reset the board after the given delay,
+    /// clamped to `Watchdog::max_timeout()`.
+    Reboot(Duration),
+}
+
+/// Reboot delay in milliseconds, or `HALT` to halt forever.
+static REBOOT_AFTER_MS: AtomicU64 = AtomicU64::new(DEFAULT);
+
+const HALT: u64 = u64::max_value();
+
+/// Debug builds halt so the crash can be inspected; release builds reboot.
+#[cfg(debug_assertions)]
+const DEFAULT: u64 = HALT;
+#[cfg(not(debug_assertions))]
+const DEFAULT: u64 = 5000;
+
+/// Sets the policy used by all subsequent panics.
+pub fn set(policy: PanicPolicy) {
+    let ms = match policy {
+        PanicPolicy::Halt => HALT,
+        PanicPolicy::Reboot(delay) => delay.as_millis() as u64,
+    };
+
+    REBOOT_AFTER_MS.store(ms, Ordering::Relaxed);
+}
+
+/// Returns the current panic policy.
+pub fn get() -> PanicPolicy {
+    match REBOOT_AFTER_MS.load(Ordering::Relaxed) {
+        HALT => PanicPolicy::Halt,
+        ms => PanicPolicy::Reboot(Duration::from_millis(ms)),
+    }
+}
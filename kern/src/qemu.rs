@@ -0,0 +1,113 @@
+//! Support for running under `qemu-system-aarch64`.
+//!
+//! QEMU implements the Arm semihosting interface when started with
+//! `-semihosting` (as `qemu.sh` does): the guest executes `hlt #0xf000` with an
+//! operation number in `x0` and a pointer to its parameter block in `x1`, and
+//! QEMU performs the operation on the host. This lets automated runs report
+//! their result through QEMU's exit status and write logs to host files.
+//!
+//! Semihosting calls raise an undefined instruction exception on real
+//! hardware, so callers should check `is_qemu()` first.
+
+use pi::mailbox::Mailbox;
+use shim::io;
+
+/// Semihosting operation numbers (ref: Arm "Semihosting for AArch32 and
+/// AArch64").
+const SYS_OPEN: u64 = 0x01;
+const SYS_CLOSE: u64 = 0x02;
+const SYS_WRITE0: u64 = 0x04;
+const SYS_WRITE: u64 = 0x05;
+const SYS_EXIT: u64 = 0x18;
+
+/// The `SYS_EXIT` reason for a normal application exit with a status code.
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+/// `SYS_OPEN` mode for `fopen(path, "w")`.
+const OPEN_MODE_WRITE: u64 = 4;
+
+/// The longest host path accepted by `HostFile::create`.
+const MAX_PATH: usize = 255;
+
+/// Performs semihosting operation `op` with parameter `arg`.
+unsafe fn call(op: u64, arg: u64) -> u64 {
+    let ret: u64;
+    asm!("hlt #0xf000" : "={x0}"(ret) : "{x0}"(op), "{x1}"(arg) : "memory" : "volatile");
+    ret
+}
+
+/// Returns `true` if the kernel appears to be running under QEMU.
+///
+/// QEMU's firmware emulation reports a board serial number of zero, which no
+/// real board has.
+pub fn is_qemu() -> bool {
+    Mailbox::new().board_serial() == Some(0)
+}
+
+/// Exits QEMU with the given status code.
+pub fn exit(status: u32) -> ! {
+    let block = [ADP_STOPPED_APPLICATION_EXIT, status as u64];
+    unsafe { call(SYS_EXIT, &block as *const _ as u64) };
+
+    // not reached unless semihosting is disabled
+    loop {
+        crate::aarch64::wfe();
+    }
+}
+
+/// Writes `s` to QEMU's standard error (the host's semihosting console).
+pub fn write_str(s: &str) {
+    let mut buf = [0u8; 128];
+    for chunk in s.as_bytes().chunks(buf.len() - 1) {
+        buf[..chunk.len()].copy_from_slice(chunk);
+        buf[chunk.len()] = 0;
+        unsafe { call(SYS_WRITE0, buf.as_ptr() as u64) };
+    }
+}
+
+/// A file on the host, opened for writing through semihosting.
+pub struct HostFile {
+    handle: u64,
+}
+
+impl HostFile {
+    /// Creates (or truncates) the host file at `path`.
+    pub fn create(path: &str) -> io::Result<HostFile> {
+        if path.len() > MAX_PATH {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path too long"));
+        }
+
+        let mut cpath = [0u8; MAX_PATH + 1];
+        cpath[..path.len()].copy_from_slice(path.as_bytes());
+
+        let block = [cpath.as_ptr() as u64, OPEN_MODE_WRITE, path.len() as u64];
+        match unsafe { call(SYS_OPEN, &block as *const _ as u64) } as i64 {
+            -1 => Err(io::Error::new(io::ErrorKind::Other, "semihosting open failed")),
+            handle => Ok(HostFile { handle: handle as u64 }),
+        }
+    }
+}
+
+impl io::Write for HostFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let block = [self.handle, buf.as_ptr() as u64, buf.len() as u64];
+        // returns the number of bytes that were *not* written
+        let unwritten = unsafe { call(SYS_WRITE, &block as *const _ as u64) } as usize;
+        if unwritten > buf.len() {
+            return Err(io::Error::new(io::ErrorKind::Other, "semihosting write failed"));
+        }
+
+        Ok(buf.len() - unwritten)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for HostFile {
+    fn drop(&mut self) {
+        let block = [self.handle];
+        unsafe { call(SYS_CLOSE, &block as *const _ as u64) };
+    }
+}
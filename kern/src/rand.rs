@@ -0,0 +1,104 @@
+//! The kernel's random number generator.
+//!
+//! A xoshiro256** generator seeded at boot from the hardware RNG, or from
+//! timer jitter if the hardware RNG doesn't produce anything. It is fast and
+//! statistically good, which is what heap poisoning patterns, stack canaries
+//! and address randomization experiments need, but it is not a
+//! cryptographically secure generator.
+
+use core::time::Duration;
+
+use pi::rng::Rng;
+use pi::timer;
+
+use crate::mutex::Mutex;
+use crate::selftest::{ensure, selftest};
+
+/// How many times to poll the hardware RNG for each word before giving up.
+const HW_TRIES: usize = 100_000;
+
+/// Generator state. Until `init()` runs this is a fixed, non-zero seed.
+static STATE: Mutex<[u64; 4]> = Mutex::new([
+    0x9e37_79b9_7f4a_7c15,
+    0xbf58_476d_1ce4_e5b9,
+    0x94d0_49bb_1331_11eb,
+    0x2545_f491_4f6c_dd1d,
+]);
+
+/// The splitmix64 step, used to expand a seed into the generator state.
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Returns a seed from the hardware RNG, or `None` if it isn't producing.
+fn hardware_seed() -> Option<u64> {
+    let mut rng = Rng::new();
+    let hi = rng.try_read(HW_TRIES)? as u64;
+    let lo = rng.try_read(HW_TRIES)? as u64;
+    Some(hi << 32 | lo)
+}
+
+/// Returns a seed gathered from the jitter in how long short busy loops take
+/// to run, as measured by the system timer.
+fn jitter_seed() -> u64 {
+    let mut seed = 0u64;
+    for i in 0..64 {
+        let start = timer::current_time();
+        let mut spins = 0u32;
+        while timer::current_time() - start < Duration::from_micros(1 + (i & 3)) {
+            spins = spins.wrapping_add(1);
+        }
+
+        seed = seed.rotate_left(7) ^ spins as u64 ^ timer::current_time().subsec_nanos() as u64;
+    }
+
+    seed
+}
+
+/// Seeds the generator. Returns `true` if the hardware RNG was used.
+pub fn init() -> bool {
+    let (mut seed, hardware) = match hardware_seed() {
+        Some(seed) => (seed, true),
+        None => (jitter_seed(), false),
+    };
+
+    let mut state = STATE.lock();
+    for word in state.iter_mut() {
+        *word = splitmix64(&mut seed);
+    }
+
+    hardware
+}
+
+/// Returns a random `u64`.
+pub fn rand_u64() -> u64 {
+    let mut s = STATE.lock();
+    let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+    let t = s[1] << 17;
+
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+    s[2] ^= t;
+    s[3] = s[3].rotate_left(45);
+
+    result
+}
+
+/// Fills `buf` with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = rand_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+selftest!(hardware_rng_produces, {
+    ensure!(hardware_seed().is_some());
+    Ok(())
+});
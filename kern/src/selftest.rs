@@ -0,0 +1,107 @@
+//! Boot-time self-tests.
+//!
+//! Unlike the host-side `#[cfg(test)]` tests, these run on the board itself
+//! and so can exercise code that touches MMIO. Tests are declared anywhere in
+//! the kernel with the `selftest!` macro, which places them in the
+//! `.selftests` linker section. They are run at boot when the command line
+//! contains `selftest=1`, or on demand with the `selftest` shell command.
+
+use core::{mem, slice};
+
+use pi::timer;
+
+use crate::console::kprintln;
+
+/// A registered self-test.
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn() -> Result<(), &'static str>,
+}
+
+/// Declares a self-test named `$name` with body `$body`, which evaluates to
+/// `Result<(), &'static str>`.
+pub macro selftest($name:ident, $body:block) {
+    #[used]
+    #[allow(non_upper_case_globals)]
+    #[link_section = ".selftests"]
+    static $name: $crate::selftest::Test = $crate::selftest::Test {
+        name: concat!(module_path!(), "::", stringify!($name)),
+        run: {
+            fn run() -> Result<(), &'static str> $body
+            run
+        },
+    };
+}
+
+/// Returns `Err` with the text of `$cond` from the enclosing test if `$cond`
+/// is false.
+pub macro ensure($cond:expr) {
+    if !$cond {
+        return Err(concat!("assertion failed: ", stringify!($cond)));
+    }
+}
+
+/// Returns all registered tests.
+#[cfg(not(test))]
+fn tests() -> &'static [Test] {
+    extern "C" {
+        static __selftests_beg: Test;
+        static __selftests_end: Test;
+    }
+
+    unsafe {
+        let beg = &__selftests_beg as *const Test;
+        let end = &__selftests_end as *const Test;
+        let len = (end as usize - beg as usize) / mem::size_of::<Test>();
+        slice::from_raw_parts(beg, len)
+    }
+}
+
+/// The linker section only exists in the kernel image.
+#[cfg(test)]
+fn tests() -> &'static [Test] {
+    &[]
+}
+
+/// Runs every registered test, printing the result and duration of each, and
+/// returns the number of failures.
+pub fn run_all() -> usize {
+    let tests = tests();
+    let mut failed = 0;
+
+    kprintln!("running {} self-tests", tests.len());
+    for test in tests {
+        let start = timer::current_time();
+        let result = (test.run)();
+        let elapsed = timer::current_time() - start;
+
+        match result {
+            Ok(()) => kprintln!("test {} ... ok ({}us)", test.name, elapsed.as_micros()),
+            Err(msg) => {
+                failed += 1;
+                kprintln!("test {} ... FAILED ({}us): {}", test.name, elapsed.as_micros(), msg);
+            }
+        }
+    }
+
+    kprintln!("self-test result: {} passed; {} failed", tests.len() - failed, failed);
+    failed
+}
+
+selftest!(timer_advances, {
+    let start = timer::current_time();
+    timer::spin_sleep(core::time::Duration::from_micros(100));
+    ensure!(timer::current_time() - start >= core::time::Duration::from_micros(100));
+    Ok(())
+});
+
+selftest!(pl011_loopback, {
+    // the PL011 belongs to the GDB stub when it's enabled
+    if crate::gdb::attached() {
+        return Ok(());
+    }
+
+    let report = pi::pl011::Pl011::new().loopback_test(256, 1);
+    ensure!(report.passed());
+    Ok(())
+});
@@ -1,6 +1,8 @@
 use stack_vec::StackVec;
 
+use crate::aarch64;
 use crate::console::{kprint, kprintln, CONSOLE};
+use crate::watchdog;
 
 /// Error type for `Command` parse failures.
 #[derive(Debug)]
@@ -23,10 +25,8 @@ impl<'a> Command<'a> {
     /// If `s` contains no arguments, returns `Error::Empty`. If there are more
     /// arguments than `buf` can hold, returns `Error::TooManyArgs`.
     fn parse(s: &'a str, buf: &'a mut [&'a str]) -> Result<Command<'a>, Error> {
-        let mut args = StackVec::new(buf);
-        for arg in s.split(' ').filter(|a| !a.is_empty()) {
-            args.push(arg).map_err(|_| Error::TooManyArgs)?;
-        }
+        let args = StackVec::try_from_iter(buf, s.split(' ').filter(|a| !a.is_empty()))
+            .map_err(|_| Error::TooManyArgs)?;
 
         if args.is_empty() {
             return Err(Error::Empty);
@@ -37,12 +37,273 @@ impl<'a> Command<'a> {
 
     /// Returns this command's path. This is equivalent to the first argument.
     fn path(&self) -> &str {
-        unimplemented!()
+        self.args[0]
+    }
+}
+
+/// Implements `date`: prints the wall-clock time, or sets it from
+/// `date <unix-seconds>` or `date YYYY-MM-DD[T ]HH:MM:SS`.
+fn date(args: &[&str]) {
+    use crate::clock::{self, DateTime};
+    use core::time::Duration;
+
+    let secs = match args {
+        [] => {
+            match clock::now() {
+                Some(now) => kprintln!("{}", DateTime::from_unix(now.as_secs())),
+                None => kprintln!("date: the clock has not been set"),
+            }
+            return;
+        }
+        [secs] if secs.bytes().all(|b| b.is_ascii_digit()) => secs.parse::<u64>().ok(),
+        [datetime] => DateTime::parse(datetime).map(|dt| dt.to_unix()),
+        [date, time] if date.len() == 10 && time.len() == 8 => {
+            let mut buf = [b'T'; 19];
+            buf[..10].copy_from_slice(date.as_bytes());
+            buf[11..].copy_from_slice(time.as_bytes());
+            core::str::from_utf8(&buf).ok().and_then(DateTime::parse).map(|dt| dt.to_unix())
+        }
+        _ => None,
+    };
+
+    match secs {
+        Some(secs) => clock::set(Duration::from_secs(secs)),
+        None => kprintln!("usage: date [unix-seconds | YYYY-MM-DD HH:MM:SS]"),
+    }
+}
+
+/// Implements `selftest`: runs the self-tests, or with `uart`, a loopback
+/// test of the console UART that needs TX jumpered to RX.
+fn selftest(args: &[&str]) {
+    /// Enough bytes to catch an occasional framing error at 115200 baud.
+    const DEFAULT_LOOPBACK_BYTES: usize = 4096;
+
+    let len = match args {
+        [] => {
+            crate::selftest::run_all();
+            return;
+        }
+        ["uart"] => Some(DEFAULT_LOOPBACK_BYTES),
+        ["uart", len] => len.parse::<usize>().ok(),
+        _ => None,
+    };
+
+    match len {
+        Some(len) => {
+            kprintln!("uart loopback: sending {} bytes; TX must be jumpered to RX", len);
+            let report = CONSOLE.with(|console| console.loopback_test(len));
+            let result = if report.passed() { "ok" } else { "FAILED" };
+            kprintln!("uart loopback: {}: {}", result, report);
+        }
+        None => kprintln!("usage: selftest [uart [bytes]]"),
+    }
+}
+
+/// Implements `watchdog`: prints whether the watchdog is on, turns it
+/// `on` or `off`, or prints or sets its `timeout` in seconds.
+fn watchdog(args: &[&str]) {
+    use core::time::Duration;
+
+    match args {
+        [] => {
+            let state = if watchdog::is_enabled() { "on" } else { "off" };
+            kprintln!("watchdog: {}, timeout {}ms", state, watchdog::timeout().as_millis());
+        }
+        ["on"] => watchdog::enable(),
+        ["off"] => watchdog::disable(),
+        ["timeout"] => kprintln!("{}ms", watchdog::timeout().as_millis()),
+        ["timeout", secs] => match secs.parse::<u64>() {
+            Ok(secs) => {
+                watchdog::set_timeout(Duration::from_secs(secs));
+                kprintln!("timeout set to {}ms", watchdog::timeout().as_millis());
+            }
+            Err(_) => kprintln!("usage: watchdog timeout <seconds>"),
+        },
+        _ => kprintln!("usage: watchdog [on | off | timeout [seconds]]"),
+    }
+}
+
+/// Executes the built-in command `cmd`.
+fn execute(cmd: &Command) {
+    match cmd.path() {
+        "selftest" => selftest(&cmd.args[1..]),
+        "date" => date(&cmd.args[1..]),
+        "watchdog" => watchdog(&cmd.args[1..]),
+        "random" => {
+            let count = match cmd.args.get(1).map(|n| n.parse::<usize>()) {
+                None => 1,
+                Some(Ok(count)) => count,
+                Some(Err(_)) => {
+                    kprintln!("usage: random [count]");
+                    return;
+                }
+            };
+
+            for _ in 0..count {
+                kprintln!("{:016x}", crate::rand::rand_u64());
+            }
+        }
+        path => kprintln!("unknown command: {}", path),
     }
 }
 
-/// Starts a shell using `prefix` as the prefix for each line. This function
-/// returns if the `exit` command is called.
+/// The longest line that can be typed.
+const MAX_LINE: usize = 512;
+/// The most arguments a command can have, including its path.
+const MAX_ARGS: usize = 64;
+
+/// Starts a shell using `prefix` as the prefix for each line. Reads a line at
+/// a time from the console, echoing what's typed, and runs it with `execute`.
+/// Never returns.
 pub fn shell(prefix: &str) -> ! {
-    unimplemented!()
+    kprintln!("");
+    loop {
+        kprint!("{}", prefix);
+
+        let mut storage = [0u8; MAX_LINE];
+        let line = read_line(&mut storage);
+        run(line);
+    }
+}
+
+/// Reads a line typed at the console into `storage`, echoing it, until
+/// return is pressed. Backspace and delete erase the last character; other
+/// control characters, and anything past the end of `storage`, ring the bell.
+fn read_line(storage: &mut [u8]) -> &str {
+    let mut line = StackVec::new(storage);
+    loop {
+        match read_byte() {
+            b'\r' | b'\n' => break,
+            BACKSPACE | DELETE => {
+                if line.pop().is_some() {
+                    kprint!("\u{8} \u{8}");
+                }
+            }
+            byte @ b' '..=b'~' => match line.push(byte) {
+                Ok(()) => kprint!("{}", byte as char),
+                Err(()) => kprint!("{}", BELL as char),
+            },
+            _ => kprint!("{}", BELL as char),
+        }
+    }
+    kprintln!("");
+
+    // only printable ASCII is ever pushed
+    core::str::from_utf8(line.into_slice()).unwrap()
+}
+
+/// Parses `line` and executes the command it holds, if any.
+fn run(line: &str) {
+    let mut args = [""; MAX_ARGS];
+    match Command::parse(line, &mut args) {
+        Ok(cmd) => execute(&cmd),
+        Err(Error::Empty) => {}
+        Err(Error::TooManyArgs) => kprintln!("error: too many arguments"),
+    };
+}
+
+const BELL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// Reads a byte typed at the console. Sleeps in `wfe` between polls, woken by
+/// the timer event stream, and doesn't hold the console lock while waiting so
+/// other cores can still print. Each poll is a watchdog heartbeat.
+fn read_byte() -> u8 {
+    loop {
+        watchdog::heartbeat();
+        let byte = CONSOLE.with(|console| {
+            if console.has_byte() { Some(console.read_byte()) } else { None }
+        });
+
+        if let Some(byte) = byte {
+            return byte;
+        }
+
+        aarch64::wfe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_line, run, Command, Error, MAX_LINE};
+    use crate::console::mock;
+
+    /// Types `input` at the console and returns the line read and the echo.
+    fn type_line(input: &[u8]) -> (String, Vec<u8>) {
+        let console = mock::console();
+        console.push_input(input);
+        let mut storage = [0u8; MAX_LINE];
+        let line = read_line(&mut storage).to_string();
+        (line, console.take_output())
+    }
+
+    /// Runs `line` and returns what it printed.
+    fn run_line(line: &str) -> String {
+        let console = mock::console();
+        run(line);
+        String::from_utf8(console.take_output()).unwrap()
+    }
+
+    #[test]
+    fn parses_arguments() {
+        let mut buf = [""; 4];
+        let cmd = Command::parse("  echo   hello  world ", &mut buf).expect("parsed");
+        assert_eq!(cmd.path(), "echo");
+        assert_eq!(&cmd.args[..], &["echo", "hello", "world"][..]);
+
+        let mut buf = [""; 4];
+        assert!(match Command::parse("   ", &mut buf) { Err(Error::Empty) => true, _ => false });
+        let mut buf = [""; 2];
+        assert!(match Command::parse("a b c", &mut buf) { Err(Error::TooManyArgs) => true, _ => false });
+    }
+
+    #[test]
+    fn echoes_typed_line() {
+        let (line, echo) = type_line(b"date now\r");
+        assert_eq!(line, "date now");
+        assert_eq!(echo, b"date now\r\n".to_vec());
+
+        let (line, _) = type_line(b"\n");
+        assert_eq!(line, "");
+    }
+
+    #[test]
+    fn erases_with_backspace_and_delete() {
+        let (line, echo) = type_line(b"dx\x08ate\x7f\x7fte\r");
+        assert_eq!(line, "date");
+        assert_eq!(echo, b"dx\x08 \x08ate\x08 \x08\x08 \x08te\r\n".to_vec());
+
+        // nothing to erase: no echo
+        let (line, echo) = type_line(b"\x08\x7fa\r");
+        assert_eq!(line, "a");
+        assert_eq!(echo, b"a\r\n".to_vec());
+    }
+
+    #[test]
+    fn rings_bell_for_control_and_overflow() {
+        let (line, echo) = type_line(b"a\x01\x1bb\r");
+        assert_eq!(line, "ab");
+        assert_eq!(echo, b"a\x07\x07b\r\n".to_vec());
+
+        let mut input = vec![b'x'; MAX_LINE + 2];
+        input.push(b'\r');
+        let (line, echo) = type_line(&input);
+        assert_eq!(line.len(), MAX_LINE);
+        assert_eq!(&echo[MAX_LINE..], b"\x07\x07\r\n");
+    }
+
+    #[test]
+    fn executes_commands() {
+        assert_eq!(run_line(""), "");
+        assert_eq!(run_line("frobnicate --now"), "unknown command: frobnicate\r\n");
+        assert_eq!(run_line("random many"), "usage: random [count]\r\n");
+        assert_eq!(run_line("random 0"), "");
+        assert_eq!(run_line("watchdog sometimes"), "usage: watchdog [on | off | timeout [seconds]]\r\n");
+        assert_eq!(run_line("date 2019-13-40 25:00:00"), "usage: date [unix-seconds | YYYY-MM-DD HH:MM:SS]\r\n");
+        assert_eq!(run_line("selftest uart lots"), "usage: selftest [uart [bytes]]\r\n");
+
+        let too_many = vec!["x"; 65].join(" ");
+        assert_eq!(run_line(&too_many), "error: too many arguments\r\n");
+    }
 }
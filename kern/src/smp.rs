@@ -0,0 +1,93 @@
+//! Secondary core bring-up.
+//!
+//! On boot, cores 1-3 spin on their slot of the firmware's spin table until
+//! it holds an entry address. `start_cores()` points them at
+//! `_start_secondary`, which gives each core its own stack, drops it to EL1
+//! and calls `kinit_secondary`. Released cores enable the MMU and then idle in
+//! `park()`, except for the watchdog feeder core.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::aarch64;
+use crate::watchdog;
+
+/// Number of cores on the Raspberry Pi 3.
+pub const NCORES: usize = 4;
+
+/// Size of each core's boot stack. Must match `STACK_SIZE` in `init.s`.
+pub const STACK_SIZE: usize = 0x10000;
+
+/// Size of the unmapped guard region at the bottom of each core's stack. Must
+/// match `GUARD_SIZE` in `init.s`.
+pub const GUARD_SIZE: usize = 0x1000;
+
+/// Address of core 0's spin-table slot. Core N's slot is `8 * N` bytes above.
+const SPIN_TABLE_BASE: usize = 0xd8;
+
+/// Number of secondary cores that have reached `park()`.
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    static _start: u8;
+    fn _start_secondary();
+}
+
+/// Returns the ID (0-3) of the calling core.
+#[inline(always)]
+pub fn core_id() -> usize {
+    aarch64::affinity()
+}
+
+/// Returns the number of cores running the kernel, including core 0.
+pub fn cores_online() -> usize {
+    ONLINE.load(Ordering::Acquire) + 1
+}
+
+/// Returns the range of addresses `[bottom, top)` of `core`'s boot stack,
+/// including its guard region.
+pub fn stack_bounds(core: usize) -> (usize, usize) {
+    let top = unsafe { &_start as *const u8 as usize } - core * STACK_SIZE;
+    (top - STACK_SIZE, top)
+}
+
+/// Returns the core whose stack guard region contains `addr`, if any.
+pub fn stack_guard_owner(addr: usize) -> Option<usize> {
+    (0..NCORES).find(|&core| {
+        let (bottom, _) = stack_bounds(core);
+        addr >= bottom && addr < bottom + GUARD_SIZE
+    })
+}
+
+/// Returns `true` if `addr` lies in any core's stack guard region.
+pub fn is_stack_guard(addr: usize) -> bool {
+    stack_guard_owner(addr).is_some()
+}
+
+/// Releases cores 1-3 from the spin table.
+///
+/// # Safety
+///
+/// Must be called once, from core 0, after `vm::init()`.
+pub unsafe fn start_cores() {
+    for core in 1..NCORES {
+        let slot = (SPIN_TABLE_BASE + core * 8) as *mut u64;
+        slot.write_volatile(_start_secondary as usize as u64);
+        // The parked cores run with their caches off.
+        aarch64::clean_dcache_line(slot);
+    }
+
+    aarch64::sev();
+}
+
+/// Idles the calling secondary core forever. Core `watchdog::FEEDER_CORE`
+/// feeds the watchdog instead.
+pub fn park() -> ! {
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+    if core_id() == watchdog::FEEDER_CORE {
+        watchdog::feed();
+    }
+
+    loop {
+        aarch64::wfe();
+    }
+}
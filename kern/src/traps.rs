@@ -0,0 +1,84 @@
+mod frame;
+mod syndrome;
+mod syscall;
+
+pub use self::frame::TrapFrame;
+
+use crate::aarch64;
+use crate::backtrace;
+use crate::console::kprintln;
+use crate::gdb;
+use crate::smp;
+
+use self::syndrome::{Fault, Syndrome};
+use self::syscall::handle_syscall;
+
+/// The kind of an exception, matching the entry order of the vector table.
+#[repr(u16)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Kind {
+    Synchronous = 0,
+    Irq = 1,
+    Fiq = 2,
+    SError = 3,
+}
+
+/// The state the exception was taken from, matching the entry order of the
+/// vector table.
+#[repr(u16)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Source {
+    CurrentSpEl0 = 0,
+    CurrentSpElx = 1,
+    LowerAArch64 = 2,
+    LowerAArch32 = 3,
+}
+
+/// Information about an exception, built by the `HANDLER` vector stubs.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Info {
+    pub source: Source,
+    pub kind: Kind,
+}
+
+/// This function is called when an exception occurs. The `info` parameter
+/// specifies the source and kind of exception that has occurred. The `esr` is
+/// the value of the exception syndrome register. Finally, `tf` is a pointer to
+/// the trap frame for the exception.
+#[no_mangle]
+pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame) {
+    if info.kind != Kind::Synchronous {
+        kprintln!("unhandled {:?} exception from {:?}", info.kind, info.source);
+        return;
+    }
+
+    match Syndrome::from(esr) {
+        Syndrome::Svc(num) => handle_syscall(num, tf),
+        Syndrome::DataAbort { kind: Fault::Translation, .. } if info.source == Source::CurrentSpElx => {
+            let addr = aarch64::far();
+            backtrace::print_trap(tf);
+            match smp::stack_guard_owner(addr) {
+                Some(core) => panic!(
+                    "kernel stack overflow on core {} (access to {:#x}, elr = {:#x})",
+                    core, addr, tf.elr
+                ),
+                None => panic!("kernel translation fault at {:#x} (elr = {:#x})", addr, tf.elr),
+            }
+        }
+        Syndrome::Brk(_) if gdb::attached() => gdb::handle_breakpoint(tf),
+        Syndrome::Step if gdb::attached() => gdb::handle_step(tf),
+        Syndrome::Brk(comment) => {
+            kprintln!("brk #{} at {:#x}", comment, tf.elr);
+            // `brk` is not skipped over automatically.
+            tf.elr += 4;
+        }
+        syndrome => {
+            backtrace::print_trap(tf);
+            panic!(
+                "unhandled synchronous exception from {:?}: {:?} (elr = {:#x})",
+                info.source, syndrome, tf.elr
+            )
+        }
+    }
+}
@@ -0,0 +1,22 @@
+/// The state saved by `context_save` when an exception is taken.
+///
+/// The layout of this structure must match the order in which `context_save`
+/// pushes registers onto the stack in `init.s`.
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct TrapFrame {
+    /// Address to return to (`ELR_EL1`).
+    pub elr: u64,
+    /// Saved program status (`SPSR_EL1`).
+    pub spsr: u64,
+    /// Stack pointer of the interrupted EL0 context (`SP_EL0`).
+    pub sp: u64,
+    /// Thread ID register (`TPIDR_EL0`), used to hold the process ID.
+    pub tpidr: u64,
+    /// SIMD/FP registers `q0` through `q31`.
+    pub q: [u128; 32],
+    /// General purpose registers `x0` through `x30`.
+    pub x: [u64; 31],
+    /// Padding slot that keeps the frame 16-byte aligned.
+    pub reserved: u64,
+}
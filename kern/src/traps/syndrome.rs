@@ -0,0 +1,89 @@
+/// A decoded exception syndrome (`ESR_EL1`).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Syndrome {
+    Unknown,
+    WfiWfe,
+    SimdFp,
+    IllegalExecutionState,
+    Svc(u16),
+    Hvc(u16),
+    Smc(u16),
+    MsrMrsSystem,
+    InstructionAbort { kind: Fault, level: u8 },
+    PCAlignmentFault,
+    DataAbort { kind: Fault, level: u8 },
+    SpAlignmentFault,
+    TrappedFpu,
+    SError,
+    Breakpoint,
+    Step,
+    Watchpoint,
+    Brk(u16),
+    Other(u32),
+}
+
+/// The kind of fault reported by an instruction or data abort.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Fault {
+    AddressSize,
+    Translation,
+    AccessFlag,
+    Permission,
+    Alignment,
+    TlbConflict,
+    Other(u8),
+}
+
+impl From<u32> for Fault {
+    fn from(val: u32) -> Fault {
+        use self::Fault::*;
+
+        match (val & 0b111100) >> 2 {
+            0b0000 => AddressSize,
+            0b0001 => Translation,
+            0b0010 => AccessFlag,
+            0b0011 => Permission,
+            0b1000 => Alignment,
+            0b1100 => TlbConflict,
+            _ => Other((val & 0b111111) as u8),
+        }
+    }
+}
+
+impl From<u32> for Syndrome {
+    fn from(esr: u32) -> Syndrome {
+        use self::Syndrome::*;
+
+        let iss = esr & 0x1FF_FFFF;
+        let imm16 = (iss & 0xFFFF) as u16;
+        let abort = |iss: u32| (Fault::from(iss), (iss & 0b11) as u8);
+
+        match esr >> 26 {
+            0b000000 => Unknown,
+            0b000001 => WfiWfe,
+            0b000111 => SimdFp,
+            0b001110 => IllegalExecutionState,
+            0b010101 => Svc(imm16),
+            0b010110 => Hvc(imm16),
+            0b010111 => Smc(imm16),
+            0b011000 => MsrMrsSystem,
+            0b100000 | 0b100001 => {
+                let (kind, level) = abort(iss);
+                InstructionAbort { kind, level }
+            }
+            0b100010 => PCAlignmentFault,
+            0b100100 | 0b100101 => {
+                let (kind, level) = abort(iss);
+                DataAbort { kind, level }
+            }
+            0b100110 => SpAlignmentFault,
+            0b101100 => TrappedFpu,
+            0b101111 => SError,
+            0b110000 | 0b110001 => Breakpoint,
+            0b110010 | 0b110011 => Step,
+            0b110100 | 0b110101 => Watchpoint,
+            0b111100 => Brk(imm16),
+            other => Other(other),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use core::time::Duration;
+
+use pi::timer;
+
+use crate::aarch64;
+use crate::console::{kprintln, CONSOLE};
+use crate::traps::TrapFrame;
+
+/// Syscall numbers, passed as the immediate of `svc #N`.
+pub const NR_SLEEP: u16 = 1;
+pub const NR_TIME: u16 = 2;
+pub const NR_EXIT: u16 = 3;
+pub const NR_WRITE: u16 = 4;
+pub const NR_GETPID: u16 = 5;
+
+/// Error codes returned to the caller in `x7`.
+#[repr(u64)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OsError {
+    Ok = 0,
+    Unknown = 1,
+    InvalidArgument = 2,
+}
+
+/// Sleep for `ms` milliseconds.
+///
+/// Returns the number of milliseconds actually slept in `x0`.
+pub fn sys_sleep(ms: u32, tf: &mut TrapFrame) {
+    let start = timer::current_time();
+    timer::spin_sleep(Duration::from_millis(ms as u64));
+    let slept = timer::current_time() - start;
+
+    tf.x[0] = slept.as_millis() as u64;
+    tf.x[7] = OsError::Ok as u64;
+}
+
+/// Returns the current time as seconds in `x0` and the fractional part in
+/// nanoseconds in `x1`.
+pub fn sys_time(tf: &mut TrapFrame) {
+    let now = timer::current_time();
+
+    tf.x[0] = now.as_secs();
+    tf.x[1] = now.subsec_nanos() as u64;
+    tf.x[7] = OsError::Ok as u64;
+}
+
+/// Terminates the calling context with exit status `status`.
+///
+/// There is no process table to return to yet, so the core is parked.
+pub fn sys_exit(status: u64, tf: &mut TrapFrame) -> ! {
+    kprintln!("pid {} exited with status {}", tf.tpidr, status);
+    loop {
+        aarch64::wfe();
+    }
+}
+
+/// Writes the byte in `x0` to the console.
+pub fn sys_write(b: u64, tf: &mut TrapFrame) {
+    if b > u8::max_value() as u64 {
+        tf.x[7] = OsError::InvalidArgument as u64;
+        return;
+    }
+
+    CONSOLE.lock().write_byte(b as u8);
+    tf.x[7] = OsError::Ok as u64;
+}
+
+/// Returns the current process's ID in `x0`.
+pub fn sys_getpid(tf: &mut TrapFrame) {
+    tf.x[0] = tf.tpidr;
+    tf.x[7] = OsError::Ok as u64;
+}
+
+/// Dispatches the syscall `num`. Arguments are read from and results written
+/// to the registers saved in `tf`.
+pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
+    match num {
+        NR_SLEEP => sys_sleep(tf.x[0] as u32, tf),
+        NR_TIME => sys_time(tf),
+        NR_EXIT => sys_exit(tf.x[0], tf),
+        NR_WRITE => sys_write(tf.x[0], tf),
+        NR_GETPID => sys_getpid(tf),
+        _ => tf.x[7] = OsError::Unknown as u64,
+    }
+}
@@ -0,0 +1,180 @@
+//! EL1 translation tables and MMU bring-up.
+//!
+//! The kernel runs on an identity map: every virtual address translates to the
+//! same physical address. RAM is mapped as cacheable normal memory and the
+//! peripheral windows as device memory, using 2MiB blocks below 1GiB and a
+//! single 1GiB block for the ARM local peripherals above it. The first 2MiB,
+//! which hold the boot stacks, are mapped with 4KiB pages so that the guard
+//! page at the bottom of each core's stack can be left unmapped.
+
+use pi::common::IO_BASE;
+
+use crate::smp;
+
+/// End of the peripheral window starting at `IO_BASE` and start of the ARM
+/// local peripherals (core timers, mailboxes).
+const IO_END: usize = 0x4000_0000;
+
+/// Size of the region mapped by a level 2 block descriptor.
+const L2_BLOCK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Size of the region mapped by a level 3 page descriptor.
+const PAGE_SIZE: usize = 4096;
+
+/// Number of entries in a translation table with a 4KiB granule.
+const ENTRIES: usize = 512;
+
+/// `MAIR_EL1` attribute indices.
+mod attr {
+    /// Normal memory, inner/outer write-back, read/write-allocate.
+    pub const NORMAL: u64 = 0;
+    /// Device-nGnRE memory.
+    pub const DEVICE: u64 = 1;
+
+    /// The value programmed into `MAIR_EL1` for the indices above.
+    pub const MAIR: u64 = (0xFF << (NORMAL * 8)) | (0x04 << (DEVICE * 8));
+}
+
+/// Descriptor bits (ref: D4.3.3).
+mod desc {
+    pub const VALID: u64 = 1 << 0;
+    pub const TABLE: u64 = 1 << 1;
+    pub const BLOCK: u64 = 0 << 1;
+    pub const PAGE: u64 = 1 << 1;
+    pub const ATTR_SHIFT: u64 = 2;
+    pub const AP_EL1_RW: u64 = 0b00 << 6;
+    pub const SH_OUTER: u64 = 0b10 << 8;
+    pub const SH_INNER: u64 = 0b11 << 8;
+    pub const AF: u64 = 1 << 10;
+    pub const PXN: u64 = 1 << 53;
+    pub const UXN: u64 = 1 << 54;
+}
+
+/// `TCR_EL1` fields (ref: D7.2.91).
+mod tcr {
+    /// 32-bit input address space for TTBR0: walks start at level 1.
+    pub const T0SZ: u64 = 32 << 0;
+    pub const IRGN0_WBWA: u64 = 0b01 << 8;
+    pub const ORGN0_WBWA: u64 = 0b01 << 10;
+    pub const SH0_INNER: u64 = 0b11 << 12;
+    pub const TG0_4K: u64 = 0b00 << 14;
+    /// Disable table walks through TTBR1.
+    pub const EPD1: u64 = 1 << 23;
+    pub const IPS_SHIFT: u64 = 32;
+}
+
+/// `SCTLR_EL1` bits (ref: D7.2.88).
+mod sctlr {
+    pub const M: u64 = 1 << 0;
+    pub const C: u64 = 1 << 2;
+    pub const I: u64 = 1 << 12;
+}
+
+/// A 4KiB-aligned translation table.
+#[repr(C, align(4096))]
+struct PageTable {
+    entries: [u64; ENTRIES],
+}
+
+impl PageTable {
+    const fn new() -> PageTable {
+        PageTable { entries: [0; ENTRIES] }
+    }
+}
+
+/// Level 1 table: entry 0 points at `L2`, entry 1 maps the local peripherals.
+static mut L1: PageTable = PageTable::new();
+
+/// Level 2 table covering the first 1GiB: entry 0 points at `L3`, the rest
+/// are 2MiB blocks.
+static mut L2: PageTable = PageTable::new();
+
+/// Level 3 table covering the first 2MiB in 4KiB pages.
+static mut L3: PageTable = PageTable::new();
+
+/// Returns the output address and attribute bits for a descriptor mapping
+/// `addr` with the memory attributes appropriate for that physical address.
+fn mapping(addr: usize) -> u64 {
+    let flags = if addr >= IO_BASE {
+        (attr::DEVICE << desc::ATTR_SHIFT) | desc::SH_OUTER | desc::PXN
+    } else {
+        (attr::NORMAL << desc::ATTR_SHIFT) | desc::SH_INNER
+    };
+
+    addr as u64 | flags | desc::AP_EL1_RW | desc::AF | desc::UXN
+}
+
+/// Returns a level 1 or 2 block descriptor mapping `addr`.
+fn block_entry(addr: usize) -> u64 {
+    mapping(addr) | desc::BLOCK | desc::VALID
+}
+
+/// Returns a level 3 page descriptor mapping `addr`, or an invalid descriptor
+/// if `addr` is a stack guard page.
+fn page_entry(addr: usize) -> u64 {
+    if smp::is_stack_guard(addr) {
+        0
+    } else {
+        mapping(addr) | desc::PAGE | desc::VALID
+    }
+}
+
+/// Returns a table descriptor pointing at `table`.
+fn table_entry(table: &PageTable) -> u64 {
+    table as *const PageTable as u64 | desc::TABLE | desc::VALID
+}
+
+/// Fills in the identity-mapped kernel translation tables.
+unsafe fn build_tables() {
+    for (i, entry) in L3.entries.iter_mut().enumerate() {
+        *entry = page_entry(i * PAGE_SIZE);
+    }
+
+    for (i, entry) in L2.entries.iter_mut().enumerate() {
+        *entry = block_entry(i * L2_BLOCK_SIZE);
+    }
+
+    L2.entries[0] = table_entry(&L3);
+    L1.entries[0] = table_entry(&L2);
+    L1.entries[1] = block_entry(IO_END);
+}
+
+/// Builds the kernel's translation tables and enables the MMU on the calling
+/// core.
+///
+/// # Safety
+///
+/// Must be called exactly once, at EL1, before any other core is released.
+pub unsafe fn init() {
+    build_tables();
+    enable();
+}
+
+/// Programs `MAIR_EL1`, `TCR_EL1` and `TTBR0_EL1` with the kernel's
+/// translation tables and enables the MMU along with the data and instruction
+/// caches on the calling core.
+///
+/// # Safety
+///
+/// Must be called at EL1 after `init()` has built the tables.
+pub unsafe fn enable() {
+    let parange: u64;
+    asm!("mrs $0, ID_AA64MMFR0_EL1" : "=r"(parange) ::: "volatile");
+
+    let tcr = tcr::T0SZ | tcr::IRGN0_WBWA | tcr::ORGN0_WBWA | tcr::SH0_INNER
+        | tcr::TG0_4K | tcr::EPD1 | ((parange & 0b111) << tcr::IPS_SHIFT);
+
+    asm!("msr MAIR_EL1, $0" :: "r"(attr::MAIR) :: "volatile");
+    asm!("msr TCR_EL1, $0" :: "r"(tcr) :: "volatile");
+    asm!("msr TTBR0_EL1, $0" :: "r"(&L1 as *const PageTable as u64) :: "volatile");
+    asm!("dsb ish
+          tlbi vmalle1
+          dsb ish
+          isb" :::: "volatile");
+
+    let mut sctlr: u64;
+    asm!("mrs $0, SCTLR_EL1" : "=r"(sctlr) ::: "volatile");
+    sctlr |= sctlr::M | sctlr::C | sctlr::I;
+    asm!("msr SCTLR_EL1, $0
+          isb" :: "r"(sctlr) :: "volatile");
+}
@@ -0,0 +1,185 @@
+//! Feeds the hardware watchdog while the kernel is making progress.
+//!
+//! Once enabled with `enable()` (or the shell's `watchdog on`), core
+//! `FEEDER_CORE` restarts the watchdog every quarter of its timeout, but only
+//! while the kernel looks healthy:
+//!
+//!   * no core has panicked,
+//!   * `heartbeat()` has been called within the timeout, and
+//!   * no watched `Source` has been busy without making progress for longer
+//!     than the timeout.
+//!
+//! The shell beats while it waits for input, so a kernel stuck anywhere else,
+//! printing or not, stops the feeding once the timeout passes. A source is
+//! busy between `begin()` and `end()`; each call counts as progress. `kprint!`
+//! marks the console busy while it prints, so a console stuck on its lock or
+//! on the UART stops the feeding too. Once feeding stops, the board resets
+//! when the timeout expires, so a wedged board comes back on its own instead
+//! of staying hung until someone power-cycles it.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use pi::pm::Watchdog;
+use pi::timer;
+
+use crate::aarch64;
+
+/// The core that feeds the watchdog instead of parking.
+pub const FEEDER_CORE: usize = 3;
+
+/// The timeout used until `set_timeout` is called.
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+/// The shortest timeout accepted by `set_timeout`, leaving the feeder time to
+/// run between checks.
+const MIN_TIMEOUT_MS: u64 = 100;
+
+/// Something whose progress the feeder watches.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Kernel console output.
+    Console,
+}
+
+const NSOURCES: usize = 1;
+
+/// The progress of one `Source`.
+struct Progress {
+    /// Number of `begin()` calls without a matching `end()`.
+    busy: AtomicUsize,
+    /// The time of the last `begin()` or `end()`, in microseconds.
+    last: AtomicU64,
+}
+
+impl Progress {
+    const fn new() -> Progress {
+        Progress { busy: AtomicUsize::new(0), last: AtomicU64::new(0) }
+    }
+}
+
+static PROGRESS: [Progress; NSOURCES] = [Progress::new()];
+
+/// The time of the last `heartbeat()`, in microseconds.
+static HEARTBEAT: AtomicU64 = AtomicU64::new(0);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PANICKED: AtomicBool = AtomicBool::new(false);
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT_MS);
+
+fn now_us() -> u64 {
+    timer::current_time().as_micros() as u64
+}
+
+/// Records progress of `source`. The timer is only read while the watchdog
+/// is enabled, so `kprint!` works before the timer does.
+fn touch(source: Source) {
+    if is_enabled() {
+        PROGRESS[source as usize].last.store(now_us(), Ordering::Relaxed);
+    }
+}
+
+/// Marks `source` busy.
+pub fn begin(source: Source) {
+    touch(source);
+    PROGRESS[source as usize].busy.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Marks the end of a `begin(source)`.
+pub fn end(source: Source) {
+    touch(source);
+    PROGRESS[source as usize].busy.fetch_sub(1, Ordering::AcqRel);
+}
+
+/// Records that the kernel's main loop is still running. Without one within
+/// the timeout, the feeding stops.
+pub fn heartbeat() {
+    if is_enabled() {
+        HEARTBEAT.store(now_us(), Ordering::Relaxed);
+    }
+}
+
+/// Stops the feeding for good. Called by the panic handler.
+pub fn note_panic() {
+    PANICKED.store(true, Ordering::SeqCst);
+}
+
+/// Arms the watchdog and starts feeding it.
+pub fn enable() {
+    let now = now_us();
+    for progress in PROGRESS.iter() {
+        progress.last.store(now, Ordering::Relaxed);
+    }
+
+    HEARTBEAT.store(now, Ordering::Relaxed);
+    Watchdog::new().start(timeout());
+    ENABLED.store(true, Ordering::SeqCst);
+    aarch64::sev();
+}
+
+/// Stops feeding the watchdog and disarms it.
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+    Watchdog::new().stop();
+}
+
+/// Returns `true` if the watchdog is being fed.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Returns the time the kernel may go without progress before it's reset.
+pub fn timeout() -> Duration {
+    Duration::from_millis(TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+/// Sets the timeout, clamped to between 100ms and `Watchdog::max_timeout()`.
+/// Takes effect at the next feeding.
+pub fn set_timeout(timeout: Duration) {
+    let max = Watchdog::max_timeout().as_millis() as u64;
+    let ms = (timeout.as_millis() as u64).max(MIN_TIMEOUT_MS).min(max);
+    TIMEOUT_MS.store(ms, Ordering::Relaxed);
+}
+
+/// Returns the first source that has been busy without progress for longer
+/// than the timeout.
+fn stalled_source() -> Option<Source> {
+    let limit = TIMEOUT_MS.load(Ordering::Relaxed) * 1000;
+    let now = now_us();
+    [Source::Console].iter().cloned().find(|&source| {
+        let progress = &PROGRESS[source as usize];
+        progress.busy.load(Ordering::Acquire) > 0
+            && now.saturating_sub(progress.last.load(Ordering::Relaxed)) > limit
+    })
+}
+
+/// Returns `true` if the last `heartbeat()` is older than the timeout.
+fn heartbeat_stale() -> bool {
+    let limit = TIMEOUT_MS.load(Ordering::Relaxed) * 1000;
+    now_us().saturating_sub(HEARTBEAT.load(Ordering::Relaxed)) > limit
+}
+
+/// Returns `true` if the kernel is healthy enough to be kept alive.
+fn healthy() -> bool {
+    !PANICKED.load(Ordering::SeqCst) && !heartbeat_stale() && stalled_source().is_none()
+}
+
+/// The feeder loop run by `FEEDER_CORE`. Sleeps until `enable` is called.
+pub fn feed() -> ! {
+    loop {
+        if !is_enabled() {
+            aarch64::wfe();
+            continue;
+        }
+
+        if healthy() {
+            Watchdog::new().start(timeout());
+            // don't leave it armed if `disable` ran in between
+            if !is_enabled() {
+                Watchdog::new().stop();
+            }
+        }
+
+        timer::spin_sleep(timeout() / 4);
+    }
+}
@@ -0,0 +1,205 @@
+use core::{slice, str};
+
+/// The address at which the firmware places the ATAGS.
+const ATAG_BASE: usize = 0x100;
+
+/// Tag values (ref: Booting ARM Linux, section 4).
+const TAG_NONE: u32 = 0x0000_0000;
+const TAG_CORE: u32 = 0x5441_0001;
+const TAG_MEM: u32 = 0x5441_0002;
+const TAG_CMDLINE: u32 = 0x5441_0009;
+
+/// The header common to every ATAG.
+#[repr(C)]
+struct Header {
+    /// Size of the tag, including this header, in 32-bit words.
+    dwords: u32,
+    tag: u32,
+}
+
+/// The payload of a `CORE` tag.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Core {
+    pub flags: u32,
+    pub page_size: u32,
+    pub root_dev: u32,
+}
+
+/// The payload of a `MEM` tag: one region of physical memory.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Mem {
+    pub size: u32,
+    pub start: u32,
+}
+
+/// A single ATAG.
+#[derive(Debug, Copy, Clone)]
+pub enum Atag {
+    Core(Core),
+    Mem(Mem),
+    /// The kernel command line (the contents of `cmdline.txt` plus anything
+    /// the firmware adds).
+    Cmd(&'static str),
+    Unknown(u32),
+}
+
+/// An iterator over the ATAGS left in memory by the firmware.
+pub struct Atags {
+    ptr: Option<&'static Header>,
+}
+
+impl Atags {
+    /// Returns an iterator over the ATAGS on this system.
+    pub fn get() -> Atags {
+        Atags {
+            ptr: Some(unsafe { &*(ATAG_BASE as *const Header) }),
+        }
+    }
+
+    /// Returns the kernel command line, if the firmware passed one.
+    pub fn cmdline() -> Option<&'static str> {
+        Atags::get().find_map(|atag| match atag {
+            Atag::Cmd(cmd) => Some(cmd),
+            _ => None,
+        })
+    }
+}
+
+impl Header {
+    /// Returns a pointer to the payload that follows this header.
+    fn data<T>(&self) -> *const T {
+        unsafe { (self as *const Header).add(1) as *const T }
+    }
+
+    /// Returns the tag following this one, or `None` if this is the last one.
+    fn next(&self) -> Option<&'static Header> {
+        if self.tag == TAG_NONE || self.dwords < 2 {
+            return None;
+        }
+
+        let next = unsafe { (self as *const Header as *const u32).add(self.dwords as usize) };
+        Some(unsafe { &*(next as *const Header) })
+    }
+
+    /// Parses this header and its payload.
+    fn parse(&self) -> Atag {
+        match self.tag {
+            TAG_CORE => Atag::Core(unsafe { *self.data::<Core>() }),
+            TAG_MEM => Atag::Mem(unsafe { *self.data::<Mem>() }),
+            TAG_CMDLINE => Atag::Cmd(unsafe { cstr(self.data::<u8>()) }),
+            tag => Atag::Unknown(tag),
+        }
+    }
+}
+
+/// Returns the NUL-terminated string starting at `ptr`, or an empty string if
+/// it isn't valid UTF-8.
+unsafe fn cstr(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+
+    str::from_utf8(slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
+
+impl Iterator for Atags {
+    type Item = Atag;
+
+    fn next(&mut self) -> Option<Atag> {
+        let header = self.ptr?;
+        if header.tag == TAG_NONE {
+            self.ptr = None;
+            return None;
+        }
+
+        self.ptr = header.next();
+        Some(header.parse())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{Atag, Atags, Header, TAG_CMDLINE, TAG_CORE, TAG_MEM, TAG_NONE};
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Lays `tags` out as the firmware would, ending with a `NONE` tag, and
+    /// returns an iterator over them.
+    fn atags(tags: &[(u32, &[u32])]) -> Atags {
+        let mut words = Vec::new();
+        for &(tag, data) in tags {
+            words.extend_from_slice(&[2 + data.len() as u32, tag]);
+            words.extend_from_slice(data);
+        }
+
+        words.extend_from_slice(&[0, TAG_NONE]);
+        let words: &'static [u32] = Box::leak(words.into_boxed_slice());
+        Atags { ptr: Some(unsafe { &*(words.as_ptr() as *const Header) }) }
+    }
+
+    /// Packs `s` and its NUL into native-endian words.
+    fn cmdline_words(s: &str) -> Vec<u32> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        bytes.chunks(4).map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]])).collect()
+    }
+
+    #[test]
+    fn parses_core_mem_and_cmdline() {
+        let cmdline = cmdline_words("console=ttyS0 shell=0");
+        let mut tags = atags(&[
+            (TAG_CORE, &[1, 4096, 0]),
+            (TAG_MEM, &[0x3b00_0000, 0]),
+            (TAG_CMDLINE, &cmdline),
+            (0x5441_0042, &[7]),
+        ]);
+
+        match tags.next() {
+            Some(Atag::Core(core)) => assert_eq!((core.flags, core.page_size, core.root_dev), (1, 4096, 0)),
+            other => panic!("expected CORE, got {:?}", other),
+        }
+        match tags.next() {
+            Some(Atag::Mem(mem)) => assert_eq!((mem.size, mem.start), (0x3b00_0000, 0)),
+            other => panic!("expected MEM, got {:?}", other),
+        }
+        match tags.next() {
+            Some(Atag::Cmd(cmd)) => assert_eq!(cmd, "console=ttyS0 shell=0"),
+            other => panic!("expected CMDLINE, got {:?}", other),
+        }
+        match tags.next() {
+            Some(Atag::Unknown(tag)) => assert_eq!(tag, 0x5441_0042),
+            other => panic!("expected an unknown tag, got {:?}", other),
+        }
+        assert!(tags.next().is_none());
+        assert!(tags.next().is_none());
+    }
+
+    #[test]
+    fn stops_at_none_or_a_short_tag() {
+        assert!(atags(&[]).next().is_none());
+
+        // a tag claiming less than its own header can't be stepped over
+        let words: &'static [u32] = Box::leak(vec![1, TAG_MEM, 0, 0, 2, TAG_CORE].into_boxed_slice());
+        let mut tags = Atags { ptr: Some(unsafe { &*(words.as_ptr() as *const Header) }) };
+        match tags.next() {
+            Some(Atag::Mem(_)) => {}
+            other => panic!("expected MEM, got {:?}", other),
+        }
+        assert!(tags.next().is_none());
+    }
+
+    #[test]
+    fn invalid_cmdline_is_empty() {
+        let mut tags = atags(&[(TAG_CMDLINE, &[u32::from_ne_bytes([0xff, 0xfe, b'x', 0])])]);
+        match tags.next() {
+            Some(Atag::Cmd(cmd)) => assert_eq!(cmd, ""),
+            other => panic!("expected CMDLINE, got {:?}", other),
+        }
+    }
+}
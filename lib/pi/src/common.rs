@@ -2,6 +2,26 @@
 pub const IO_BASE: usize   = 0x3F000000;
 pub const CLOCK_HZ: u64 = 250 * 1000 * 1000;
 
+/// The cache line size of the Cortex-A53.
+pub const CACHE_LINE: usize = 64;
+
+/// Cleans and invalidates the data cache lines covering `len` bytes at
+/// `addr`, so the memory is coherent with the VideoCore, which doesn't snoop
+/// the ARM caches. Off the Pi, there's no VideoCore, and it does nothing.
+#[cfg(target_arch = "aarch64")]
+pub(crate) unsafe fn clean_and_invalidate(addr: usize, len: usize) {
+    let mut line = addr & !(CACHE_LINE - 1);
+    while line < addr + len {
+        asm!("dc civac, $0" :: "r"(line) :: "volatile");
+        line += CACHE_LINE;
+    }
+
+    asm!("dsb sy" ::: "memory" : "volatile");
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+pub(crate) unsafe fn clean_and_invalidate(_addr: usize, _len: usize) {}
+
 /// Generates `pub enums` with no variants for each `ident` passed in.
 pub macro states($($name:ident),*) {
     $(
@@ -0,0 +1,153 @@
+use core::ptr;
+
+use crate::common::clean_and_invalidate;
+use crate::mailbox::Mailbox;
+
+/// The framebuffer tag identifiers (ref: firmware wiki, "Mailbox property
+/// interface").
+const TAG_ALLOCATE: u32 = 0x0004_0001;
+const TAG_GET_PHYSICAL_SIZE: u32 = 0x0004_0003;
+const TAG_GET_PITCH: u32 = 0x0004_0008;
+const TAG_SET_PHYSICAL_SIZE: u32 = 0x0004_8003;
+const TAG_SET_VIRTUAL_SIZE: u32 = 0x0004_8004;
+const TAG_SET_DEPTH: u32 = 0x0004_8005;
+const TAG_SET_PIXEL_ORDER: u32 = 0x0004_8006;
+
+/// Bits per pixel of the framebuffer.
+const DEPTH: u32 = 32;
+
+/// `TAG_SET_PIXEL_ORDER` value for RGB, so pixels are `0x00RRGGBB`.
+const PIXEL_ORDER_RGB: u32 = 1;
+
+/// The alignment requested for the framebuffer.
+const ALIGNMENT: u32 = 4096;
+
+/// The firmware returns VideoCore bus addresses; these bits select the
+/// VideoCore's cache alias and aren't part of the ARM physical address.
+const BUS_ADDRESS_MASK: u32 = 0x3FFF_FFFF;
+
+/// A 32-bit linear framebuffer allocated by the GPU firmware.
+///
+/// Colors are `0x00RRGGBB`. The framebuffer is in ordinary cacheable memory
+/// and the GPU doesn't snoop the ARM caches, so drawing only shows up on
+/// screen after the affected area is passed to `flush`.
+#[derive(Debug)]
+pub struct Framebuffer {
+    base: usize,
+    width: usize,
+    height: usize,
+    /// Bytes per row, which may be more than `width * 4`.
+    pitch: usize,
+}
+
+impl Framebuffer {
+    /// Returns the resolution of the attached display, if the firmware knows
+    /// it.
+    pub fn display_size() -> Option<(u32, u32)> {
+        let mut out = [0; 2];
+        Mailbox::new().property(TAG_GET_PHYSICAL_SIZE, &[], &mut out)?;
+        match out {
+            [0, _] | [_, 0] => None,
+            [width, height] => Some((width, height)),
+        }
+    }
+
+    /// Asks the firmware for a `width` by `height` framebuffer. Returns
+    /// `None` if the request is refused or the firmware can't provide 32-bit
+    /// pixels.
+    pub fn new(width: u32, height: u32) -> Option<Framebuffer> {
+        let mut tags = [
+            TAG_SET_PHYSICAL_SIZE, 8, 0, width, height,
+            TAG_SET_VIRTUAL_SIZE, 8, 0, width, height,
+            TAG_SET_DEPTH, 4, 0, DEPTH,
+            TAG_SET_PIXEL_ORDER, 4, 0, PIXEL_ORDER_RGB,
+            TAG_ALLOCATE, 8, 0, ALIGNMENT, 0,
+            TAG_GET_PITCH, 4, 0, 0,
+        ];
+
+        if !Mailbox::new().properties(&mut tags) {
+            return None;
+        }
+
+        let (width, height, depth) = (tags[3], tags[4], tags[13]);
+        let (base, pitch) = (tags[21] & BUS_ADDRESS_MASK, tags[26]);
+        if depth != DEPTH || base == 0 || width == 0 || height == 0 || pitch < width * 4 {
+            return None;
+        }
+
+        Some(Framebuffer {
+            base: base as usize,
+            width: width as usize,
+            height: height as usize,
+            pitch: pitch as usize,
+        })
+    }
+
+    /// Returns the width, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of bytes between the start of two rows.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    fn row(&self, y: usize) -> *mut u32 {
+        (self.base + y * self.pitch) as *mut u32
+    }
+
+    /// Sets the pixel at (`x`, `y`) to `color`. Pixels outside the
+    /// framebuffer are ignored.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        if x < self.width && y < self.height {
+            unsafe { ptr::write_volatile(self.row(y).add(x), color) };
+        }
+    }
+
+    /// Fills the `w` by `h` rectangle at (`x`, `y`) with `color`, clipped to
+    /// the framebuffer.
+    pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: u32) {
+        let x_end = self.width.min(x.saturating_add(w));
+        let y_end = self.height.min(y.saturating_add(h));
+        for y in y..y_end {
+            let row = self.row(y);
+            for x in x..x_end {
+                unsafe { ptr::write_volatile(row.add(x), color) };
+            }
+        }
+    }
+
+    /// Moves the whole image up by `lines` rows and fills the rows uncovered
+    /// at the bottom with `color`.
+    pub fn scroll_up(&mut self, lines: usize, color: u32) {
+        let lines = lines.min(self.height);
+        let moved = self.height - lines;
+        unsafe {
+            ptr::copy(self.row(lines) as *const u8, self.row(0) as *mut u8, moved * self.pitch);
+        }
+
+        let width = self.width;
+        self.fill_rect(0, moved, width, lines, color);
+    }
+
+    /// Writes the `w` by `h` rectangle at (`x`, `y`) back from the data
+    /// cache so the GPU displays it.
+    pub fn flush(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        let x_end = self.width.min(x.saturating_add(w));
+        let y_end = self.height.min(y.saturating_add(h));
+        if x >= x_end {
+            return;
+        }
+
+        for y in y..y_end {
+            let start = self.row(y) as usize + x * 4;
+            unsafe { clean_and_invalidate(start, (x_end - x) * 4) };
+        }
+    }
+}
@@ -5,7 +5,15 @@
 #![feature(never_type)]
 #![no_std]
 
+pub mod atags;
 pub mod common;
+pub mod framebuffer;
 pub mod gpio;
+pub mod mailbox;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod pl011;
+pub mod pm;
+pub mod rng;
 pub mod timer;
 pub mod uart;
@@ -0,0 +1,202 @@
+use crate::common::{clean_and_invalidate, IO_BASE};
+
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile, Reserved};
+
+/// The base address of the VideoCore mailbox 0 registers.
+const MBOX_REG_BASE: usize = IO_BASE + 0xB880;
+
+/// `STATUS` register bits.
+const MBOX_FULL: u32 = 1 << 31;
+const MBOX_EMPTY: u32 = 1 << 30;
+
+/// The property-tag channel (ARM to VideoCore).
+const CHANNEL_PROPERTY: u32 = 8;
+
+/// Request and response codes in the property buffer header.
+const CODE_REQUEST: u32 = 0x0000_0000;
+const CODE_RESPONSE_OK: u32 = 0x8000_0000;
+
+/// The tag identifiers used by this module (ref: firmware wiki, "Mailbox
+/// property interface").
+const TAG_FIRMWARE_REVISION: u32 = 0x0000_0001;
+const TAG_BOARD_MODEL: u32 = 0x0001_0001;
+const TAG_BOARD_REVISION: u32 = 0x0001_0002;
+const TAG_BOARD_SERIAL: u32 = 0x0001_0004;
+const TAG_ARM_MEMORY: u32 = 0x0001_0005;
+const TAG_CLOCK_RATE: u32 = 0x0003_0002;
+
+/// Size, in words, of the property buffer.
+const BUFFER_WORDS: usize = 64;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    READ: ReadVolatile<u32>,
+    __r0: [Reserved<u32>; 5],
+    STATUS: ReadVolatile<u32>,
+    CONFIG: Volatile<u32>,
+    WRITE: Volatile<u32>,
+}
+
+/// A property buffer. The low four bits of its address carry the channel
+/// number, so it must be 16-byte aligned; it's cache-line aligned so that
+/// cleaning it doesn't touch unrelated data.
+#[repr(C, align(64))]
+struct Buffer([u32; BUFFER_WORDS]);
+
+/// Clocks whose rates can be queried with `clock_rate`.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Clock {
+    Emmc = 1,
+    Uart = 2,
+    Arm = 3,
+    Core = 4,
+}
+
+/// The VideoCore mailbox, used to talk to the GPU firmware.
+pub struct Mailbox {
+    registers: &'static mut Registers,
+}
+
+impl Mailbox {
+    /// Returns a new instance of `Mailbox`.
+    pub fn new() -> Mailbox {
+        Mailbox {
+            registers: unsafe { &mut *(MBOX_REG_BASE as *mut Registers) },
+        }
+    }
+
+    /// Sends `data` (whose low four bits must be clear) on `channel` and
+    /// waits for the firmware's reply on the same channel.
+    fn call(&mut self, channel: u32, data: u32) -> u32 {
+        while self.registers.STATUS.has_mask(MBOX_FULL) {}
+        self.registers.WRITE.write(data | channel);
+
+        loop {
+            while self.registers.STATUS.has_mask(MBOX_EMPTY) {}
+            let reply = self.registers.READ.read();
+            if reply & 0xF == channel {
+                return reply & !0xF;
+            }
+        }
+    }
+
+    /// Sends a single property tag `tag` with request values `values` and
+    /// writes the response values to `out`. Returns the number of response
+    /// bytes reported by the firmware, or `None` if the request failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` or `out` doesn't fit in the property buffer.
+    pub fn property(&mut self, tag: u32, values: &[u32], out: &mut [u32]) -> Option<usize> {
+        let len = values.len().max(out.len());
+        assert!(len + 6 <= BUFFER_WORDS, "mailbox property too large");
+
+        let mut buf = Buffer([0; BUFFER_WORDS]);
+        buf.0[0] = ((len + 6) * 4) as u32;
+        buf.0[1] = CODE_REQUEST;
+        buf.0[2] = tag;
+        buf.0[3] = (len * 4) as u32;
+        buf.0[4] = 0;
+        buf.0[5..5 + values.len()].copy_from_slice(values);
+        // buf.0[5 + len] is the end tag, already zero
+
+        let addr = &mut buf as *mut Buffer;
+        unsafe {
+            clean_and_invalidate(addr as usize, core::mem::size_of::<Buffer>());
+            self.call(CHANNEL_PROPERTY, addr as u32);
+            clean_and_invalidate(addr as usize, core::mem::size_of::<Buffer>());
+        }
+
+        let buf = unsafe { core::ptr::read_volatile(addr) };
+        if buf.0[1] != CODE_RESPONSE_OK || buf.0[4] & CODE_RESPONSE_OK == 0 {
+            return None;
+        }
+
+        let resp_len = (buf.0[4] & !CODE_RESPONSE_OK) as usize;
+        let words = (resp_len / 4).min(out.len());
+        out[..words].copy_from_slice(&buf.0[5..5 + words]);
+        Some(resp_len)
+    }
+
+    /// Sends several property tags in one request. `tags` holds the tags
+    /// back to back, each as its identifier, value buffer size in bytes,
+    /// request code (zero) and value buffer, without the buffer header or the
+    /// end tag. On success, the firmware's response is copied back into
+    /// `tags` and `true` is returned.
+    ///
+    /// Some requests, like the framebuffer's, only take effect when their
+    /// tags are sent together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tags` doesn't fit in the property buffer.
+    pub fn properties(&mut self, tags: &mut [u32]) -> bool {
+        assert!(tags.len() + 3 <= BUFFER_WORDS, "mailbox properties too large");
+
+        let mut buf = Buffer([0; BUFFER_WORDS]);
+        buf.0[0] = ((tags.len() + 3) * 4) as u32;
+        buf.0[1] = CODE_REQUEST;
+        buf.0[2..2 + tags.len()].copy_from_slice(tags);
+        // buf.0[2 + tags.len()] is the end tag, already zero
+
+        let addr = &mut buf as *mut Buffer;
+        unsafe {
+            clean_and_invalidate(addr as usize, core::mem::size_of::<Buffer>());
+            self.call(CHANNEL_PROPERTY, addr as u32);
+            clean_and_invalidate(addr as usize, core::mem::size_of::<Buffer>());
+        }
+
+        let buf = unsafe { core::ptr::read_volatile(addr) };
+        if buf.0[1] != CODE_RESPONSE_OK {
+            return false;
+        }
+
+        tags.copy_from_slice(&buf.0[2..2 + tags.len()]);
+        true
+    }
+
+    /// Queries a tag that takes no arguments, returning its response values.
+    fn get<T: Default + AsMut<[u32]>>(&mut self, tag: u32) -> Option<T> {
+        let mut out = T::default();
+        self.property(tag, &[], out.as_mut())?;
+        Some(out)
+    }
+
+    /// Returns the VideoCore firmware revision.
+    pub fn firmware_revision(&mut self) -> Option<u32> {
+        self.get::<[u32; 1]>(TAG_FIRMWARE_REVISION).map(|v| v[0])
+    }
+
+    /// Returns the board model.
+    pub fn board_model(&mut self) -> Option<u32> {
+        self.get::<[u32; 1]>(TAG_BOARD_MODEL).map(|v| v[0])
+    }
+
+    /// Returns the board revision code.
+    pub fn board_revision(&mut self) -> Option<u32> {
+        self.get::<[u32; 1]>(TAG_BOARD_REVISION).map(|v| v[0])
+    }
+
+    /// Returns the board's serial number.
+    pub fn board_serial(&mut self) -> Option<u64> {
+        self.get::<[u32; 2]>(TAG_BOARD_SERIAL)
+            .map(|v| (v[1] as u64) << 32 | v[0] as u64)
+    }
+
+    /// Returns the base address and size, in bytes, of the memory reserved
+    /// for the ARM cores.
+    pub fn arm_memory(&mut self) -> Option<(u32, u32)> {
+        self.get::<[u32; 2]>(TAG_ARM_MEMORY).map(|v| (v[0], v[1]))
+    }
+
+    /// Returns the current rate of `clock` in Hz.
+    pub fn clock_rate(&mut self, clock: Clock) -> Option<u32> {
+        let mut out = [0; 2];
+        self.property(TAG_CLOCK_RATE, &[clock as u32], &mut out)?;
+        Some(out[1])
+    }
+}
+
@@ -0,0 +1,268 @@
+//! An in-memory stand-in for the mini UART, for testing on the host.
+//!
+//! `mock::MiniUart` has the same interface as `uart::MiniUart`, so code that
+//! uses the UART can swap it in under `#[cfg(test)]`:
+//!
+//! ```rust,ignore
+//! #[cfg(not(test))]
+//! use pi::uart::MiniUart;
+//! #[cfg(test)]
+//! use pi::mock::MiniUart;
+//! ```
+//!
+//! Instead of talking to the hardware, a mock UART reads bytes scripted with
+//! `MockHandle::push_input` and records every byte written, for the test to
+//! inspect with `MockHandle::take_output`. Reads block like the hardware
+//! does: until input is pushed (possibly from another thread) or the read
+//! timeout expires.
+
+extern crate std;
+
+use core::fmt;
+use core::time::Duration;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+use std::vec::Vec;
+
+use shim::io;
+
+use crate::uart::{loopback, LoopbackReport};
+
+/// The state shared by a mock UART and its handles.
+#[derive(Default)]
+struct Channels {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Shared {
+    channels: Mutex<Channels>,
+    input_ready: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Channels> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A mock "mini UART" backed by in-memory buffers.
+pub struct MiniUart {
+    shared: Arc<Shared>,
+    timeout: Option<Duration>,
+}
+
+impl MiniUart {
+    /// Creates a mock UART with no scripted input. By default, reads never
+    /// time out.
+    pub fn new() -> MiniUart {
+        MiniUart { shared: Arc::new(Shared::default()), timeout: None }
+    }
+
+    /// Returns a handle that scripts this UART's input and captures its
+    /// output.
+    pub fn handle(&self) -> MockHandle {
+        MockHandle { shared: self.shared.clone() }
+    }
+
+    /// Set the read timeout to `t` duration.
+    pub fn set_read_timeout(&mut self, t: Duration) {
+        self.timeout = Some(t);
+    }
+
+    /// Write the byte `byte`.
+    pub fn write_byte(&mut self, byte: u8) {
+        self.shared.lock().output.push(byte);
+    }
+
+    /// Returns `true` if there is at least one byte ready to be read.
+    pub fn has_byte(&self) -> bool {
+        !self.shared.lock().input.is_empty()
+    }
+
+    /// Blocks until there is a byte ready to read or the read timeout, if
+    /// any, expires. Returns `Err(())` on timeout.
+    pub fn wait_for_byte(&self) -> Result<(), ()> {
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        let mut channels = self.shared.lock();
+        while channels.input.is_empty() {
+            channels = match deadline {
+                None => self.shared.input_ready.wait(channels).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(());
+                    }
+
+                    self.shared
+                        .input_ready
+                        .wait_timeout(channels, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Runs `uart::MiniUart::loopback_test` as if TX were jumpered to RX:
+    /// every byte written comes straight back, unless the test has pushed
+    /// input to simulate noise on the line. Written bytes are recorded.
+    pub fn loopback_test(&mut self, len: usize, seed: u32) -> LoopbackReport {
+        loopback(len, seed, |byte| {
+            let mut channels = self.shared.lock();
+            channels.output.push(byte);
+            Some(channels.input.pop_front().unwrap_or(byte))
+        })
+    }
+
+    /// Reads a byte. Blocks indefinitely until a byte is ready to be read.
+    pub fn read_byte(&mut self) -> u8 {
+        let mut channels = self.shared.lock();
+        loop {
+            if let Some(byte) = channels.input.pop_front() {
+                return byte;
+            }
+
+            channels = self.shared.input_ready.wait(channels).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl fmt::Write for MiniUart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+impl io::Read for MiniUart {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.wait_for_byte().is_err() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "mock UART read timed out"));
+        }
+
+        let mut channels = self.shared.lock();
+        let mut read = 0;
+        while read < buf.len() {
+            match channels.input.pop_front() {
+                Some(byte) => buf[read] = byte,
+                None => break,
+            }
+            read += 1;
+        }
+
+        Ok(read)
+    }
+}
+
+impl io::Write for MiniUart {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.shared.lock().output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The test's end of a mock UART.
+#[derive(Clone)]
+pub struct MockHandle {
+    shared: Arc<Shared>,
+}
+
+impl MockHandle {
+    /// Appends `bytes` to the input the UART will read, waking any blocked
+    /// reader.
+    pub fn push_input(&self, bytes: &[u8]) {
+        self.shared.lock().input.extend(bytes);
+        self.shared.input_ready.notify_all();
+    }
+
+    /// Returns the number of scripted input bytes not read yet.
+    pub fn pending_input(&self) -> usize {
+        self.shared.lock().input.len()
+    }
+
+    /// Removes and returns everything written to the UART so far.
+    pub fn take_output(&self) -> Vec<u8> {
+        core::mem::replace(&mut self.shared.lock().output, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MiniUart;
+    use core::fmt::Write as FmtWrite;
+    use core::time::Duration;
+    use shim::io::{Read, Write};
+
+    #[test]
+    fn scripted_input_and_captured_output() {
+        let mut uart = MiniUart::new();
+        let handle = uart.handle();
+
+        handle.push_input(b"hi");
+        assert!(uart.has_byte());
+        assert_eq!(uart.read_byte(), b'h');
+
+        let mut buf = [0; 8];
+        assert_eq!(uart.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'i');
+        assert_eq!(handle.pending_input(), 0);
+
+        uart.write_all(b"raw\n").unwrap();
+        uart.write_str("fmt\n").unwrap();
+        assert_eq!(handle.take_output(), b"raw\nfmt\r\n".to_vec());
+        assert!(handle.take_output().is_empty());
+    }
+
+    #[test]
+    fn read_times_out_without_input() {
+        let mut uart = MiniUart::new();
+        uart.set_read_timeout(Duration::from_millis(10));
+        assert!(uart.wait_for_byte().is_err());
+        assert!(uart.read(&mut [0; 1]).is_err());
+    }
+
+    #[test]
+    fn loopback_counts_corrupted_bytes() {
+        let mut uart = MiniUart::new();
+        let report = uart.loopback_test(64, 1);
+        assert!(report.passed());
+        assert_eq!(uart.handle().take_output().len(), 64);
+
+        // noise on the line replaces the first two echoes
+        uart.handle().push_input(&[0, 0]);
+        let report = uart.loopback_test(64, 1);
+        assert_eq!(report.sent, 64);
+        assert!(report.corrupted >= 1 && report.matched + report.corrupted == 64);
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn read_wakes_on_input_from_another_thread() {
+        let mut uart = MiniUart::new();
+        let handle = uart.handle();
+        let writer = super::std::thread::spawn(move || handle.push_input(b"x"));
+        assert_eq!(uart.read_byte(), b'x');
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile, WriteVolatile, Reserved};
+
+use crate::common::IO_BASE;
+use crate::timer;
+use crate::uart::{loopback, LoopbackReport, LOOPBACK_TIMEOUT};
+
+/// The base address of the PL011 UART registers.
+const PL011_REG_BASE: usize = IO_BASE + 0x201000;
+
+/// The UART reference clock set by the firmware (`init_uart_clock`).
+const UART_CLOCK_HZ: u32 = 48_000_000;
+
+/// The baud rate used by `Pl011::new()`.
+const BAUD_RATE: u32 = 115_200;
+
+/// `FR` register bits.
+const FR_RXFE: u32 = 1 << 4;
+const FR_TXFF: u32 = 1 << 5;
+const FR_BUSY: u32 = 1 << 3;
+
+/// `LCRH` register bits: 8-bit words, FIFOs enabled.
+const LCRH_WLEN_8: u32 = 0b11 << 5;
+const LCRH_FEN: u32 = 1 << 4;
+
+/// `CR` register bits.
+const CR_UARTEN: u32 = 1 << 0;
+const CR_LBE: u32 = 1 << 7;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    DR: Volatile<u32>,
+    RSRECR: Volatile<u32>,
+    __r0: [Reserved<u32>; 4],
+    FR: ReadVolatile<u32>,
+    __r1: Reserved<u32>,
+    ILPR: Volatile<u32>,
+    IBRD: Volatile<u32>,
+    FBRD: Volatile<u32>,
+    LCRH: Volatile<u32>,
+    CR: Volatile<u32>,
+    IFLS: Volatile<u32>,
+    IMSC: Volatile<u32>,
+    RIS: ReadVolatile<u32>,
+    MIS: ReadVolatile<u32>,
+    ICR: WriteVolatile<u32>,
+}
+
+/// The Raspberry Pi's PL011 UART.
+///
+/// On the Raspberry Pi 3 the PL011 is wired to the Bluetooth module unless
+/// `config.txt` routes it to the GPIO header (for example with
+/// `dtoverlay=disable-bt`, which moves it to GPIO 14/15). Under QEMU it is the
+/// first serial port. This driver doesn't change any pin functions.
+pub struct Pl011 {
+    registers: &'static mut Registers,
+}
+
+impl Pl011 {
+    /// Initializes the PL011 for 8N1 at 115200 baud with its FIFOs enabled.
+    pub fn new() -> Pl011 {
+        let registers = unsafe { &mut *(PL011_REG_BASE as *mut Registers) };
+
+        registers.CR.write(0);
+        while registers.FR.has_mask(FR_BUSY) {}
+        registers.ICR.write(0x7FF);
+
+        // divisor = clock / (16 * baud), with a 6-bit fractional part
+        let divisor = (UART_CLOCK_HZ * 4 + BAUD_RATE / 2) / BAUD_RATE;
+        registers.IBRD.write(divisor >> 6);
+        registers.FBRD.write(divisor & 0x3F);
+        registers.LCRH.write(LCRH_WLEN_8 | LCRH_FEN);
+        registers.IMSC.write(0);
+        registers.CR.write(CR_UARTEN | CR_TXE | CR_RXE);
+
+        Pl011 { registers }
+    }
+
+    /// Returns `true` if there is at least one byte ready to be read.
+    pub fn has_byte(&self) -> bool {
+        !self.registers.FR.has_mask(FR_RXFE)
+    }
+
+    /// Reads a byte, blocking until one is available.
+    pub fn read_byte(&mut self) -> u8 {
+        while !self.has_byte() {}
+        self.registers.DR.read() as u8
+    }
+
+    /// Writes the byte `byte`, blocking until there is space in the FIFO.
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.registers.FR.has_mask(FR_TXFF) {}
+        self.registers.DR.write(byte as u32);
+    }
+
+    /// Enables or disables internal loopback, which feeds the transmitter
+    /// straight into the receiver instead of the pins.
+    pub fn set_loopback(&mut self, enabled: bool) {
+        if enabled {
+            self.registers.CR.or_mask(CR_LBE);
+        } else {
+            self.registers.CR.and_mask(!CR_LBE);
+        }
+    }
+
+    /// Sends `len` pseudo-random bytes generated from `seed` in internal
+    /// loopback mode and checks that each one is received back. Errors mean
+    /// the UART itself is misconfigured, since the pins aren't involved.
+    /// Pending input is discarded and loopback is disabled afterwards.
+    pub fn loopback_test(&mut self, len: usize, seed: u32) -> LoopbackReport {
+        while self.has_byte() {
+            self.read_byte();
+        }
+
+        self.set_loopback(true);
+        let report = loopback(len, seed, |byte| {
+            self.write_byte(byte);
+            let start = timer::current_time();
+            while !self.has_byte() {
+                if timer::current_time() - start >= LOOPBACK_TIMEOUT {
+                    return None;
+                }
+            }
+
+            Some(self.read_byte())
+        });
+
+        while self.registers.FR.has_mask(FR_BUSY) {}
+        self.set_loopback(false);
+        report
+    }
+}
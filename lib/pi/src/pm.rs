@@ -0,0 +1,81 @@
+use core::time::Duration;
+
+use crate::common::IO_BASE;
+
+use volatile::prelude::*;
+use volatile::{Volatile, Reserved};
+
+/// The base address of the power management (`PM`) registers.
+const PM_REG_BASE: usize = IO_BASE + 0x100000;
+
+/// Every write to a `PM` register must carry this value in its top byte.
+const PM_PASSWORD: u32 = 0x5a00_0000;
+
+/// `RSTC` configuration field and its "full reset" value.
+const PM_RSTC_WRCFG_CLR: u32 = 0xffff_ffcf;
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x0000_0020;
+const PM_RSTC_RESET: u32 = 0x0000_0102;
+
+/// The watchdog counts down in ticks of 1/65536 seconds in a 20-bit field.
+const PM_WDOG_TICKS_PER_SEC: u64 = 1 << 16;
+const PM_WDOG_TIME_SET: u32 = 0x000f_ffff;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    __r0: [Reserved<u32>; 7],
+    RSTC: Volatile<u32>,
+    RSTS: Volatile<u32>,
+    WDOG: Volatile<u32>,
+}
+
+/// The Raspberry Pi's hardware watchdog, part of the power management block.
+///
+/// Once started, the watchdog resets the board when its timeout expires unless
+/// it is restarted (fed) or stopped first.
+pub struct Watchdog {
+    registers: &'static mut Registers,
+}
+
+impl Watchdog {
+    /// Returns a new instance of `Watchdog`.
+    pub fn new() -> Watchdog {
+        Watchdog {
+            registers: unsafe { &mut *(PM_REG_BASE as *mut Registers) },
+        }
+    }
+
+    /// Returns the longest timeout the watchdog supports (just under 16s).
+    pub fn max_timeout() -> Duration {
+        Duration::from_micros(PM_WDOG_TIME_SET as u64 * 1_000_000 / PM_WDOG_TICKS_PER_SEC)
+    }
+
+    /// Arms the watchdog to reset the board after `timeout`, restarting the
+    /// countdown if it is already running. Timeouts longer than
+    /// `max_timeout()` are clamped.
+    pub fn start(&mut self, timeout: Duration) {
+        let ticks = timeout.as_micros() as u64 * PM_WDOG_TICKS_PER_SEC / 1_000_000;
+        let ticks = core::cmp::min(ticks, PM_WDOG_TIME_SET as u64) as u32;
+
+        let rstc = self.registers.RSTC.read() & PM_RSTC_WRCFG_CLR;
+        self.registers.WDOG.write(PM_PASSWORD | ticks);
+        self.registers.RSTC.write(PM_PASSWORD | rstc | PM_RSTC_WRCFG_FULL_RESET);
+    }
+
+    /// Disarms the watchdog.
+    pub fn stop(&mut self) {
+        self.registers.RSTC.write(PM_PASSWORD | PM_RSTC_RESET);
+    }
+
+    /// Returns the time left before the watchdog resets the board.
+    pub fn remaining(&self) -> Duration {
+        let ticks = (self.registers.WDOG.read() & PM_WDOG_TIME_SET) as u64;
+        Duration::from_micros(ticks * 1_000_000 / PM_WDOG_TICKS_PER_SEC)
+    }
+
+    /// Resets the board as soon as possible.
+    pub fn reset(&mut self) -> ! {
+        self.start(Duration::from_micros(150));
+        loop {}
+    }
+}
@@ -0,0 +1,69 @@
+use volatile::prelude::*;
+use volatile::Volatile;
+
+use crate::common::IO_BASE;
+
+/// The base address of the hardware random number generator registers.
+const RNG_REG_BASE: usize = IO_BASE + 0x104000;
+
+/// `CTRL` enable bit.
+const RNG_CTRL_EN: u32 = 1;
+
+/// Initial number of values discarded while the generator warms up.
+const RNG_WARMUP_COUNT: u32 = 0x40000;
+
+/// `INT_MASK` bit that disables the "data ready" interrupt.
+const RNG_INT_OFF: u32 = 1;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    CTRL: Volatile<u32>,
+    STATUS: Volatile<u32>,
+    DATA: Volatile<u32>,
+    FF_THRESHOLD: Volatile<u32>,
+    INT_MASK: Volatile<u32>,
+}
+
+/// The BCM2837 hardware random number generator.
+pub struct Rng {
+    registers: &'static mut Registers,
+}
+
+impl Rng {
+    /// Returns a new instance of `Rng`, enabling the generator if it isn't
+    /// running already.
+    pub fn new() -> Rng {
+        let registers = unsafe { &mut *(RNG_REG_BASE as *mut Registers) };
+        if !registers.CTRL.has_mask(RNG_CTRL_EN) {
+            registers.STATUS.write(RNG_WARMUP_COUNT);
+            registers.INT_MASK.or_mask(RNG_INT_OFF);
+            registers.CTRL.or_mask(RNG_CTRL_EN);
+        }
+
+        Rng { registers }
+    }
+
+    /// Returns the number of words waiting in the FIFO.
+    fn available(&self) -> u32 {
+        self.registers.STATUS.read() >> 24
+    }
+
+    /// Returns a random word, or `None` if none becomes available after
+    /// polling `tries` times.
+    pub fn try_read(&mut self, tries: usize) -> Option<u32> {
+        for _ in 0..tries {
+            if self.available() != 0 {
+                return Some(self.registers.DATA.read());
+            }
+        }
+
+        None
+    }
+
+    /// Returns a random word, blocking until one is available.
+    pub fn read(&mut self) -> u32 {
+        while self.available() == 0 {}
+        self.registers.DATA.read()
+    }
+}
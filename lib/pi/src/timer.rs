@@ -32,17 +32,27 @@ impl Timer {
     /// Reads the system timer's counter and returns Duration.
     /// `CLO` and `CHI` together can represent the number of elapsed microseconds.
     pub fn read(&self) -> Duration {
-        unimplemented!()
+        // `CLO` can wrap between the two reads; retry if `CHI` moved
+        let micros = loop {
+            let hi = self.registers.CHI.read();
+            let lo = self.registers.CLO.read();
+            if self.registers.CHI.read() == hi {
+                break (u64::from(hi) << 32) | u64::from(lo);
+            }
+        };
+
+        Duration::from_micros(micros)
     }
 }
 
 /// Returns current time.
 pub fn current_time() -> Duration {
-    unimplemented!()
+    Timer::new().read()
 }
 
 /// Spins until `t` duration have passed.
 pub fn spin_sleep(t: Duration) {
-    unimplemented!()
+    let deadline = current_time() + t;
+    while current_time() < deadline {}
 }
 
@@ -27,9 +27,31 @@ enum LsrStatus {
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
-    // FIXME: Declare the "MU" registers from page 8.
+    IO: Volatile<u8>,
+    __r0: [Reserved<u8>; 3],
+    IER: Volatile<u8>,
+    __r1: [Reserved<u8>; 3],
+    IIR: Volatile<u8>,
+    __r2: [Reserved<u8>; 3],
+    LCR: Volatile<u8>,
+    __r3: [Reserved<u8>; 3],
+    MCR: Volatile<u8>,
+    __r4: [Reserved<u8>; 3],
+    LSR: ReadVolatile<u8>,
+    __r5: [Reserved<u8>; 3],
+    MSR: ReadVolatile<u8>,
+    __r6: [Reserved<u8>; 3],
+    SCRATCH: Volatile<u8>,
+    __r7: [Reserved<u8>; 3],
+    CNTL: Volatile<u8>,
+    __r8: [Reserved<u8>; 3],
+    STAT: ReadVolatile<u32>,
+    BAUD: Volatile<u16>,
+    __r9: [Reserved<u8>; 2],
 }
 
+const_assert_size!(Registers, 0x7E21506C - 0x7E215040);
+
 /// The Raspberry Pi's "mini UART".
 pub struct MiniUart {
     registers: &'static mut Registers,
@@ -51,26 +73,33 @@ impl MiniUart {
             &mut *(MU_REG_BASE as *mut Registers)
         };
 
-        // FIXME: Implement remaining mini UART initialization.
-        unimplemented!()
+        Gpio::new(14).into_alt(Function::Alt5);
+        Gpio::new(15).into_alt(Function::Alt5);
+
+        registers.LCR.write(0b11);
+        registers.BAUD.write(270);
+        registers.CNTL.write(0b11);
+
+        MiniUart { registers, timeout: None }
     }
 
     /// Set the read timeout to `t` duration.
     pub fn set_read_timeout(&mut self, t: Duration) {
-        unimplemented!()
+        self.timeout = Some(t);
     }
 
     /// Write the byte `byte`. This method blocks until there is space available
     /// in the output FIFO.
     pub fn write_byte(&mut self, byte: u8) {
-        unimplemented!()
+        while !self.registers.LSR.has_mask(LsrStatus::TxAvailable as u8) {}
+        self.registers.IO.write(byte);
     }
 
     /// Returns `true` if there is at least one byte ready to be read. If this
     /// method returns `true`, a subsequent call to `read_byte` is guaranteed to
     /// return immediately. This method does not block.
     pub fn has_byte(&self) -> bool {
-        unimplemented!()
+        self.registers.LSR.has_mask(LsrStatus::DataReady as u8)
     }
 
     /// Blocks until there is a byte ready to read. If a read timeout is set,
@@ -82,30 +111,164 @@ impl MiniUart {
     /// returns `Ok(())`, a subsequent call to `read_byte` is guaranteed to
     /// return immediately.
     pub fn wait_for_byte(&self) -> Result<(), ()> {
-        unimplemented!()
+        let deadline = self.timeout.map(|t| timer::current_time() + t);
+        while !self.has_byte() {
+            match deadline {
+                Some(deadline) if timer::current_time() >= deadline => return Err(()),
+                _ => {}
+            }
+        }
+
+        Ok(())
     }
 
     /// Reads a byte. Blocks indefinitely until a byte is ready to be read.
     pub fn read_byte(&mut self) -> u8 {
-        unimplemented!()
+        while !self.has_byte() {}
+        self.registers.IO.read()
+    }
+}
+
+impl MiniUart {
+    /// Sends `len` pseudo-random bytes generated from `seed` at the current
+    /// baud rate and checks that each one is received back.
+    ///
+    /// The mini UART has no internal loopback mode, so TX (GPIO 14) must be
+    /// jumpered to RX (GPIO 15). A clean report means the UART and the pins
+    /// work; errors with a jumper in place point at the hardware, and errors
+    /// only once a cable or level shifter is added point at the wiring rather
+    /// than at the protocol running over it.
+    ///
+    /// Bytes already waiting to be read are discarded first. The read timeout
+    /// is restored afterwards.
+    pub fn loopback_test(&mut self, len: usize, seed: u32) -> LoopbackReport {
+        while self.has_byte() {
+            self.read_byte();
+        }
+
+        let timeout = self.timeout;
+        self.timeout = Some(LOOPBACK_TIMEOUT);
+        let report = loopback(len, seed, |byte| {
+            self.write_byte(byte);
+            match self.wait_for_byte() {
+                Ok(()) => Some(self.read_byte()),
+                Err(()) => None,
+            }
+        });
+
+        self.timeout = timeout;
+        report
+    }
+}
+
+/// How long a loopback test waits for each byte to come back.
+pub(crate) const LOOPBACK_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// The result of a loopback test.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LoopbackReport {
+    /// Bytes sent.
+    pub sent: usize,
+    /// Bytes received back with the value that was sent.
+    pub matched: usize,
+    /// Bytes received back with a different value.
+    pub corrupted: usize,
+    /// Bytes that never came back.
+    pub lost: usize,
+}
+
+impl LoopbackReport {
+    /// Returns `true` if every byte came back intact.
+    pub fn passed(&self) -> bool {
+        self.matched == self.sent
     }
 }
 
-// FIXME: Implement `fmt::Write` for `MiniUart`. A b'\r' byte should be written
-// before writing any b'\n' byte.
+impl fmt::Display for LoopbackReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} sent, {} ok, {} corrupted, {} lost",
+               self.sent, self.matched, self.corrupted, self.lost)
+    }
+}
+
+/// Runs a loopback test of `len` bytes: `transfer` sends a byte and returns
+/// the byte received in response, or `None` if none arrived in time. The
+/// bytes come from a xorshift generator seeded with `seed`, so every value
+/// and bit transition is exercised.
+pub(crate) fn loopback<F>(len: usize, seed: u32, mut transfer: F) -> LoopbackReport
+    where F: FnMut(u8) -> Option<u8>
+{
+    let mut state = if seed == 0 { 0x2545_F491 } else { seed };
+    let mut report = LoopbackReport::default();
+    for _ in 0..len {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+
+        let byte = state as u8;
+        report.sent += 1;
+        match transfer(byte) {
+            Some(received) if received == byte => report.matched += 1,
+            Some(_) => report.corrupted += 1,
+            None => report.lost += 1,
+        }
+    }
+
+    report
+}
+
+impl fmt::Write for MiniUart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
 
 mod uart_io {
     use super::io;
     use super::MiniUart;
-    use volatile::prelude::*;
-
-    // FIXME: Implement `io::Read` and `io::Write` for `MiniUart`.
-    //
-    // The `io::Read::read()` implementation must respect the read timeout by
-    // waiting at most that time for the _first byte_. It should not wait for
-    // any additional bytes but _should_ read as many bytes as possible. If the
-    // read times out, an error of kind `TimedOut` should be returned.
-    //
-    // The `io::Write::write()` method must write all of the requested bytes
-    // before returning.
+
+    /// Waits at most the read timeout for the first byte, then reads as many
+    /// bytes as are ready without waiting for more.
+    impl io::Read for MiniUart {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            if self.wait_for_byte().is_err() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "UART read timed out"));
+            }
+
+            let mut read = 0;
+            while read < buf.len() && self.has_byte() {
+                buf[read] = self.read_byte();
+                read += 1;
+            }
+
+            Ok(read)
+        }
+    }
+
+    /// Writes every byte before returning.
+    impl io::Write for MiniUart {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for &byte in buf {
+                self.write_byte(byte);
+            }
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,324 @@
+//! Boots the kernel under QEMU and drives it over the emulated serial port.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use qemu_test::Qemu;
+//!
+//! let mut qemu = Qemu::new(qemu_test::kernel_path()).spawn().unwrap();
+//! qemu.expect("> ", Duration::from_secs(10)).unwrap();
+//! let output = qemu.run("random 2", "> ", Duration::from_secs(5)).unwrap();
+//! assert_eq!(output.lines().count(), 3);
+//! ```
+//!
+//! The QEMU binary, machine and kernel image can be overridden with the
+//! `QEMU`, `QEMU_MACHINE` and `KERNEL` environment variables.
+
+use std::env;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The machine emulated when `QEMU_MACHINE` isn't set.
+pub const DEFAULT_MACHINE: &str = "raspi3b";
+
+/// Returns the repository's root directory.
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+/// Returns the kernel image to boot: `$KERNEL`, or the one `make build` in
+/// `kern/` produces.
+pub fn kernel_path() -> PathBuf {
+    env::var_os("KERNEL")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| root().join("kern/build/kernel.bin"))
+}
+
+/// Returns the QEMU binary to run: `$QEMU`, or the one in `bin/`.
+pub fn qemu_path() -> PathBuf {
+    env::var_os("QEMU")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| root().join("bin/qemu-system-aarch64"))
+}
+
+/// An error from a QEMU session.
+#[derive(Debug)]
+pub enum Error {
+    /// Starting QEMU or talking to it failed.
+    Io(io::Error),
+    /// The expected output didn't appear in time.
+    Timeout { expected: String, output: String },
+    /// QEMU exited before the expected output appeared.
+    Exited { status: ExitStatus, output: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Timeout { expected, output } => {
+                write!(f, "timed out waiting for {:?}; output so far:\n{}", expected, output)
+            }
+            Error::Exited { status, output } => {
+                write!(f, "QEMU exited ({}); output:\n{}", status, output)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The configuration of a QEMU run.
+#[derive(Debug, Clone)]
+pub struct Qemu {
+    qemu: PathBuf,
+    machine: String,
+    kernel: PathBuf,
+    cmdline: Option<String>,
+}
+
+impl Qemu {
+    /// Returns a configuration that boots `kernel` with the default QEMU
+    /// binary and machine.
+    pub fn new<P: Into<PathBuf>>(kernel: P) -> Qemu {
+        Qemu {
+            qemu: qemu_path(),
+            machine: env::var("QEMU_MACHINE").unwrap_or_else(|_| DEFAULT_MACHINE.to_string()),
+            kernel: kernel.into(),
+            cmdline: None,
+        }
+    }
+
+    /// Sets the QEMU binary to run.
+    pub fn qemu<P: Into<PathBuf>>(mut self, qemu: P) -> Qemu {
+        self.qemu = qemu.into();
+        self
+    }
+
+    /// Sets the machine to emulate.
+    pub fn machine(mut self, machine: &str) -> Qemu {
+        self.machine = machine.to_string();
+        self
+    }
+
+    /// Sets the kernel command line.
+    pub fn cmdline(mut self, cmdline: &str) -> Qemu {
+        self.cmdline = Some(cmdline.to_string());
+        self
+    }
+
+    /// Starts QEMU. The mini UART, QEMU's second serial port, is connected to
+    /// the returned session; the first is discarded.
+    pub fn spawn(&self) -> Result<Session> {
+        let mut cmd = Command::new(&self.qemu);
+        cmd.arg("-M").arg(&self.machine)
+            .arg("-display").arg("none")
+            .arg("-monitor").arg("none")
+            .arg("-semihosting")
+            .arg("-serial").arg("null")
+            .arg("-serial").arg("stdio")
+            .arg("-kernel").arg(&self.kernel)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+
+        if let Some(cmdline) = &self.cmdline {
+            cmd.arg("-append").arg(cmdline);
+        }
+
+        let mut child = cmd.spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+
+        let output = Arc::new(Output::default());
+        let reader = output.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            loop {
+                let n = match stdout.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+
+                reader.buffer.lock().unwrap().bytes.extend_from_slice(&buf[..n]);
+                reader.changed.notify_all();
+            }
+
+            reader.buffer.lock().unwrap().closed = true;
+            reader.changed.notify_all();
+        });
+
+        Ok(Session { child, stdin, output, cursor: 0 })
+    }
+}
+
+/// Everything QEMU has written to the serial port, filled in by a reader
+/// thread.
+#[derive(Default)]
+struct Output {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Buffer {
+    bytes: Vec<u8>,
+    /// Whether QEMU closed its end of the serial port.
+    closed: bool,
+}
+
+/// A running QEMU instance. QEMU is killed when the session is dropped.
+pub struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    output: Arc<Output>,
+    /// How much of the output previous `expect` calls have consumed.
+    cursor: usize,
+}
+
+impl Session {
+    /// Returns all output received so far, with `\r` removed.
+    pub fn output(&self) -> String {
+        clean(&self.output.buffer.lock().unwrap().bytes)
+    }
+
+    /// Waits up to `timeout` for `pattern` to appear in output that hasn't
+    /// been consumed yet. Returns the output up to and including `pattern`
+    /// and consumes it.
+    pub fn expect(&mut self, pattern: &str, timeout: Duration) -> Result<String> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = self.output.buffer.lock().unwrap();
+        loop {
+            let pending = strip_cr(&buffer.bytes[self.cursor..]);
+            if let Some(i) = find(&pending, pattern.as_bytes()) {
+                let end = i + pattern.len();
+                self.cursor += raw_len(&buffer.bytes[self.cursor..], end);
+                return Ok(String::from_utf8_lossy(&pending[..end]).into_owned());
+            }
+
+            if buffer.closed {
+                drop(buffer);
+                let status = self.child.wait()?;
+                return Err(Error::Exited { status, output: self.output() });
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                drop(buffer);
+                return Err(Error::Timeout { expected: pattern.to_string(), output: self.output() });
+            }
+
+            buffer = self.output.changed.wait_timeout(buffer, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Writes `bytes` to the serial port.
+    pub fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stdin.write_all(bytes)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Types `line` followed by a carriage return, as a terminal would.
+    pub fn send_line(&mut self, line: &str) -> Result<()> {
+        self.send(line.as_bytes())?;
+        self.send(b"\r")
+    }
+
+    /// Runs the shell command `command` and waits for the next `prompt`.
+    /// Returns the command's output, without the echoed command line and the
+    /// prompt.
+    pub fn run(&mut self, command: &str, prompt: &str, timeout: Duration) -> Result<String> {
+        self.send_line(command)?;
+        let output = self.expect(prompt, timeout)?;
+        let output = &output[..output.len() - prompt.len()];
+        let output = match output.find('\n') {
+            Some(i) if output[..i].trim_end() == command => &output[i + 1..],
+            _ => output,
+        };
+
+        Ok(output.to_string())
+    }
+
+    /// Waits up to `timeout` for QEMU to exit, returning its exit status. With
+    /// semihosting, `qemu::exit(n)` in the kernel exits with status `n`.
+    pub fn wait(&mut self, timeout: Duration) -> Result<ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Ok(status);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout { expected: "QEMU to exit".to_string(), output: self.output() });
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Converts serial output to a string with `\r` removed.
+fn clean(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(&strip_cr(bytes)).into_owned()
+}
+
+fn strip_cr(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().cloned().filter(|&b| b != b'\r').collect()
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Returns the length of the prefix of `bytes` that `strip_cr` turns into
+/// `stripped_len` bytes.
+fn raw_len(bytes: &[u8], stripped_len: usize) -> usize {
+    let mut stripped = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        if stripped == stripped_len {
+            return i;
+        }
+        if b != b'\r' {
+            stripped += 1;
+        }
+    }
+
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, raw_len, strip_cr};
+
+    #[test]
+    fn raw_len_skips_carriage_returns() {
+        let raw = b"a\r\nb\r\n> x";
+        let stripped = strip_cr(raw);
+        assert_eq!(stripped, b"a\nb\n> x");
+
+        let end = find(&stripped, b"> ").unwrap() + 2;
+        assert_eq!(&raw[..raw_len(raw, end)], b"a\r\nb\r\n> ");
+        assert_eq!(raw_len(raw, stripped.len()), raw.len());
+    }
+}
@@ -0,0 +1,72 @@
+//! End-to-end tests of the kernel under QEMU.
+//!
+//! These need QEMU and a kernel built with `make build` in `kern/`, so they're
+//! ignored by default. Run them with `make qemu-test` in `kern/`, or with
+//! `cargo test -- --ignored` here.
+
+use std::time::Duration;
+
+use qemu_test::{kernel_path, Qemu, Session};
+
+const PROMPT: &str = "> ";
+const BOOT_TIMEOUT: Duration = Duration::from_secs(20);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn boot(cmdline: &str) -> Session {
+    Qemu::new(kernel_path())
+        .cmdline(cmdline)
+        .spawn()
+        .expect("failed to start QEMU")
+}
+
+fn boot_to_shell() -> Session {
+    let mut qemu = boot("");
+    qemu.expect(PROMPT, BOOT_TIMEOUT).unwrap();
+    qemu
+}
+
+#[test]
+#[ignore]
+fn prints_banner() {
+    let mut qemu = boot("shell=off");
+    qemu.expect("oxidation kernel v", BOOT_TIMEOUT).unwrap();
+    qemu.expect("board:", COMMAND_TIMEOUT).unwrap();
+}
+
+#[test]
+#[ignore]
+fn selftests_pass() {
+    let mut qemu = boot("selftest=on shell=off");
+    let status = qemu.wait(BOOT_TIMEOUT).unwrap();
+    assert!(status.success(), "self-tests failed:\n{}", qemu.output());
+}
+
+#[test]
+#[ignore]
+fn shell_rejects_unknown_commands() {
+    let mut qemu = boot_to_shell();
+    let output = qemu.run("frobnicate", PROMPT, COMMAND_TIMEOUT).unwrap();
+    assert!(output.contains("unknown command: frobnicate"), "{}", output);
+}
+
+#[test]
+#[ignore]
+fn shell_random() {
+    let mut qemu = boot_to_shell();
+    let output = qemu.run("random 3", PROMPT, COMMAND_TIMEOUT).unwrap();
+    let values: Vec<&str> = output.lines().filter(|l| !l.is_empty()).collect();
+    assert_eq!(values.len(), 3, "{}", output);
+    assert!(values.iter().all(|v| v.len() == 16 && u64::from_str_radix(v, 16).is_ok()));
+
+    let output = qemu.run("random x", PROMPT, COMMAND_TIMEOUT).unwrap();
+    assert!(output.contains("usage: random [count]"), "{}", output);
+}
+
+#[test]
+#[ignore]
+fn shell_date() {
+    let mut qemu = boot_to_shell();
+    qemu.run("date 2020-02-29T12:34:56", PROMPT, COMMAND_TIMEOUT).unwrap();
+    let output = qemu.run("date", PROMPT, COMMAND_TIMEOUT).unwrap();
+    assert!(output.contains("2020-02-29 12:34:5"), "{}", output);
+}
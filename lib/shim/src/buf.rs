@@ -0,0 +1,233 @@
+//! Buffered readers and writers that don't need an allocator.
+//!
+//! These work like `std::io::BufReader` and `std::io::BufWriter`, but keep
+//! their buffer inline instead of on the heap, so they're available in
+//! `no_std` builds. Each holds `BUF_SIZE` bytes: one SD card sector, or four
+//! XMODEM packets.
+
+use core::cmp;
+use core::fmt;
+
+use crate::io;
+
+/// The size of the buffer inside a `BufReader` or `BufWriter`.
+pub const BUF_SIZE: usize = 512;
+
+/// Adds buffering to a reader, so that many small reads turn into a few
+/// `BUF_SIZE` reads of the inner reader.
+pub struct BufReader<R> {
+    inner: R,
+    buf: [u8; BUF_SIZE],
+    /// The next unread byte in `buf`.
+    pos: usize,
+    /// The end of the valid data in `buf`.
+    filled: usize,
+}
+
+impl<R: io::Read> BufReader<R> {
+    /// Returns a buffered reader wrapping `inner`.
+    pub fn new(inner: R) -> BufReader<R> {
+        BufReader { inner, buf: [0; BUF_SIZE], pos: 0, filled: 0 }
+    }
+}
+
+impl<R> BufReader<R> {
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader. Reading from it
+    /// directly skips any buffered data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the buffered data that hasn't been read yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Returns the inner reader. Any buffered data is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn discard_buffer(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+}
+
+impl<R: io::Read> io::Read for BufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // skip the copy if the caller's buffer is at least as big as ours
+        if self.pos == self.filled && buf.len() >= BUF_SIZE {
+            self.discard_buffer();
+            return self.inner.read(buf);
+        }
+
+        let n = {
+            let mut available = io::BufRead::fill_buf(self)?;
+            io::Read::read(&mut available, buf)?
+        };
+        io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+impl<R: io::Read> io::BufRead for BufReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.filled {
+            self.filled = self.inner.read(&mut self.buf)?;
+            self.pos = 0;
+        }
+
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = cmp::min(self.pos + amt, self.filled);
+    }
+}
+
+impl<R: io::Seek> io::Seek for BufReader<R> {
+    /// Seeks the inner reader, discarding the buffer. `SeekFrom::Current` is
+    /// relative to the next byte `read` would return.
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let result = match pos {
+            io::SeekFrom::Current(n) => {
+                let remaining = (self.filled - self.pos) as i64;
+                self.inner.seek(io::SeekFrom::Current(n - remaining))?
+            }
+            pos => self.inner.seek(pos)?,
+        };
+
+        self.discard_buffer();
+        Ok(result)
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for BufReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufReader")
+            .field("inner", &self.inner)
+            .field("buffered", &(self.filled - self.pos))
+            .finish()
+    }
+}
+
+/// Adds buffering to a writer, so that many small writes turn into a few
+/// `BUF_SIZE` writes of the inner writer.
+///
+/// Buffered data is written out when the buffer fills, on `flush`, and when
+/// the `BufWriter` is dropped; errors when dropping are ignored, so call
+/// `flush` or `into_inner` to see them.
+pub struct BufWriter<W: io::Write> {
+    /// Always `Some` except during `into_inner`.
+    inner: Option<W>,
+    buf: [u8; BUF_SIZE],
+    len: usize,
+}
+
+impl<W: io::Write> BufWriter<W> {
+    /// Returns a buffered writer wrapping `inner`.
+    pub fn new(inner: W) -> BufWriter<W> {
+        BufWriter { inner: Some(inner), buf: [0; BUF_SIZE], len: 0 }
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Returns a mutable reference to the inner writer. Writing to it
+    /// directly skips ahead of any buffered data.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Returns the data that hasn't been written out yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Writes out the buffered data and returns the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// If writing out the buffer fails, returns the error and this
+    /// `BufWriter`, still holding the data that wasn't written.
+    pub fn into_inner(mut self) -> Result<W, (io::Error, BufWriter<W>)> {
+        match self.flush_buf() {
+            Ok(()) => Ok(self.inner.take().unwrap()),
+            Err(e) => Err((e, self)),
+        }
+    }
+
+    /// Writes the buffered data to the inner writer.
+    fn flush_buf(&mut self) -> io::Result<()> {
+        let inner = self.inner.as_mut().unwrap();
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < self.len {
+            match inner.write(&self.buf[written..self.len]) {
+                Ok(0) => {
+                    result = Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write buffered data"));
+                    break;
+                }
+                Ok(n) => written += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        // keep whatever couldn't be written for the next attempt
+        for i in written..self.len {
+            self.buf[i - written] = self.buf[i];
+        }
+        self.len -= written;
+        result
+    }
+}
+
+impl<W: io::Write> io::Write for BufWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len + buf.len() > BUF_SIZE {
+            self.flush_buf()?;
+        }
+
+        if buf.len() >= BUF_SIZE {
+            self.get_mut().write(buf)
+        } else {
+            self.buf[self.len..self.len + buf.len()].copy_from_slice(buf);
+            self.len += buf.len();
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buf()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: io::Write> Drop for BufWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.flush_buf();
+        }
+    }
+}
+
+impl<W: io::Write + fmt::Debug> fmt::Debug for BufWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufWriter")
+            .field("inner", self.get_ref())
+            .field("buffered", &self.len)
+            .finish()
+    }
+}
@@ -0,0 +1,260 @@
+//! Structured errors that behave the same in `std` and `no_std` builds.
+//!
+//! `io::Error` only carries an `ErrorKind` and, without `alloc`, a static
+//! message, and its constructor differs between `std` and `core_io`. Crates
+//! that want to report more than that (which packet failed, which lower-level
+//! error caused it) build a `shim::error::Error` instead and convert it into an
+//! `io::Error` at the `Read`/`Write` boundary:
+//!
+//! ```rust,ignore
+//! return Err(Error::new(ErrorKind::ChecksumMismatch, "bad packet checksum")
+//!     .with_payload(packet as u64)
+//!     .into());
+//! ```
+//!
+//! With `std` the structured error is kept inside the `io::Error` and can be
+//! recovered with `Error::from_io`; with `no_std` only its kind and message
+//! survive the conversion.
+
+use core::fmt;
+use core::num::ParseIntError;
+use core::str::Utf8Error;
+
+use crate::io;
+
+/// The kind of an `Error`: the `io::ErrorKind`s shared by `std` and
+/// `core_io`, plus finer-grained kinds for drivers and protocols.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    WouldBlock,
+    InvalidInput,
+    InvalidData,
+    TimedOut,
+    WriteZero,
+    Interrupted,
+    UnexpectedEof,
+    BrokenPipe,
+    ConnectionAborted,
+    Other,
+    /// Data failed a checksum or CRC check.
+    ChecksumMismatch,
+    /// The peer sent something the protocol doesn't allow at this point.
+    ProtocolViolation,
+    /// The operation was cancelled by either side.
+    Cancelled,
+    /// A caller-provided buffer can't hold the result.
+    BufferTooSmall,
+    /// The operation isn't supported by this device or implementation.
+    Unsupported,
+    /// The device is busy and the operation should be retried later.
+    DeviceBusy,
+    /// The device isn't present or didn't respond.
+    NoDevice,
+    /// There is no space left on the device.
+    NoSpace,
+    /// On-disk or in-memory structures are inconsistent.
+    Corrupted,
+}
+
+impl ErrorKind {
+    /// Returns the closest `io::ErrorKind`.
+    pub fn io_kind(self) -> io::ErrorKind {
+        use self::ErrorKind::*;
+
+        match self {
+            NotFound | NoDevice => io::ErrorKind::NotFound,
+            PermissionDenied => io::ErrorKind::PermissionDenied,
+            AlreadyExists => io::ErrorKind::AlreadyExists,
+            WouldBlock | DeviceBusy => io::ErrorKind::WouldBlock,
+            InvalidInput | BufferTooSmall => io::ErrorKind::InvalidInput,
+            InvalidData | ChecksumMismatch | ProtocolViolation | Corrupted => io::ErrorKind::InvalidData,
+            TimedOut => io::ErrorKind::TimedOut,
+            WriteZero | NoSpace => io::ErrorKind::WriteZero,
+            Interrupted => io::ErrorKind::Interrupted,
+            UnexpectedEof => io::ErrorKind::UnexpectedEof,
+            BrokenPipe => io::ErrorKind::BrokenPipe,
+            ConnectionAborted | Cancelled => io::ErrorKind::ConnectionAborted,
+            Other | Unsupported => io::ErrorKind::Other,
+        }
+    }
+
+    /// Returns a short description of this kind.
+    pub fn as_str(self) -> &'static str {
+        use self::ErrorKind::*;
+
+        match self {
+            NotFound => "entity not found",
+            PermissionDenied => "permission denied",
+            AlreadyExists => "entity already exists",
+            WouldBlock => "operation would block",
+            InvalidInput => "invalid input parameter",
+            InvalidData => "invalid data",
+            TimedOut => "timed out",
+            WriteZero => "write zero",
+            Interrupted => "operation interrupted",
+            UnexpectedEof => "unexpected end of file",
+            BrokenPipe => "broken pipe",
+            ConnectionAborted => "connection aborted",
+            Other => "other error",
+            ChecksumMismatch => "checksum mismatch",
+            ProtocolViolation => "protocol violation",
+            Cancelled => "operation cancelled",
+            BufferTooSmall => "buffer too small",
+            Unsupported => "unsupported operation",
+            DeviceBusy => "device busy",
+            NoDevice => "no such device",
+            NoSpace => "no space left on device",
+            Corrupted => "data structures corrupted",
+        }
+    }
+}
+
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(kind: io::ErrorKind) -> ErrorKind {
+        match kind {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            io::ErrorKind::WouldBlock => ErrorKind::WouldBlock,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+            io::ErrorKind::InvalidData => ErrorKind::InvalidData,
+            io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            io::ErrorKind::WriteZero => ErrorKind::WriteZero,
+            io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            io::ErrorKind::UnexpectedEof => ErrorKind::UnexpectedEof,
+            io::ErrorKind::BrokenPipe => ErrorKind::BrokenPipe,
+            io::ErrorKind::ConnectionAborted => ErrorKind::ConnectionAborted,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// A structured error: a kind, a static message, an optional numeric payload
+/// (a packet number, register value, sector...) and the kind of the
+/// lower-level I/O error that caused it, if any.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: &'static str,
+    payload: Option<u64>,
+    source: Option<io::ErrorKind>,
+}
+
+impl Error {
+    /// Creates an error of kind `kind` with message `message`.
+    pub const fn new(kind: ErrorKind, message: &'static str) -> Error {
+        Error { kind, message, payload: None, source: None }
+    }
+
+    /// Attaches a numeric payload to this error.
+    pub const fn with_payload(self, payload: u64) -> Error {
+        Error { payload: Some(payload), ..self }
+    }
+
+    /// Records the kind of the I/O error that caused this one.
+    pub const fn with_source(self, source: io::ErrorKind) -> Error {
+        Error { source: Some(source), ..self }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &'static str {
+        self.message
+    }
+
+    pub fn payload(&self) -> Option<u64> {
+        self.payload
+    }
+
+    pub fn source_kind(&self) -> Option<io::ErrorKind> {
+        self.source
+    }
+
+    /// Recovers the structured error from an `io::Error` created from one.
+    /// Always `None` in `no_std` builds, where only the kind and message are
+    /// kept.
+    pub fn from_io(err: &io::Error) -> Option<Error> {
+        #[cfg(not(feature = "no_std"))]
+        {
+            err.get_ref().and_then(|e| e.downcast_ref::<Error>()).cloned()
+        }
+
+        #[cfg(feature = "no_std")]
+        {
+            let _ = err;
+            None
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)?;
+        if let Some(payload) = self.payload {
+            write!(f, " ({:#x})", payload)?;
+        }
+
+        if let Some(source) = self.source {
+            write!(f, ", caused by: {:?}", source)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for Error {}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind, kind.as_str())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        if let Some(err) = Error::from_io(&err) {
+            return err;
+        }
+
+        let kind = err.kind();
+        Error::new(ErrorKind::from(kind), "I/O error").with_source(kind)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        #[cfg(not(feature = "no_std"))]
+        {
+            io::Error::new(err.kind.io_kind(), err)
+        }
+
+        #[cfg(feature = "no_std")]
+        {
+            io::Error::new(err.kind.io_kind(), err.message)
+        }
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(_: Utf8Error) -> Error {
+        Error::new(ErrorKind::InvalidData, "invalid UTF-8")
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(_: ParseIntError) -> Error {
+        Error::new(ErrorKind::InvalidInput, "invalid integer")
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Error {
+        Error::new(ErrorKind::Other, "formatting failed")
+    }
+}
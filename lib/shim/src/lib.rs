@@ -19,5 +19,8 @@ cfg_if::cfg_if! {
 #[macro_use]
 pub mod macros;
 
+pub mod buf;
+pub mod error;
+
 #[cfg(test)]
 mod tests;
@@ -9,4 +9,176 @@ mod assert {
         const_assert_size!(S2, 2+2);
         S2(2, 2);
     }
-}
\ No newline at end of file
+}
+mod error {
+    use crate::error::{Error, ErrorKind};
+    use crate::io;
+
+    #[test]
+    fn test_error_into_io() {
+        let err = Error::new(ErrorKind::ChecksumMismatch, "bad packet").with_payload(7);
+        let io_err: io::Error = err.into();
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(Error::from_io(&io_err), Some(err));
+        assert_eq!(Error::from(io_err), err);
+    }
+
+    #[test]
+    fn test_error_from_io() {
+        let err = Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert_eq!(err.source_kind(), Some(io::ErrorKind::TimedOut));
+        assert_eq!(err.payload(), None);
+    }
+
+    #[test]
+    fn test_error_display() {
+        let err = Error::new(ErrorKind::Cancelled, "receiver cancelled").with_payload(0x18);
+        assert_eq!(err.to_string(), "operation cancelled: receiver cancelled (0x18)");
+    }
+}
+
+mod buf {
+    use crate::buf::{BufReader, BufWriter, BUF_SIZE};
+    use crate::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+    /// Counts the calls made to the reader or writer it wraps.
+    struct Counting<T> {
+        inner: T,
+        calls: usize,
+    }
+
+    impl<T: Read> Read for Counting<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.calls += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl<T: Write> Write for Counting<T> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_buf_reader() {
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let mut reader = BufReader::new(Counting { inner: &data[..], calls: 0 });
+
+        let mut byte = [0];
+        let mut read = Vec::new();
+        while reader.read(&mut byte).unwrap() == 1 {
+            read.push(byte[0]);
+        }
+
+        assert_eq!(read, data);
+        assert_eq!(reader.get_ref().calls, 3);
+    }
+
+    #[test]
+    fn test_buf_reader_large_reads_bypass_buffer() {
+        let data = [7u8; 2 * BUF_SIZE];
+        let mut reader = BufReader::new(&data[..]);
+        let mut small = [0; 4];
+        reader.read_exact(&mut small).unwrap();
+        assert_eq!(reader.buffer().len(), BUF_SIZE - 4);
+
+        let mut large = [0; BUF_SIZE];
+        assert_eq!(reader.read(&mut large).unwrap(), BUF_SIZE - 4);
+        assert_eq!(reader.read(&mut large).unwrap(), BUF_SIZE);
+        assert!(reader.buffer().is_empty());
+    }
+
+    #[test]
+    fn test_buf_reader_bufread_and_seek() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut reader = BufReader::new(io::Cursor::new(&data[..]));
+        assert_eq!(&reader.fill_buf().unwrap()[..3], &[0, 1, 2]);
+        reader.consume(10);
+
+        assert_eq!(reader.seek(SeekFrom::Current(5)).unwrap(), 15);
+        let mut byte = [0];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], 15);
+    }
+
+    #[test]
+    fn test_buf_writer() {
+        let mut writer = BufWriter::new(Counting { inner: Vec::new(), calls: 0 });
+        for i in 0..1000 {
+            writer.write_all(&[i as u8]).unwrap();
+        }
+        assert_eq!(writer.get_ref().calls, 1);
+        assert_eq!(writer.buffer().len(), 1000 - BUF_SIZE);
+
+        let inner = writer.into_inner().map_err(|(e, _)| e).unwrap();
+        assert_eq!(inner.calls, 2);
+        assert_eq!(inner.inner, (0..1000).map(|i| i as u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_buf_writer_flushes_on_drop() {
+        let mut out = [0u8; 8];
+        {
+            let mut writer = BufWriter::new(&mut out[..]);
+            writer.write_all(b"abc").unwrap();
+            assert_eq!(writer.buffer(), b"abc");
+        }
+        assert_eq!(&out[..3], b"abc");
+    }
+
+    #[test]
+    fn test_buf_writer_keeps_unwritten_data() {
+        let mut out = [0u8; 2];
+        let mut writer = BufWriter::new(&mut out[..]);
+        writer.write_all(b"abcd").unwrap();
+        assert!(writer.flush().is_err());
+        assert_eq!(writer.buffer(), b"cd");
+    }
+}
+
+// `copy` and `Cursor` come from `std::io` or `core_io`; these check that
+// both stay available without `alloc`.
+mod io {
+    use crate::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_copy_into_slice_cursor() {
+        let data: Vec<u8> = (0..200u8).collect();
+        let mut storage = [0u8; 256];
+        let mut cursor = Cursor::new(&mut storage[..]);
+
+        let copied = io::copy(&mut &data[..], &mut cursor).unwrap();
+        assert_eq!(copied, 200);
+        assert_eq!(cursor.position(), 200);
+        assert_eq!(&storage[..200], &data[..]);
+    }
+
+    #[test]
+    fn test_copy_into_full_cursor_fails() {
+        let mut storage = [0u8; 4];
+        let mut cursor = Cursor::new(&mut storage[..]);
+        let err = io::copy(&mut &b"too long"[..], &mut cursor).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(&storage, b"too ");
+    }
+
+    #[test]
+    fn test_slice_cursor_seek_and_read() {
+        let mut storage = *b"hello world";
+        let mut cursor = Cursor::new(&mut storage[..]);
+        cursor.seek(SeekFrom::Start(6)).unwrap();
+        cursor.write_all(b"there").unwrap();
+
+        cursor.set_position(0);
+        let mut read = String::new();
+        cursor.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "hello there");
+    }
+}
@@ -0,0 +1,372 @@
+//! `ArrayVec`, a vector that owns its fixed-capacity storage.
+//!
+//! The capacity is given by an array type, as in `ArrayVec<[T; 16]>`, through
+//! the `Array` trait rather than by a const parameter, `ArrayVec<T, const N:
+//! usize>`. The nightly this tree builds with has const generics only behind
+//! the incomplete `const_generics` feature, which xmodem already avoids for
+//! its packet size; `Array` covers the capacities in use without it.
+
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::{ptr, slice};
+
+use crate::{CapacityError, StackVec};
+
+/// An array type that can back an `ArrayVec`.
+///
+/// This is implemented for arrays of up to 32 elements and for some larger
+/// sizes, up to 4096.
+///
+/// # Safety
+///
+/// `ArrayVec` trusts `CAPACITY` to be the number of `Item`s in the array.
+pub unsafe trait Array {
+    /// The type of the array's elements.
+    type Item;
+    /// The number of elements in the array.
+    const CAPACITY: usize;
+}
+
+macro_rules! impl_array {
+    ($($n:expr),*) => {
+        $(unsafe impl<T> Array for [T; $n] {
+            type Item = T;
+            const CAPACITY: usize = $n;
+        })*
+    }
+}
+
+impl_array!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28,
+            29, 30, 31, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 2048, 4096);
+
+/// A contiguous array type that owns its storage, an array of type `A`.
+///
+/// `ArrayVec` has the same API as `StackVec`, but needs no backing slice, so
+/// it has no lifetime and can be a field of another type. Its capacity is the
+/// length of the array: an `ArrayVec<[T; 16]>` holds up to 16 `T`s.
+pub struct ArrayVec<A: Array> {
+    /// The first `len` elements are initialized; the rest may not be.
+    array: MaybeUninit<A>,
+    len: usize,
+}
+
+impl<A: Array> ArrayVec<A> {
+    /// Constructs a new, empty `ArrayVec`.
+    pub fn new() -> ArrayVec<A> {
+        ArrayVec { array: MaybeUninit::uninit(), len: 0 }
+    }
+
+    /// Returns a pointer to the first element of the array, initialized or
+    /// not.
+    fn array_ptr(&self) -> *const A::Item {
+        self.array.as_ptr() as *const A::Item
+    }
+
+    /// Runs `f` with a `StackVec` over this vector's storage and elements,
+    /// then takes back the elements `f` left in it.
+    pub(crate) fn with_stack_vec<R, F: FnOnce(&mut StackVec<A::Item>) -> R>(&mut self, f: F) -> R {
+        let storage = unsafe {
+            slice::from_raw_parts_mut(self.array.as_mut_ptr() as *mut MaybeUninit<A::Item>, A::CAPACITY)
+        };
+
+        // if `f` panics, the `StackVec` drops the elements, not `self`
+        let mut vec = StackVec::from_uninit(storage);
+        vec.len = self.len;
+        self.len = 0;
+
+        let result = f(&mut vec);
+        self.len = vec.len;
+        mem::forget(vec);
+        result
+    }
+
+    /// Returns the number of elements this vector can hold.
+    pub fn capacity(&self) -> usize {
+        A::CAPACITY
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// rest. If `len` is greater than the vector's current length, this has
+    /// no effect.
+    pub fn truncate(&mut self, len: usize) {
+        self.with_stack_vec(|vec| vec.truncate(len))
+    }
+
+    /// Removes and drops all of the elements.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Extracts a slice containing the entire vector.
+    pub fn as_slice(&self) -> &[A::Item] {
+        unsafe { slice::from_raw_parts(self.array_ptr(), self.len) }
+    }
+
+    /// Extracts a mutable slice of the entire vector.
+    pub fn as_mut_slice(&mut self) -> &mut [A::Item] {
+        unsafe { slice::from_raw_parts_mut(self.array.as_mut_ptr() as *mut A::Item, self.len) }
+    }
+
+    /// Returns the number of elements in the vector, also referred to as its
+    /// 'length'.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the vector contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the vector is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    /// Appends `value` to the back of this vector if the vector is not full.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full, an `Err` holding `value` is returned.
+    /// Otherwise, `Ok` is returned.
+    pub fn push(&mut self, value: A::Item) -> Result<(), CapacityError<A::Item>> {
+        self.try_push(value)
+    }
+
+    /// Appends `value` to the back of this vector if the vector is not full.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full, an `Err` holding `value` is returned.
+    /// Otherwise, `Ok` is returned.
+    pub fn try_push(&mut self, value: A::Item) -> Result<(), CapacityError<A::Item>> {
+        self.with_stack_vec(|vec| vec.try_push(value))
+    }
+
+    /// Appends `value` to the back of this vector without checking whether
+    /// the vector is full.
+    ///
+    /// # Safety
+    ///
+    /// The vector must not be full.
+    pub unsafe fn push_unchecked(&mut self, value: A::Item) {
+        debug_assert!(!self.is_full());
+        ptr::write((self.array.as_mut_ptr() as *mut A::Item).add(self.len), value);
+        self.len += 1;
+    }
+
+    /// Returns the storage past the end of the vector, which is
+    /// uninitialized. Values written into it become part of the vector with
+    /// `set_len`.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<A::Item>] {
+        let ptr = self.array.as_mut_ptr() as *mut MaybeUninit<A::Item>;
+        unsafe { slice::from_raw_parts_mut(ptr.add(self.len), A::CAPACITY - self.len) }
+    }
+
+    /// Sets the length of the vector to `len`, without dropping or
+    /// initializing any elements.
+    ///
+    /// # Safety
+    ///
+    /// `len` must be at most the capacity, and the elements up to `len` must
+    /// be initialized, for example by writing them into `spare_capacity_mut`.
+    pub unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= A::CAPACITY);
+        self.len = len;
+    }
+
+    /// Removes every element for which `f` returns `false`, in place. The
+    /// remaining elements keep their order.
+    pub fn retain<F: FnMut(&A::Item) -> bool>(&mut self, f: F) {
+        self.with_stack_vec(|vec| vec.retain(f))
+    }
+
+    /// Removes consecutive elements for which `same` returns `true`, keeping
+    /// the first of each run. `same` is passed an element and the one kept
+    /// before it.
+    pub fn dedup_by<F: FnMut(&mut A::Item, &mut A::Item) -> bool>(&mut self, same: F) {
+        self.with_stack_vec(|vec| vec.dedup_by(same))
+    }
+
+    /// If this vector is not empty, removes the last element from this vector
+    /// and returns it. Otherwise returns `None`.
+    pub fn pop(&mut self) -> Option<A::Item> {
+        self.with_stack_vec(|vec| vec.pop())
+    }
+}
+
+impl<A: Array> ArrayVec<A> where A::Item: PartialEq {
+    /// Removes consecutive repeated elements, keeping the first of each run.
+    pub fn dedup(&mut self) {
+        self.dedup_by(|a, b| a == b)
+    }
+}
+
+impl<A: Array> ArrayVec<A> where A::Item: Clone {
+    /// Appends clones of all of `other`'s elements to the back of this vector
+    /// if they all fit.
+    ///
+    /// # Error
+    ///
+    /// If there isn't room for all of `other`, nothing is appended and an
+    /// `Err` is returned.
+    pub fn try_extend_from_slice(&mut self, other: &[A::Item]) -> Result<(), CapacityError> {
+        self.with_stack_vec(|vec| vec.try_extend_from_slice(other))
+    }
+}
+
+impl<A: Array> Drop for ArrayVec<A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<A: Array> Deref for ArrayVec<A> {
+    type Target = [A::Item];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<A: Array> DerefMut for ArrayVec<A> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+impl<A: Array> fmt::Debug for ArrayVec<A> where A::Item: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<A: Array> Default for ArrayVec<A> {
+    fn default() -> ArrayVec<A> {
+        ArrayVec::new()
+    }
+}
+
+impl<A: Array> Clone for ArrayVec<A> where A::Item: Clone {
+    fn clone(&self) -> ArrayVec<A> {
+        let mut vec = ArrayVec::new();
+        vec.extend(self.iter().cloned());
+        vec
+    }
+}
+
+impl<A: Array, B: Array> PartialEq<ArrayVec<B>> for ArrayVec<A> where A::Item: PartialEq<B::Item> {
+    fn eq(&self, other: &ArrayVec<B>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<A: Array> Eq for ArrayVec<A> where A::Item: Eq {}
+
+impl<A: Array, U> PartialEq<[U]> for ArrayVec<A> where A::Item: PartialEq<U> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<'b, A: Array, U> PartialEq<&'b [U]> for ArrayVec<A> where A::Item: PartialEq<U> {
+    fn eq(&self, other: &&'b [U]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+/// Hashes the same as the slice of its elements.
+impl<A: Array> Hash for ArrayVec<A> where A::Item: Hash {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+/// Appends elements until the vector is full. Those that don't fit are left
+/// in the iterator.
+impl<A: Array> Extend<A::Item> for ArrayVec<A> {
+    fn extend<I: IntoIterator<Item = A::Item>>(&mut self, iter: I) {
+        self.with_stack_vec(|vec| vec.extend(iter))
+    }
+}
+
+impl<A: Array> IntoIterator for ArrayVec<A> {
+    type Item = A::Item;
+    type IntoIter = IntoIter<A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { vec: self, start: 0 }
+    }
+}
+
+impl<'a, A: Array> IntoIterator for &'a ArrayVec<A> {
+    type Item = &'a A::Item;
+    type IntoIter = slice::Iter<'a, A::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, A: Array> IntoIterator for &'a mut ArrayVec<A> {
+    type Item = &'a mut A::Item;
+    type IntoIter = slice::IterMut<'a, A::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator that moves the elements out of an `ArrayVec`.
+///
+/// This `struct` is created by the `into_iter` method on `ArrayVec`. The
+/// elements it doesn't yield are dropped along with it.
+pub struct IntoIter<A: Array> {
+    /// The elements in `start..vec.len` are not yet yielded.
+    vec: ArrayVec<A>,
+    start: usize,
+}
+
+impl<A: Array> Iterator for IntoIter<A> {
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<A::Item> {
+        if self.start == self.vec.len {
+            return None;
+        }
+
+        self.start += 1;
+        Some(unsafe { ptr::read(self.vec.array_ptr().add(self.start - 1)) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.vec.len - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<A: Array> DoubleEndedIterator for IntoIter<A> {
+    fn next_back(&mut self) -> Option<A::Item> {
+        if self.start == self.vec.len {
+            return None;
+        }
+
+        self.vec.len -= 1;
+        Some(unsafe { ptr::read(self.vec.array_ptr().add(self.vec.len)) })
+    }
+}
+
+impl<A: Array> ExactSizeIterator for IntoIter<A> {}
+
+impl<A: Array> Drop for IntoIter<A> {
+    fn drop(&mut self) {
+        let rest = unsafe {
+            slice::from_raw_parts_mut(self.vec.array_ptr().add(self.start) as *mut A::Item, self.vec.len - self.start)
+        };
+
+        // if a destructor panics, the rest are leaked
+        self.vec.len = 0;
+        unsafe { ptr::drop_in_place(rest) };
+    }
+}
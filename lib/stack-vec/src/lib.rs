@@ -2,10 +2,44 @@
 
 #[cfg(test)]
 mod tests;
+pub mod array_vec;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod stack_deque;
+pub mod stack_map;
+mod stack_string;
 
-// use core::slice;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use core::iter::IntoIterator;
+use core::mem::{self, MaybeUninit};
 use core::ops::{Deref, DerefMut};
+use core::{ptr, slice};
+
+pub use array_vec::{Array, ArrayVec};
+pub use stack_deque::StackDeque;
+pub use stack_map::StackMap;
+pub use stack_string::StackString;
+
+/// The error returned when a `StackVec` is too full for an operation. It
+/// holds the element that couldn't be added, if any.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CapacityError<T = ()> {
+    element: T,
+}
+
+impl<T> CapacityError<T> {
+    /// Returns the element that couldn't be added.
+    pub fn element(self) -> T {
+        self.element
+    }
+}
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("insufficient capacity")
+    }
+}
 
 /// A contiguous array type backed by a slice.
 ///
@@ -15,32 +49,81 @@ use core::ops::{Deref, DerefMut};
 /// result, `StackVec`'s capacity is _bounded_ by the user-supplied slice. This
 /// results in `push` being fallible: if `push` is called when the vector is
 /// full, an `Err` is returned.
-#[derive(Debug)]
 pub struct StackVec<'a, T: 'a> {
-    storage: &'a mut [T],
+    /// The first `len` elements are initialized; the rest may not be.
+    storage: &'a mut [MaybeUninit<T>],
     len: usize
 }
 
 impl<'a, T> Deref for StackVec<'a, T> {
     type Target = [T];
     fn deref(&self) -> &Self::Target {
-        &self.storage[..self.len]
+        self.as_slice()
     }
 }
 
 impl<'a, T> DerefMut for StackVec<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.storage[..self.len]
+        self.as_mut_slice()
+    }
+}
+
+impl<'a, T> Drop for StackVec<'a, T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for StackVec<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An empty `StackVec` with no room for any elements.
+impl<'a, T> Default for StackVec<'a, T> {
+    fn default() -> StackVec<'a, T> {
+        StackVec { storage: &mut [], len: 0 }
+    }
+}
+
+impl<'a, 'b, T: PartialEq<U>, U> PartialEq<StackVec<'b, U>> for StackVec<'a, T> {
+    fn eq(&self, other: &StackVec<'b, U>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<'a, T: Eq> Eq for StackVec<'a, T> {}
+
+impl<'a, T: PartialEq<U>, U> PartialEq<[U]> for StackVec<'a, T> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<'a, 'b, T: PartialEq<U>, U> PartialEq<&'b [U]> for StackVec<'a, T> {
+    fn eq(&self, other: &&'b [U]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+/// Hashes the same as the slice of its elements.
+impl<'a, T: Hash> Hash for StackVec<'a, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
     }
 }
 
 // IntoIterator for owned StackVec
 impl<'a, T> IntoIterator for StackVec<'a, T> {
-    type Item = &'a T;  // Change this to &'a T
-    type IntoIter = core::iter::Take<core::slice::Iter<'a, T>>;
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.storage[..self.len].iter().take(self.len)
+        let (ptr, capacity, end) = (self.storage.as_mut_ptr(), self.capacity(), self.len);
+        mem::forget(self);
+        let storage = unsafe { slice::from_raw_parts_mut(ptr, capacity) };
+        IntoIter { storage, start: 0, end }
     }
 }
 
@@ -50,7 +133,7 @@ impl<'a, T> IntoIterator for &'a StackVec<'a, T> {
     type IntoIter = core::slice::Iter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.storage[..self.len].iter()
+        self.as_slice().iter()
     }
 }
 
@@ -60,16 +143,90 @@ impl<'a, T> IntoIterator for &'a mut StackVec<'a, T> {
     type IntoIter = core::slice::IterMut<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.storage[..self.len].iter_mut()
+        self.as_mut_slice().iter_mut()
     }
 }
 
-impl<'a, T: 'a> StackVec<'a, T> {
+/// Appends elements until the vector is full. Those that don't fit are left
+/// in the iterator.
+impl<'a, T> Extend<T> for StackVec<'a, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+        while !self.is_full() {
+            match iter.next() {
+                Some(value) => {
+                    let _ = self.push(value);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// An iterator that moves the elements out of a `StackVec`.
+///
+/// This `struct` is created by the `into_iter` method on `StackVec`. The
+/// elements it doesn't yield are dropped along with it.
+pub struct IntoIter<'a, T: 'a> {
+    /// The elements in `start..end` are initialized and not yet yielded.
+    storage: &'a mut [MaybeUninit<T>],
+    start: usize,
+    end: usize,
+}
+
+impl<'a, T> Iterator for IntoIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+
+        self.start += 1;
+        Some(unsafe { ptr::read(self.storage[self.start - 1].as_ptr()) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IntoIter<'a, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+
+        self.end -= 1;
+        Some(unsafe { ptr::read(self.storage[self.end].as_ptr()) })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IntoIter<'a, T> {}
+
+impl<'a, T> Drop for IntoIter<'a, T> {
+    fn drop(&mut self) {
+        let rest = unsafe {
+            slice::from_raw_parts_mut(self.storage.as_mut_ptr().add(self.start) as *mut T, self.end - self.start)
+        };
+
+        // if a destructor panics, the rest are leaked
+        self.start = self.end;
+        unsafe { ptr::drop_in_place(rest) };
+    }
+}
+
+impl<'a, T: Copy + 'a> StackVec<'a, T> {
     /// Constructs a new, empty `StackVec<T>` using `storage` as the backing
     /// store. The returned `StackVec` will be able to hold `storage.len()`
     /// values.
+    ///
+    /// `T` must be `Copy`: values popped off the vector are moved out of
+    /// `storage`, and the owner of `storage` would otherwise drop them again.
+    /// Other types are stored with `from_uninit`.
     pub fn new(storage: &'a mut [T]) -> StackVec<'a, T> {
-        StackVec { storage, len: 0 }
+        StackVec::with_len(storage, 0)
     }
 
     /// Constructs a new `StackVec<T>` using `storage` as the backing store. The
@@ -82,39 +239,117 @@ impl<'a, T: 'a> StackVec<'a, T> {
     /// Panics if `len > storage.len()`.
     pub fn with_len(storage: &'a mut [T], len: usize) -> StackVec<'a, T> {
         assert!(len <= storage.len());
+        // `MaybeUninit<T>` has the same layout as `T`, and nothing but a
+        // valid `T` is ever written into the storage
+        let storage = unsafe {
+            slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut MaybeUninit<T>, storage.len())
+        };
+
         StackVec { storage, len }
     }
 
+    /// Constructs a new `StackVec<T>` using `storage` as the backing store,
+    /// holding the values of `iter` in order.
+    ///
+    /// # Error
+    ///
+    /// If `iter` yields more than `storage.len()` values, an `Err` is
+    /// returned.
+    pub fn try_from_iter<I>(storage: &'a mut [T], iter: I) -> Result<StackVec<'a, T>, CapacityError>
+        where I: IntoIterator<Item = T>
+    {
+        let mut vec = StackVec::new(storage);
+        for value in iter {
+            vec.try_push(value).map_err(|_| CapacityError { element: () })?;
+        }
+
+        Ok(vec)
+    }
+}
+
+impl<'a, T: 'a> StackVec<'a, T> {
+    /// Constructs a new, empty `StackVec<T>` using the uninitialized
+    /// `storage` as the backing store. The returned `StackVec` will be able to
+    /// hold `storage.len()` values, and drops those it holds when it's
+    /// dropped.
+    ///
+    /// Unlike `new`, this works for any `T`, without filling `storage` with
+    /// placeholder values first. An array of uninitialized values can be made
+    /// with `MaybeUninit::uninit().assume_init()`, as
+    /// `MaybeUninit<[MaybeUninit<T>; N]>`.
+    pub fn from_uninit(storage: &'a mut [MaybeUninit<T>]) -> StackVec<'a, T> {
+        StackVec { storage, len: 0 }
+    }
+
     /// Returns the number of elements this vector can hold.
     pub fn capacity(&self) -> usize {
         self.storage.len()
     }
 
-    /// Shortens the vector, keeping the first `len` elements. If `len` is
-    /// greater than the vector's current length, this has no effect. Note that
-    /// this method has no effect on the capacity of the vector.
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// rest. If `len` is greater than the vector's current length, this has
+    /// no effect. Note that this method has no effect on the capacity of the
+    /// vector.
     pub fn truncate(&mut self, len: usize) {
         if len < self.len {
+            let tail = unsafe {
+                slice::from_raw_parts_mut(self.storage.as_mut_ptr().add(len) as *mut T, self.len - len)
+            };
+
+            // if a destructor panics, the rest of the tail is leaked
             self.len = len;
+            unsafe { ptr::drop_in_place(tail) };
         }
     }
 
+    /// Removes and drops all of the elements. Note that this method has no
+    /// effect on the capacity of the vector.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Splits the vector in two at `at`. The elements from `at` on, and the
+    /// storage for them onwards, are returned as a new `StackVec`; this vector
+    /// keeps the first `at` elements and the storage for them, so its capacity
+    /// becomes `at`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> StackVec<'a, T> {
+        assert!(at <= self.len);
+
+        // the two halves don't overlap, and each lives as long as the whole
+        let (ptr, capacity) = (self.storage.as_mut_ptr(), self.capacity());
+        let tail = unsafe {
+            self.storage = slice::from_raw_parts_mut(ptr, at);
+            slice::from_raw_parts_mut(ptr.add(at), capacity - at)
+        };
+
+        let len = self.len - at;
+        self.len = at;
+        StackVec { storage: tail, len }
+    }
+
     /// Extracts a slice containing the entire vector, consuming `self`.
     ///
     /// Note that the returned slice's length will be the length of this vector,
-    /// _not_ the length of the original backing storage.
+    /// _not_ the length of the original backing storage. The elements are
+    /// left in the storage rather than dropped.
     pub fn into_slice(self) -> &'a mut [T] {
-        &mut self.storage[..self.len]
+        let (ptr, len) = (self.storage.as_mut_ptr(), self.len);
+        mem::forget(self);
+        unsafe { slice::from_raw_parts_mut(ptr as *mut T, len) }
     }
 
     /// Extracts a slice containing the entire vector.
     pub fn as_slice(&self) -> &[T] {
-        &self.storage[..self.len]
+        unsafe { slice::from_raw_parts(self.storage.as_ptr() as *const T, self.len) }
     }
 
     /// Extracts a mutable slice of the entire vector.
     pub fn as_mut_slice(&mut self) -> &mut [T] {
-        &mut self.storage[..self.len]
+        unsafe { slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut T, self.len) }
     }
 
     /// Returns the number of elements in the vector, also referred to as its
@@ -140,24 +375,163 @@ impl<'a, T: 'a> StackVec<'a, T> {
     /// If this vector is full, an `Err` is returned. Otherwise, `Ok` is
     /// returned.
     pub fn push(&mut self, value: T) -> Result<(), ()> {
+        self.try_push(value).map_err(|_| ())
+    }
+
+    /// Appends `value` to the back of this vector if the vector is not full.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full, an `Err` holding `value` is returned.
+    /// Otherwise, `Ok` is returned.
+    pub fn try_push(&mut self, value: T) -> Result<(), CapacityError<T>> {
         if self.is_full() {
-            return Err(());
+            return Err(CapacityError { element: value });
         }
-        self.storage[self.len] = value;
+        unsafe { ptr::write(self.storage[self.len].as_mut_ptr(), value) };
         self.len += 1;
         Ok(())
     }
-}
 
-impl<'a, T: Clone + 'a> StackVec<'a, T> {
+    /// Appends `value` to the back of this vector without checking whether
+    /// the vector is full.
+    ///
+    /// # Safety
+    ///
+    /// The vector must not be full.
+    pub unsafe fn push_unchecked(&mut self, value: T) {
+        debug_assert!(!self.is_full());
+        ptr::write((self.storage.as_mut_ptr() as *mut T).add(self.len), value);
+        self.len += 1;
+    }
+
+    /// Returns the storage past the end of the vector, which may be
+    /// uninitialized. Values written into it become part of the vector with
+    /// `set_len`.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        &mut self.storage[self.len..]
+    }
+
+    /// Sets the length of the vector to `len`, without dropping or
+    /// initializing any elements.
+    ///
+    /// # Safety
+    ///
+    /// `len` must be at most the capacity, and the elements up to `len` must
+    /// be initialized, for example by writing them into `spare_capacity_mut`.
+    pub unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity());
+        self.len = len;
+    }
+
+    /// Removes every element for which `f` returns `false`, in place. The
+    /// remaining elements keep their order.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let len = self.len;
+        // if `f` panics, the elements are leaked rather than dropped twice
+        self.len = 0;
+
+        let mut kept = 0;
+        for i in 0..len {
+            unsafe {
+                let value = self.storage[i].as_mut_ptr();
+                if f(&*value) {
+                    ptr::copy(value, self.storage[kept].as_mut_ptr(), 1);
+                    kept += 1;
+                } else {
+                    ptr::drop_in_place(value);
+                }
+            }
+        }
+
+        self.len = kept;
+    }
+
+    /// Removes consecutive elements for which `same` returns `true`, keeping
+    /// the first of each run. `same` is passed an element and the one kept
+    /// before it.
+    pub fn dedup_by<F: FnMut(&mut T, &mut T) -> bool>(&mut self, mut same: F) {
+        let len = self.len;
+        if len <= 1 {
+            return;
+        }
+
+        // if `same` panics, the elements are leaked rather than dropped twice
+        self.len = 0;
+
+        let ptr = self.storage.as_mut_ptr() as *mut T;
+        let mut kept = 1;
+        for i in 1..len {
+            unsafe {
+                let value = ptr.add(i);
+                if same(&mut *value, &mut *ptr.add(kept - 1)) {
+                    ptr::drop_in_place(value);
+                } else {
+                    ptr::copy(value, ptr.add(kept), 1);
+                    kept += 1;
+                }
+            }
+        }
+
+        self.len = kept;
+    }
+
     /// If this vector is not empty, removes the last element from this vector
-    /// by cloning it and returns it. Otherwise returns `None`.
+    /// and returns it. Otherwise returns `None`.
     pub fn pop(&mut self) -> Option<T> {
         if self.len > 0 {
             self.len -= 1;
-            Some(self.storage[self.len].clone())
+            // the element is past `len` now, so it's never read again
+            Some(unsafe { ptr::read(self.storage[self.len].as_ptr()) })
         } else {
             None
         }
     }
 }
+
+impl<'a, T: PartialEq + 'a> StackVec<'a, T> {
+    /// Removes consecutive repeated elements, keeping the first of each run.
+    pub fn dedup(&mut self) {
+        self.dedup_by(|a, b| a == b)
+    }
+}
+
+impl<'a, T: Clone + 'a> StackVec<'a, T> {
+    /// Appends clones of all of `other`'s elements to the back of this vector
+    /// if they all fit.
+    ///
+    /// # Error
+    ///
+    /// If there isn't room for all of `other`, nothing is appended and an
+    /// `Err` is returned.
+    pub fn try_extend_from_slice(&mut self, other: &[T]) -> Result<(), CapacityError> {
+        if self.capacity() - self.len < other.len() {
+            return Err(CapacityError { element: () });
+        }
+
+        for value in other {
+            let _ = self.push(value.clone());
+        }
+
+        Ok(())
+    }
+}
+
+/// Appends as much of each write as fits. Fails with `WriteZero` once the
+/// vector is full.
+#[cfg(feature = "shim")]
+impl<'a> shim::io::Write for StackVec<'a, u8> {
+    fn write(&mut self, buf: &[u8]) -> shim::io::Result<usize> {
+        let n = buf.len().min(self.capacity() - self.len);
+        if n == 0 && !buf.is_empty() {
+            return Err(shim::io::Error::new(shim::io::ErrorKind::WriteZero, "StackVec is full"));
+        }
+
+        let _ = self.try_extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> shim::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use core::fmt;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{Array, ArrayVec, StackVec};
+
+/// Serializes as a sequence of its elements.
+impl<'a, T: Serialize> Serialize for StackVec<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Serializes as a sequence of its elements.
+impl<A: Array> Serialize for ArrayVec<A> where A::Item: Serialize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// A `StackVec` can't be deserialized on its own, for want of storage, so
+/// one is used as a seed instead: the elements of a sequence are appended to
+/// it. Fails if they don't all fit.
+impl<'de, 'a, T: Deserialize<'de>> DeserializeSeed<'de> for StackVec<'a, T> {
+    type Value = StackVec<'a, T>;
+
+    fn deserialize<D: Deserializer<'de>>(mut self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(Fill::new(&mut self))?;
+        Ok(self)
+    }
+}
+
+/// Deserializes from a sequence. Fails if it's longer than the capacity.
+impl<'de, A: Array> Deserialize<'de> for ArrayVec<A> where A::Item: Deserialize<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut vec = ArrayVec::new();
+        vec.with_stack_vec(|vec| deserializer.deserialize_seq(Fill::new(vec)))?;
+        Ok(vec)
+    }
+}
+
+/// Appends the elements of a sequence to a `StackVec`.
+struct Fill<'v, 'a: 'v, T: 'a> {
+    vec: &'v mut StackVec<'a, T>,
+    /// The number of elements there was room for to begin with.
+    room: usize,
+}
+
+impl<'v, 'a, T> Fill<'v, 'a, T> {
+    fn new(vec: &'v mut StackVec<'a, T>) -> Fill<'v, 'a, T> {
+        let room = vec.capacity() - vec.len();
+        Fill { vec, room }
+    }
+}
+
+impl<'de, 'v, 'a, T: Deserialize<'de>> Visitor<'de> for Fill<'v, 'a, T> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a sequence of at most {} elements", self.room)
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<(), S::Error> {
+        let mut count = 0;
+        while let Some(value) = seq.next_element()? {
+            count += 1;
+            if self.vec.try_push(value).is_err() {
+                return Err(de::Error::invalid_length(count, &self));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use core::fmt;
+use core::iter::Chain;
+use core::mem::MaybeUninit;
+use core::ops::{Index, IndexMut};
+use core::{ptr, slice};
+
+use crate::CapacityError;
+
+/// A double-ended queue backed by a slice, used as a ring buffer.
+///
+/// Elements are pushed and popped at either end without moving the others:
+/// the queue wraps around the end of the slice. Like `StackVec`, its capacity
+/// is bounded by the user-supplied slice, so pushing is fallible.
+pub struct StackDeque<'a, T: 'a> {
+    /// The `len` elements starting at `head`, wrapping around the end, are
+    /// initialized; the rest may not be.
+    storage: &'a mut [MaybeUninit<T>],
+    head: usize,
+    len: usize,
+}
+
+impl<'a, T: Copy + 'a> StackDeque<'a, T> {
+    /// Constructs a new, empty `StackDeque<T>` using `storage` as the backing
+    /// store. The returned `StackDeque` will be able to hold `storage.len()`
+    /// values.
+    ///
+    /// As with `StackVec::new`, `T` must be `Copy`. Other types are stored
+    /// with `from_uninit`.
+    pub fn new(storage: &'a mut [T]) -> StackDeque<'a, T> {
+        // `MaybeUninit<T>` has the same layout as `T`, and nothing but a
+        // valid `T` is ever written into the storage
+        let storage = unsafe {
+            slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut MaybeUninit<T>, storage.len())
+        };
+
+        StackDeque::from_uninit(storage)
+    }
+}
+
+impl<'a, T: 'a> StackDeque<'a, T> {
+    /// Constructs a new, empty `StackDeque<T>` using the uninitialized
+    /// `storage` as the backing store. The returned `StackDeque` will be able
+    /// to hold `storage.len()` values, and drops those it holds when it's
+    /// dropped.
+    pub fn from_uninit(storage: &'a mut [MaybeUninit<T>]) -> StackDeque<'a, T> {
+        StackDeque { storage, head: 0, len: 0 }
+    }
+
+    /// Returns the number of elements this queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns the number of elements in the queue.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the queue contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the queue is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    /// Returns the index in `storage` of the slot `i` places from the front,
+    /// wrapping around the end.
+    fn slot(&self, i: usize) -> usize {
+        (self.head + i) % self.capacity()
+    }
+
+    /// Appends `value` to the back of this queue if the queue is not full.
+    ///
+    /// # Error
+    ///
+    /// If this queue is full, an `Err` holding `value` is returned.
+    /// Otherwise, `Ok` is returned.
+    pub fn push_back(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError { element: value });
+        }
+
+        let slot = self.slot(self.len);
+        unsafe { ptr::write(self.storage[slot].as_mut_ptr(), value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Prepends `value` to the front of this queue if the queue is not full.
+    ///
+    /// # Error
+    ///
+    /// If this queue is full, an `Err` holding `value` is returned.
+    /// Otherwise, `Ok` is returned.
+    pub fn push_front(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError { element: value });
+        }
+
+        self.head = self.slot(self.capacity() - 1);
+        unsafe { ptr::write(self.storage[self.head].as_mut_ptr(), value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the first element and returns it, or `None` if the queue is
+    /// empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let slot = self.head;
+        self.head = self.slot(1);
+        self.len -= 1;
+        Some(unsafe { ptr::read(self.storage[slot].as_ptr()) })
+    }
+
+    /// Removes the last element and returns it, or `None` if the queue is
+    /// empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.len -= 1;
+        let slot = self.slot(self.len);
+        Some(unsafe { ptr::read(self.storage[slot].as_ptr()) })
+    }
+
+    /// Returns the element `i` places from the front, or `None` if there
+    /// isn't one.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        match i < self.len {
+            true => Some(unsafe { &*self.storage[self.slot(i)].as_ptr() }),
+            false => None,
+        }
+    }
+
+    /// Returns the element `i` places from the front mutably, or `None` if
+    /// there isn't one.
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        match i < self.len {
+            true => {
+                let slot = self.slot(i);
+                Some(unsafe { &mut *self.storage[slot].as_mut_ptr() })
+            }
+            false => None,
+        }
+    }
+
+    /// Returns the first element, or `None` if the queue is empty.
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns the last element, or `None` if the queue is empty.
+    pub fn back(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    /// Returns the elements in order as two slices: the front of the queue
+    /// up to the end of the storage, then the rest, wrapped around to the
+    /// start of the storage.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let first = self.len.min(self.capacity() - self.head);
+        let ptr = self.storage.as_ptr() as *const T;
+        unsafe {
+            (slice::from_raw_parts(ptr.add(self.head), first), slice::from_raw_parts(ptr, self.len - first))
+        }
+    }
+
+    /// Returns the elements in order as two mutable slices, as `as_slices`
+    /// does.
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let first = self.len.min(self.capacity() - self.head);
+        let ptr = self.storage.as_mut_ptr() as *mut T;
+        unsafe {
+            (slice::from_raw_parts_mut(ptr.add(self.head), first), slice::from_raw_parts_mut(ptr, self.len - first))
+        }
+    }
+
+    /// Returns an iterator over the elements, front to back.
+    pub fn iter(&self) -> Chain<slice::Iter<'_, T>, slice::Iter<'_, T>> {
+        let (first, second) = self.as_slices();
+        first.iter().chain(second.iter())
+    }
+
+    /// Returns an iterator over the elements, front to back, that allows
+    /// modifying them.
+    pub fn iter_mut(&mut self) -> Chain<slice::IterMut<'_, T>, slice::IterMut<'_, T>> {
+        let (first, second) = self.as_mut_slices();
+        first.iter_mut().chain(second.iter_mut())
+    }
+
+    /// Removes and drops all of the elements.
+    pub fn clear(&mut self) {
+        let (first, second) = self.as_mut_slices();
+        let (first, second) = (first as *mut [T], second as *mut [T]);
+
+        // if a destructor panics, the rest are leaked
+        self.head = 0;
+        self.len = 0;
+        unsafe {
+            ptr::drop_in_place(first);
+            ptr::drop_in_place(second);
+        }
+    }
+}
+
+impl<'a, T> Drop for StackDeque<'a, T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<'a, T> Index<usize> for StackDeque<'a, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        self.get(i).expect("index out of bounds")
+    }
+}
+
+impl<'a, T> IndexMut<usize> for StackDeque<'a, T> {
+    fn index_mut(&mut self, i: usize) -> &mut T {
+        self.get_mut(i).expect("index out of bounds")
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for StackDeque<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Appends elements to the back until the queue is full. Those that don't
+/// fit are left in the iterator.
+impl<'a, T> Extend<T> for StackDeque<'a, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+        while !self.is_full() {
+            match iter.next() {
+                Some(value) => {
+                    let _ = self.push_back(value);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a, 'b, T> IntoIterator for &'b StackDeque<'a, T> {
+    type Item = &'b T;
+    type IntoIter = Chain<slice::Iter<'b, T>, slice::Iter<'b, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, 'b, T> IntoIterator for &'b mut StackDeque<'a, T> {
+    type Item = &'b mut T;
+    type IntoIter = Chain<slice::IterMut<'b, T>, slice::IterMut<'b, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
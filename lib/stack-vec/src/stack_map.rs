@@ -0,0 +1,156 @@
+use core::borrow::Borrow;
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+use core::slice;
+
+use crate::{CapacityError, StackVec};
+
+/// A map backed by a slice of key-value pairs.
+///
+/// Lookups scan every entry, so `StackMap` suits the handful of entries that
+/// fit in a fixed slice, such as environment variables. Entries are kept in
+/// the order they were inserted. Like `StackVec`, its capacity is bounded by
+/// the user-supplied slice, so inserting is fallible.
+pub struct StackMap<'a, K: 'a, V: 'a> {
+    /// Holds no two entries with the same key.
+    entries: StackVec<'a, (K, V)>,
+}
+
+impl<'a, K: Copy + 'a, V: Copy + 'a> StackMap<'a, K, V> {
+    /// Constructs a new, empty `StackMap` using `storage` as the backing
+    /// store. The returned `StackMap` will be able to hold `storage.len()`
+    /// entries.
+    ///
+    /// As with `StackVec::new`, `K` and `V` must be `Copy`. Other types are
+    /// stored with `from_uninit`.
+    pub fn new(storage: &'a mut [(K, V)]) -> StackMap<'a, K, V> {
+        StackMap { entries: StackVec::new(storage) }
+    }
+}
+
+impl<'a, K: 'a, V: 'a> StackMap<'a, K, V> {
+    /// Constructs a new, empty `StackMap` using the uninitialized `storage` as
+    /// the backing store. The returned `StackMap` will be able to hold
+    /// `storage.len()` entries, and drops those it holds when it's dropped.
+    pub fn from_uninit(storage: &'a mut [MaybeUninit<(K, V)>]) -> StackMap<'a, K, V> {
+        StackMap { entries: StackVec::from_uninit(storage) }
+    }
+
+    /// Returns the number of entries this map can hold.
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns true if the map is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.entries.is_full()
+    }
+
+    /// Returns the index of the entry for `key`, if there is one.
+    fn position<Q: Eq + ?Sized>(&self, key: &Q) -> Option<usize> where K: Borrow<Q> {
+        self.entries.iter().position(|(k, _)| k.borrow() == key)
+    }
+
+    /// Returns the value for `key`, or `None` if there isn't one.
+    pub fn get<Q: Eq + ?Sized>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        self.position(key).map(|i| &self.entries[i].1)
+    }
+
+    /// Returns the value for `key` mutably, or `None` if there isn't one.
+    pub fn get_mut<Q: Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V> where K: Borrow<Q> {
+        match self.position(key) {
+            Some(i) => Some(&mut self.entries[i].1),
+            None => None,
+        }
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key<Q: Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.position(key).is_some()
+    }
+
+    /// Sets the value for `key` to `value`. If there was a value for `key`
+    /// already, it's replaced and returned; the key is not replaced.
+    ///
+    /// # Error
+    ///
+    /// If there is no value for `key` and the map is full, an `Err` holding
+    /// `key` and `value` is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, CapacityError<(K, V)>> where K: Eq {
+        match self.position(&key) {
+            Some(i) => Ok(Some(mem::replace(&mut self.entries[i].1, value))),
+            None => self.entries.try_push((key, value)).map(|_| None),
+        }
+    }
+
+    /// Removes the entry for `key` and returns its value, or returns `None`
+    /// if there isn't one. The other entries keep their order.
+    pub fn remove<Q: Eq + ?Sized>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q> {
+        let i = self.position(key)?;
+        self.entries[i..].rotate_left(1);
+        self.entries.pop().map(|(_, v)| v)
+    }
+
+    /// Removes and drops all of the entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns an iterator over the entries, in the order they were
+    /// inserted.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { entries: self.entries.iter() }
+    }
+}
+
+impl<'a, K: fmt::Debug, V: fmt::Debug> fmt::Debug for StackMap<'a, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, 'b, K, V> IntoIterator for &'b StackMap<'a, K, V> {
+    type Item = (&'b K, &'b V);
+    type IntoIter = Iter<'b, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a `StackMap`.
+///
+/// This `struct` is created by the `iter` method on `StackMap`.
+pub struct Iter<'b, K: 'b, V: 'b> {
+    entries: slice::Iter<'b, (K, V)>,
+}
+
+impl<'b, K, V> Iterator for Iter<'b, K, V> {
+    type Item = (&'b K, &'b V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(k, v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'b, K, V> DoubleEndedIterator for Iter<'b, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl<'b, K, V> ExactSizeIterator for Iter<'b, K, V> {}
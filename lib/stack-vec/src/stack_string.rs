@@ -0,0 +1,147 @@
+use core::fmt;
+use core::ops::Deref;
+use core::str;
+
+use crate::{CapacityError, StackVec};
+
+/// A UTF-8 string backed by a byte slice.
+///
+/// `StackString` is to `StackVec<u8>` what `String` is to `Vec<u8>`: its
+/// capacity is bounded by the user-supplied slice, so appending is fallible.
+/// It implements `fmt::Write`, so `write!` formats into it.
+pub struct StackString<'a> {
+    /// Always holds valid UTF-8.
+    vec: StackVec<'a, u8>,
+}
+
+impl<'a> StackString<'a> {
+    /// Constructs a new, empty `StackString` using `storage` as the backing
+    /// store. The returned `StackString` will be able to hold `storage.len()`
+    /// bytes.
+    pub fn new(storage: &'a mut [u8]) -> StackString<'a> {
+        StackString { vec: StackVec::new(storage) }
+    }
+
+    /// Returns the number of bytes this string can hold.
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// Returns the length of this string in bytes.
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// Returns true if this string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Extracts a string slice containing the entire string.
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.vec) }
+    }
+
+    /// Returns this string's bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.vec
+    }
+
+    /// Extracts a string slice containing the entire string, consuming
+    /// `self`.
+    pub fn into_str(self) -> &'a str {
+        unsafe { str::from_utf8_unchecked(self.vec.into_slice()) }
+    }
+
+    /// Appends `s` to the end of this string if it fits.
+    ///
+    /// # Error
+    ///
+    /// If there isn't room for all of `s`, nothing is appended and an `Err`
+    /// is returned.
+    pub fn push_str(&mut self, s: &str) -> Result<(), CapacityError> {
+        self.vec.try_extend_from_slice(s.as_bytes())
+    }
+
+    /// Appends `ch` to the end of this string if it fits.
+    ///
+    /// # Error
+    ///
+    /// If there isn't room for `ch`, an `Err` holding it is returned.
+    pub fn push_char(&mut self, ch: char) -> Result<(), CapacityError<char>> {
+        self.push_str(ch.encode_utf8(&mut [0; 4])).map_err(|_| CapacityError { element: ch })
+    }
+
+    /// Removes the last character from this string and returns it, or
+    /// returns `None` if it's empty.
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.as_str().chars().next_back()?;
+        let len = self.len() - ch.len_utf8();
+        self.vec.truncate(len);
+        Some(ch)
+    }
+
+    /// Shortens this string to `len` bytes. If `len` is greater than the
+    /// string's current length, this has no effect.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` does not lie on a `char` boundary.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            assert!(self.as_str().is_char_boundary(len));
+            self.vec.truncate(len);
+        }
+    }
+
+    /// Removes all of the contents of this string.
+    pub fn clear(&mut self) {
+        self.vec.clear();
+    }
+}
+
+impl<'a> Deref for StackString<'a> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Fails, writing nothing, if the string doesn't fit.
+impl<'a> fmt::Write for StackString<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<'a> fmt::Display for StackString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a> fmt::Debug for StackString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a, 'b> PartialEq<StackString<'b>> for StackString<'a> {
+    fn eq(&self, other: &StackString<'b>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<'a> Eq for StackString<'a> {}
+
+impl<'a> PartialEq<str> for StackString<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for StackString<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_str() == *other
+    }
+}
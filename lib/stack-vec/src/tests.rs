@@ -1,4 +1,9 @@
-use crate::StackVec;
+use core::cell::Cell;
+use core::fmt::Write;
+use core::hash::{Hash, Hasher};
+use core::mem::MaybeUninit;
+
+use crate::{ArrayVec, CapacityError, StackDeque, StackMap, StackString, StackVec};
 
 #[test]
 fn assignment_text_example() {
@@ -179,7 +184,7 @@ fn iterator() {
 
     let mut i = 0;
     for val in stack_vec {
-        assert_eq!(*val, i * i);
+        assert_eq!(val, i * i);
         i += 1;
     }
 }
@@ -220,3 +225,631 @@ fn errors() {
         assert_eq!(vec.pop(), None);
     }
 }
+
+#[test]
+fn pop_moves() {
+    #[derive(Debug, PartialEq)]
+    struct NoClone(usize);
+
+    let mut storage: [MaybeUninit<NoClone>; 4] = unsafe { MaybeUninit::uninit().assume_init() };
+    let mut stack_vec = StackVec::from_uninit(&mut storage);
+    stack_vec.push(NoClone(1)).expect("cap = 4");
+    stack_vec.push(NoClone(2)).expect("cap = 4");
+    assert_eq!(stack_vec.pop(), Some(NoClone(2)));
+    assert_eq!(stack_vec.pop(), Some(NoClone(1)));
+    assert_eq!(stack_vec.pop(), None);
+}
+
+#[test]
+fn extend_from_slice() {
+    let mut storage = [0usize; 5];
+    let mut stack_vec = StackVec::new(&mut storage);
+    assert_eq!(stack_vec.try_extend_from_slice(&[]), Ok(()));
+    assert_eq!(stack_vec.try_extend_from_slice(&[1, 2, 3]), Ok(()));
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 3]);
+
+    assert_eq!(stack_vec.try_extend_from_slice(&[4, 5, 6]), Err(CapacityError { element: () }));
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 3]);
+
+    assert_eq!(stack_vec.try_extend_from_slice(&[4, 5]), Ok(()));
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 3, 4, 5]);
+    assert!(stack_vec.try_extend_from_slice(&[6]).is_err());
+}
+
+#[test]
+fn extend() {
+    let mut storage = [0usize; 5];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..3);
+    assert_eq!(stack_vec.as_slice(), &[0, 1, 2]);
+
+    let mut rest = 3..10;
+    stack_vec.extend(&mut rest);
+    assert_eq!(stack_vec.as_slice(), &[0, 1, 2, 3, 4]);
+    assert_eq!(rest.next(), Some(5));
+
+    stack_vec.extend(0..0);
+    assert_eq!(stack_vec.len(), 5);
+}
+
+#[test]
+fn retain() {
+    let mut storage = [0usize; 10];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..10);
+    stack_vec.retain(|&v| v % 3 != 0);
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 4, 5, 7, 8]);
+
+    stack_vec.retain(|_| true);
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 4, 5, 7, 8]);
+
+    stack_vec.push(9).expect("cap = 10");
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 4, 5, 7, 8, 9]);
+
+    stack_vec.retain(|_| false);
+    assert!(stack_vec.is_empty());
+}
+
+#[test]
+fn clear() {
+    let mut storage = [0usize; 10];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..10);
+    stack_vec.clear();
+    assert!(stack_vec.is_empty());
+    assert_eq!(stack_vec.capacity(), 10);
+
+    stack_vec.push(7).expect("cap = 10");
+    assert_eq!(stack_vec.as_slice(), &[7]);
+}
+
+#[test]
+fn into_iter() {
+    let mut storage = [0usize; 10];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..6);
+
+    let mut iter = stack_vec.into_iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next(), Some(0));
+    assert_eq!(iter.next_back(), Some(5));
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.len(), 3);
+    assert_eq!(iter.next_back(), Some(4));
+    assert_eq!(iter.next_back(), Some(3));
+    assert_eq!(iter.next_back(), Some(2));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+
+    let mut storage = [0usize; 10];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..6);
+    assert!(stack_vec.into_iter().rev().eq((0..6).rev()));
+}
+
+#[test]
+fn default() {
+    let mut stack_vec = StackVec::<usize>::default();
+    assert!(stack_vec.is_empty());
+    assert!(stack_vec.is_full());
+    assert_eq!(stack_vec.push(1), Err(()));
+}
+
+#[test]
+fn equality() {
+    let (mut a, mut b) = ([0usize; 4], [0usize; 8]);
+    let mut x = StackVec::new(&mut a);
+    let mut y = StackVec::new(&mut b);
+    assert_eq!(x, y);
+    assert_eq!(x, StackVec::default());
+
+    x.extend(1..4);
+    assert_ne!(x, y);
+    y.extend(1..4);
+    assert_eq!(x, y);
+    y.push(4).expect("cap = 8");
+    assert_ne!(x, y);
+
+    assert_eq!(x, &[1, 2, 3][..]);
+    assert!(x == [1, 2, 3][..]);
+    assert_ne!(x, &[1, 2][..]);
+}
+
+#[test]
+fn hash() {
+    /// FNV-1a.
+    struct Fnv(u64);
+
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 ^ b as u64).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+    }
+
+    fn hash<H: Hash + ?Sized>(value: &H) -> u64 {
+        let mut fnv = Fnv(0xcbf2_9ce4_8422_2325);
+        value.hash(&mut fnv);
+        fnv.finish()
+    }
+
+    let mut storage = [0u8; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(1..4);
+    assert_eq!(hash(&stack_vec), hash(&[1u8, 2, 3][..]));
+    assert_ne!(hash(&stack_vec), hash(&[1u8, 2][..]));
+}
+
+#[test]
+fn try_push() {
+    let mut storage = [0usize; 2];
+    let mut stack_vec = StackVec::new(&mut storage);
+    assert_eq!(stack_vec.try_push(1), Ok(()));
+    assert_eq!(stack_vec.try_push(2), Ok(()));
+
+    let error = stack_vec.try_push(3).expect_err("full");
+    assert_eq!(error.element(), 3);
+    assert_eq!(stack_vec.as_slice(), &[1, 2]);
+
+    stack_vec.pop();
+    assert_eq!(stack_vec.try_push(3), Ok(()));
+    assert_eq!(stack_vec.as_slice(), &[1, 3]);
+}
+
+/// Counts its drops in the `Cell` it borrows.
+#[derive(Debug)]
+struct Counted<'c>(usize, &'c Cell<usize>);
+
+impl<'c> Drop for Counted<'c> {
+    fn drop(&mut self) {
+        self.1.set(self.1.get() + 1);
+    }
+}
+
+#[test]
+fn from_uninit() {
+    let drops = Cell::new(0);
+    let mut storage: [MaybeUninit<Counted>; 8] = unsafe { MaybeUninit::uninit().assume_init() };
+    let mut stack_vec = StackVec::from_uninit(&mut storage);
+    assert_eq!(stack_vec.capacity(), 8);
+    assert!(stack_vec.is_empty());
+
+    stack_vec.extend((0..8).map(|i| Counted(i, &drops)));
+    assert!(stack_vec.is_full());
+    let rejected = stack_vec.try_push(Counted(8, &drops)).expect_err("full").element();
+    assert_eq!(rejected.0, 8);
+    drop(rejected);
+    assert_eq!(drops.get(), 1);
+
+    let popped = stack_vec.pop().expect("has elements");
+    assert_eq!((popped.0, drops.get()), (7, 1));
+    drop(popped);
+    assert_eq!(drops.get(), 2);
+
+    stack_vec.retain(|c| c.0 % 2 == 0);
+    assert_eq!(stack_vec.iter().map(|c| c.0).sum::<usize>(), 2 + 4 + 6);
+    assert_eq!(drops.get(), 5);
+
+    stack_vec.truncate(3);
+    assert_eq!(drops.get(), 6);
+    stack_vec.truncate(5);
+    assert_eq!(drops.get(), 6);
+
+    let mut iter = stack_vec.into_iter();
+    assert_eq!(iter.next().map(|c| c.0), Some(0));
+    assert_eq!(drops.get(), 7);
+    drop(iter);
+    assert_eq!(drops.get(), 9);
+
+    let mut stack_vec = StackVec::from_uninit(&mut storage);
+    stack_vec.extend((0..4).map(|i| Counted(i, &drops)));
+    stack_vec.clear();
+    assert_eq!(drops.get(), 13);
+    stack_vec.extend((0..4).map(|i| Counted(i, &drops)));
+    drop(stack_vec);
+    assert_eq!(drops.get(), 17);
+}
+
+#[test]
+fn array_vec() {
+    let mut vec = ArrayVec::<[usize; 4]>::new();
+    assert_eq!(vec.capacity(), 4);
+    assert!(vec.is_empty());
+    assert_eq!(vec.pop(), None);
+
+    vec.push(1).expect("cap = 4");
+    vec.extend(2..10);
+    assert!(vec.is_full());
+    assert_eq!(vec, &[1, 2, 3, 4][..]);
+    assert_eq!(vec.try_push(5).expect_err("full").element(), 5);
+    assert_eq!(vec.push(6), Err(CapacityError { element: 6 }));
+
+    vec[0] = 10;
+    assert_eq!(vec.pop(), Some(4));
+    vec.retain(|&v| v != 2);
+    assert_eq!(vec.as_slice(), &[10, 3]);
+    assert_eq!(vec.try_extend_from_slice(&[1, 2, 3]), Err(CapacityError { element: () }));
+    assert_eq!(vec.try_extend_from_slice(&[1, 2]), Ok(()));
+
+    let copy = vec.clone();
+    assert_eq!(copy, vec);
+    assert_ne!(copy, ArrayVec::<[usize; 8]>::default());
+    assert!(vec.iter().eq(&copy));
+    assert!(vec.into_iter().rev().eq([2, 1, 3, 10].iter().cloned()));
+}
+
+#[test]
+fn array_vec_drops() {
+    /// Owns its vector, which borrows nothing.
+    struct Holder<'c> {
+        items: ArrayVec<[Counted<'c>; 8]>,
+    }
+
+    let drops = Cell::new(0);
+    let mut holder = Holder { items: ArrayVec::new() };
+    holder.items.extend((0..8).map(|i| Counted(i, &drops)));
+    assert_eq!(drops.get(), 0);
+
+    holder.items.truncate(6);
+    assert_eq!(drops.get(), 2);
+    holder.items.retain(|c| c.0 != 0);
+    assert_eq!(drops.get(), 3);
+    drop(holder.items.pop());
+    assert_eq!(drops.get(), 4);
+
+    let mut iter = holder.items.into_iter();
+    assert_eq!(iter.len(), 4);
+    assert_eq!(iter.next().map(|c| c.0), Some(1));
+    assert_eq!(iter.next_back().map(|c| c.0), Some(4));
+    assert_eq!(drops.get(), 6);
+    drop(iter);
+    assert_eq!(drops.get(), 8);
+
+    let mut items = ArrayVec::<[Counted; 2]>::new();
+    items.extend((0..2).map(|i| Counted(i, &drops)));
+    drop(items);
+    assert_eq!(drops.get(), 10);
+}
+
+#[test]
+fn stack_string() {
+    let mut storage = [0u8; 8];
+    let mut string = StackString::new(&mut storage);
+    assert!(string.is_empty());
+    assert_eq!(string.capacity(), 8);
+
+    string.push_str("ab").expect("cap = 8");
+    string.push_char('é').expect("cap = 8");
+    assert_eq!(string, "abé");
+    assert_eq!(string.len(), 4);
+    assert_eq!(string.as_bytes(), "abé".as_bytes());
+
+    assert!(string.push_str("cdefg").is_err());
+    assert_eq!(string, "abé");
+    string.push_str("cde").expect("cap = 8");
+    string.push_char('f').expect("cap = 8");
+    assert_eq!(string.push_char('g'), Err(CapacityError { element: 'g' }));
+
+    assert_eq!(string.pop(), Some('f'));
+    assert_eq!(string.pop(), Some('e'));
+    assert_eq!(string.pop(), Some('d'));
+    assert_eq!(string.pop(), Some('c'));
+    assert_eq!(string.pop(), Some('é'));
+    assert_eq!(string.as_str(), "ab");
+
+    string.clear();
+    assert_eq!(string.pop(), None);
+    write!(string, "{}-{:02}", 7, 3).expect("fits");
+    assert_eq!(&*string, "7-03");
+    assert!(write!(string, "{}", 12345).is_err());
+    assert_eq!(string.into_str(), "7-03");
+}
+
+#[test]
+#[should_panic]
+fn stack_string_truncate_mid_char() {
+    let mut storage = [0u8; 8];
+    let mut string = StackString::new(&mut storage);
+    string.push_str("aé").expect("cap = 8");
+    string.truncate(2);
+}
+
+#[test]
+fn stack_deque() {
+    let mut storage = [0usize; 4];
+    let mut deque = StackDeque::new(&mut storage);
+    assert!(deque.is_empty());
+    assert_eq!(deque.capacity(), 4);
+    assert_eq!((deque.pop_front(), deque.pop_back()), (None, None));
+    assert_eq!((deque.front(), deque.back()), (None, None));
+
+    // wrap around the end of the storage, twice over
+    for i in 0..10 {
+        deque.push_back(i).expect("not full");
+        deque.push_back(i + 100).expect("not full");
+        assert_eq!(deque.pop_front(), Some(i));
+        assert_eq!(deque.pop_front(), Some(i + 100));
+    }
+
+    deque.extend(1..10);
+    assert!(deque.is_full());
+    assert_eq!(deque.push_back(5).expect_err("full").element(), 5);
+    assert_eq!(deque.push_front(0).expect_err("full").element(), 0);
+    assert!(deque.iter().eq([1, 2, 3, 4].iter()));
+    assert_eq!((deque.front(), deque.back()), (Some(&1), Some(&4)));
+    assert_eq!((deque[0], deque[3]), (1, 4));
+    assert_eq!(deque.get(4), None);
+
+    assert_eq!(deque.pop_back(), Some(4));
+    assert_eq!(deque.pop_front(), Some(1));
+    deque.push_front(0).expect("not full");
+    deque[1] = 20;
+    for value in &mut deque {
+        *value += 1;
+    }
+    assert!(deque.iter().eq([1, 21, 4].iter()));
+
+    let (first, second) = deque.as_slices();
+    assert_eq!(first.len() + second.len(), 3);
+    deque.clear();
+    assert!(deque.is_empty());
+    assert_eq!(deque.iter().next(), None);
+}
+
+#[test]
+#[should_panic]
+fn stack_deque_index_oob() {
+    let mut storage = [0usize; 4];
+    let mut deque = StackDeque::new(&mut storage);
+    deque.push_back(1).expect("not full");
+    let _ = deque[1];
+}
+
+#[test]
+fn stack_deque_drops() {
+    let drops = Cell::new(0);
+    let mut storage: [MaybeUninit<Counted>; 4] = unsafe { MaybeUninit::uninit().assume_init() };
+    let mut deque = StackDeque::from_uninit(&mut storage);
+    assert!(deque.push_front(Counted(0, &drops)).is_ok());
+    deque.extend((1..3).map(|i| Counted(i, &drops)));
+    assert!(deque.push_front(Counted(3, &drops)).is_ok());
+    assert_eq!(drops.get(), 0);
+
+    assert_eq!(deque.pop_back().map(|c| c.0), Some(2));
+    assert_eq!(deque.pop_front().map(|c| c.0), Some(3));
+    assert_eq!(drops.get(), 2);
+    drop(deque);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn stack_map() {
+    let mut storage = [("", ""); 3];
+    let mut map = StackMap::new(&mut storage);
+    assert!(map.is_empty());
+    assert_eq!(map.capacity(), 3);
+    assert_eq!(map.get("PATH"), None);
+    assert_eq!(map.remove("PATH"), None);
+
+    assert_eq!(map.insert("PATH", "/bin"), Ok(None));
+    assert_eq!(map.insert("HOME", "/"), Ok(None));
+    assert_eq!(map.insert("PS1", "> "), Ok(None));
+    assert!(map.is_full());
+    assert_eq!(map.insert("TERM", "vt100"), Err(CapacityError { element: ("TERM", "vt100") }));
+
+    assert_eq!(map.insert("PATH", "/sbin"), Ok(Some("/bin")));
+    assert_eq!(map.get("PATH"), Some(&"/sbin"));
+    assert!(map.contains_key("HOME"));
+    *map.get_mut("HOME").expect("has HOME") = "/root";
+    assert_eq!(map.len(), 3);
+
+    assert_eq!(map.remove("PATH"), Some("/sbin"));
+    assert!(!map.contains_key("PATH"));
+    assert!(map.iter().eq([(&"HOME", &"/root"), (&"PS1", &"> ")].iter().cloned()));
+
+    assert_eq!(map.insert("TERM", "vt100"), Ok(None));
+    let keys = map.iter().map(|(k, _)| *k);
+    assert!(keys.eq(["HOME", "PS1", "TERM"].iter().cloned()));
+
+    map.clear();
+    assert!(map.is_empty());
+}
+
+#[test]
+fn stack_map_drops() {
+    let drops = Cell::new(0);
+    let mut storage: [MaybeUninit<(usize, Counted)>; 2] = unsafe { MaybeUninit::uninit().assume_init() };
+    let mut map = StackMap::from_uninit(&mut storage);
+    assert!(map.insert(1, Counted(1, &drops)).is_ok());
+    assert!(map.insert(2, Counted(2, &drops)).is_ok());
+
+    let old = map.insert(1, Counted(10, &drops)).ok().and_then(|old| old);
+    assert_eq!(old.map(|c| c.0), Some(1));
+    assert_eq!(drops.get(), 1);
+
+    let rejected = map.insert(3, Counted(3, &drops)).err().map(|e| e.element());
+    assert_eq!(rejected.map(|(k, c)| (k, c.0)), Some((3, 3)));
+    assert_eq!(drops.get(), 2);
+
+    assert_eq!(map.remove(&1).map(|c| c.0), Some(10));
+    assert_eq!(drops.get(), 3);
+    drop(map);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+fn dedup() {
+    let mut storage = [0usize; 10];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.dedup();
+    assert!(stack_vec.is_empty());
+
+    stack_vec.try_extend_from_slice(&[1, 1, 2, 3, 3, 3, 1, 4, 4, 4]).expect("cap = 10");
+    stack_vec.dedup();
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 3, 1, 4]);
+
+    // each element is compared with the one kept before it
+    stack_vec.dedup_by(|a, b| *a == *b + 1);
+    assert_eq!(stack_vec.as_slice(), &[1, 3, 1, 4]);
+
+    let mut vec = ArrayVec::<[usize; 4]>::new();
+    vec.extend([7, 7, 7, 8].iter().cloned());
+    vec.dedup();
+    assert_eq!(vec, &[7, 8][..]);
+}
+
+#[test]
+fn dedup_drops() {
+    let drops = Cell::new(0);
+    let mut storage: [MaybeUninit<Counted>; 8] = unsafe { MaybeUninit::uninit().assume_init() };
+    let mut stack_vec = StackVec::from_uninit(&mut storage);
+    stack_vec.extend([0, 0, 1, 1, 1, 2].iter().map(|&i| Counted(i, &drops)));
+    stack_vec.dedup_by(|a, b| a.0 == b.0);
+    assert!(stack_vec.iter().map(|c| c.0).eq(0..3));
+    assert_eq!(drops.get(), 3);
+}
+
+#[test]
+fn split_off() {
+    let mut storage = [0usize; 8];
+    let mut head = StackVec::new(&mut storage);
+    head.extend(0..6);
+
+    let mut tail = head.split_off(2);
+    assert_eq!((head.as_slice(), head.capacity()), (&[0, 1][..], 2));
+    assert_eq!((tail.as_slice(), tail.capacity()), (&[2, 3, 4, 5][..], 6));
+    assert!(head.push(2).is_err());
+    tail.extend(6..10);
+    assert_eq!(tail.as_slice(), &[2, 3, 4, 5, 6, 7]);
+
+    let rest = tail.split_off(6);
+    assert!(rest.is_empty());
+    assert_eq!(rest.capacity(), 0);
+
+    let all = head.split_off(0);
+    assert_eq!(all.as_slice(), &[0, 1]);
+    assert_eq!(head.capacity(), 0);
+}
+
+#[test]
+#[should_panic]
+fn split_off_past_len() {
+    let mut storage = [0usize; 8];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..4);
+    stack_vec.split_off(5);
+}
+
+#[test]
+fn spare_capacity() {
+    /// Reads like a UART would, filling what it's given from `data`.
+    fn read(data: &mut &[u8], buf: &mut [MaybeUninit<u8>]) -> usize {
+        let n = data.len().min(buf.len());
+        for (slot, &byte) in buf.iter_mut().zip(&data[..n]) {
+            unsafe { slot.as_mut_ptr().write(byte) };
+        }
+        *data = &data[n..];
+        n
+    }
+
+    let mut data = &b"hello, world"[..];
+    let mut storage = [0u8; 8];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.push(b'>').expect("cap = 8");
+    assert_eq!(stack_vec.spare_capacity_mut().len(), 7);
+
+    let n = read(&mut data, stack_vec.spare_capacity_mut());
+    unsafe { stack_vec.set_len(stack_vec.len() + n) };
+    assert_eq!(stack_vec.as_slice(), b">hello, ");
+    assert!(stack_vec.spare_capacity_mut().is_empty());
+
+    stack_vec.truncate(1);
+    unsafe { stack_vec.push_unchecked(b'!') };
+    assert_eq!(stack_vec.as_slice(), b">!");
+
+    let mut vec = ArrayVec::<[u8; 16]>::new();
+    unsafe { vec.push_unchecked(b'>') };
+    let n = read(&mut data, vec.spare_capacity_mut());
+    unsafe { vec.set_len(vec.len() + n) };
+    assert_eq!(vec.as_slice(), b">world");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    use serde::de::value::{Error, SeqDeserializer};
+    use serde::de::DeserializeSeed;
+    use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+    let mut storage = [0u16; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(1..4);
+    serde_test::assert_ser_tokens(&stack_vec, &[
+        Token::Seq { len: Some(3) }, Token::U16(1), Token::U16(2), Token::U16(3), Token::SeqEnd,
+    ]);
+
+    // a `StackVec` is filled by using it as the seed
+    let mut storage = [0u16; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.push(7).expect("cap = 4");
+    let input = SeqDeserializer::<_, Error>::new([1u16, 2, 3].iter().cloned());
+    let stack_vec = stack_vec.deserialize(input).expect("fits");
+    assert_eq!(stack_vec.as_slice(), &[7, 1, 2, 3]);
+
+    let mut storage = [0u16; 2];
+    let input = SeqDeserializer::<_, Error>::new([1u16, 2, 3].iter().cloned());
+    let error = StackVec::new(&mut storage).deserialize(input).expect_err("too long");
+    let mut buf = [0u8; 128];
+    let mut message = StackString::new(&mut buf);
+    write!(message, "{}", error).expect("fits");
+    assert_eq!(message, "invalid length 3, expected a sequence of at most 2 elements");
+
+    let mut vec = ArrayVec::<[u16; 4]>::new();
+    vec.extend(1..3);
+    assert_tokens(&vec, &[Token::Seq { len: Some(2) }, Token::U16(1), Token::U16(2), Token::SeqEnd]);
+    assert_de_tokens_error::<ArrayVec<[u16; 1]>>(
+        &[Token::Seq { len: Some(2) }, Token::U16(1), Token::U16(2), Token::SeqEnd],
+        "invalid length 2, expected a sequence of at most 1 elements",
+    );
+}
+
+#[cfg(feature = "shim")]
+#[test]
+fn io_write() {
+    use shim::io::{ErrorKind, Write};
+
+    let mut storage = [0u8; 8];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.write_all(b"abc").expect("fits");
+    write!(stack_vec, "{}", 12).expect("fits");
+    stack_vec.flush().expect("flushes");
+    assert_eq!(stack_vec.as_slice(), b"abc12");
+
+    assert_eq!(stack_vec.write(b"defg").expect("partly fits"), 3);
+    assert_eq!(stack_vec.as_slice(), b"abc12def");
+    assert_eq!(stack_vec.write(b"").expect("nothing to write"), 0);
+    let error = stack_vec.write(b"g").expect_err("full");
+    assert_eq!(error.kind(), ErrorKind::WriteZero);
+
+    stack_vec.truncate(6);
+    let error = stack_vec.write_all(b"xyz").expect_err("doesn't fit");
+    assert_eq!(error.kind(), ErrorKind::WriteZero);
+    assert_eq!(stack_vec.as_slice(), b"abc12dxy");
+}
+
+#[test]
+fn try_from_iter() {
+    let mut storage = [""; 4];
+    let args = StackVec::try_from_iter(&mut storage, "ls  -l /".split(' ').filter(|a| !a.is_empty()));
+    assert_eq!(args.expect("fits").as_slice(), &["ls", "-l", "/"]);
+
+    let mut storage = [0usize; 4];
+    assert_eq!(StackVec::try_from_iter(&mut storage, 0..4).expect("fits"), &[0, 1, 2, 3][..]);
+    assert_eq!(StackVec::try_from_iter(&mut storage, 0..5).err(), Some(CapacityError { element: () }));
+    assert!(StackVec::try_from_iter(&mut storage, None).expect("fits").is_empty());
+}
@@ -0,0 +1,128 @@
+//! The interactive console: keyboard input goes to the device, device output
+//! goes to the terminal, until the user types the escape sequence.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use crate::device::{Cancel, Port};
+
+/// The bytes that end the console, and how much of them has been typed.
+#[derive(Debug)]
+pub struct Escape {
+    sequence: Vec<u8>,
+    matched: usize,
+}
+
+impl Escape {
+    /// Returns an `Escape` for `sequence`, which must not be empty.
+    pub fn new(sequence: Vec<u8>) -> Escape {
+        assert!(!sequence.is_empty(), "empty escape sequence");
+        Escape { sequence, matched: 0 }
+    }
+
+    /// Feeds a typed byte to the matcher, appending what should be sent to
+    /// the device to `out`. Bytes that might start the sequence are held back
+    /// until it's clear they don't. Returns true once the whole sequence has
+    /// been typed.
+    fn feed(&mut self, byte: u8, out: &mut Vec<u8>) -> bool {
+        if byte != self.sequence[self.matched] {
+            out.extend_from_slice(&self.sequence[..self.matched]);
+            self.matched = 0;
+            if byte != self.sequence[0] {
+                out.push(byte);
+                return false;
+            }
+        }
+
+        self.matched += 1;
+        self.matched == self.sequence.len()
+    }
+}
+
+/// Shows control characters the way they're typed, as in `^]`.
+impl fmt::Display for Escape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &byte in &self.sequence {
+            match byte {
+                0x00..=0x1f => write!(f, "^{}", (byte + b'@') as char)?,
+                _ => write!(f, "{}", byte as char)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Puts the terminal on stdin in raw mode, so that keys like Ctrl-C reach
+/// the device instead of this process, until it's dropped.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Enables raw mode, or returns `None` if stdin isn't a terminal.
+    pub fn enable() -> io::Result<Option<RawMode>> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return Ok(None);
+            }
+
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // keep output processing so that the device's bare newlines
+            // still return the cursor
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            raw.c_oflag |= libc::OPOST;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Some(RawMode { original }))
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Copies stdin to `port` until `escape` is typed or stdin closes, then sets
+/// `cancel`. Blocks, so run it on its own thread: reading stdin can't be
+/// interrupted, so the thread may outlive the console.
+pub fn forward_stdin(mut port: Port, mut escape: Escape, cancel: Cancel) -> impl FnOnce() + Send + 'static {
+    move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut buf = [0u8; 256];
+        let mut out = Vec::with_capacity(buf.len());
+        'read: loop {
+            let n = match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+
+            out.clear();
+            for &byte in &buf[..n] {
+                if escape.feed(byte, &mut out) {
+                    let _ = port.write_all(&out);
+                    break 'read;
+                }
+            }
+
+            if port.write_all(&out).is_err() {
+                break;
+            }
+        }
+
+        cancel.cancel();
+    }
+}
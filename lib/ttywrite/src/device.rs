@@ -0,0 +1,205 @@
+//! The serial device, driven by async reader and writer tasks.
+//!
+//! A reader task forwards everything the device sends to a channel and a
+//! writer task writes everything sent to it, so reading device output never
+//! waits on sending and vice versa. `Port` gives blocking code, like the
+//! XMODEM implementation, an `io::Read + io::Write` view of the two channels.
+
+use std::cmp;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// How often blocked reads check for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A flag set when the user presses Ctrl-C.
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+type Incoming = Arc<Mutex<mpsc::Receiver<Vec<u8>>>>;
+
+/// A serial device with its reader and writer tasks running.
+pub struct Device {
+    incoming: Incoming,
+    outgoing: UnboundedSender<Vec<u8>>,
+    writer: JoinHandle<io::Result<()>>,
+}
+
+impl Device {
+    /// Spawns the tasks that read from `reader` and write to `writer`.
+    pub fn spawn<R, W>(mut reader: R, mut writer: W) -> Device
+        where R: AsyncRead + Unpin + Send + 'static, W: AsyncWrite + Unpin + Send + 'static
+    {
+        let (incoming_tx, incoming) = mpsc::channel();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 256];
+            loop {
+                match reader.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(n) => if incoming_tx.send(buf[..n].to_vec()).is_err() { break },
+                    Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let (outgoing, mut outgoing_rx) = unbounded_channel::<Vec<u8>>();
+        let writer = tokio::spawn(async move {
+            while let Some(bytes) = outgoing_rx.recv().await {
+                writer.write_all(&bytes).await?;
+                writer.flush().await?;
+            }
+
+            Ok::<_, io::Error>(())
+        });
+
+        Device { incoming: Arc::new(Mutex::new(incoming)), outgoing, writer }
+    }
+
+    /// Returns a blocking reader and writer for the device. Reads time out
+    /// after `timeout`. Once `cancel` is set, every read and write fails with
+    /// `ConnectionAborted`.
+    pub fn port(&self, timeout: Duration, cancel: Cancel) -> Port {
+        Port {
+            incoming: self.incoming.clone(),
+            outgoing: self.outgoing.clone(),
+            pending: Vec::new(),
+            pos: 0,
+            timeout,
+            cancel,
+            on_cancel: &[],
+            cancelled: false,
+        }
+    }
+
+    /// Copies device output to stdout until the device closes or `cancel` is
+    /// set. Blocks, so run it with `spawn_blocking`.
+    pub fn passthrough(&self, cancel: Cancel) -> impl FnOnce() -> io::Result<()> + Send + 'static {
+        let incoming = self.incoming.clone();
+        move || {
+            let stdout = io::stdout();
+            while !cancel.is_cancelled() {
+                match incoming.lock().unwrap().recv_timeout(POLL_INTERVAL) {
+                    Ok(bytes) => {
+                        let mut stdout = stdout.lock();
+                        stdout.write_all(&bytes)?;
+                        stdout.flush()?;
+                    }
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Waits until everything sent so far has been written to the device.
+    /// Every `Port` must have been dropped first.
+    pub async fn close(self) -> io::Result<()> {
+        drop(self.outgoing);
+        self.writer.await.expect("writer task panicked")
+    }
+}
+
+/// A blocking `io::Read + io::Write` view of a `Device`.
+pub struct Port {
+    incoming: Incoming,
+    outgoing: UnboundedSender<Vec<u8>>,
+    /// Received bytes not returned by `read` yet.
+    pending: Vec<u8>,
+    pos: usize,
+    timeout: Duration,
+    cancel: Cancel,
+    /// Sent to the device once when the port is cancelled.
+    on_cancel: &'static [u8],
+    /// Whether `on_cancel` has been sent.
+    cancelled: bool,
+}
+
+impl Port {
+    /// Makes the port send `bytes` to the device when it's cancelled, to tell
+    /// the other end to stop.
+    pub fn cancel_with(self, bytes: &'static [u8]) -> Port {
+        Port { on_cancel: bytes, ..self }
+    }
+
+    fn check_cancel(&mut self) -> io::Result<()> {
+        if !self.cancel.is_cancelled() {
+            return Ok(());
+        }
+
+        if !self.cancelled {
+            self.cancelled = true;
+            if !self.on_cancel.is_empty() {
+                let _ = self.outgoing.send(self.on_cancel.to_vec());
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::ConnectionAborted, "cancelled"))
+    }
+}
+
+impl io::Read for Port {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check_cancel()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        while self.pos == self.pending.len() {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for the device"));
+            }
+
+            let wait = cmp::min(POLL_INTERVAL, deadline - now);
+            match self.incoming.lock().unwrap().recv_timeout(wait) {
+                Ok(bytes) => {
+                    self.pending = bytes;
+                    self.pos = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+
+            self.check_cancel()?;
+        }
+
+        let n = cmp::min(buf.len(), self.pending.len() - self.pos);
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl io::Write for Port {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_cancel()?;
+        self.outgoing.send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "device writer closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
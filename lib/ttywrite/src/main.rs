@@ -1,17 +1,26 @@
+mod console;
+mod device;
 mod parsers;
+mod ports;
 
-use serial;
 use structopt;
 use structopt_derive::StructOpt;
-use xmodem::Xmodem;
+use xmodem::{Progress, Xmodem};
 
+use std::error::Error;
+use std::io;
 use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use structopt::StructOpt;
-use serial::core::{CharSize, BaudRate, StopBits, FlowControl, SerialDevice, SerialPortSettings};
+use tokio_serial::{DataBits, FlowControl, Parity, Serial, SerialPortSettings, StopBits};
 
-use parsers::{parse_width, parse_stop_bits, parse_flow_control, parse_baud_rate};
+use console::{Escape, RawMode};
+use device::{Cancel, Device};
+use parsers::{parse_width, parse_stop_bits, parse_flow_control, parse_baud_rate, parse_escape};
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Write to TTY using the XMODEM protocol by default.")]
@@ -19,9 +28,13 @@ struct Opt {
     #[structopt(short = "i", help = "Input file (defaults to stdin if not set)", parse(from_os_str))]
     input: Option<PathBuf>,
 
+    #[structopt(short = "o", help = "Output file when receiving (defaults to stdout if not set)",
+                parse(from_os_str))]
+    output: Option<PathBuf>,
+
     #[structopt(short = "b", long = "baud", parse(try_from_str = "parse_baud_rate"),
                 help = "Set baud rate", default_value = "115200")]
-    baud_rate: BaudRate,
+    baud_rate: u32,
 
     #[structopt(short = "t", long = "timeout", parse(try_from_str),
                 help = "Set timeout in seconds", default_value = "10")]
@@ -29,10 +42,17 @@ struct Opt {
 
     #[structopt(short = "w", long = "width", parse(try_from_str = "parse_width"),
                 help = "Set data character width in bits", default_value = "8")]
-    char_width: CharSize,
+    char_width: DataBits,
 
     #[structopt(help = "Path to TTY device", parse(from_os_str))]
-    tty_path: PathBuf,
+    tty_path: Option<PathBuf>,
+
+    #[structopt(short = "a", long = "auto", conflicts_with = "tty_path",
+                help = "Use the only TTY device that looks like a USB serial adapter")]
+    auto: bool,
+
+    #[structopt(short = "l", long = "list", help = "List the TTY devices that look like USB serial adapters and exit")]
+    list: bool,
 
     #[structopt(short = "f", long = "flow-control", parse(try_from_str = "parse_flow_control"),
                 help = "Enable flow control ('hardware' or 'software')", default_value = "none")]
@@ -44,41 +64,222 @@ struct Opt {
 
     #[structopt(short = "r", long = "raw", help = "Disable XMODEM")]
     raw: bool,
+
+    #[structopt(short = "R", long = "receive", conflicts_with = "raw",
+                help = "Receive a file from TTY over XMODEM instead of sending one")]
+    receive: bool,
+
+    #[structopt(long = "verify",
+                help = "Confirm the whole file's CRC-32 with the other end after the transfer, if it asks")]
+    verify: bool,
+
+    #[structopt(short = "F", long = "follow",
+                help = "Keep printing device output after sending, until Ctrl-C")]
+    follow: bool,
+
+    #[structopt(short = "c", long = "console", conflicts_with = "follow",
+                help = "Open an interactive console on TTY after the transfer, until the escape sequence is typed")]
+    console: bool,
+
+    #[structopt(short = "e", long = "escape", parse(try_from_str = "parse_escape"),
+                help = "Set the console's escape sequence ('^X' for Ctrl-X)", default_value = "^]")]
+    escape: Escape,
 }
 
-fn main() {
+/// Sent when the user cancels a transfer: receivers give up after two CANs.
+const XMODEM_CANCEL: &[u8] = &[0x18; 3];
+
+/// How long to wait for keyboard input to be written when the console closes.
+const CONSOLE_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of bytes sent or received so far and the rate they're being
+/// transferred at in bytes per second, updated by `progress`.
+static BYTES_TRANSFERRED: AtomicU64 = AtomicU64::new(0);
+static THROUGHPUT: AtomicU64 = AtomicU64::new(0);
+
+fn progress(p: Progress) {
+    if let Progress::Transferred(totals) = p {
+        BYTES_TRANSFERRED.store(totals.bytes, Ordering::Relaxed);
+        THROUGHPUT.store(totals.throughput().unwrap_or(0), Ordering::Relaxed);
+    }
+}
+
+/// Redraws the progress line every 100ms until `done` is set. `verb` says
+/// which way the bytes are going.
+async fn render_progress(verb: &'static str, total: Option<u64>, done: Arc<AtomicBool>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+    while !done.load(Ordering::Relaxed) {
+        interval.tick().await;
+        let bytes = BYTES_TRANSFERRED.load(Ordering::Relaxed);
+        let rate = THROUGHPUT.load(Ordering::Relaxed);
+        match total {
+            Some(total) if total > 0 => {
+                let bytes = bytes.min(total);
+                eprint!("\r{} {} / {} bytes ({}%) at {} B/s", verb, bytes, total, bytes * 100 / total, rate);
+            }
+            _ => eprint!("\r{} {} bytes at {} B/s", verb, bytes, rate),
+        }
+    }
+    eprintln!();
+}
+
+#[tokio::main]
+async fn main() {
     use std::fs::File;
-    use std::io::{self, BufReader};
 
     let opt = Opt::from_args();
-    let mut port = serial::open(&opt.tty_path).expect("Failed to open serial port");
+    if opt.list {
+        for path in ports::candidates().expect("Failed to list serial devices") {
+            println!("{}", path.display());
+        }
+
+        return;
+    }
+
+    let tty_path = match opt.tty_path {
+        Some(ref path) => path.clone(),
+        None if opt.auto => match ports::only_candidate() {
+            Ok(path) => {
+                eprintln!("using {}", path.display());
+                path
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        },
+        None => {
+            eprintln!("error: no TTY device given; pass its path, or use --auto or --list");
+            process::exit(1);
+        }
+    };
+
+    let timeout = Duration::from_secs(opt.timeout);
+    let settings = SerialPortSettings {
+        baud_rate: opt.baud_rate,
+        data_bits: opt.char_width,
+        flow_control: opt.flow_control,
+        parity: Parity::None,
+        stop_bits: opt.stop_bits,
+        timeout,
+    };
 
-    // Create and configure serial port settings
-    let mut settings = port.read_settings().expect("failed to get settings");
-    settings.set_baud_rate(opt.baud_rate).expect("Failed to set baud rate");
-    settings.set_char_size(opt.char_width); 
-    settings.set_stop_bits(opt.stop_bits);
-    settings.set_flow_control(opt.flow_control); 
-    port.set_timeout(Duration::from_secs(opt.timeout)).expect("failed to set timeout");; 
-    port.write_settings(&settings).expect("failed to write settings");
+    let port = Serial::from_path(&tty_path, &settings).expect("Failed to open serial port");
+    let (reader, writer) = tokio::io::split(port);
+    let device = Device::spawn(reader, writer);
+
+    // Ctrl-C cancels the transfer instead of killing the process, so that the
+    // receiver is told to give up
+    let cancel = Cancel::default();
+    let on_interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_interrupt.cancel();
+        }
+    });
 
     // Handle input source
-    let mut input: Box<dyn io::Read> = match opt.input {
-        Some(path) => Box::new(File::open(path).expect("Failed to open input file")),
-        None => Box::new(io::stdin()),
+    let (input, size): (Box<dyn io::Read + Send>, Option<u64>) = match opt.input {
+        Some(path) => {
+            let file = File::open(path).expect("Failed to open input file");
+            let size = file.metadata().ok().map(|m| m.len());
+            (Box::new(file), size)
+        }
+        None => (Box::new(io::stdin()), None),
+    };
+
+    // Handle output destination, for receiving
+    let output: Box<dyn io::Write + Send> = match opt.output {
+        Some(ref path) if opt.receive => Box::new(File::create(path).expect("Failed to create output file")),
+        _ => Box::new(io::stdout()),
+    };
+
+    // Print device output while sending raw data; XMODEM owns it otherwise
+    let console = if opt.raw {
+        Some(tokio::task::spawn_blocking(device.passthrough(cancel.clone())))
+    } else {
+        None
+    };
+
+    // Handle transmission mode; XMODEM's errors say more than the
+    // `io::Error`s they convert to
+    let port = device.port(timeout, cancel.clone());
+    let result: Result<u64, Box<dyn Error + Send + Sync>> = if opt.raw {
+        let mut port = port;
+        tokio::task::spawn_blocking(move || {
+            let mut input = input;
+            io::copy(&mut input, &mut port)
+        }).await.expect("sender panicked").map_err(Box::from)
+    } else {
+        let done = Arc::new(AtomicBool::new(false));
+        let (verb, total) = if opt.receive { ("received", None) } else { ("sent", size) };
+        let renderer = tokio::spawn(render_progress(verb, total, done.clone()));
+        let port = port.cancel_with(XMODEM_CANCEL);
+        let (verify, receive) = (opt.verify, opt.receive);
+        let result = tokio::task::spawn_blocking(move || {
+            let xmodem = Xmodem::builder().progress(progress).verify(verify);
+            let stats = match receive {
+                true => xmodem.receive(port, output),
+                false => xmodem.transmit(input, port),
+            };
+
+            stats.map(|stats| stats.bytes).map_err(Box::from)
+        }).await.expect("XMODEM task panicked");
+        done.store(true, Ordering::Relaxed);
+        let _ = renderer.await;
+        result
+    };
+
+    match result {
+        // the received data may be going to stdout
+        Ok(bytes_read) if opt.receive => eprintln!("read {} bytes", bytes_read),
+        Ok(bytes_written) => println!("wrote {} bytes", bytes_written),
+        Err(ref e) if cancel.is_cancelled() => {
+            eprintln!("cancelled: {}", e);
+            let _ = device.close().await;
+            process::exit(130);
+        }
+        Err(e) => {
+            eprintln!("transfer failed: {}", e);
+            process::exit(1);
+        }
+    }
+
+    let console = match console {
+        None if opt.follow || opt.console => Some(tokio::task::spawn_blocking(device.passthrough(cancel.clone()))),
+        console => console,
     };
 
-    // Handle transmission mode
-    if opt.raw {
-        let bytes_written = io::copy(&mut input, &mut port).expect("Failed to write data");
-        println!("wrote {} bytes", bytes_written);
+    // with `--follow`, Ctrl-C stops the console; with `--console`, the escape
+    // sequence does; otherwise, stop it now. Stdin is read on a plain thread,
+    // since nothing can interrupt it
+    let raw_mode = if opt.console {
+        eprintln!("console open; type {} to quit", opt.escape);
+        let raw_mode = RawMode::enable().expect("Failed to put the terminal in raw mode");
+        let port = device.port(timeout, cancel.clone());
+        std::thread::spawn(console::forward_stdin(port, opt.escape, cancel.clone()));
+        raw_mode
     } else {
-        let progress = |p| println!("Progress: {:?}", p);
-        let bytes_written = Xmodem::transmit_with_progress(
-            &mut *input,
-            &mut port,
-            progress)
-            .expect("XMODEM transmission failed");
-        println!("wrote {} bytes", bytes_written);
+        if !opt.follow {
+            cancel.cancel();
+        }
+
+        None
+    };
+
+    if let Some(console) = console {
+        console.await.expect("console panicked").expect("Failed to print device output");
     }
-}
\ No newline at end of file
+
+    drop(raw_mode);
+
+    // the keyboard thread holds a port until it reads the escape sequence,
+    // and may never get to if the console ended some other way, so only
+    // wait a moment for what was typed to be written
+    if opt.console {
+        let _ = tokio::time::timeout(CONSOLE_CLOSE_TIMEOUT, device.close()).await;
+        return;
+    }
+
+    device.close().await.expect("Failed to write data");
+}
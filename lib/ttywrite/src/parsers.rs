@@ -1,32 +1,45 @@
-use serial::core::{CharSize, BaudRate, StopBits, FlowControl};
+use tokio_serial::{DataBits, StopBits, FlowControl};
 
-pub fn parse_width(s: &str) -> Result<CharSize, &str> {
+use crate::console::Escape;
+
+pub fn parse_width(s: &str) -> Result<DataBits, &str> {
     match s {
-        "5" => Ok(CharSize::Bits5),
-        "6" => Ok(CharSize::Bits6),
-        "7" => Ok(CharSize::Bits7),
-        "8" => Ok(CharSize::Bits8),
+        "5" => Ok(DataBits::Five),
+        "6" => Ok(DataBits::Six),
+        "7" => Ok(DataBits::Seven),
+        "8" => Ok(DataBits::Eight),
         _ => Err("value must be >= 5 and <= 8")
     }
 }
 
 pub fn parse_stop_bits(s: &str) -> Result<StopBits, &str> {
     match s {
-        "1" => Ok(StopBits::Stop1),
-        "2" => Ok(StopBits::Stop2),
+        "1" => Ok(StopBits::One),
+        "2" => Ok(StopBits::Two),
         _ => Err("value must '1' or '2'")
     }
 }
 
 pub fn parse_flow_control(s: &str) -> Result<FlowControl, &str> {
     match s {
-        "none" => Ok(FlowControl::FlowNone),
-        "software" => Ok(FlowControl::FlowSoftware),
-        "hardware" => Ok(FlowControl::FlowHardware),
+        "none" => Ok(FlowControl::None),
+        "software" => Ok(FlowControl::Software),
+        "hardware" => Ok(FlowControl::Hardware),
         _ => Err("value must be 'none', 'software' (xon/xoff), or 'hardware' (rts/cts)")
     }
 }
 
-pub fn parse_baud_rate(s: &str) -> Result<BaudRate, ::std::num::ParseIntError> {
-    Ok(BaudRate::from_speed(s.parse()?))
+pub fn parse_baud_rate(s: &str) -> Result<u32, ::std::num::ParseIntError> {
+    s.parse()
+}
+
+pub fn parse_escape(s: &str) -> Result<Escape, &str> {
+    let bytes = s.as_bytes();
+    match bytes {
+        [] => Err("value must not be empty"),
+        [b'^', c] if (b'@'..=b'_').contains(&c.to_ascii_uppercase()) => {
+            Ok(Escape::new(vec![c.to_ascii_uppercase() - b'@']))
+        }
+        _ => Ok(Escape::new(bytes.to_vec())),
+    }
 }
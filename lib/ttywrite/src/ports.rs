@@ -0,0 +1,46 @@
+//! Finding the serial devices the Pi might be plugged in as.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Where device files live.
+const DEV: &str = "/dev";
+
+/// Name prefixes of USB serial adapters' device files: Linux's, then macOS's
+/// callout devices, which don't wait for a carrier like the `tty.` ones do.
+const PREFIXES: &[&str] = &[
+    "ttyUSB", "ttyACM",
+    "cu.usbserial", "cu.usbmodem", "cu.SLAB_USBtoUART", "cu.wchusbserial",
+];
+
+/// Returns the paths of the devices that look like USB serial adapters,
+/// sorted.
+pub fn candidates() -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(DEV)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+            paths.push(entry.path());
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns the only device that looks like a USB serial adapter, or an error
+/// saying why there isn't one.
+pub fn only_candidate() -> Result<PathBuf, String> {
+    let mut paths = candidates().map_err(|e| format!("failed to read {}: {}", DEV, e))?;
+    match paths.len() {
+        0 => Err("no serial devices found; is the adapter plugged in?".to_string()),
+        1 => Ok(paths.remove(0)),
+        _ => {
+            let names: Vec<_> = paths.iter().map(|path| path.display().to_string()).collect();
+            Err(format!("several serial devices found ({}); pick one", names.join(", ")))
+        }
+    }
+}
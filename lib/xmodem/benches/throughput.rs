@@ -0,0 +1,107 @@
+//! XMODEM throughput over an in-memory transport, with a fraction of packets
+//! corrupted in flight.
+//!
+//! Run with `cargo bench` in `lib/xmodem`.
+
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use xmodem::Xmodem;
+
+/// The size of the file sent in each iteration.
+const FILE_SIZE: usize = 64 * 1024;
+
+/// One end of an in-memory serial line.
+struct Pipe {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    /// The fraction of outgoing 128-byte payloads to corrupt.
+    error_rate: f64,
+    rng: u64,
+}
+
+fn pipe(error_rate: f64) -> (Pipe, Pipe) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    (
+        Pipe { tx: tx1, rx: rx2, error_rate, rng: 0x2545_f491_4f6c_dd1d },
+        Pipe { tx: tx2, rx: rx1, error_rate: 0.0, rng: 1 },
+    )
+}
+
+impl Pipe {
+    /// Returns a pseudo-random number in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for i in 0..buf.len() {
+            match self.rx.recv() {
+                Ok(byte) => buf[i] = byte,
+                Err(_) => return Ok(i),
+            }
+        }
+
+        Ok(buf.len())
+    }
+}
+
+impl io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the transmitter writes each payload in one call and the framing
+        // bytes in smaller ones; only payloads are corrupted, so that errors
+        // cost a retransmission rather than aborting the transfer
+        let corrupt = if buf.len() == 128 && self.next_f64() < self.error_rate {
+            Some((self.rng as usize) % buf.len())
+        } else {
+            None
+        };
+
+        for (i, &byte) in buf.iter().enumerate() {
+            let byte = if Some(i) == corrupt { byte ^ 0x01 } else { byte };
+            if self.tx.send(byte).is_err() {
+                return Ok(i);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends `file` from a transmitter thread to a receiver thread.
+fn transfer(file: &'static [u8], error_rate: f64) {
+    let (tx, rx) = pipe(error_rate);
+    let sender = thread::spawn(move || Xmodem::transmit(file, tx));
+    let mut received = Vec::with_capacity(file.len());
+    Xmodem::receive(rx, &mut received).expect("receive failed");
+    sender.join().unwrap().expect("transmit failed");
+    assert_eq!(&received[..], file);
+}
+
+fn throughput(c: &mut Criterion) {
+    let file: &'static [u8] = Box::leak((0..FILE_SIZE).map(|i| i as u8).collect::<Vec<_>>().into_boxed_slice());
+
+    let mut group = c.benchmark_group("xmodem");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.sample_size(20);
+    for &error_rate in &[0.0, 0.01, 0.05, 0.1] {
+        let id = BenchmarkId::new("transfer", format!("{}% errors", error_rate * 100.0));
+        group.bench_with_input(id, &error_rate, |b, &error_rate| b.iter(|| transfer(file, error_rate)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+//! Feeds arbitrary bytes to `XmodemCore::handle_byte`, one at a time.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use xmodem::{Action, XmodemCore};
+
+fuzz_target!(|data: &[u8]| {
+    let (mode, data) = match data.split_first() {
+        Some((&mode, data)) => (mode, data),
+        None => return,
+    };
+
+    let mut core = XmodemCore::new(mode & 1 != 0);
+    let mut expected = 1u8;
+    for &byte in data {
+        let wanted = core.wanted();
+        assert!(wanted >= 1 && wanted <= 128);
+
+        match core.handle_byte(byte) {
+            // packets arrive in order, whatever else happens in between
+            Action::Packet(num) => {
+                assert_eq!(num, expected);
+                expected = expected.wrapping_add(1);
+            }
+            Action::Duplicate(num) => assert_eq!(num, expected.wrapping_sub(1)),
+            Action::Continue | Action::Reply(_) | Action::Reject(_) => {}
+            Action::End | Action::Fail(_) | Action::Cancelled => return,
+        }
+    }
+});
@@ -0,0 +1,22 @@
+//! Feeds arbitrary bytes to `Xmodem::read_packet` until it fails or sees EOT.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use xmodem::Xmodem;
+use xmodem_fuzz::Transport;
+
+fuzz_target!(|data: &[u8]| {
+    let mut receiver = Xmodem::new(Transport::new(data));
+    let mut packet = [0u8; 128];
+
+    // every call consumes at least one byte, so this terminates
+    for _ in 0..=data.len() {
+        match receiver.read_packet(&mut packet) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => assert_eq!(n, 128),
+        }
+    }
+
+    panic!("read_packet kept succeeding without consuming input");
+});
@@ -0,0 +1,23 @@
+//! Runs a whole `Xmodem::receive` against a sender that sends arbitrary bytes.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use xmodem::Xmodem;
+use xmodem_fuzz::Transport;
+
+fuzz_target!(|data: &[u8]| {
+    let mut transport = Transport::new(data);
+    let mut output = Vec::new();
+
+    if let Ok(stats) = Xmodem::receive(&mut transport, &mut output) {
+        let n = stats.bytes;
+        assert_eq!(n % 128, 0);
+        assert_eq!(n, output.len() as u64);
+        assert!(n <= data.len() as u64);
+    }
+
+    // the initial C, then at most one reply per byte the sender sent: the
+    // receiver can't be made to retry without the sender's help
+    assert!(transport.output.len() <= data.len() + 1);
+});
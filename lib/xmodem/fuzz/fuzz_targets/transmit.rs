@@ -0,0 +1,28 @@
+//! Runs a whole `Xmodem::transmit` against a receiver that replies with
+//! arbitrary bytes. The first input byte picks how much of the rest is the
+//! file being sent; what follows is the receiver's side of the conversation.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use xmodem::Xmodem;
+use xmodem_fuzz::Transport;
+
+fuzz_target!(|data: &[u8]| {
+    let (&len, rest) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let (file, replies) = rest.split_at((len as usize * 4).min(rest.len()));
+    let mut transport = Transport::new(replies);
+
+    if let Ok(stats) = Xmodem::transmit(file, &mut transport) {
+        assert_eq!(stats.bytes, file.len() as u64);
+    }
+
+    // each packet or EOT is sent in response to one reply from the receiver,
+    // so the receiver controls how many times anything is retransmitted
+    let sent = transport.output.len();
+    assert!(sent <= (replies.len() - transport.remaining() + 1) * 133);
+});
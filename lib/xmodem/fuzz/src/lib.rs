@@ -0,0 +1,38 @@
+//! Helpers shared by the xmodem fuzz targets.
+
+use std::io;
+
+/// An in-memory transport: reads come from a fixed script of peer bytes and
+/// end with EOF, writes are recorded.
+pub struct Transport<'a> {
+    input: &'a [u8],
+    pub output: Vec<u8>,
+}
+
+impl<'a> Transport<'a> {
+    pub fn new(input: &'a [u8]) -> Transport<'a> {
+        Transport { input, output: Vec::new() }
+    }
+
+    /// Returns the number of scripted bytes not read yet.
+    pub fn remaining(&self) -> usize {
+        self.input.len()
+    }
+}
+
+impl<'a> io::Read for Transport<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl<'a> io::Write for Transport<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use core::time::Duration;
+
+#[cfg(not(feature = "no_std"))]
+use std::time::Instant;
+
+/// A source of time for timeouts and transfer statistics. Set with
+/// `Builder::clock`.
+///
+/// Any `Fn() -> Duration` returning the time since some fixed point is a
+/// clock, so on the Pi, `pi::timer::current_time` can be used as is.
+pub trait Clock {
+    /// The time since some fixed point, or `None` if it can't be told.
+    fn now(&self) -> Option<Duration>;
+}
+
+impl<F: Fn() -> Duration> Clock for F {
+    fn now(&self) -> Option<Duration> {
+        Some(self())
+    }
+}
+
+/// A clock that can't tell the time. Timeouts fire on the first read that
+/// times out, and no durations are measured.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoClock;
+
+impl Clock for NoClock {
+    fn now(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Measures time with `std::time::Instant`.
+#[cfg(not(feature = "no_std"))]
+#[derive(Debug, Copy, Clone)]
+pub struct StdClock {
+    epoch: Instant,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl Default for StdClock {
+    fn default() -> StdClock {
+        StdClock { epoch: Instant::now() }
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl Clock for StdClock {
+    fn now(&self) -> Option<Duration> {
+        Some(self.epoch.elapsed())
+    }
+}
+
+/// The clock used unless `Builder::clock` sets another: `StdClock`, or
+/// `NoClock` under `no_std`.
+#[cfg(not(feature = "no_std"))]
+pub type DefaultClock = StdClock;
+
+/// The clock used unless `Builder::clock` sets another: `StdClock`, or
+/// `NoClock` under `no_std`.
+#[cfg(feature = "no_std")]
+pub type DefaultClock = NoClock;
+
+/// Tracks how long the transport has gone without delivering a byte.
+pub(crate) struct Silence {
+    since: Option<Duration>,
+}
+
+impl Silence {
+    pub(crate) fn new() -> Silence {
+        Silence { since: None }
+    }
+
+    /// Records that a read timed out at `now`. Returns `true` once the
+    /// transport has been silent for `limit` or longer, or if there's no
+    /// clock to tell.
+    pub(crate) fn exceeds(&mut self, now: Option<Duration>, limit: Duration) -> bool {
+        match now {
+            Some(now) => now >= *self.since.get_or_insert(now) + limit,
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use core::fmt;
+
+use shim::io;
+
+/// Why a transfer failed.
+#[derive(Debug)]
+pub enum XmodemError {
+    /// The transport failed, or a read timed out.
+    Io(io::Error),
+    /// The peer cancelled the transfer with `CAN` after `packets` packets
+    /// were acknowledged. These include any skipped with `Xmodem::resume`,
+    /// so resuming from `packets` picks up where the transfer stopped.
+    Cancelled { packets: u64 },
+    /// A packet's checksum or CRC didn't match its data. It was NAKed.
+    ChecksumMismatch,
+    /// The receiver NAKed a packet.
+    Nak,
+    /// The sender repeated the last packet, most likely because it missed
+    /// the ACK. It was acknowledged again and its contents discarded.
+    Duplicate,
+    /// A packet arrived out of sequence.
+    PacketNumber { expected: u8, received: u8 },
+    /// The peer sent a byte the protocol doesn't allow at this point.
+    UnexpectedByte { expected: &'static str, received: u8 },
+    /// A packet failed as many times as the `RetryPolicy` allows.
+    RetriesExhausted,
+    /// A buffer passed to `read_packet` or `write_packet` has the wrong
+    /// length.
+    BadBuffer,
+    /// A YMODEM header or a manifest is malformed, or a file can't be
+    /// described by one.
+    BadHeader(&'static str),
+    /// The data received doesn't match the CRC-32 the sender computed for
+    /// it, although every packet was intact. `expected` is the sender's and
+    /// `computed` the receiver's.
+    CrcMismatch { expected: u32, computed: u32 },
+    /// The sender sent more than `Builder::max_len` allows, so the transfer
+    /// was cancelled.
+    TooLarge { max_len: usize },
+}
+
+/// The result of an XMODEM operation.
+pub type Result<T> = core::result::Result<T, XmodemError>;
+
+impl XmodemError {
+    /// Returns `true` if this is a transport read that timed out.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            XmodemError::Io(e) => e.kind() == io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` for errors the transfer recovers from by trying the
+    /// packet again.
+    pub(crate) fn is_retryable(&self) -> bool {
+        match self {
+            XmodemError::ChecksumMismatch | XmodemError::Nak => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for XmodemError {
+    fn from(e: io::Error) -> XmodemError {
+        XmodemError::Io(e)
+    }
+}
+
+/// Converts to the `io::Error`s the API returned before `XmodemError`:
+/// a rejected packet is `Interrupted`, which callers driving `read_packet`
+/// themselves retry on.
+impl From<XmodemError> for io::Error {
+    fn from(e: XmodemError) -> io::Error {
+        let (kind, message) = match e {
+            XmodemError::Io(e) => return e,
+            XmodemError::Cancelled { .. } => (io::ErrorKind::ConnectionAborted, "received CAN"),
+            XmodemError::ChecksumMismatch => (io::ErrorKind::Interrupted, "checksum mismatch"),
+            XmodemError::Nak => (io::ErrorKind::Interrupted, "checksum failed"),
+            XmodemError::Duplicate => (io::ErrorKind::Interrupted, "duplicate packet"),
+            XmodemError::PacketNumber { .. } => (io::ErrorKind::InvalidData, "packet number mismatch"),
+            XmodemError::UnexpectedByte { expected, .. } => (io::ErrorKind::InvalidData, expected),
+            XmodemError::RetriesExhausted => (io::ErrorKind::BrokenPipe, "too many retries"),
+            XmodemError::BadBuffer => (io::ErrorKind::UnexpectedEof, "buffer length must be 128 or 0"),
+            XmodemError::BadHeader(message) => (io::ErrorKind::InvalidData, message),
+            XmodemError::CrcMismatch { .. } => (io::ErrorKind::InvalidData, "CRC-32 mismatch"),
+            XmodemError::TooLarge { .. } => (io::ErrorKind::InvalidData, "transfer exceeds maximum length"),
+        };
+
+        io::Error::new(kind, message)
+    }
+}
+
+impl fmt::Display for XmodemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmodemError::Io(e) => write!(f, "{}", e),
+            XmodemError::Cancelled { packets } => write!(f, "transfer cancelled by the peer after {} packets", packets),
+            XmodemError::ChecksumMismatch => f.write_str("packet checksum mismatch"),
+            XmodemError::Nak => f.write_str("packet NAKed by the receiver"),
+            XmodemError::Duplicate => f.write_str("duplicate packet"),
+            XmodemError::PacketNumber { expected, received } => {
+                write!(f, "expected packet {}, received packet {}", expected, received)
+            }
+            XmodemError::UnexpectedByte { expected, received } => write!(f, "{}, received {:#04x}", expected, received),
+            XmodemError::RetriesExhausted => f.write_str("too many retries"),
+            XmodemError::BadBuffer => f.write_str("buffer length must be 128 or 0"),
+            XmodemError::BadHeader(message) => f.write_str(message),
+            XmodemError::CrcMismatch { expected, computed } => {
+                write!(f, "CRC-32 mismatch: sender has {:#010x}, receiver computed {:#010x}", expected, computed)
+            }
+            XmodemError::TooLarge { max_len } => write!(f, "transfer exceeds maximum length of {} bytes", max_len),
+        }
+    }
+}
+
+/// Transport errors are displayed as they are, so their source is the
+/// transport error's source.
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for XmodemError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XmodemError::Io(e) => e.source(),
+            _ => None,
+        }
+    }
+}
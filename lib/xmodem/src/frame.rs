@@ -0,0 +1,152 @@
+//! The bytes XMODEM puts on the wire, for code that drives the protocol
+//! itself, like an interrupt-driven receive loop.
+//!
+//! A packet is `SOH`, its number, the number's complement, 128 bytes of
+//! data, and either an 8-bit checksum or a big-endian CRC-16 of the data:
+//!
+//! ```text
+//! | SOH | num | !num | data (128) | checksum (1) or CRC-16 (2) |
+//! ```
+//!
+//! `encode_packet` and `decode_packet` build and take apart whole packets;
+//! `XmodemCore` takes them apart a byte at a time.
+
+use crate::{Result, XmodemError};
+
+/// Starts a packet.
+pub const SOH: u8 = 0x01;
+/// Ends a transmission.
+pub const EOT: u8 = 0x04;
+/// Acknowledges a packet or `EOT`.
+pub const ACK: u8 = 0x06;
+/// Rejects a packet, or asks for checksum packets to start.
+pub const NAK: u8 = 0x15;
+/// Cancels the transfer, sent twice.
+pub const CAN: u8 = 0x18;
+/// Escapes the bytes that follow it inside packets. See `Builder::escape`.
+pub const DLE: u8 = 0x10;
+/// Software flow control: resume and pause sending.
+pub const XON: u8 = 0x11;
+pub const XOFF: u8 = 0x13;
+/// Sent by the receiver instead of `NAK` to ask for CRC-16 trailers.
+pub const CRC: u8 = b'C';
+/// Sent by the receiver instead of `C` to ask for XMODEM-G: CRC-16 packets
+/// streamed without waiting for ACKs.
+pub const STREAM: u8 = b'G';
+
+/// The byte the XMODEM specification pads the last packet with. See
+/// `Builder::pad`.
+pub const SUB: u8 = 0x1A;
+
+/// The bytes of data in a packet.
+pub const DATA_LEN: usize = 128;
+
+/// The length of the longest packet, one with a CRC-16.
+pub const MAX_PACKET_LEN: usize = 3 + DATA_LEN + 2;
+
+/// Computes the 8-bit checksum of `buf`: the sum of its bytes.
+pub fn checksum(buf: &[u8]) -> u8 {
+    buf.iter().fold(0, |a, b| a.wrapping_add(*b))
+}
+
+/// Computes the CRC-16/XMODEM of `buf`: polynomial 0x1021, initial value 0,
+/// no reflection.
+pub fn crc16(buf: &[u8]) -> u16 {
+    update_crc16(0, buf)
+}
+
+/// Continues the CRC-16/XMODEM `crc` over `buf`.
+pub fn update_crc16(crc: u16, buf: &[u8]) -> u16 {
+    buf.iter().fold(crc, |crc, &byte| {
+        let mut crc = crc ^ ((byte as u16) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Continues the CRC-32 `crc` over `buf`. This is the CRC-32 of zip and
+/// Ethernet: reflected polynomial 0xEDB88320, with the input and output
+/// inverted, so the CRC-32 of `buf` is `update_crc32(0, buf)`.
+pub fn update_crc32(crc: u32, buf: &[u8]) -> u32 {
+    !buf.iter().fold(!crc, |crc, &byte| {
+        let mut crc = crc ^ byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+/// Whether `byte` is escaped inside packets: XON, XOFF, and `DLE` itself,
+/// with or without the high bit set.
+pub(crate) fn needs_escape(byte: u8) -> bool {
+    match byte & 0x7F {
+        DLE | XON | XOFF => true,
+        _ => false,
+    }
+}
+
+/// The length of a packet ending in a CRC-16 if `crc` is set, or in an
+/// 8-bit checksum otherwise.
+pub fn packet_len(crc: bool) -> usize {
+    match crc {
+        true => MAX_PACKET_LEN,
+        false => MAX_PACKET_LEN - 1,
+    }
+}
+
+/// Writes packet number `num` carrying `data` into `out`, ending it in a
+/// CRC-16 if `crc` is set, or in a checksum otherwise. Returns the length
+/// of the packet, `packet_len(crc)`.
+///
+/// Fails with `BadBuffer` unless `data` is `DATA_LEN` bytes long.
+pub fn encode_packet(num: u8, data: &[u8], crc: bool, out: &mut [u8; MAX_PACKET_LEN]) -> Result<usize> {
+    if data.len() != DATA_LEN {
+        return Err(XmodemError::BadBuffer);
+    }
+
+    out[..3].copy_from_slice(&[SOH, num, !num]);
+    out[3..3 + DATA_LEN].copy_from_slice(data);
+    match crc {
+        true => out[3 + DATA_LEN..].copy_from_slice(&crc16(data).to_be_bytes()),
+        false => out[3 + DATA_LEN] = checksum(data),
+    }
+
+    Ok(packet_len(crc))
+}
+
+/// Takes apart the packet in `packet`, which ends in a CRC-16 if `crc` is
+/// set, or in a checksum otherwise. Returns its number and its data.
+///
+/// Whether the number is the one expected is up to the caller. Fails with
+/// `BadBuffer` unless `packet` is `packet_len(crc)` bytes long,
+/// `UnexpectedByte` if it doesn't start with `SOH`, `PacketNumber` if the
+/// number and its complement disagree, and `ChecksumMismatch` if the data
+/// is corrupt.
+pub fn decode_packet(packet: &[u8], crc: bool) -> Result<(u8, &[u8])> {
+    if packet.len() != packet_len(crc) {
+        return Err(XmodemError::BadBuffer);
+    }
+
+    if packet[0] != SOH {
+        return Err(XmodemError::UnexpectedByte { expected: "expected SOH", received: packet[0] });
+    }
+
+    let (num, neg) = (packet[1], packet[2]);
+    if neg != !num {
+        return Err(XmodemError::PacketNumber { expected: !neg, received: num });
+    }
+
+    let (data, check) = packet[3..].split_at(DATA_LEN);
+    let valid = match crc {
+        true => crc16(data).to_be_bytes() == [check[0], check[1]],
+        false => checksum(data) == check[0],
+    };
+
+    match valid {
+        true => Ok((num, data)),
+        false => Err(XmodemError::ChecksumMismatch),
+    }
+}
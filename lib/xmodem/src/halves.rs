@@ -0,0 +1,32 @@
+use shim::io;
+
+/// A transport made of a separate reader and writer, such as the two ends of
+/// a split UART. Returned by `Xmodem::with_halves`.
+#[derive(Debug)]
+pub struct Halves<R, W> {
+    pub reader: R,
+    pub writer: W,
+}
+
+impl<R, W> Halves<R, W> {
+    /// Returns the reader and the writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: io::Read, W> io::Read for Halves<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R, W: io::Write> io::Write for Halves<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -2,268 +2,860 @@
 
 #![feature(decl_macro)]
 
+#[cfg(all(feature = "no_std", feature = "testing"))]
+extern crate std;
+
+#[cfg(any(feature = "alloc", not(feature = "no_std")))]
+extern crate alloc;
+
+use core::time::Duration;
+
+#[cfg(any(feature = "alloc", not(feature = "no_std")))]
+use alloc::vec::Vec;
+
 use shim::io;
-use shim::ioerr;
+
+/// Records a protocol event as a `log` record at `$level` when the `log`
+/// feature is enabled. Otherwise, the arguments are only type-checked.
+macro_rules! event {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
 
 #[cfg(test)] mod tests;
+mod clock;
+mod error;
+pub mod frame;
+mod halves;
 mod read_ext;
 mod progress;
-
-pub use progress::{Progress, ProgressFn};
+mod policy;
+mod stats;
+pub mod machine;
+pub mod manifest;
+pub mod ymodem;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod zmodem;
+
+pub use clock::{Clock, DefaultClock, NoClock};
+#[cfg(not(feature = "no_std"))]
+pub use clock::StdClock;
+pub use error::{Result, XmodemError};
+pub use frame::SUB;
+pub use halves::Halves;
+pub use progress::{Progress, ProgressFn, Totals};
+pub use policy::{Builder, RetryPolicy};
+pub use stats::TransferStats;
+pub use machine::{Action, XmodemCore};
+pub use manifest::Manifest;
+pub use ymodem::Ymodem;
+pub use zmodem::Zmodem;
 
 use read_ext::ReadExt;
+use frame::{needs_escape, update_crc32, ACK, CAN, CRC, DLE, EOT, MAX_PACKET_LEN, NAK, SOH, STREAM};
+use clock::Silence;
+use stats::Tracker;
 
-const SOH: u8 = 0x01;
-const EOT: u8 = 0x04;
-const ACK: u8 = 0x06;
-const NAK: u8 = 0x15;
-const CAN: u8 = 0x18;
+/// How many times the receiver sends `C` before falling back to checksums,
+/// unless `Builder::polls` says otherwise.
+const CRC_POLLS: usize = 3;
 
 /// Implementation of the XMODEM protocol.
-pub struct Xmodem<R> {
-    packet: u8,
+///
+/// `P` is the progress callback. It can be any `FnMut(Progress)`, so it may
+/// capture and update state such as a progress bar. `C` is the `Clock` that
+/// timeouts are measured with.
+pub struct Xmodem<R, P = ProgressFn, C = DefaultClock> {
+    /// The packet number and mode, shared by both directions, and the
+    /// packet being received. When streaming, any error cancels the
+    /// transfer, since there's no way to ask for a packet again.
+    core: XmodemCore,
+    /// Whether `core` starts out streaming, as `Builder::streaming` set.
+    /// Transfers may fall back, so `cancel` restores it from here.
+    streaming: bool,
+    /// Whether `core` escapes flow control bytes, as `Builder::escape` set.
+    escape: bool,
     started: bool,
+    /// Whether to exchange a CRC-32 of the payload after EOT, if the peer
+    /// agrees.
+    verify: bool,
+    /// Whether the receiver answered the last EOT by asking for a CRC-32.
+    verify_requested: bool,
+    /// The CRC-32 of the packets transferred so far.
+    payload_crc: u32,
     inner: R,
-    progress: ProgressFn
+    progress: P,
+    tracker: Tracker<C>,
+    policy: RetryPolicy,
+    /// When the packet being read must have arrived by, per the clock.
+    deadline: Option<Duration>,
+    /// Fills out the last packet of a transmission.
+    pad: u8,
+    /// Trimmed from the end of the last packet received, if set.
+    trim: Option<u8>,
+    /// The most bytes a transfer may deliver when receiving, if set.
+    max_len: Option<usize>,
+    /// How many packets were skipped with `resume`.
+    resumed: u64,
 }
 
 impl Xmodem<()> {
+    /// Returns a builder to configure retries, timeouts, and progress
+    /// reporting.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
     #[inline]
-    pub fn transmit<R, W>(data: R, to: W) -> io::Result<usize>
+    pub fn transmit<R, W>(data: R, to: W) -> Result<TransferStats>
         where W: io::Read + io::Write, R: io::Read
     {
         Xmodem::transmit_with_progress(data, to, progress::noop)
     }
 
-    pub fn receive_with_progress<R, W>(from: R, mut into: W, f: ProgressFn) -> io::Result<usize>
-    where R: io::Read + io::Write, W: io::Write
+    pub fn receive_with_progress<R, W, P>(from: R, into: W, f: P) -> Result<TransferStats>
+    where R: io::Read + io::Write, W: io::Write, P: FnMut(Progress)
     {
-        let mut receiver = Xmodem::new_with_progress(from, f);
-        let mut packet = [0u8; 128];
-        let mut received = 0;
-
-        // Send initial NAK to initiate transfer
-        receiver.write_byte(NAK)?;
-
-        'next_packet: loop {
-            for _ in 0..10 {
-                match receiver.read_packet(&mut packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(0) => break 'next_packet,
-                    Ok(n) => {
-                        received += n;
-                        into.write_all(&packet)?;
-                        continue 'next_packet;
-                    }
-                }
-            }
-            return ioerr!(BrokenPipe, "bad receive");
-        }
-
-        Ok(received)
+        Xmodem::builder().progress(f).receive(from, into)
     }
 
     #[inline]
-    pub fn receive<R, W>(from: R, into: W) -> io::Result<usize>
+    pub fn receive<R, W>(from: R, into: W) -> Result<TransferStats>
        where R: io::Read + io::Write, W: io::Write
     {
         Xmodem::receive_with_progress(from, into, progress::noop)
     }
 
-    pub fn transmit_with_progress<R, W>(mut data: R, to: W, f: ProgressFn) -> io::Result<usize>
-    where W: io::Read + io::Write, R: io::Read
+    pub fn transmit_with_progress<R, W, P>(data: R, to: W, f: P) -> Result<TransferStats>
+    where W: io::Read + io::Write, R: io::Read, P: FnMut(Progress)
+    {
+        Xmodem::builder().progress(f).transmit(data, to)
+    }
+
+    /// Transmits all of `data`.
+    #[inline]
+    pub fn transmit_slice<W>(data: &[u8], to: W) -> Result<TransferStats>
+        where W: io::Read + io::Write
+    {
+        Xmodem::transmit(data, to)
+    }
+
+    /// Receives a whole transfer into a new `Vec`. Under `no_std`, this
+    /// needs the `alloc` feature.
+    #[cfg(any(feature = "alloc", not(feature = "no_std")))]
+    #[inline]
+    pub fn receive_to_vec<R>(from: R) -> Result<Vec<u8>>
+        where R: io::Read + io::Write
     {
-        let mut transmitter = Xmodem::new_with_progress(to, f);
+        Xmodem::builder().receive_to_vec(from)
+    }
+
+    /// Like `receive`, but passes each packet to `handler` as it arrives
+    /// instead of writing it to an `io::Write`. Packets are whole, so the
+    /// last one includes its padding.
+    #[inline]
+    pub fn receive_with_handler<R, F>(from: R, handler: F) -> Result<TransferStats>
+        where R: io::Read + io::Write, F: FnMut(&[u8; 128])
+    {
+        Xmodem::builder().receive_with_handler(from, handler)
+    }
+}
+
+impl<T: io::Read + io::Write> Xmodem<T> {
+   
+    pub fn new(inner: T) -> Self {
+        Xmodem::new_with_progress(inner, progress::noop as ProgressFn)
+    }
+}
+
+impl<R: io::Read, W: io::Write> Xmodem<Halves<R, W>> {
+    /// Returns an `Xmodem` that reads from `reader` and writes to `writer`.
+    pub fn with_halves(reader: R, writer: W) -> Self {
+        Xmodem::new(Halves { reader, writer })
+    }
+}
+
+impl<T: io::Read + io::Write, P: FnMut(Progress)> Xmodem<T, P> {
+    pub fn new_with_progress(inner: T, f: P) -> Self {
+        Xmodem::new_with_clock(inner, f, DefaultClock::default())
+    }
+}
+
+impl<T: io::Read + io::Write, P: FnMut(Progress), C: Clock> Xmodem<T, P, C> {
+    pub(crate) fn new_with_clock(inner: T, f: P, clock: C) -> Self {
+        Xmodem {
+            core: XmodemCore::new(false),
+            streaming: false,
+            escape: false,
+            started: false,
+            verify: false,
+            verify_requested: false,
+            payload_crc: 0,
+            inner,
+            progress: f,
+            tracker: Tracker::new(clock),
+            policy: RetryPolicy::default(),
+            deadline: None,
+            pad: 0,
+            trim: None,
+            max_len: None,
+            resumed: 0,
+        }
+    }
+ 
+    /// Receives a whole transfer into `into`.
+    ///
+    /// Unlike `Xmodem::receive`, this leaves the transport in `self`, so it
+    /// can be recovered with `into_inner` afterwards.
+    pub fn receive_into<W: io::Write>(&mut self, mut into: W) -> Result<TransferStats> {
+        self.receive_trimmed(|data| Ok(into.write_all(data)?))
+    }
+
+    /// Receives a whole transfer, passing the data to `write` with any
+    /// `trim` applied.
+    fn receive_trimmed<F>(&mut self, mut write: F) -> Result<TransferStats>
+        where F: FnMut(&[u8]) -> Result<()>
+    {
+        // with `trim`, each packet is held back until the next one arrives,
+        // since only the last one can be padded
+        let trim = self.trim;
+        let mut held = None;
+        self.receive_packets(|packet| match trim {
+            Some(_) => match held.replace(*packet) {
+                Some(previous) => write(&previous),
+                None => Ok(()),
+            },
+            None => write(packet),
+        })?;
+
+        if let (Some(last), Some(pad)) = (held, trim) {
+            let len = last.iter().rposition(|&b| b != pad).map_or(0, |i| i + 1);
+            write(&last[..len])?;
+            self.tracker.trimmed(128 - len);
+        }
+
+        Ok(self.stats())
+    }
+
+    /// Receives a whole transfer, passing each packet to `handle`.
+    fn receive_packets<F>(&mut self, mut handle: F) -> Result<TransferStats>
+        where F: FnMut(&[u8; 128]) -> Result<()>
+    {
+        let mut packet = [0u8; 128];
+        self.tracker.reset();
+
+        // Ask for CRC mode to initiate transfer; `next_packet` falls back to
+        // checksums if the sender doesn't respond.
+        self.core.crc = true;
+        self.request_start()?;
+
+        loop {
+            match self.next_packet(&mut packet)? {
+                0 => {
+                    self.tracker.finish();
+                    event!(debug, "received {:?}", self.stats());
+                    return Ok(self.stats());
+                }
+                n => {
+                    let delivered = self.stats().bytes + n as u64;
+                    if let Some(max_len) = self.max_len.filter(|&max_len| delivered > max_len as u64) {
+                        self.cancel()?;
+                        return Err(XmodemError::TooLarge { max_len });
+                    }
+
+                    self.tracker.delivered(n);
+                    handle(&packet)?;
+                }
+            }
+        }
+    }
+
+    /// Transmits all of `data`.
+    ///
+    /// Unlike `Xmodem::transmit`, this leaves the transport in `self`, so it
+    /// can be recovered with `into_inner` afterwards.
+    pub fn transmit_from<R: io::Read>(&mut self, mut data: R) -> Result<TransferStats> {
         let mut packet = [0u8; 128];
-        let mut written = 0;
+        self.tracker.reset();
 
-        'next_packet: loop {
+        loop {
             let n = data.read_max(&mut packet)?;
-            packet[n..].iter_mut().for_each(|b| *b = 0);
+            let pad = self.pad;
+            packet[n..].iter_mut().for_each(|b| *b = pad);
 
             if n == 0 {
-                transmitter.write_packet(&[])?;
-                return Ok(written);
+                self.write_packet(&[])?;
+                self.tracker.finish();
+                event!(debug, "transmitted {:?}", self.stats());
+                return Ok(self.stats());
+            }
+
+            self.write_packet(&packet)?;
+            self.tracker.delivered(n);
+        }
+    }
+
+    /// Fills `buf` from the transport. Reads that time out are retried until
+    /// no byte has arrived for the policy's `byte_timeout`, or the packet's
+    /// deadline has passed.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        let mut silence = Silence::new();
+        while !buf.is_empty() {
+            if self.overdue() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "packet timed out").into());
             }
 
-            for _ in 0..10 {
-                match transmitter.write_packet(&packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(_) => {
-                        written += n;
-                        continue 'next_packet;
+            match self.inner.read(buf) {
+                Ok(0) => {
+                    let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer");
+                    return Err(eof.into());
+                }
+                Ok(n) => {
+                    let tmp = buf;
+                    buf = &mut tmp[n..];
+                    silence = Silence::new();
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let waiting = match (e.kind(), self.policy.byte_timeout) {
+                        (io::ErrorKind::TimedOut, Some(limit))
+                        | (io::ErrorKind::WouldBlock, Some(limit)) => !silence.exceeds(self.tracker.now(), limit),
+                        _ => false,
+                    };
+
+                    if !waiting {
+                        return Err(e.into());
                     }
                 }
             }
+        }
 
-            return ioerr!(BrokenPipe, "bad transmit");
+        Ok(())
+    }
+
+    /// Fills `buf` with the contents of a packet, undoing `escape`. Bare
+    /// XON and XOFF are then flow control rather than data, and skipped.
+    fn read_escaped(&mut self, buf: &mut [u8]) -> Result<()> {
+        if !self.core.escape {
+            return self.read_exact(buf);
         }
+
+        for slot in buf.iter_mut() {
+            let mut escaped = false;
+            *slot = loop {
+                match self.read_byte(false)? {
+                    DLE if !escaped => escaped = true,
+                    byte if needs_escape(byte) => continue,
+                    byte if escaped => break byte ^ 0x40,
+                    byte => break byte,
+                }
+            };
+        }
+
+        Ok(())
     }
-}
 
-fn get_checksum(buf: &[u8]) -> u8 {
-    return buf.iter().fold(0, |a, b| a.wrapping_add(*b));
-}
+    /// Writes the contents of a packet, escaping the bytes that software
+    /// flow control would swallow if `escape` is set.
+    fn write_escaped(&mut self, data: &[u8]) -> Result<()> {
+        if !self.core.escape {
+            return Ok(self.inner.write_all(data)?);
+        }
 
-impl<T: io::Read + io::Write> Xmodem<T> {
-   
-    pub fn new(inner: T) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: progress::noop}
+        let mut buf = [0u8; 2 * 128];
+        let mut len = 0;
+        for &byte in data {
+            if len + 2 > buf.len() {
+                self.inner.write_all(&buf[..len])?;
+                len = 0;
+            }
+
+            if needs_escape(byte) {
+                buf[len] = DLE;
+                buf[len + 1] = byte ^ 0x40;
+                len += 2;
+            } else {
+                buf[len] = byte;
+                len += 1;
+            }
+        }
+
+        Ok(self.inner.write_all(&buf[..len])?)
     }
 
-    pub fn new_with_progress(inner: T, f: ProgressFn) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: f }
+    /// Whether the deadline for the packet being read has passed.
+    fn overdue(&self) -> bool {
+        match self.deadline {
+            Some(deadline) => self.tracker.now() >= Some(deadline),
+            None => false,
+        }
     }
- 
-    fn read_byte(&mut self, abort_on_can: bool) -> io::Result<u8> {
+
+    fn read_byte(&mut self, abort_on_can: bool) -> Result<u8> {
         let mut buf = [0u8; 1];
-        self.inner.read_exact(&mut buf)?;
+        self.read_exact(&mut buf)?;
 
         let byte = buf[0];
         if abort_on_can && byte == CAN {
-            return ioerr!(ConnectionAborted, "received CAN");
+            return Err(self.cancelled());
         }
 
         Ok(byte)
     }
 
    
-    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
         self.inner.write_all(&[byte])?;
-        self.inner.flush()
+        Ok(self.inner.flush()?)
     }
 
-    fn expect_byte_or_cancel(&mut self, byte: u8, expected: &'static str) -> io::Result<u8> {
+    fn expect_byte_or_cancel(&mut self, byte: u8, expected: &'static str) -> Result<u8> {
         let received = self.read_byte(false)?;
         if received == byte {
             Ok(received)
         } else {
             self.write_byte(CAN)?;
             if received == CAN {
-                Err(io::Error::new(io::ErrorKind::ConnectionAborted, "received CAN"))
+                Err(self.cancelled())
             } else {
-                Err(io::Error::new(io::ErrorKind::InvalidData, expected))
+                Err(XmodemError::UnexpectedByte { expected, received })
             }
         }
     }
 
-    fn expect_byte(&mut self, byte: u8, expected: &'static str) -> io::Result<u8> {
+    fn expect_byte(&mut self, byte: u8, expected: &'static str) -> Result<u8> {
         let received = self.read_byte(false)?;
         if received == byte {
             Ok(received)
         } else if received == CAN {
-            Err(io::Error::new(io::ErrorKind::ConnectionAborted, "received CAN"))
+            Err(self.cancelled())
         } else {
-            Err(io::Error::new(io::ErrorKind::InvalidData, expected))
+            Err(XmodemError::UnexpectedByte { expected, received })
         }
     }
 
-    pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    /// Reads the receiver's answer to the last EOT: `ACK`, or `C` if it
+    /// asks to verify the payload, which `write_packet` then answers.
+    fn expect_eot_ack(&mut self, expected: &'static str) -> Result<()> {
+        match self.read_byte(false)? {
+            ACK => Ok(()),
+            CRC => {
+                self.verify_requested = true;
+                Ok(())
+            }
+            CAN => Err(self.cancelled()),
+            received => Err(XmodemError::UnexpectedByte { expected, received }),
+        }
+    }
+
+    /// Reads a packet into `buf`, or EOT, in which case it returns `0`. The
+    /// whole packet must arrive within the policy's `packet_timeout`.
+    pub fn read_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
         if buf.len() < 128 {
-            return ioerr!(UnexpectedEof, "buffer too small");
+            return Err(XmodemError::BadBuffer);
         }
 
         let byte = self.read_byte(false)?;
+        self.deadline = self.policy.packet_timeout.and_then(|limit| Some(self.tracker.now()? + limit));
+        let result = self.read_packet_from(byte, buf);
+        self.deadline = None;
 
-        if byte == CAN {
-            return ioerr!(ConnectionAborted, "received CAN");
+        match result {
+            Ok(0) if self.verify => self.verify_payload(false).map(|_| 0),
+            result => result,
         }
+    }
 
-        match byte {
-            SOH => {
-                // Mark started only on first SOH
-                if !self.started {
-                    self.started = true;
-                    (self.progress)(Progress::Started);
+    /// Exchanges CRC-32s of the payload once EOT was acknowledged, if both
+    /// ends want to. A verifying receiver acknowledges the EOT with `C`
+    /// rather than `ACK`; the sender then agrees with `ACK` followed by its
+    /// CRC-32, to which the receiver answers with its own, or declines with
+    /// `NAK`. Both ends fail with `CrcMismatch` if the CRC-32s differ.
+    fn verify_payload(&mut self, sending: bool) -> Result<()> {
+        let ours = self.payload_crc.to_be_bytes();
+        let mut theirs = [0u8; 4];
+        if sending && !self.verify {
+            event!(debug, "receiver asked for a payload CRC-32; declining");
+            return self.write_byte(NAK);
+        } else if sending {
+            self.write_byte(ACK)?;
+            self.write_escaped(&ours)?;
+            self.inner.flush()?;
+            self.read_escaped(&mut theirs)?;
+        } else {
+            match self.read_byte(false)? {
+                ACK => {}
+                NAK => {
+                    event!(debug, "sender declined to verify the payload");
+                    return Ok(());
                 }
+                CAN => return Err(self.cancelled()),
+                received => {
+                    return Err(XmodemError::UnexpectedByte { expected: "expected ACK or NAK after C", received });
+                }
+            }
 
-                let packet_num = self.read_byte(false)?;
-                let packet_num_neg = self.read_byte(false)?;
+            self.read_escaped(&mut theirs)?;
+            self.write_escaped(&ours)?;
+            self.inner.flush()?;
+        }
 
-                // Ensure self.packet starts at 1
-                if packet_num != self.packet || packet_num_neg != !self.packet {
-                    self.write_byte(NAK)?;
-                    return ioerr!(InvalidData, "packet number mismatch");
-                }
+        let theirs = u32::from_be_bytes(theirs);
+        let (expected, computed) = match sending {
+            true => (self.payload_crc, theirs),
+            false => (theirs, self.payload_crc),
+        };
+
+        if expected != computed {
+            event!(debug, "payload CRC-32 mismatch: sender has {:#010x}, receiver {:#010x}", expected, computed);
+            return Err(XmodemError::CrcMismatch { expected, computed });
+        }
+
+        Ok(())
+    }
 
-                self.inner.read_exact(&mut buf[..128])?;
-                let checksum = self.read_byte(false)?;
+    /// Reads the rest of the packet that began with `byte`, feeding it to
+    /// the core and acting on what it says.
+    fn read_packet_from(&mut self, byte: u8, buf: &mut [u8]) -> Result<usize> {
+        // Mark started only on first SOH
+        if byte == SOH && !self.started {
+            self.started = true;
+            self.payload_crc = 0;
+            self.tracker.start();
+            event!(debug, "receiving with {}", self.mode());
+            (self.progress)(Progress::Started);
+        }
+
+        let mut chunk = [0u8; 128];
+        let mut action = self.core.handle_byte(byte);
+        loop {
+            action = match action {
+                Action::Continue => {
+                    let n = self.core.wanted();
+                    if let Err(e) = self.read_exact(&mut chunk[..n]) {
+                        self.core.discard();
+                        return Err(e);
+                    }
 
-                if get_checksum(&buf[..128]) != checksum {
-                    self.write_byte(NAK)?;
-                    return ioerr!(Interrupted, "checksum mismatch");
+                    // `wanted` bytes never take the core past an action
+                    chunk[..n].iter().fold(Action::Continue, |_, &byte| self.core.handle_byte(byte))
                 }
+                Action::Reply(byte) => {
+                    self.write_byte(byte)?;
+                    Action::Continue
+                }
+                Action::Packet(num) => {
+                    if !self.core.streaming {
+                        self.write_byte(ACK)?;
+                    }
 
-                self.write_byte(ACK)?;
-                // Report current packet before incrementing
-                (self.progress)(Progress::Packet(packet_num));
-                self.packet = self.packet.wrapping_add(1);
-                Ok(128)
-            }
-            EOT => {
-                self.write_byte(NAK)?;
-                let byte = self.read_byte(false)?;
-                if byte != EOT {
-                    return ioerr!(InvalidData, "expected second EOT");
+                    event!(trace, "received packet {}", num);
+                    buf[..128].copy_from_slice(self.core.packet());
+                    self.payload_crc = update_crc32(self.payload_crc, &buf[..128]);
+                    (self.progress)(Progress::Packet(num));
+                    (self.progress)(Progress::Transferred(self.tracker.transferred(128)));
+                    return Ok(128);
                 }
-                self.write_byte(ACK)?;
-                Ok(0)
-            }
-            _ => {
-                let next_byte = self.read_byte(false)?;
-                if next_byte == CAN {
-                    return ioerr!(ConnectionAborted, "received CAN");
-                } else {
-                    self.write_byte(NAK)?;
-                    return ioerr!(InvalidData, "expected SOH or EOT");
+                Action::Duplicate(num) => {
+                    self.write_byte(ACK)?;
+                    event!(trace, "packet {} again; acknowledged and dropped", num);
+                    return Err(XmodemError::Duplicate);
+                }
+                Action::End => {
+                    // `C` also asks the sender to verify the payload
+                    self.write_byte(if self.verify { CRC } else { ACK })?;
+                    event!(debug, "received EOT after {} packets", self.core.sequence.saturating_sub(1));
+                    return Ok(0);
+                }
+                Action::Reject(e) => {
+                    self.reject()?;
+                    return Err(e);
+                }
+                Action::Fail(e) => return Err(e),
+                Action::Cancelled => return Err(self.cancelled()),
+            };
+        }
+    }
+
+    /// Sends the packet in `buf`, or EOT if it's empty. A NAKed packet is
+    /// sent again, byte for byte, until it's ACKed or the policy's retries
+    /// run out.
+    pub fn write_packet(&mut self, buf: &[u8]) -> Result<usize> {
+        for _ in 0..self.policy.retries.max(1) {
+            match self.write_packet_once(buf) {
+                Ok(0) if self.verify_requested => return self.verify_payload(true).map(|_| 0),
+                Err(ref e) if e.is_retryable() => self.retried(),
+                Err(e @ XmodemError::Cancelled { .. }) => {
+                    self.tracker.cancelled();
+                    return Err(e);
                 }
+                result => return result,
             }
         }
+
+        event!(debug, "giving up on packet {} after {} tries", self.core.sequence, self.policy.retries.max(1));
+        Err(XmodemError::RetriesExhausted)
     }
-    
-    pub fn write_packet(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+    fn write_packet_once(&mut self, buf: &[u8]) -> Result<usize> {
         if buf.len() != 128 && !buf.is_empty() {
-            return ioerr!(UnexpectedEof, "buffer length must be 128 or 0");
+            return Err(XmodemError::BadBuffer);
         }
     
         if !self.started {
             (self.progress)(Progress::Waiting);
-            self.expect_byte(NAK, "expected NAK to start transmission")?;
+            // the receiver picks the mode: NAK for checksums, C for CRC-16,
+            // G for streamed CRC-16. Anything else is line noise, up to the
+            // policy's `purge` limit.
+            let mut purged = 0;
+            let (crc, streaming) = loop {
+                match self.read_byte(false)? {
+                    NAK => break (false, false),
+                    CRC => break (true, false),
+                    STREAM => break (true, true),
+                    CAN => return Err(self.cancelled()),
+                    byte if purged < self.policy.purge => {
+                        event!(trace, "purged {:#04x} while waiting for the receiver", byte);
+                        purged += 1;
+                    }
+                    received => {
+                        let expected = "expected NAK, C, or G to start transmission";
+                        return Err(XmodemError::UnexpectedByte { expected, received });
+                    }
+                }
+            };
+            self.core.crc = crc;
+            self.core.streaming = streaming;
             self.started = true;
+            self.verify_requested = false;
+            self.payload_crc = 0;
+            self.tracker.start();
+            event!(debug, "transmitting with {}", self.mode());
             (self.progress)(Progress::Started);
         }
     
+        if buf.is_empty() && self.core.streaming {
+            self.write_byte(EOT)?;
+            self.expect_eot_ack("expected ACK or C after EOT")?;
+            event!(debug, "EOT acknowledged after {} packets", self.core.sequence.saturating_sub(1));
+            return Ok(0);
+        }
+
         if buf.is_empty() {
             self.write_byte(EOT)?;
             self.expect_byte(NAK, "expected NAK after first EOT")?;
             self.write_byte(EOT)?;
-            self.expect_byte(ACK, "expected ACK after second EOT")?;
+            self.expect_eot_ack("expected ACK or C after second EOT")?;
+            event!(debug, "EOT acknowledged after {} packets", self.core.sequence.saturating_sub(1));
             return Ok(0);
         }
     
+        // everything after the SOH is escaped
+        let mut packet = [0u8; MAX_PACKET_LEN];
+        let len = frame::encode_packet(self.core.sequence as u8, buf, self.core.crc, &mut packet)?;
         self.write_byte(SOH)?;
-        self.write_byte(self.packet)?;
-        self.write_byte(!self.packet)?;
+        self.write_escaped(&packet[1..len])?;
         self.inner.flush()?;
     
-        self.inner.write_all(buf)?;
-        self.write_byte(get_checksum(buf))?;
-    
-        match self.read_byte(false)? {
+        // a streaming receiver only answers the EOT
+        let response = if self.core.streaming { ACK } else { self.read_byte(false)? };
+        match response {
             ACK => {
-                self.packet = self.packet.wrapping_add(1);
-                (self.progress)(Progress::Packet(self.packet));
+                event!(trace, "packet {} acknowledged", self.core.sequence);
+                self.payload_crc = update_crc32(self.payload_crc, buf);
+                self.core.sequence += 1;
+                (self.progress)(Progress::Packet(self.core.sequence as u8));
+                (self.progress)(Progress::Transferred(self.tracker.transferred(128)));
                 Ok(128)
             }
-            NAK => ioerr!(Interrupted, "checksum failed"),
-            CAN => ioerr!(ConnectionAborted, "connection aborted by receiver"),
-            _ => ioerr!(InvalidData, "expected ACK, NAK, or CAN"),
+            NAK => {
+                self.tracker.nak();
+                event!(trace, "packet {} NAKed; {} NAKs so far", self.core.sequence, self.stats().naks);
+                Err(XmodemError::Nak)
+            }
+            // a receiver still polling for CRC mode; send the packet again
+            CRC if self.core.crc && self.stats().packets == 0 => Err(XmodemError::Nak),
+            CAN => Err(self.cancelled()),
+            received => Err(XmodemError::UnexpectedByte { expected: "expected ACK, NAK, or CAN", received }),
         }
     }   
 
-    pub fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+    /// Picks up a transfer that was interrupted after `packets` packets were
+    /// acknowledged: packets are numbered from `packets + 1` instead of `1`.
+    ///
+    /// XMODEM has no way to agree on this, so both ends must be told the
+    /// same number, and numbers wrap every 256 packets, so a mismatch by a
+    /// multiple of 256 goes unnoticed. The sender's data should be seeked
+    /// past the first `packets * 128` bytes, and the receiver's output should
+    /// already hold them; `TransferStats` only counts what's sent from here.
+    /// If the sender repeats the last packet the receiver already has, it's
+    /// discarded as a duplicate.
+    pub fn resume(&mut self, packets: u64) {
+        self.core.sequence = packets + 1;
+        self.resumed = packets;
+    }
+
+    /// Returns what has happened in the current or last transfer.
+    pub fn stats(&self) -> TransferStats {
+        self.tracker.stats()
+    }
+
+    /// Returns a reference to the transport.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the transport. Reading from or writing
+    /// to it in the middle of a transfer will likely derail the transfer.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the transport, for example to reuse a serial port once the
+    /// transfer is done.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.inner.flush()?)
+    }
+
+    /// Aborts the transfer in progress: sends the peer two `CAN`s, discards
+    /// whatever it has sent already, and resets this `Xmodem` so the
+    /// transport can be used for a new transfer.
+    ///
+    /// Input is discarded until a read returns nothing or fails, so the
+    /// transport's reads should time out; otherwise this blocks until the
+    /// peer closes the connection.
+    pub fn cancel(&mut self) -> Result<()> {
+        event!(debug, "cancelling after {} packets", self.resumed + self.stats().packets);
+        self.inner.write_all(&[CAN, CAN])?;
+        self.inner.flush()?;
+
+        let mut buf = [0u8; 64];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+
+        self.core = XmodemCore::new(false);
+        self.core.streaming = self.streaming;
+        self.core.escape = self.escape;
+        self.resumed = 0;
+        self.started = false;
+        self.tracker.cancelled();
+        Ok(())
+    }
+
+    /// Asks the sender to start sending: `G` when streaming, `C` in CRC
+    /// mode, `NAK` otherwise.
+    fn request_start(&mut self) -> Result<()> {
+        event!(trace, "asking the sender to start with {}", self.mode());
+        self.write_byte(match (self.core.streaming, self.core.crc) {
+            (true, _) => STREAM,
+            (false, true) => CRC,
+            (false, false) => NAK,
+        })
+    }
+
+    /// Describes the mode the transfer is in or is asked for, for `event!`.
+    fn mode(&self) -> &'static str {
+        match (self.core.streaming, self.core.crc) {
+            (true, _) => "XMODEM-G",
+            (false, true) => "CRC-16",
+            (false, false) => "checksums",
+        }
+    }
+
+    /// Tells the sender a packet was bad: `NAK` asks for it again, and when
+    /// streaming, where that isn't possible, two `CAN`s end the transfer.
+    fn reject(&mut self) -> Result<()> {
+        if self.core.streaming {
+            self.tracker.cancelled();
+            event!(debug, "bad packet {} while streaming; cancelling", self.core.sequence);
+            self.inner.write_all(&[CAN, CAN])?;
+            Ok(self.inner.flush()?)
+        } else {
+            self.tracker.nak();
+            event!(trace, "NAKing packet {}; {} NAKs so far", self.core.sequence, self.stats().naks);
+            self.write_byte(NAK)
+        }
+    }
+
+    /// Reads the next packet into `buf`, retrying as the policy allows.
+    ///
+    /// While the transfer hasn't started, the sender is asked to start again
+    /// every `poll_interval`, or on each read timeout. If the sender doesn't
+    /// respond to the policy's `polls` requests for streaming, this falls
+    /// back to asking for CRC mode, and after as many more, to NAK and
+    /// checksums, the original protocol.
+    fn next_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let max_polls = self.policy.polls.max(1);
+        let mut polls = 1;
+        let mut polled = self.tracker.now();
+        let mut attempts = 0;
+        while attempts < self.policy.retries.max(1) {
+            match self.read_packet(buf) {
+                // acknowledged again, but counted, so that a sender stuck
+                // repeating it runs out of retries
+                Err(XmodemError::Duplicate) => self.tracker.retried(),
+                Err(ref e) if e.is_timeout() && !self.started => {
+                    let now = self.tracker.now();
+                    let waiting = match (self.policy.poll_interval, polled, now) {
+                        (Some(interval), Some(polled), Some(now)) => now < polled + interval,
+                        _ => false,
+                    };
+
+                    if waiting {
+                        continue;
+                    }
+
+                    if polls >= max_polls && self.core.streaming {
+                        event!(debug, "no answer to XMODEM-G; falling back to CRC-16");
+                        self.core.streaming = false;
+                        polls = 0;
+                    } else if polls >= max_polls && self.core.crc {
+                        event!(debug, "no answer to CRC-16; falling back to checksums");
+                        self.core.crc = false;
+                    }
+
+                    polls += 1;
+                    self.request_start()?;
+                    polled = now;
+                }
+                Err(ref e) if e.is_retryable() && !self.core.streaming => self.retried(),
+                Err(e @ XmodemError::Cancelled { .. }) => {
+                    self.tracker.cancelled();
+                    return Err(e);
+                }
+                result => return result,
+            }
+
+            attempts += 1;
+        }
+
+        event!(debug, "giving up on packet {} after {} tries", self.core.sequence, attempts);
+        Err(XmodemError::RetriesExhausted)
+    }
+
+    /// The error for a transfer the peer cancelled now.
+    fn cancelled(&self) -> XmodemError {
+        event!(debug, "cancelled by the peer after {} packets", self.resumed + self.stats().packets);
+        XmodemError::Cancelled { packets: self.resumed + self.stats().packets }
+    }
+
+    fn retried(&mut self) {
+        self.tracker.retried();
+        (self.progress)(Progress::NAK);
     }
 }
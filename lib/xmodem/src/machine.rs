@@ -0,0 +1,277 @@
+//! The receiving side of the protocol as a state machine, fed one byte at a
+//! time and doing no I/O of its own.
+//!
+//! `Xmodem` drives an `XmodemCore` from blocking reads, but anything that
+//! gets bytes one at a time, like a UART interrupt handler, can drive one
+//! directly: each byte goes to `handle_byte`, and the `Action` it returns
+//! says what to send back and what arrived.
+//!
+//! ```rust,ignore
+//! let mut core = XmodemCore::new(true);
+//! uart.write_byte(b'C');
+//! loop {
+//!     match core.handle_byte(uart.read_byte()) {
+//!         Action::Continue => {}
+//!         Action::Reply(byte) => uart.write_byte(byte),
+//!         Action::Packet(_) => {
+//!             output.write_all(core.packet())?;
+//!             uart.write_byte(ACK);
+//!         }
+//!         Action::Duplicate(_) => uart.write_byte(ACK),
+//!         Action::End => break uart.write_byte(ACK),
+//!         Action::Reject(_) => uart.write_byte(NAK),
+//!         Action::Fail(e) => return Err(e),
+//!         Action::Cancelled => return Err(..),
+//!     }
+//! }
+//! ```
+
+use crate::frame::{checksum, crc16, needs_escape, CAN, DLE, EOT, NAK, SOH};
+use crate::XmodemError;
+
+/// What the driver of an `XmodemCore` should do after a byte.
+#[derive(Debug)]
+pub enum Action {
+    /// Nothing yet; the packet isn't complete.
+    Continue,
+    /// Send this byte to the sender, then keep going. This is the `NAK`
+    /// that asks for the second `EOT`.
+    Reply(u8),
+    /// The packet with this number arrived intact, and `XmodemCore::packet`
+    /// holds it. It should be ACKed, unless streaming.
+    Packet(u8),
+    /// The packet with this number, the previous one, arrived again because
+    /// the sender missed its ACK. It should be ACKed again and dropped.
+    Duplicate(u8),
+    /// The sender has sent everything. The `EOT` should be ACKed.
+    End,
+    /// A packet was bad, for the reason given. It should be NAKed so that
+    /// the sender tries again, or the transfer cancelled if streaming.
+    Reject(XmodemError),
+    /// The sender broke the protocol, for the reason given.
+    Fail(XmodemError),
+    /// The sender cancelled the transfer.
+    Cancelled,
+}
+
+/// Where the next byte goes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    /// Waiting for `SOH`, `EOT`, or `CAN`.
+    Header,
+    /// Reading the packet number, then its complement.
+    Number(usize),
+    /// Reading the data, `.0` bytes in.
+    Data(usize),
+    /// Reading the checksum or CRC-16, `.0` bytes in.
+    Check(usize),
+    /// Waiting for the second `EOT`.
+    Eot,
+    /// Checking whether the unexpected header byte `.0` starts a cancel.
+    Noise(u8),
+}
+
+/// The state of an XMODEM receiver.
+pub struct XmodemCore {
+    /// The number of the next packet expected, counted without wrapping.
+    /// Its low byte is the number on the wire.
+    pub(crate) sequence: u64,
+    /// Whether packets end with a CRC-16 rather than an 8-bit checksum.
+    pub(crate) crc: bool,
+    /// Whether packets are streamed without ACKs (XMODEM-G).
+    pub(crate) streaming: bool,
+    /// Whether flow control bytes inside packets are escaped.
+    pub(crate) escape: bool,
+    state: State,
+    /// Whether the last byte read was a `DLE` escaping the next one.
+    escaped: bool,
+    number: [u8; 2],
+    data: [u8; 128],
+    check: [u8; 2],
+}
+
+impl XmodemCore {
+    /// Returns a receiver expecting packet 1, with packets ending in a
+    /// CRC-16 if `crc` is set, or an 8-bit checksum otherwise. Which one
+    /// the sender uses depends on whether the receiver asked with `C` or
+    /// `NAK`.
+    pub fn new(crc: bool) -> XmodemCore {
+        XmodemCore {
+            sequence: 1,
+            crc,
+            streaming: false,
+            escape: false,
+            state: State::Header,
+            escaped: false,
+            number: [0; 2],
+            data: [0; 128],
+            check: [0; 2],
+        }
+    }
+
+    /// The number of the next packet expected. Packet numbers on the wire
+    /// wrap from 255 to 0; this one doesn't, so it also counts the packets
+    /// received so far.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The data of the last packet that arrived intact.
+    pub fn packet(&self) -> &[u8; 128] {
+        &self.data
+    }
+
+    /// How many bytes the next `Action` other than `Continue` is at least
+    /// away. Feeding up to this many bytes at once never skips one.
+    pub fn wanted(&self) -> usize {
+        match self.state {
+            State::Number(i) => 2 - i,
+            State::Data(i) => 128 - i,
+            State::Check(i) => self.check_len() - i,
+            State::Header | State::Eot | State::Noise(_) => 1,
+        }
+    }
+
+    /// Forgets the packet partway in, if any, for example after the
+    /// sender went quiet. The next byte is read as the start of a packet.
+    pub fn discard(&mut self) {
+        self.state = State::Header;
+        self.escaped = false;
+    }
+
+    /// Handles the next byte from the sender.
+    pub fn handle_byte(&mut self, byte: u8) -> Action {
+        match self.state {
+            State::Header => self.handle_header(byte),
+            State::Eot => {
+                self.state = State::Header;
+                match byte {
+                    EOT => Action::End,
+                    received => Action::Fail(XmodemError::UnexpectedByte { expected: "expected second EOT", received }),
+                }
+            }
+            State::Noise(header) => {
+                self.state = State::Header;
+                match byte {
+                    CAN => Action::Cancelled,
+                    _ => Action::Reject(XmodemError::UnexpectedByte { expected: "expected SOH or EOT", received: header }),
+                }
+            }
+            State::Number(_) | State::Data(_) | State::Check(_) => match self.unescape(byte) {
+                Some(byte) => self.handle_packet_byte(byte),
+                None => Action::Continue,
+            },
+        }
+    }
+
+    fn handle_header(&mut self, byte: u8) -> Action {
+        match byte {
+            SOH => {
+                self.state = State::Number(0);
+                Action::Continue
+            }
+            CAN => Action::Cancelled,
+            EOT if self.streaming => Action::End,
+            EOT => {
+                self.state = State::Eot;
+                Action::Reply(NAK)
+            }
+            byte => {
+                self.state = State::Noise(byte);
+                Action::Continue
+            }
+        }
+    }
+
+    /// Undoes `escape`: returns the byte `byte` stands for, or `None` if
+    /// it's a `DLE` or flow control rather than data.
+    fn unescape(&mut self, byte: u8) -> Option<u8> {
+        if !self.escape {
+            return Some(byte);
+        }
+
+        match byte {
+            DLE if !self.escaped => {
+                self.escaped = true;
+                None
+            }
+            byte if needs_escape(byte) => None,
+            byte if self.escaped => {
+                self.escaped = false;
+                Some(byte ^ 0x40)
+            }
+            byte => Some(byte),
+        }
+    }
+
+    fn handle_packet_byte(&mut self, byte: u8) -> Action {
+        match self.state {
+            State::Number(0) => {
+                self.number[0] = byte;
+                self.state = State::Number(1);
+                Action::Continue
+            }
+            State::Number(_) => {
+                self.number[1] = byte;
+                self.state = State::Data(0);
+
+                // the previous packet comes again if the sender missed our
+                // ACK for it
+                let (num, neg) = (self.number[0], byte);
+                if (num != self.sequence as u8 && !self.duplicate()) || neg != !num {
+                    self.state = State::Header;
+                    return Action::Reject(XmodemError::PacketNumber { expected: self.sequence as u8, received: num });
+                }
+
+                Action::Continue
+            }
+            State::Data(i) => {
+                self.data[i] = byte;
+                self.state = match i + 1 {
+                    128 => State::Check(0),
+                    i => State::Data(i),
+                };
+                Action::Continue
+            }
+            State::Check(i) => {
+                self.check[i] = byte;
+                if i + 1 < self.check_len() {
+                    self.state = State::Check(i + 1);
+                    return Action::Continue;
+                }
+
+                self.state = State::Header;
+                let valid = match self.crc {
+                    true => crc16(&self.data) == u16::from_be_bytes(self.check),
+                    false => checksum(&self.data) == self.check[0],
+                };
+
+                match self.number[0] {
+                    _ if !valid => Action::Reject(XmodemError::ChecksumMismatch),
+                    num if self.duplicate() => Action::Duplicate(num),
+                    num => {
+                        self.sequence += 1;
+                        Action::Packet(num)
+                    }
+                }
+            }
+            State::Header | State::Eot | State::Noise(_) => unreachable!("not inside a packet"),
+        }
+    }
+
+    /// Whether the packet being read is the previous one again. Only the
+    /// low byte of its number is sent, so packet 255 is the one before 256.
+    fn duplicate(&self) -> bool {
+        match self.sequence.checked_sub(1) {
+            Some(previous) => !self.streaming && self.number[0] == previous as u8,
+            None => false,
+        }
+    }
+
+    fn check_len(&self) -> usize {
+        match self.crc {
+            true => 2,
+            false => 1,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Several files in one XMODEM transfer, described up front by a manifest.
+//!
+//! This is lighter than YMODEM: there is a single transfer, so a receiver
+//! that can take one XMODEM file can take several. The transfer starts with
+//! a block holding `MAGIC` and the number of files, then one block per file
+//! with its name, size, and CRC-32. Each file's data follows in turn, padded
+//! to whole packets:
+//!
+//! ```text
+//! | MAGIC count | name size crc | name size crc | file 1 ... | file 2 ... |
+//! ```
+//!
+//! The receiver checks each file against its CRC-32 once it has arrived,
+//! catching what the per-packet checksums can't, such as a file that changed
+//! while it was being sent.
+
+use core::{mem, str};
+
+use shim::io::{self, SeekFrom};
+
+use crate::frame::update_crc32;
+use crate::progress::{self, Progress};
+use crate::read_ext::ReadExt;
+use crate::ymodem::MAX_NAME_LEN;
+use crate::{Result, TransferStats, Xmodem, XmodemError};
+
+/// Starts the first block of a manifest transfer.
+pub const MAGIC: &[u8] = b"XMANIFEST";
+
+/// The most files a manifest can list.
+pub const MAX_FILES: usize = 16;
+
+/// A file listed in a manifest.
+#[derive(Clone, Copy)]
+pub struct Entry {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    size: u64,
+    crc: u32,
+}
+
+impl Entry {
+    const EMPTY: Entry = Entry { name: [0; MAX_NAME_LEN], name_len: 0, size: 0, crc: 0 };
+
+    /// Returns the entry for the file `name` by reading `data` to its end,
+    /// then seeks `data` back to where it was.
+    fn scan<R: io::Read + io::Seek>(name: &str, data: &mut R) -> Result<Entry> {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() || bytes.len() > MAX_NAME_LEN || bytes.contains(&0) {
+            return Err(XmodemError::BadHeader("invalid manifest file name"));
+        }
+
+        let mut entry = Entry { name_len: bytes.len(), ..Entry::EMPTY };
+        entry.name[..bytes.len()].copy_from_slice(bytes);
+
+        let start = data.seek(SeekFrom::Current(0))?;
+        let mut buf = [0u8; 128];
+        loop {
+            match data.read_max(&mut buf)? {
+                0 => break,
+                n => {
+                    entry.size += n as u64;
+                    entry.crc = update_crc32(entry.crc, &buf[..n]);
+                }
+            }
+        }
+
+        data.seek(SeekFrom::Start(start))?;
+        Ok(entry)
+    }
+
+    /// The file's name.
+    pub fn name(&self) -> &str {
+        // only ever built from a `&str` or checked by `parse`
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// The file's size in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The CRC-32 of the file's data.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Writes this entry into `block`: the name, padded with NULs to
+    /// `MAX_NAME_LEN` bytes, then the size and the CRC-32, big-endian.
+    fn encode(&self, block: &mut [u8]) {
+        block.iter_mut().for_each(|b| *b = 0);
+        block[..self.name_len].copy_from_slice(&self.name[..self.name_len]);
+        block[MAX_NAME_LEN..MAX_NAME_LEN + 8].copy_from_slice(&self.size.to_be_bytes());
+        block[MAX_NAME_LEN + 8..MAX_NAME_LEN + 12].copy_from_slice(&self.crc.to_be_bytes());
+    }
+
+    /// Parses an entry block.
+    fn parse(block: &[u8]) -> Result<Entry> {
+        let name = &block[..MAX_NAME_LEN];
+        let name_len = name.iter().position(|&b| b == 0).unwrap_or(MAX_NAME_LEN);
+        if name_len == 0 || str::from_utf8(&name[..name_len]).is_err() {
+            return Err(XmodemError::BadHeader("invalid manifest file name"));
+        }
+
+        let mut size = [0u8; 8];
+        let mut crc = [0u8; 4];
+        size.copy_from_slice(&block[MAX_NAME_LEN..MAX_NAME_LEN + 8]);
+        crc.copy_from_slice(&block[MAX_NAME_LEN + 8..MAX_NAME_LEN + 12]);
+
+        let mut entry = Entry { name_len, size: u64::from_be_bytes(size), crc: u32::from_be_bytes(crc), ..Entry::EMPTY };
+        entry.name[..name_len].copy_from_slice(&name[..name_len]);
+        Ok(entry)
+    }
+}
+
+impl core::fmt::Debug for Entry {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Entry")
+            .field("name", &self.name())
+            .field("size", &self.size)
+            .field("crc", &self.crc)
+            .finish()
+    }
+}
+
+/// Where a receiver is in a manifest transfer.
+enum State<W> {
+    /// Waiting for the block with the number of files.
+    Start,
+    /// Waiting for the entry of file `.0`.
+    Entries(usize),
+    /// Receiving file `index`, with `remaining` bytes to go.
+    File { index: usize, into: W, remaining: u64, crc: u32 },
+    /// Every file has arrived.
+    Done,
+}
+
+/// Implementation of manifest transfers.
+pub struct Manifest;
+
+impl Manifest {
+    /// Sends the manifest for `files`, then each file's data, to `to`. Each
+    /// file is read twice, once to compute its size and CRC-32 and once to
+    /// send it, so it mustn't change in between. Fails with `BadHeader`,
+    /// before anything is sent, if there are more than `MAX_FILES` files or
+    /// a name can't be sent.
+    #[inline]
+    pub fn transmit<R, W>(files: &mut [(&str, R)], to: W) -> Result<TransferStats>
+        where R: io::Read + io::Seek, W: io::Read + io::Write
+    {
+        Manifest::transmit_with_progress(files, to, progress::noop)
+    }
+
+    pub fn transmit_with_progress<R, W, P>(files: &mut [(&str, R)], to: W, f: P) -> Result<TransferStats>
+        where R: io::Read + io::Seek, W: io::Read + io::Write, P: FnMut(Progress)
+    {
+        if files.len() > MAX_FILES {
+            return Err(XmodemError::BadHeader("too many files for a manifest"));
+        }
+
+        let mut entries = [Entry::EMPTY; MAX_FILES];
+        for (entry, file) in entries.iter_mut().zip(files.iter_mut()) {
+            *entry = Entry::scan(file.0, &mut file.1)?;
+        }
+
+        let mut transmitter = Xmodem::new_with_progress(to, f);
+        let mut packet = [0u8; 128];
+        packet[..MAGIC.len()].copy_from_slice(MAGIC);
+        packet[MAGIC.len()] = files.len() as u8;
+        transmitter.write_packet(&packet)?;
+
+        for entry in &entries[..files.len()] {
+            entry.encode(&mut packet);
+            transmitter.write_packet(&packet)?;
+        }
+
+        for (_, data) in files.iter_mut() {
+            loop {
+                let n = data.read_max(&mut packet)?;
+                if n == 0 {
+                    break;
+                }
+
+                packet[n..].iter_mut().for_each(|b| *b = 0);
+                transmitter.write_packet(&packet)?;
+                transmitter.tracker.delivered(n);
+            }
+        }
+
+        transmitter.write_packet(&[])?;
+        transmitter.tracker.finish();
+        Ok(transmitter.stats())
+    }
+
+    /// Receives a manifest transfer from `from`. For each file, `open` is
+    /// called with its entry and returns where to write the file's data.
+    /// Returns the number of files received.
+    ///
+    /// Fails with `CrcMismatch` as soon as a file doesn't match its CRC-32,
+    /// after its data was written.
+    #[inline]
+    pub fn receive<R, F, W>(from: R, open: F) -> Result<usize>
+        where R: io::Read + io::Write, F: FnMut(&Entry) -> io::Result<W>, W: io::Write
+    {
+        Manifest::receive_with_progress(from, open, progress::noop)
+    }
+
+    pub fn receive_with_progress<R, F, W, P>(from: R, mut open: F, f: P) -> Result<usize>
+        where R: io::Read + io::Write, F: FnMut(&Entry) -> io::Result<W>, W: io::Write, P: FnMut(Progress)
+    {
+        let mut receiver = Xmodem::new_with_progress(from, f);
+        let mut entries = [Entry::EMPTY; MAX_FILES];
+        let mut count = 0;
+        let mut state = State::Start;
+
+        receiver.receive_packets(|packet| {
+            state = match mem::replace(&mut state, State::Done) {
+                State::Start => {
+                    if !packet.starts_with(MAGIC) || packet[MAGIC.len()] as usize > MAX_FILES {
+                        return Err(XmodemError::BadHeader("invalid manifest"));
+                    }
+
+                    count = packet[MAGIC.len()] as usize;
+                    match count {
+                        0 => State::Done,
+                        _ => State::Entries(0),
+                    }
+                }
+                State::Entries(i) => {
+                    entries[i] = Entry::parse(packet)?;
+                    match i + 1 {
+                        n if n == count => open_next(&entries[..count], 0, &mut open)?,
+                        n => State::Entries(n),
+                    }
+                }
+                State::File { index, mut into, remaining, crc } => {
+                    let n = remaining.min(128) as usize;
+                    into.write_all(&packet[..n])?;
+                    let crc = update_crc32(crc, &packet[..n]);
+                    match remaining - n as u64 {
+                        0 if crc != entries[index].crc => {
+                            return Err(XmodemError::CrcMismatch { expected: entries[index].crc, computed: crc });
+                        }
+                        0 => open_next(&entries[..count], index + 1, &mut open)?,
+                        remaining => State::File { index, into, remaining, crc },
+                    }
+                }
+                State::Done => return Err(XmodemError::BadHeader("data past the manifest's files")),
+            };
+
+            Ok(())
+        })?;
+
+        match state {
+            State::Done => Ok(count),
+            _ => Err(XmodemError::BadHeader("transfer ended before the manifest's files")),
+        }
+    }
+}
+
+/// Opens the files in `entries` from `index` on, until one has data to
+/// receive.
+fn open_next<F, W>(entries: &[Entry], mut index: usize, open: &mut F) -> Result<State<W>>
+    where F: FnMut(&Entry) -> io::Result<W>
+{
+    while let Some(entry) = entries.get(index) {
+        let into = open(entry)?;
+        if entry.size > 0 {
+            return Ok(State::File { index, into, remaining: entry.size, crc: 0 });
+        }
+
+        index += 1;
+    }
+
+    Ok(State::Done)
+}
@@ -0,0 +1,295 @@
+use core::time::Duration;
+
+#[cfg(any(feature = "alloc", not(feature = "no_std")))]
+use alloc::vec::Vec;
+
+use shim::io;
+
+use crate::clock::{Clock, DefaultClock};
+use crate::progress::{self, Progress, ProgressFn};
+use crate::{Result, TransferStats, Xmodem, CRC_POLLS};
+
+/// How hard a transfer tries before giving up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The most times a packet is sent or received before the transfer
+    /// fails. Values below 1 are treated as 1.
+    pub retries: usize,
+    /// How long to keep waiting for the next byte while the transport's
+    /// reads time out. With `None`, the first read that times out fails.
+    ///
+    /// This lets the transport poll with a short timeout while the transfer
+    /// waits much longer. Measuring it needs a `Clock`; without one, as
+    /// under `no_std` by default, it has no effect.
+    pub byte_timeout: Option<Duration>,
+    /// How long a packet may take to arrive, from its first byte to its
+    /// last, before the read fails as timed out. This catches a peer that
+    /// trickles bytes too slowly for `byte_timeout` to notice. Like it, it
+    /// needs a `Clock`.
+    pub packet_timeout: Option<Duration>,
+    /// How many times the receiver asks for CRC mode before falling back
+    /// to NAK and checksums, or for XMODEM-G before falling back to CRC
+    /// mode. Values below 1 are treated as 1.
+    pub polls: usize,
+    /// How long the receiver waits for the sender to start before asking
+    /// again. With `None`, it asks again whenever a read times out, so the
+    /// transport's timeout sets the pace. Like `byte_timeout`, it needs a
+    /// `Clock`.
+    pub poll_interval: Option<Duration>,
+    /// How many stray bytes the sender discards while waiting for the
+    /// receiver to start, such as a rebooting board's console output. With
+    /// `0`, the first one fails the transfer. `CAN` always does.
+    pub purge: usize,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            retries: 10,
+            byte_timeout: None,
+            packet_timeout: None,
+            polls: CRC_POLLS,
+            poll_interval: None,
+            purge: 0,
+        }
+    }
+}
+
+/// Configures an `Xmodem` transfer. Returned by `Xmodem::builder()`.
+///
+/// ```rust,ignore
+/// let received = Xmodem::builder()
+///     .retries(20)
+///     .byte_timeout(Duration::from_secs(5))
+///     .receive(port, &mut buffer[..])?;
+/// ```
+#[derive(Copy, Clone)]
+pub struct Builder<P = ProgressFn, C = DefaultClock> {
+    policy: RetryPolicy,
+    progress: P,
+    clock: C,
+    pad: u8,
+    trim: Option<u8>,
+    streaming: bool,
+    escape: bool,
+    verify: bool,
+    resume: u64,
+    max_len: Option<usize>,
+}
+
+impl Builder {
+    pub(crate) fn new() -> Builder {
+        Builder {
+            policy: RetryPolicy::default(),
+            progress: progress::noop,
+            clock: DefaultClock::default(),
+            pad: 0,
+            trim: None,
+            streaming: false,
+            escape: false,
+            verify: false,
+            resume: 0,
+            max_len: None,
+        }
+    }
+}
+
+impl<P: FnMut(Progress), C: Clock> Builder<P, C> {
+    /// Sets the whole retry policy.
+    pub fn policy(mut self, policy: RetryPolicy) -> Builder<P, C> {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets `RetryPolicy::retries`.
+    pub fn retries(mut self, retries: usize) -> Builder<P, C> {
+        self.policy.retries = retries;
+        self
+    }
+
+    /// Sets `RetryPolicy::byte_timeout`.
+    pub fn byte_timeout(mut self, timeout: Duration) -> Builder<P, C> {
+        self.policy.byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets `RetryPolicy::polls`.
+    pub fn polls(mut self, polls: usize) -> Builder<P, C> {
+        self.policy.polls = polls;
+        self
+    }
+
+    /// Sets `RetryPolicy::poll_interval`.
+    pub fn poll_interval(mut self, interval: Duration) -> Builder<P, C> {
+        self.policy.poll_interval = Some(interval);
+        self
+    }
+
+    /// Sets `RetryPolicy::purge`.
+    pub fn purge(mut self, bytes: usize) -> Builder<P, C> {
+        self.policy.purge = bytes;
+        self
+    }
+
+    /// Sets the byte that fills out the last packet when transmitting. The
+    /// default is `0x00`; the XMODEM specification uses `SUB` (`0x1A`),
+    /// which some receivers trim from the end of the file.
+    pub fn pad(mut self, byte: u8) -> Builder<P, C> {
+        self.pad = byte;
+        self
+    }
+
+    /// When receiving, drops copies of `byte` from the end of the last
+    /// packet, so that the output and the returned length match the file the
+    /// sender read instead of being rounded up to whole packets. It should be
+    /// the sender's pad byte, and a file that itself ends with `byte` loses
+    /// those bytes too, so `SUB` is the safer choice for arbitrary data.
+    pub fn trim(mut self, byte: u8) -> Builder<P, C> {
+        self.trim = Some(byte);
+        self
+    }
+
+    /// When receiving, asks the sender for XMODEM-G: packets are streamed
+    /// without waiting for ACKs, roughly doubling throughput over links that
+    /// don't lose or corrupt data, such as USB serial adapters. A bad packet
+    /// cancels the whole transfer instead of being sent again. Senders that
+    /// don't answer are asked for plain CRC mode instead.
+    ///
+    /// Transmitting always streams if the receiver asks for it.
+    pub fn streaming(mut self, streaming: bool) -> Builder<P, C> {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Sets `RetryPolicy::packet_timeout`.
+    pub fn packet_timeout(mut self, timeout: Duration) -> Builder<P, C> {
+        self.policy.packet_timeout = Some(timeout);
+        self
+    }
+
+    /// Escapes XON and XOFF inside packets, so that transfers survive links
+    /// with software flow control, whose drivers would swallow them. Each is
+    /// sent as `0x10` followed by the byte XORed with `0x40`, as is `0x10`
+    /// itself. XMODEM has no way to negotiate this, so it must be set on
+    /// both ends.
+    pub fn escape(mut self, escape: bool) -> Builder<P, C> {
+        self.escape = escape;
+        self
+    }
+
+    /// After EOT, has the sender send a CRC-32 of every packet it sent and
+    /// the receiver answer with one of every packet it received, so that
+    /// both can tell the transfer is intact beyond each packet's checksum.
+    /// Either fails with `XmodemError::CrcMismatch` if they differ. Padding
+    /// counts, and so do only the packets sent since the last `resume`.
+    /// Unlike `escape`, the ends agree on it after EOT: if only one of them
+    /// sets it, the transfer ends as usual, unverified.
+    pub fn verify(mut self, verify: bool) -> Builder<P, C> {
+        self.verify = verify;
+        self
+    }
+
+    /// When receiving, cancels the transfer with `XmodemError::TooLarge` as
+    /// soon as a packet would take what it has received past `max_len`
+    /// bytes, for example when the output is a fixed region of memory. The
+    /// limit applies to whole packets, including the last one's padding.
+    pub fn max_len(mut self, max_len: usize) -> Builder<P, C> {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Resumes an interrupted transfer after `packets` packets. See
+    /// `Xmodem::resume`.
+    pub fn resume(mut self, packets: u64) -> Builder<P, C> {
+        self.resume = packets;
+        self
+    }
+
+    /// Sets the progress callback.
+    pub fn progress<F: FnMut(Progress)>(self, f: F) -> Builder<F, C> {
+        Builder {
+            policy: self.policy,
+            progress: f,
+            clock: self.clock,
+            pad: self.pad,
+            trim: self.trim,
+            streaming: self.streaming,
+            escape: self.escape,
+            verify: self.verify,
+            resume: self.resume,
+            max_len: self.max_len,
+        }
+    }
+
+    /// Sets the clock that timeouts and `TransferStats::elapsed` are
+    /// measured with. Under `no_std`, there is none unless one is set here.
+    pub fn clock<D: Clock>(self, clock: D) -> Builder<P, D> {
+        Builder {
+            policy: self.policy,
+            progress: self.progress,
+            clock,
+            pad: self.pad,
+            trim: self.trim,
+            streaming: self.streaming,
+            escape: self.escape,
+            verify: self.verify,
+            resume: self.resume,
+            max_len: self.max_len,
+        }
+    }
+
+    /// Returns an `Xmodem` over `inner` with this configuration.
+    pub fn build<T: io::Read + io::Write>(self, inner: T) -> Xmodem<T, P, C> {
+        let mut xmodem = Xmodem::new_with_clock(inner, self.progress, self.clock);
+        xmodem.policy = self.policy;
+        xmodem.pad = self.pad;
+        xmodem.trim = self.trim;
+        xmodem.streaming = self.streaming;
+        xmodem.escape = self.escape;
+        xmodem.core.streaming = self.streaming;
+        xmodem.core.escape = self.escape;
+        xmodem.verify = self.verify;
+        xmodem.max_len = self.max_len;
+        xmodem.resume(self.resume);
+        xmodem
+    }
+
+    /// Like `Xmodem::transmit`, with this configuration.
+    pub fn transmit<R, W>(self, data: R, to: W) -> Result<TransferStats>
+        where W: io::Read + io::Write, R: io::Read
+    {
+        self.build(to).transmit_from(data)
+    }
+
+    /// Like `Xmodem::receive`, with this configuration.
+    pub fn receive<R, W>(self, from: R, into: W) -> Result<TransferStats>
+       where R: io::Read + io::Write, W: io::Write
+    {
+        self.build(from).receive_into(into)
+    }
+
+    /// Like `Xmodem::receive_to_vec`, with this configuration.
+    #[cfg(any(feature = "alloc", not(feature = "no_std")))]
+    pub fn receive_to_vec<R>(self, from: R) -> Result<Vec<u8>>
+        where R: io::Read + io::Write
+    {
+        let mut received = Vec::new();
+        self.build(from).receive_trimmed(|data| {
+            received.extend_from_slice(data);
+            Ok(())
+        })?;
+
+        Ok(received)
+    }
+
+    /// Like `Xmodem::receive_with_handler`, with this configuration. Any
+    /// `trim` setting is ignored.
+    pub fn receive_with_handler<R, F>(self, from: R, mut handler: F) -> Result<TransferStats>
+        where R: io::Read + io::Write, F: FnMut(&[u8; 128])
+    {
+        self.build(from).receive_packets(|packet| {
+            handler(packet);
+            Ok(())
+        })
+    }
+}
@@ -1,3 +1,7 @@
+use core::time::Duration;
+
+use crate::stats;
+
 /// Enum representing how much progress has been made transmitting/receiving.
 ///
 /// A value of this type is passed in to the progress callback supplied to
@@ -12,11 +16,34 @@ pub enum Progress {
     Started,
     /// Packet `.0` was transmitted/received.
     Packet(u8),
+    /// A packet failed and is being sent again.
     NAK,
     Unknown,
+    /// The running totals, reported after each packet.
+    Transferred(Totals),
+}
+
+/// Running totals for a transfer, reported with `Progress::Transferred`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Totals {
+    /// Bytes transmitted/received so far, including any padding.
+    pub bytes: u64,
+    /// How many times a packet had to be sent again.
+    pub retries: usize,
+    /// Time since the transfer started. Measuring it needs a `Clock`, so
+    /// without one it's `None`.
+    pub elapsed: Option<Duration>,
+}
+
+impl Totals {
+    /// Average bytes per second so far, if it can be measured yet.
+    pub fn throughput(&self) -> Option<u64> {
+        stats::throughput(self.bytes, self.elapsed?)
+    }
 }
 
-/// Type for progress callbacks.
+/// The default type of progress callback. Any `FnMut(Progress)` can be
+/// used instead, including closures that capture state.
 pub type ProgressFn = fn(Progress);
 
 /// Noop progress callback.
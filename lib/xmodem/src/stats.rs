@@ -0,0 +1,122 @@
+use core::time::Duration;
+
+use crate::clock::{Clock, DefaultClock};
+use crate::progress::Totals;
+
+/// What happened during a transfer. Returned by `Xmodem::transmit`,
+/// `Xmodem::receive`, and friends.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TransferStats {
+    /// Bytes read from the source when transmitting, or received when
+    /// receiving. The latter includes padding unless it was trimmed.
+    pub bytes: u64,
+    /// Packets sent or received, not counting repeats.
+    pub packets: u64,
+    /// How many times a packet had to be sent again.
+    pub retransmissions: usize,
+    /// NAKs received when transmitting, or sent when receiving.
+    pub naks: usize,
+    /// How many times either end cancelled the transfer. Only ever nonzero
+    /// in `Xmodem::stats` after a failed transfer.
+    pub cancels: usize,
+    /// How long the transfer took, from the start to the end or the last
+    /// packet. Measuring it needs a `Clock`, so without one it's `None`.
+    pub elapsed: Option<Duration>,
+}
+
+impl TransferStats {
+    /// Average bytes per second, if it can be measured.
+    pub fn throughput(&self) -> Option<u64> {
+        throughput(self.bytes, self.elapsed?)
+    }
+}
+
+pub(crate) fn throughput(bytes: u64, elapsed: Duration) -> Option<u64> {
+    let micros = elapsed.as_micros();
+    if micros == 0 {
+        return None;
+    }
+
+    Some((u128::from(bytes) * 1_000_000 / micros) as u64)
+}
+
+/// Counts what happens during a transfer, for `Totals` and `TransferStats`,
+/// and times it with `clock`.
+pub(crate) struct Tracker<C = DefaultClock> {
+    totals: Totals,
+    stats: TransferStats,
+    clock: C,
+    since: Option<Duration>,
+}
+
+impl<C: Clock> Tracker<C> {
+    pub(crate) fn new(clock: C) -> Tracker<C> {
+        Tracker { totals: Totals::default(), stats: TransferStats::default(), clock, since: None }
+    }
+
+    /// Clears the counts and stops the clock, for a new transfer.
+    pub(crate) fn reset(&mut self) {
+        self.totals = Totals::default();
+        self.stats = TransferStats::default();
+        self.since = None;
+    }
+
+    /// Reads the clock.
+    pub(crate) fn now(&self) -> Option<Duration> {
+        self.clock.now()
+    }
+
+    /// Starts the clock if it isn't running yet.
+    pub(crate) fn start(&mut self) {
+        if self.since.is_none() {
+            self.since = self.now();
+        }
+    }
+    /// Counts a packet sent again.
+    pub(crate) fn retried(&mut self) {
+        self.totals.retries += 1;
+        self.stats.retransmissions += 1;
+    }
+
+    /// Counts a NAK sent or received.
+    pub(crate) fn nak(&mut self) {
+        self.stats.naks += 1;
+    }
+
+    /// Counts a cancelled transfer.
+    pub(crate) fn cancelled(&mut self) {
+        self.stats.cancels += 1;
+    }
+
+    /// Counts a packet of `n` bytes and returns the totals so far.
+    pub(crate) fn transferred(&mut self, n: usize) -> Totals {
+        self.stats.packets += 1;
+        self.totals.bytes += n as u64;
+        self.totals.elapsed = self.elapsed();
+        self.stats.elapsed = self.totals.elapsed;
+        self.totals
+    }
+
+    /// Counts `n` bytes of the source or sink's data.
+    pub(crate) fn delivered(&mut self, n: usize) {
+        self.stats.bytes += n as u64;
+    }
+
+    /// Uncounts `n` bytes of padding trimmed from what was delivered.
+    pub(crate) fn trimmed(&mut self, n: usize) {
+        self.stats.bytes -= n as u64;
+    }
+
+    /// Stops the clock.
+    pub(crate) fn finish(&mut self) {
+        self.stats.elapsed = self.elapsed();
+    }
+
+    pub(crate) fn stats(&self) -> TransferStats {
+        self.stats
+    }
+
+    fn elapsed(&self) -> Option<Duration> {
+        self.now()?.checked_sub(self.since?)
+    }
+}
@@ -0,0 +1,106 @@
+//! In-memory transports, for testing code that transfers files without a
+//! serial port. Needs the `testing` feature.
+//!
+//! The two ends of a `pipe()` are connected to each other, so a transmitter
+//! on one thread and a receiver on another can be paired up:
+//!
+//! ```rust,ignore
+//! let (mut tx, rx) = xmodem::testing::pipe();
+//! let sender = std::thread::spawn(move || Xmodem::transmit(&data[..], rx));
+//! Xmodem::receive(&mut tx, &mut output)?;
+//! ```
+//!
+//! Pipes are built on `std::sync::mpsc`, so this module uses `std` even when
+//! the rest of the crate is built with `no_std`.
+
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+use std::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+
+/// One end of a `pipe()`.
+#[derive(Debug)]
+pub struct Pipe {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    written: Vec<u8>,
+    timeout: Option<Duration>,
+}
+
+/// Returns the two ends of a pipe. Bytes written to one can be read from
+/// the other.
+pub fn pipe() -> (Pipe, Pipe) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    (Pipe::from_channels(tx1, rx2), Pipe::from_channels(tx2, rx1))
+}
+
+impl Pipe {
+    /// Returns a pipe end that writes to `tx` and reads from `rx`. With
+    /// `into_channels`, this lets a test put a filter between two ends.
+    pub fn from_channels(tx: Sender<u8>, rx: Receiver<u8>) -> Pipe {
+        Pipe { tx, rx, written: Vec::new(), timeout: None }
+    }
+
+    /// Returns the channels this end writes to and reads from.
+    pub fn into_channels(self) -> (Sender<u8>, Receiver<u8>) {
+        (self.tx, self.rx)
+    }
+
+    /// Sets how long a read waits for a byte before failing with
+    /// `TimedOut`, like a serial port's read timeout. With `None`, the
+    /// default, reads wait until the other end is dropped.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Everything written to this end so far.
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// Returns everything written to this end so far and forgets it.
+    pub fn take_written(&mut self) -> Vec<u8> {
+        self.written.drain(..).collect()
+    }
+}
+
+impl io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for (i, slot) in buf.iter_mut().enumerate() {
+            let byte = match self.timeout {
+                Some(timeout) => match self.rx.recv_timeout(timeout) {
+                    Ok(byte) => byte,
+                    Err(RecvTimeoutError::Timeout) => return ioerr!(TimedOut, "read timed out"),
+                    Err(RecvTimeoutError::Disconnected) => return Ok(i),
+                },
+                None => match self.rx.recv() {
+                    Ok(byte) => byte,
+                    Err(_) => return Ok(i)
+                }
+            };
+
+            *slot = byte;
+        }
+
+        Ok(buf.len())
+    }
+}
+
+impl io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        for (i, &byte) in buf.iter().enumerate() {
+            if self.tx.send(byte).is_err() {
+                return Ok(i);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,37 +1,32 @@
 use super::*;
-use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::mpsc::channel;
+use std::cell::{Cell, RefCell};
 use std::io::Cursor;
+use std::time::Duration;
+use shim::ioerr;
 
-struct Pipe(Sender<u8>, Receiver<u8>, Vec<u8>);
+use crate::frame::*;
+use crate::testing::{pipe, Pipe};
 
-fn pipe() -> (Pipe, Pipe) {
-    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
-    (Pipe(tx1, rx2, vec![]), Pipe(tx2, rx1, vec![]))
+macro_rules! assert_err {
+    ($e:expr, $p:pat $(if $guard:expr)?) => {
+        match $e {
+            $p $(if $guard)? => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    };
 }
 
-impl io::Read for Pipe {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        for i in 0..buf.len() {
-            match self.1.recv() {
-                Ok(byte) => buf[i] = byte,
-                Err(_) => return Ok(i)
-            }
-        }
+/// Received files: name, modification time, and data.
+type Received = Vec<(String, Option<u64>, Vec<u8>)>;
+type Files = RefCell<Received>;
 
-        Ok(buf.len())
-    }
-}
+/// Appends to the data of the last of `Files`.
+struct LastFile<'a>(&'a Files);
 
-impl io::Write for Pipe {
+impl io::Write for LastFile<'_> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        buf.iter().for_each(|b| self.2.push(*b));
-        for (i, byte) in buf.iter().cloned().enumerate() {
-            if let Err(e) = self.0.send(byte) {
-                eprintln!("Write error: {}", e);
-                return Ok(i);
-            }
-        }
-
+        self.0.borrow_mut().last_mut().unwrap().2.extend_from_slice(buf);
         Ok(buf.len())
     }
 
@@ -54,7 +49,7 @@ fn test_loop() {
         Xmodem::receive(tx, &mut output[..]).map(|_| output)
     });
 
-    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 384);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 384);
     let output = rx_thread.join().expect("rx join okay").expect("rx okay");
     assert_eq!(&input[..], &output[..]);
 }
@@ -71,7 +66,7 @@ fn read_byte() {
         .read_byte(true)
         .expect_err("abort on CAN");
 
-    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_err!(e, XmodemError::Cancelled { packets: 0 });
 }
 
 #[test]
@@ -79,7 +74,7 @@ fn test_expect_byte() {
     let mut xmodem = Xmodem::new(Cursor::new(vec![1, 1]));
     assert_eq!(xmodem.expect_byte(1, "1").expect("expected"), 1);
     let e = xmodem.expect_byte(2, "1, please").expect_err("expect the unexpected");
-    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_err!(e, XmodemError::UnexpectedByte { expected: "1, please", received: 1 });
 }
 
 #[test]
@@ -104,7 +99,7 @@ fn test_unexpected_can() {
         .expect_byte(SOH, "want SOH")
         .expect_err("have CAN");
 
-    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_err!(e, XmodemError::Cancelled { packets: 0 });
 }
 
 #[test]
@@ -114,7 +109,7 @@ fn test_cancel_on_unexpected() {
         .expect_byte_or_cancel(SOH, "want SOH")
         .expect_err("have CAN");
 
-    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_err!(e, XmodemError::Cancelled { packets: 0 });
     assert_eq!(buffer[1], CAN);
 
     let mut buffer = vec![0, 0];
@@ -122,7 +117,7 @@ fn test_cancel_on_unexpected() {
         .expect_byte_or_cancel(SOH, "want SOH")
         .expect_err("have 0");
 
-    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_err!(e, XmodemError::UnexpectedByte { received: 0, .. });
     assert_eq!(buffer[1], CAN);
 }
 
@@ -138,7 +133,7 @@ fn test_can_in_packet_and_checksum() {
         Xmodem::receive(tx, &mut output[..]).map(|_| output)
     });
 
-    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 256);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 256);
     let output = rx_thread.join().expect("rx join okay").expect("rx okay");
     assert_eq!(&input[..], &output[..]);
 }
@@ -149,8 +144,323 @@ fn test_transmit_reported_bytes() {
     let (tx, rx) = pipe();
     let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], rx));
     let rx_thread = std::thread::spawn(move || Xmodem::receive(tx, &mut output[..]));
-    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 50);
-    assert_eq!(rx_thread.join().expect("rx join okay").expect("rx okay"), 128);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 50);
+    assert_eq!(rx_thread.join().expect("rx join okay").expect("rx okay").bytes, 128);
+}
+
+#[test]
+fn test_pad_byte() {
+    let input = [7u8; 130];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::builder().pad(SUB).transmit(&input[..], rx));
+
+    let mut output = vec![];
+    assert_eq!(Xmodem::receive(tx, &mut output).expect("rx okay").bytes, 256);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 130);
+    assert_eq!(&output[..130], &input[..]);
+    assert!(output[130..].iter().all(|&b| b == SUB));
+}
+
+#[test]
+fn test_trim_padding() {
+    for &len in &[0, 1, 127, 128, 129, 300] {
+        let input: Vec<u8> = (0..len).map(|i| i as u8 % 0x10 + 1).collect();
+        let data = input.clone();
+        let (tx, rx) = pipe();
+        let tx_thread = std::thread::spawn(move || Xmodem::builder().pad(SUB).transmit(&data[..], rx));
+
+        let mut output = vec![];
+        let received = Xmodem::builder().trim(SUB).receive(tx, &mut output).expect("rx okay");
+        assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, len);
+        assert_eq!(received.bytes, len);
+        assert_eq!(output, input);
+    }
+}
+
+#[test]
+fn test_receive_with_handler() {
+    let mut input = [0u8; 300];
+    (0..300usize).for_each(|i| input[i] = i as u8);
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], rx));
+
+    // copy straight into a fixed buffer, as a bootloader would
+    let mut memory = [0xFFu8; 384];
+    let mut offset = 0;
+    let received = Xmodem::receive_with_handler(tx, |packet| {
+        memory[offset..offset + 128].copy_from_slice(packet);
+        offset += 128;
+    }).expect("rx okay");
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 300);
+    assert_eq!((received.bytes, offset), (384, 384));
+    assert_eq!(&memory[..300], &input[..]);
+    assert!(memory[300..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_stateful_progress() {
+    let input = [0u8; 300];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut packets = 0;
+        Xmodem::transmit_with_progress(&input[..], rx, |p| {
+            if let Progress::Packet(_) = p {
+                packets += 1;
+            }
+        }).expect("tx okay");
+        packets
+    });
+
+    let mut started = 0;
+    let mut output = vec![];
+    Xmodem::builder()
+        .progress(|p| if let Progress::Started = p { started += 1 })
+        .receive(tx, &mut output)
+        .expect("rx okay");
+
+    assert_eq!(started, 1);
+    assert_eq!(tx_thread.join().expect("tx join okay"), 3);
+}
+
+#[test]
+fn test_progress_totals() {
+    let input = [7u8; 300];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let (mut totals, mut naks) = (Totals::default(), 0);
+        Xmodem::transmit_with_progress(&input[..], Noisy(rx, 10), |p| match p {
+            Progress::Transferred(t) => totals = t,
+            Progress::NAK => naks += 1,
+            _ => {}
+        }).expect("tx okay");
+        (totals, naks)
+    });
+
+    let mut output = vec![];
+    Xmodem::receive(tx, &mut output).expect("rx okay");
+    assert_eq!(&output[..300], &input[..]);
+
+    // the corrupted first packet was sent twice, but counted once
+    let (totals, naks) = tx_thread.join().expect("tx join okay");
+    assert_eq!((totals.bytes, totals.retries, naks), (384, 1, 1));
+    assert!(totals.elapsed.is_some());
+    assert!(totals.throughput().is_some());
+}
+
+#[test]
+fn test_transfer_stats() {
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&[7u8; 300][..], Noisy(rx, 10)));
+    let received = Xmodem::builder().trim(0).receive(tx, vec![]).expect("rx okay");
+    let sent = tx_thread.join().expect("tx join okay").expect("tx okay");
+
+    // the corrupted first packet was NAKed once and sent twice
+    let expected = TransferStats { bytes: 300, packets: 3, retransmissions: 1, naks: 1, cancels: 0, elapsed: None };
+    assert_eq!(TransferStats { elapsed: None, ..sent }, expected);
+    assert_eq!(TransferStats { elapsed: None, ..received }, expected);
+    assert!(sent.elapsed.is_some() && received.throughput().is_some());
+}
+
+#[test]
+fn test_slice_and_vec() {
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit_slice(b"hello, world", rx));
+    let received = Xmodem::receive_to_vec(tx).expect("rx okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 12);
+    assert_eq!(&received[..12], b"hello, world");
+    assert_eq!(received.len(), 128);
+
+    // with the builder's settings
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::builder().pad(SUB).transmit(&b"hello"[..], rx));
+    let received = Xmodem::builder().trim(SUB).receive_to_vec(tx).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(received, b"hello");
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(update_crc32(0, b""), 0);
+    assert_eq!(update_crc32(0, b"123456789"), 0xCBF43926);
+    assert_eq!(update_crc32(update_crc32(0, b"1234"), b"56789"), 0xCBF43926);
+}
+
+#[test]
+fn test_manifest() {
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut files = [
+            ("kernel.bin", Cursor::new(vec![7u8; 300])),
+            ("empty", Cursor::new(vec![])),
+            ("config.txt", Cursor::new(b"baud=115200".to_vec())),
+        ];
+
+        Manifest::transmit(&mut files, rx)
+    });
+
+    let files: Files = RefCell::new(vec![]);
+    let count = Manifest::receive(tx, |entry| {
+        files.borrow_mut().push((entry.name().into(), Some(entry.size()), vec![]));
+        Ok(LastFile(&files))
+    });
+    let stats = tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(count.expect("rx okay"), 3);
+
+    // the manifest's blocks aren't counted as data
+    assert_eq!(stats.bytes, 311);
+    assert_eq!(stats.packets, 1 + 3 + 3 + 1);
+
+    let files = files.into_inner();
+    assert_eq!(files[0], ("kernel.bin".into(), Some(300), vec![7u8; 300]));
+    assert_eq!(files[1], ("empty".into(), Some(0), vec![]));
+    assert_eq!(files[2], ("config.txt".into(), Some(11), b"baud=115200".to_vec()));
+}
+
+/// A file that changes each time it's read.
+struct Changing(Cursor<Vec<u8>>);
+
+impl io::Read for Changing {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        self.0.get_mut()[0] ^= 1;
+        Ok(n)
+    }
+}
+
+impl io::Seek for Changing {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+#[test]
+fn test_manifest_errors() {
+    // a plain XMODEM transfer isn't a manifest
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit_slice(b"hello", rx));
+    let e = Manifest::receive(tx, |_| Ok(vec![])).expect_err("not a manifest");
+    assert_err!(e, XmodemError::BadHeader(_));
+    tx_thread.join().expect("tx join okay").expect_err("receiver hung up");
+
+    // a file that changes after its CRC-32 was computed
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        Manifest::transmit(&mut [("changing", Changing(Cursor::new(vec![1u8; 10])))], rx)
+    });
+    let e = Manifest::receive(tx, |_| Ok(vec![])).expect_err("file changed");
+    assert_err!(e, XmodemError::CrcMismatch { expected, computed } if expected != computed);
+    tx_thread.join().expect("tx join okay").expect_err("receiver hung up");
+
+    let (tx, _rx) = pipe();
+    let files = &mut [("", Cursor::new(vec![]))];
+    assert_err!(Manifest::transmit(files, tx).expect_err("no name"), XmodemError::BadHeader(_));
+}
+
+/// A pipe whose writes lose XON and XOFF, like a link with software flow
+/// control.
+struct FlowControlled(Pipe);
+
+impl io::Read for FlowControlled {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl io::Write for FlowControlled {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf.iter().filter(|&&b| b != XON && b != XOFF) {
+            self.0.write_all(&[byte])?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_escape() {
+    let input: Vec<u8> = (0..=255).collect();
+    let (mut tx, rx) = pipe();
+    let data = input.clone();
+    let tx_thread = std::thread::spawn(move || {
+        Xmodem::builder().escape(true).transmit(&data[..], FlowControlled(rx))
+    });
+
+    let mut output = vec![];
+    Xmodem::builder().escape(true).receive(&mut tx, &mut output).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(output, input);
+
+    // everything after the SOH is escaped
+    let mut script = Script(Cursor::new(vec![CRC, ACK]), vec![]);
+    let mut xmodem = Xmodem::builder().escape(true).build(&mut script);
+    xmodem.write_packet(&[XON; 128]).expect("sent");
+    assert_eq!(&script.1[..5], &[SOH, 1, !1, DLE, XON ^ 0x40]);
+
+    // and bare XONs and XOFFs in a packet are flow control
+    let mut escaped = script.1.clone();
+    escaped.insert(5, XON);
+    escaped.insert(9, XOFF);
+    let mut packet = [0u8; 128];
+    let mut xmodem = Xmodem::builder().escape(true).build(Script(Cursor::new(escaped), vec![]));
+    xmodem.core.crc = true;
+    assert_eq!(xmodem.read_packet(&mut packet[..]).expect("read okay"), 128);
+    assert_eq!(&packet[..], &[XON; 128][..]);
+}
+
+#[test]
+fn test_verify() {
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::builder().verify(true).transmit(&[9u8; 300][..], rx));
+    let mut output = vec![];
+    Xmodem::builder().verify(true).trim(0).receive(&mut tx, &mut output).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(output, vec![9u8; 300]);
+
+    // the CRC-32s follow the EOT, and cover the padding
+    let mut packet = [0u8; 128];
+    packet[..5].copy_from_slice(b"hello");
+    let crc = update_crc32(0, &packet);
+    let mut script = Script(Cursor::new(vec![CRC, ACK, NAK, CRC, 0xDE, 0xAD, 0xBE, 0xEF]), vec![]);
+    let e = Xmodem::builder().verify(true).transmit(&b"hello"[..], &mut script).expect_err("mismatch");
+    assert_err!(e, XmodemError::CrcMismatch { expected, computed } if expected == crc && computed == 0xDEADBEEF);
+    assert_eq!(&script.1[script.1.len() - 7..], &[&[EOT, EOT, ACK][..], &crc.to_be_bytes()[..]].concat()[..]);
+
+    let mut input = vec![SOH, 1, !1];
+    input.extend_from_slice(&packet);
+    input.extend_from_slice(&crc16(&packet).to_be_bytes());
+    input.extend_from_slice(&[EOT, EOT, ACK, 0xDE, 0xAD, 0xBE, 0xEF]);
+    let mut script = Script(Cursor::new(input), vec![]);
+    let e = Xmodem::builder().verify(true).receive(&mut script, &mut vec![]).expect_err("mismatch");
+    assert_err!(e, XmodemError::CrcMismatch { expected, computed } if expected == 0xDEADBEEF && computed == crc);
+    assert_eq!(&script.1[script.1.len() - 5..], &[&[CRC][..], &crc.to_be_bytes()[..]].concat()[..]);
+}
+
+#[test]
+fn test_verify_one_sided() {
+    // a verifying receiver asks with `C`, which the sender declines
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&[7u8; 300][..], rx));
+    let mut output = vec![];
+    Xmodem::builder().verify(true).trim(0).receive(&mut tx, &mut output).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(output, vec![7u8; 300]);
+
+    // a verifying sender is never asked
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::builder().verify(true).transmit(&[7u8; 300][..], rx));
+    let mut output = vec![];
+    Xmodem::builder().trim(0).receive(&mut tx, &mut output).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(output, vec![7u8; 300]);
+
+    let mut script = Script(Cursor::new(vec![CRC, ACK, NAK, CRC]), vec![]);
+    Xmodem::transmit(&b"hello"[..], &mut script).expect("tx okay");
+    assert_eq!(&script.1[script.1.len() - 3..], &[EOT, EOT, NAK]);
 }
 
 #[test]
@@ -162,12 +472,12 @@ fn test_raw_transmission() {
     let (mut tx, mut rx) = pipe();
     let tx_thread = std::thread::spawn(move || {
         Xmodem::transmit(&input[..], &mut rx).expect("transmit okay");
-        rx.2
+        rx.take_written()
     });
 
     let rx_thread = std::thread::spawn(move || {
         Xmodem::receive(&mut tx, &mut output[..]).expect("receive okay");
-        tx.2
+        tx.take_written()
     });
 
     let rx_buf = tx_thread.join().expect("tx join okay");
@@ -176,18 +486,120 @@ fn test_raw_transmission() {
     // check packet 1
     assert_eq!(&rx_buf[0..3], &[SOH, 1, 255 - 1]);
     assert_eq!(&rx_buf[3..(3 + 128)], &input[..128]);
-    assert_eq!(rx_buf[131], input[..128].iter().fold(0, |a: u8, b| a.wrapping_add(*b)));
+    assert_eq!(&rx_buf[131..133], &crc16(&input[..128]).to_be_bytes());
 
     // check packet 2
-    assert_eq!(&rx_buf[132..135], &[SOH, 2, 255 - 2]);
-    assert_eq!(&rx_buf[135..(135 + 128)], &input[128..]);
-    assert_eq!(rx_buf[263], input[128..].iter().fold(0, |a: u8, b| a.wrapping_add(*b)));
+    assert_eq!(&rx_buf[133..136], &[SOH, 2, 255 - 2]);
+    assert_eq!(&rx_buf[136..(136 + 128)], &input[128..]);
+    assert_eq!(&rx_buf[264..266], &crc16(&input[128..]).to_be_bytes());
 
     // check EOT
-    assert_eq!(&rx_buf[264..], &[EOT, EOT]);
+    assert_eq!(&rx_buf[266..], &[EOT, EOT]);
 
     // check receiver responses
-    assert_eq!(&tx_buf, &[NAK, ACK, ACK, NAK, ACK]);
+    assert_eq!(&tx_buf, &[CRC, ACK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn test_crc16() {
+    assert_eq!(crc16(b""), 0);
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+}
+
+#[test]
+fn test_checksum_fallback() {
+    let mut input = [0u8; 256];
+    (0..256usize).for_each(|i| input[i] = i as u8);
+
+    // a sender that only knows checksums never sees the receiver's `C`s
+    let (mut tx, rx) = pipe();
+    tx.set_timeout(Some(Duration::from_millis(20)));
+    let (filter_tx, filter_rx) = channel();
+    let (to_rx, from_rx) = rx.into_channels();
+    std::thread::spawn(move || {
+        for byte in from_rx.iter().filter(|&b| b != CRC) {
+            if filter_tx.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+
+    let rx = Pipe::from_channels(to_rx, filter_rx);
+    let tx_thread = std::thread::spawn(move || {
+        let mut rx = rx;
+        Xmodem::transmit(&input[..], &mut rx).expect("transmit okay");
+        rx.take_written()
+    });
+
+    let mut output = [0u8; 256];
+    Xmodem::receive(&mut tx, &mut output[..]).expect("receive okay");
+    let rx_buf = tx_thread.join().expect("tx join okay");
+
+    assert_eq!(&output[..], &input[..]);
+    assert_eq!(&tx.written()[..CRC_POLLS + 1], &[CRC, CRC, CRC, NAK]);
+    assert_eq!(rx_buf[131], checksum(&input[..128]));
+    assert_eq!(&rx_buf[132..135], &[SOH, 2, 255 - 2]);
+}
+
+#[test]
+fn test_streaming() {
+    let mut input = [0u8; 400];
+    (0..400usize).for_each(|i| input[i] = i as u8);
+
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], rx));
+    let mut output = vec![];
+    let received = Xmodem::builder().streaming(true).receive(&mut tx, &mut output).expect("rx okay");
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 400);
+    assert_eq!(received.bytes, 512);
+    assert_eq!(&output[..400], &input[..]);
+    // no ACKs until the EOT
+    assert_eq!(tx.written(), &[STREAM, ACK]);
+}
+
+#[test]
+fn test_streaming_cancels_on_error() {
+    // the sender's end is kept open so that both CANs can be written
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut noisy = Noisy(rx, 140);
+        (Xmodem::transmit(&[5u8; 300][..], &mut noisy), noisy)
+    });
+
+    let e = Xmodem::builder().streaming(true).receive(&mut tx, vec![]).expect_err("bad CRC");
+    assert_err!(e, XmodemError::ChecksumMismatch);
+    assert_eq!(tx.written(), &[STREAM, CAN, CAN]);
+
+    // the sender finds out at the end
+    let e = tx_thread.join().expect("tx join okay").0.expect_err("cancelled");
+    assert_err!(e, XmodemError::Cancelled { packets: 3 });
+}
+
+#[test]
+fn test_streaming_fallback() {
+    // a sender that doesn't know XMODEM-G never sees the receiver's `G`s
+    let (mut tx, rx) = pipe();
+    tx.set_timeout(Some(Duration::from_millis(20)));
+    let (filter_tx, filter_rx) = channel();
+    let (to_rx, from_rx) = rx.into_channels();
+    std::thread::spawn(move || {
+        for byte in from_rx.iter().filter(|&b| b != STREAM) {
+            if filter_tx.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+
+    let rx = Pipe::from_channels(to_rx, filter_rx);
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&[3u8; 128][..], rx));
+    let mut output = vec![];
+    Xmodem::builder().streaming(true).receive(&mut tx, &mut output).expect("rx okay");
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 128);
+    assert_eq!(output, vec![3u8; 128]);
+    assert_eq!(&tx.written()[..CRC_POLLS + 1], &[STREAM, STREAM, STREAM, CRC]);
+    assert_eq!(&tx.written()[CRC_POLLS + 1..], &[ACK, NAK, ACK]);
 }
 
 #[test]
@@ -196,10 +608,10 @@ fn test_small_packet_eof_error() {
 
     let mut buffer = [1, 2, 3];
     let e = xmodem.read_packet(&mut buffer[..]).expect_err("read EOF");
-    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    assert_err!(e, XmodemError::BadBuffer);
 
     let e = xmodem.write_packet(&buffer).expect_err("write EOF");
-    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    assert_err!(e, XmodemError::BadBuffer);
 }
 
 #[test]
@@ -209,13 +621,13 @@ fn test_bad_control() {
         .read_packet(&mut packet[..])
         .expect_err("CAN");
 
-    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_err!(e, XmodemError::Cancelled { packets: 0 });
 
     let e = Xmodem::new(Cursor::new(vec![0, 0xFF]))
         .read_packet(&mut packet[..])
         .expect_err("bad contorl");
 
-    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_err!(e, XmodemError::UnexpectedByte { received: 0, .. });
 }
 
 #[test]
@@ -227,3 +639,852 @@ fn test_eot() {
 
     assert_eq!(&buffer[..], &[NAK, EOT, NAK, EOT, ACK]);
 }
+
+#[test]
+fn test_frame() {
+    let data: Vec<u8> = (0..128).map(|i| i as u8 ^ 0x5A).collect();
+    let mut packet = [0u8; MAX_PACKET_LEN];
+    for &crc in &[true, false] {
+        let len = encode_packet(7, &data, crc, &mut packet).expect("encoded");
+        assert_eq!(len, packet_len(crc));
+        let (num, decoded) = decode_packet(&packet[..len], crc).expect("decoded");
+        assert_eq!((num, decoded), (7, &data[..]));
+
+        // a whole packet from the core is the same one
+        let mut core = XmodemCore::new(crc);
+        core.sequence = 7;
+        let action = packet[..len].iter().fold(Action::Continue, |_, &b| core.handle_byte(b));
+        assert_err!(action, Action::Packet(7));
+    }
+
+    // and it's what `write_packet` sends
+    let mut script = Script(Cursor::new(vec![CRC, ACK]), vec![]);
+    Xmodem::new(&mut script).write_packet(&data).expect("sent");
+    let len = encode_packet(1, &data, true, &mut packet).expect("encoded");
+    assert_eq!(&script.1[..], &packet[..len]);
+
+    assert_err!(encode_packet(1, &data[..127], true, &mut packet).expect_err("short"), XmodemError::BadBuffer);
+    assert_err!(decode_packet(&packet[..len - 1], true).expect_err("short"), XmodemError::BadBuffer);
+    packet[2] ^= 1;
+    let e = decode_packet(&packet[..len], true).expect_err("bad complement");
+    assert_err!(e, XmodemError::PacketNumber { received: 1, .. });
+    packet[2] ^= 1;
+    packet[3] ^= 1;
+    assert_err!(decode_packet(&packet[..len], true).expect_err("corrupt"), XmodemError::ChecksumMismatch);
+    packet[0] = EOT;
+    assert_err!(decode_packet(&packet[..len], true).expect_err("no SOH"), XmodemError::UnexpectedByte { received: EOT, .. });
+}
+
+#[test]
+fn test_core() {
+    let data: Vec<u8> = (0..128).collect();
+    let mut packet = vec![SOH, 1, !1];
+    packet.extend_from_slice(&data);
+    packet.extend_from_slice(&crc16(&data).to_be_bytes());
+
+    // one byte at a time, nothing happens until the packet is complete
+    let feed = |core: &mut XmodemCore, bytes: &[u8]| bytes.iter().fold(Action::Continue, |_, &b| core.handle_byte(b));
+    let mut core = XmodemCore::new(true);
+    for &byte in &packet[..packet.len() - 1] {
+        assert!(core.wanted() >= 1);
+        assert_err!(core.handle_byte(byte), Action::Continue);
+    }
+    assert_err!(core.handle_byte(packet[packet.len() - 1]), Action::Packet(1));
+    assert_eq!(&core.packet()[..], &data[..]);
+
+    // the same packet again is a duplicate, and a corrupt one is rejected
+    assert_err!(feed(&mut core, &packet), Action::Duplicate(1));
+    packet[1..3].copy_from_slice(&[2, !2]);
+    packet[10] ^= 1;
+    assert_err!(feed(&mut core, &packet), Action::Reject(XmodemError::ChecksumMismatch));
+    assert_err!(feed(&mut core, &[SOH, 3, !3]), Action::Reject(XmodemError::PacketNumber { expected: 2, received: 3 }));
+
+    // a packet cut short is forgotten
+    core.handle_byte(SOH);
+    core.discard();
+    assert_err!(core.handle_byte(EOT), Action::Reply(NAK));
+    assert_err!(core.handle_byte(EOT), Action::End);
+    assert_err!(core.handle_byte(CAN), Action::Cancelled);
+
+    // whatever arrives, the core always wants more and never panics
+    let mut core = XmodemCore::new(false);
+    let mut seed = 0x2545_f491u32;
+    for _ in 0..100_000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let byte = match seed >> 28 {
+            0 => SOH,
+            1 => EOT,
+            _ => (seed >> 16) as u8,
+        };
+        core.handle_byte(byte);
+        assert!(core.wanted() >= 1 && core.wanted() <= 128);
+    }
+}
+
+#[test]
+fn test_ymodem_header() {
+    let header = ymodem::Header::new("kernel8.img", 1000).expect("valid name").with_modified(0o1234);
+    let mut block = [0xFFu8; 128];
+    header.encode(&mut block);
+    assert_eq!(&block[..22], b"kernel8.img\x001000 1234\x00");
+    assert!(block[22..].iter().all(|&b| b == 0));
+
+    let parsed = ymodem::Header::parse(&block).expect("parse okay").expect("not the end");
+    assert_eq!(parsed.name(), "kernel8.img");
+    assert_eq!(parsed.size(), Some(1000));
+    assert_eq!(parsed.modified(), Some(0o1234));
+
+    assert!(ymodem::Header::parse(&[0; 128]).expect("parse okay").is_none());
+    assert!(ymodem::Header::new("", 0).is_err());
+    assert!(ymodem::Header::new("a\0b", 0).is_err());
+    assert!(ymodem::Header::new(&"x".repeat(ymodem::MAX_NAME_LEN + 1), 0).is_err());
+}
+
+#[test]
+fn test_ymodem_batch() {
+    let first: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let second = b"hello".to_vec();
+    let files = vec![
+        (ymodem::Header::new("first", 300).unwrap(), first.clone()),
+        (ymodem::Header::new("second", 5).unwrap().with_modified(42), second.clone()),
+    ];
+
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        Ymodem::transmit(files.into_iter().map(|(h, data)| (h, Cursor::new(data))), rx)
+    });
+
+    let received = Files::new(vec![]);
+    let count = Ymodem::receive(tx, |header| {
+        received.borrow_mut().push((header.name().to_string(), header.modified(), vec![]));
+        Ok(LastFile(&received))
+    });
+
+    assert_eq!(count.expect("rx okay"), 2);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 305);
+    assert_eq!(received.into_inner(), vec![
+        ("first".to_string(), None, first),
+        ("second".to_string(), Some(42), second),
+    ]);
+}
+
+/// Scripted input, captured output.
+struct Script(Cursor<Vec<u8>>, Vec<u8>);
+
+impl io::Read for Script {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl io::Write for Script {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.1.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A pipe that flips a bit of the byte written at offset `.1`.
+struct Noisy(Pipe, usize);
+
+impl io::Read for Noisy {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl io::Write for Noisy {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buf = buf.to_vec();
+        if self.1 < buf.len() {
+            buf[self.1] ^= 0x01;
+        }
+
+        self.1 = self.1.wrapping_sub(buf.len());
+        self.0.write(&buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn zmodem_hex_header(kind: u8, args: [u8; 4]) -> Vec<u8> {
+    let bytes = [kind, args[0], args[1], args[2], args[3]];
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let mut header = format!("**\x18B{}{:04x}\r", hex, crc16(&bytes)).into_bytes();
+    header.push(0x8A);
+    if kind != 3 && kind != 8 {
+        header.push(0x11);
+    }
+
+    header
+}
+
+/// Runs a ZMODEM transfer of `files` between two threads. The receiver has
+/// the first `have[i]` bytes of file `i` already, or skips it if `None`.
+fn zmodem_transfer<T, P>(files: Vec<(&'static str, Vec<u8>)>, have: Vec<Option<usize>>, sender: T) -> (io::Result<usize>, usize, Received)
+    where T: FnOnce(Pipe) -> P + Send + 'static, P: io::Read + io::Write
+{
+    let (mut tx, mut rx) = pipe();
+    tx.set_timeout(Some(Duration::from_millis(500)));
+    rx.set_timeout(Some(Duration::from_millis(500)));
+
+    let sent = files.clone();
+    let tx_thread = std::thread::spawn(move || {
+        let files = sent.into_iter().map(|(name, data)| {
+            (ymodem::Header::new(name, data.len() as u64).unwrap(), Cursor::new(data))
+        });
+
+        Zmodem::transmit(files, sender(rx))
+    });
+
+    let received = Files::new(vec![]);
+    let mut offered = 0;
+    let count = Zmodem::receive(tx, |header| {
+        let (name, data) = &files[offered];
+        assert_eq!((header.name(), header.size()), (*name, Some(data.len() as u64)));
+        offered += 1;
+
+        Ok(have[offered - 1].map(|n| {
+            received.borrow_mut().push((name.to_string(), None, data[..n].to_vec()));
+            (LastFile(&received), n as u64)
+        }))
+    });
+
+    let sent = tx_thread.join().expect("tx join okay").expect("tx okay");
+    (count, sent, received.into_inner())
+}
+
+#[test]
+fn test_zmodem_raw_session() {
+    assert_eq!(zmodem_hex_header(0, [0; 4]), b"**\x18B00000000000000\r\x8a\x11".to_vec());
+
+    let mut input = zmodem_hex_header(0, [0; 4]);
+    input.extend(zmodem_hex_header(8, [0; 4]));
+    input.extend(b"OO");
+
+    let mut script = Script(Cursor::new(input), vec![]);
+    let count = Zmodem::receive(&mut script, |_| -> io::Result<Option<(Vec<u8>, u64)>> {
+        panic!("no file was offered")
+    });
+
+    assert_eq!(count.expect("receive okay"), 0);
+    let mut expected = zmodem_hex_header(1, [0, 0, 0, 3]);
+    expected.extend(zmodem_hex_header(1, [0, 0, 0, 3]));
+    expected.extend(zmodem_hex_header(8, [0; 4]));
+    assert_eq!(script.1, expected);
+}
+
+#[test]
+fn test_zmodem_batch() {
+    // every byte value, so escaping is exercised, across several windows
+    let big: Vec<u8> = (0..70_000u32).map(|i| (i * 7) as u8).collect();
+    let files = vec![("big", big.clone()), ("empty", vec![]), ("small", b"hello".to_vec())];
+
+    let (count, sent, received) = zmodem_transfer(files, vec![Some(0); 3], |rx| rx);
+    assert_eq!(count.expect("rx okay"), 3);
+    assert_eq!(sent, 70_005);
+    assert_eq!(received, vec![
+        ("big".to_string(), None, big),
+        ("empty".to_string(), None, vec![]),
+        ("small".to_string(), None, b"hello".to_vec()),
+    ]);
+}
+
+#[test]
+fn test_zmodem_recovers_from_corruption() {
+    let data: Vec<u8> = (0..40_000u32).map(|i| b'a' + (i % 26) as u8).collect();
+    let files = vec![("data", data.clone())];
+
+    // well into the first window's data
+    let (count, sent, received) = zmodem_transfer(files, vec![Some(0)], |rx| Noisy(rx, 5_000));
+    assert_eq!(count.expect("rx okay"), 1);
+    assert_eq!(sent, data.len());
+    assert_eq!(received, vec![("data".to_string(), None, data)]);
+}
+
+#[test]
+fn test_zmodem_resume_and_skip() {
+    let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+    let files = vec![("partial", data.clone()), ("skipped", b"skip me".to_vec())];
+
+    let (count, sent, received) = zmodem_transfer(files, vec![Some(6_000), None], |rx| rx);
+    assert_eq!(count.expect("rx okay"), 1);
+    assert_eq!(sent, 4_000);
+    assert_eq!(received, vec![("partial".to_string(), None, data)]);
+}
+
+/// Fails every other read with `TimedOut`, like a port polled with a short
+/// timeout.
+struct Flaky(Cursor<Vec<u8>>, Vec<u8>, bool);
+
+impl io::Read for Flaky {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.2 = !self.2;
+        if self.2 {
+            return ioerr!(TimedOut, "poll timed out");
+        }
+
+        self.0.read(&mut buf[..1])
+    }
+}
+
+impl io::Write for Flaky {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.1.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_duplicate_packet() {
+    let packet = |num: u8, fill: u8| {
+        let mut packet = vec![SOH, num, !num];
+        packet.extend_from_slice(&[fill; 128]);
+        packet.extend_from_slice(&crc16(&[fill; 128]).to_be_bytes());
+        packet
+    };
+
+    // the ACK for packet 1 got lost, so it comes again
+    let mut input = packet(1, 1);
+    input.extend(packet(1, 1));
+    input.extend(packet(2, 2));
+    input.extend_from_slice(&[EOT, EOT]);
+
+    let mut script = Script(Cursor::new(input), vec![]);
+    let mut output = vec![];
+    assert_eq!(Xmodem::receive(&mut script, &mut output).expect("rx okay").bytes, 256);
+    assert_eq!(&output[..128], &[1; 128][..]);
+    assert_eq!(&output[128..], &[2; 128][..]);
+    assert_eq!(script.1, vec![CRC, ACK, ACK, ACK, NAK, ACK]);
+
+    // any other number is still out of sequence
+    let mut input = packet(1, 1);
+    input.extend(packet(3, 3));
+    let e = Xmodem::receive(Script(Cursor::new(input), vec![]), vec![]).expect_err("bad number");
+    assert_err!(e, XmodemError::PacketNumber { expected: 2, received: 3 });
+}
+
+/// Sends the same bytes over and over, forever, and records what it's sent.
+struct Repeating(Vec<u8>, usize, Vec<u8>);
+
+impl io::Read for Repeating {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.0.len() - self.1);
+        buf[..n].copy_from_slice(&self.0[self.1..self.1 + n]);
+        self.1 = (self.1 + n) % self.0.len();
+        Ok(n)
+    }
+}
+
+impl io::Write for Repeating {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.2.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_endless_duplicates() {
+    let mut packet = vec![SOH, 1, !1];
+    packet.extend_from_slice(&[1; 128]);
+    packet.extend_from_slice(&crc16(&[1; 128]).to_be_bytes());
+
+    // packet 1 arrives once, then again as a duplicate every time after
+    let mut sender = Repeating(packet, 0, vec![]);
+    let e = Xmodem::builder().retries(4).receive(&mut sender, vec![]).expect_err("repeated forever");
+    assert_err!(e, XmodemError::RetriesExhausted);
+    assert_eq!(sender.2, vec![CRC, ACK, ACK, ACK, ACK, ACK]);
+}
+
+#[test]
+fn test_builder_retries() {
+    let mut input = vec![SOH, 1, !1];
+    input.extend_from_slice(&[0; 128]);
+    input.extend_from_slice(&[0xDE, 0xAD]);
+
+    // one attempt: the bad packet fails the transfer
+    let mut script = Script(Cursor::new(input.clone()), vec![]);
+    let e = Xmodem::builder().retries(1).receive(&mut script, vec![]).expect_err("bad CRC");
+    assert_err!(e, XmodemError::RetriesExhausted);
+    assert_eq!(script.1, vec![CRC, NAK]);
+
+    // the default retries, and runs out of input instead
+    let mut script = Script(Cursor::new(input), vec![]);
+    let e = Xmodem::builder().receive(&mut script, vec![]).expect_err("EOF");
+    assert_err!(e, XmodemError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_builder_byte_timeout() {
+    let mut input = vec![SOH, 1, !1];
+    input.extend_from_slice(&[7; 128]);
+    input.extend_from_slice(&crc16(&[7; 128]).to_be_bytes());
+    input.extend_from_slice(&[EOT, EOT]);
+
+    let mut output = vec![];
+    let mut port = Flaky(Cursor::new(input.clone()), vec![], false);
+    let received = Xmodem::builder()
+        .byte_timeout(Duration::from_secs(1))
+        .receive(&mut port, &mut output)
+        .expect("receive okay");
+
+    assert_eq!(received.bytes, 128);
+    assert_eq!(output, vec![7; 128]);
+
+    // without one, the first poll to time out mid-packet fails the transfer
+    let mut port = Flaky(Cursor::new(input), vec![], false);
+    let e = Xmodem::receive(&mut port, vec![]).expect_err("timed out");
+    assert_err!(e, XmodemError::Io(ref e) if e.kind() == io::ErrorKind::TimedOut);
+}
+
+/// A transport whose reads always time out.
+struct Silent(Vec<u8>, usize);
+
+impl io::Read for Silent {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        self.1 += 1;
+        ioerr!(TimedOut, "poll timed out")
+    }
+}
+
+impl io::Write for Silent {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_clock_byte_timeout() {
+    // a clock that moves a second forward each time it's read
+    let ticks = Cell::new(0);
+    let clock = || {
+        ticks.set(ticks.get() + 1);
+        Duration::from_secs(ticks.get())
+    };
+
+    let mut port = Silent(vec![], 0);
+    let e = Xmodem::builder()
+        .clock(clock)
+        .retries(4)
+        .byte_timeout(Duration::from_secs(10))
+        .receive(&mut port, vec![])
+        .expect_err("timed out");
+
+    // each poll waits out ten seconds of silence, then asks again
+    assert_err!(e, XmodemError::RetriesExhausted);
+    assert_eq!(port.0, vec![CRC, CRC, CRC, NAK, NAK]);
+    assert_eq!(port.1, 4 * 11);
+
+    // without a clock, the byte timeout can't be measured
+    let mut port = Silent(vec![], 0);
+    let e = Xmodem::builder()
+        .clock(NoClock)
+        .byte_timeout(Duration::from_secs(10))
+        .build(&mut port)
+        .read_packet(&mut [0; 128])
+        .expect_err("timed out");
+
+    assert_err!(e, XmodemError::Io(ref e) if e.kind() == io::ErrorKind::TimedOut);
+    assert_eq!(port.1, 1);
+}
+
+#[test]
+fn test_poll_interval() {
+    let ticks = Cell::new(0);
+    let clock = || {
+        ticks.set(ticks.get() + 1);
+        Duration::from_secs(ticks.get())
+    };
+
+    // the sender is asked again every three seconds, not every timeout,
+    // and the receiver gives up on CRC mode after two requests
+    let mut port = Silent(vec![], 0);
+    let e = Xmodem::builder()
+        .clock(clock)
+        .retries(3)
+        .polls(2)
+        .poll_interval(Duration::from_secs(3))
+        .receive(&mut port, vec![])
+        .expect_err("timed out");
+
+    assert_err!(e, XmodemError::RetriesExhausted);
+    assert_eq!(port.0, vec![CRC, CRC, NAK, NAK]);
+    assert_eq!(port.1, 3 * 3);
+
+    // without a clock, every timeout is another request
+    let mut port = Silent(vec![], 0);
+    let e = Xmodem::builder()
+        .clock(NoClock)
+        .retries(3)
+        .polls(1)
+        .poll_interval(Duration::from_secs(3))
+        .receive(&mut port, vec![])
+        .expect_err("timed out");
+
+    assert_err!(e, XmodemError::RetriesExhausted);
+    assert_eq!(port.0, vec![CRC, NAK, NAK, NAK]);
+    assert_eq!(port.1, 3);
+}
+
+#[test]
+fn test_packet_timeout() {
+    let mut input = vec![SOH, 1, !1];
+    input.extend_from_slice(&[7; 128]);
+    input.extend_from_slice(&crc16(&[7; 128]).to_be_bytes());
+    input.extend_from_slice(&[EOT, EOT]);
+
+    let ticks = Cell::new(0);
+    let clock = || {
+        ticks.set(ticks.get() + 1);
+        Duration::from_secs(ticks.get())
+    };
+
+    // every byte comes within the byte timeout, but the packet takes far
+    // longer than a minute
+    let mut port = Flaky(Cursor::new(input.clone()), vec![], false);
+    let e = Xmodem::builder()
+        .clock(&clock)
+        .byte_timeout(Duration::from_secs(5))
+        .packet_timeout(Duration::from_secs(60))
+        .receive(&mut port, vec![])
+        .expect_err("timed out");
+
+    assert_err!(e, XmodemError::Io(ref e) if e.kind() == io::ErrorKind::TimedOut);
+    assert_eq!(port.1, vec![CRC]);
+
+    // given long enough, it arrives
+    let mut output = vec![];
+    let mut port = Flaky(Cursor::new(input), vec![], false);
+    let received = Xmodem::builder()
+        .clock(&clock)
+        .byte_timeout(Duration::from_secs(5))
+        .packet_timeout(Duration::from_secs(3600))
+        .receive(&mut port, &mut output)
+        .expect("receive okay");
+
+    assert_eq!(received.bytes, 128);
+    assert_eq!(output, vec![7; 128]);
+}
+
+#[test]
+fn test_purge() {
+    // boot noise, then the receiver's request for CRC mode
+    let replies = vec![b'\r', b'\n', 0xFF, CRC, ACK, NAK, ACK];
+    let mut script = Script(Cursor::new(replies.clone()), vec![]);
+    let sent = Xmodem::builder().purge(3).transmit(&[1u8; 128][..], &mut script).expect("tx okay");
+    assert_eq!(sent.bytes, 128);
+    assert_eq!(&script.1[..3], &[SOH, 1, !1]);
+
+    // one byte too many
+    let mut script = Script(Cursor::new(replies.clone()), vec![]);
+    let e = Xmodem::builder().purge(2).transmit(&[1u8; 128][..], &mut script).expect_err("noise");
+    assert_err!(e, XmodemError::UnexpectedByte { received: 0xFF, .. });
+
+    // by default, none is tolerated
+    let mut script = Script(Cursor::new(replies), vec![]);
+    let e = Xmodem::transmit(&[1u8; 128][..], &mut script).expect_err("noise");
+    assert_err!(e, XmodemError::UnexpectedByte { received: b'\r', .. });
+    assert!(script.1.is_empty());
+
+    // but a CAN always ends it
+    let mut script = Script(Cursor::new(vec![b'\r', CAN, CRC]), vec![]);
+    let e = Xmodem::builder().purge(10).transmit(&[1u8; 128][..], &mut script).expect_err("cancelled");
+    assert_err!(e, XmodemError::Cancelled { packets: 0 });
+}
+
+#[test]
+fn test_write_packet_resends_on_nak() {
+    let packet: Vec<u8> = (0..128).collect();
+    let mut script = Script(Cursor::new(vec![CRC, NAK, NAK, ACK]), vec![]);
+    assert_eq!(Xmodem::new(&mut script).write_packet(&packet).expect("sent"), 128);
+
+    // three identical copies of packet 1
+    let sent = &script.1;
+    assert_eq!(sent.len(), 3 * (3 + 128 + 2));
+    for copy in sent.chunks(3 + 128 + 2) {
+        assert_eq!(&copy[..3], &[SOH, 1, !1]);
+        assert_eq!(&copy[3..131], &packet[..]);
+        assert_eq!(&copy[131..], &crc16(&packet).to_be_bytes());
+    }
+
+    // one attempt: the NAK fails the packet
+    let mut script = Script(Cursor::new(vec![CRC, NAK]), vec![]);
+    let e = Xmodem::builder().retries(1).build(&mut script).write_packet(&packet).expect_err("NAKed");
+    assert_err!(e, XmodemError::RetriesExhausted);
+}
+
+#[test]
+fn test_into_inner() {
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut xmodem = Xmodem::new(rx);
+        xmodem.transmit_from(&[1u8; 10][..]).expect("tx okay");
+        assert_eq!(xmodem.get_ref().written().len(), 3 + 128 + 2 + 2);
+        xmodem.into_inner()
+    });
+
+    let mut xmodem = Xmodem::new(tx);
+    let mut output = vec![];
+    assert_eq!(xmodem.receive_into(&mut output).expect("rx okay").bytes, 128);
+    xmodem.get_mut().take_written();
+    let mut tx = xmodem.into_inner();
+
+    // both ends are usable again
+    let mut rx = tx_thread.join().expect("tx join okay");
+    io::Write::write_all(&mut rx, b"hi").expect("write okay");
+    let mut buf = [0u8; 2];
+    io::Read::read_exact(&mut tx, &mut buf).expect("read okay");
+    assert_eq!(&buf, b"hi");
+    assert!(tx.written().is_empty());
+}
+
+#[test]
+fn test_with_halves() {
+    // a pipe's reading end and writing end, as separate pipes
+    fn split(pipe: Pipe) -> (Pipe, Pipe) {
+        let (send, recv) = pipe.into_channels();
+        (Pipe::from_channels(channel().0, recv), Pipe::from_channels(send, channel().1))
+    }
+
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let (reader, writer) = split(rx);
+        Xmodem::with_halves(reader, writer).transmit_from(&[9u8; 200][..])
+    });
+
+    let (reader, writer) = split(tx);
+    let mut xmodem = Xmodem::with_halves(reader, writer);
+    let mut output = vec![];
+    assert_eq!(xmodem.receive_into(&mut output).expect("rx okay").bytes, 256);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay").bytes, 200);
+    assert_eq!(&output[..200], &[9u8; 200][..]);
+
+    // the receiver only wrote to its writer
+    let (reader, writer) = xmodem.into_inner().into_inner();
+    assert!(reader.written().is_empty());
+    assert_eq!(writer.written()[0], CRC);
+}
+
+#[test]
+fn test_resume() {
+    let mut input = [0u8; 400];
+    (0..400usize).for_each(|i| input[i] = i as u8);
+
+    // the first two packets made it last time
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut rx = rx;
+        let sent = Xmodem::builder().resume(2).transmit(&input[256..], &mut rx).expect("tx okay");
+        (sent, rx.take_written())
+    });
+
+    let mut output = input[..256].to_vec();
+    let received = Xmodem::builder().resume(2).trim(0).receive(&mut tx, &mut output).expect("rx okay");
+    let (sent, rx_buf) = tx_thread.join().expect("tx join okay");
+
+    assert_eq!(&output[..], &input[..]);
+    assert_eq!(&rx_buf[..3], &[SOH, 3, !3]);
+    assert_eq!((sent.bytes, sent.packets, received.bytes), (144, 2, 144));
+
+    // a sender one packet behind repeats the receiver's last packet, which
+    // is discarded
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::builder().resume(1).transmit(&input[128..], rx));
+    let mut output = input[..256].to_vec();
+    Xmodem::builder().resume(2).trim(0).receive(&mut tx, &mut output).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(&output[..], &input[..]);
+
+    // but one two packets behind is rejected
+    let (mut tx, rx) = pipe();
+    std::thread::spawn(move || Xmodem::builder().resume(0).transmit(&input[..], rx));
+    let e = Xmodem::builder().resume(2).receive(&mut tx, vec![]).expect_err("out of sequence");
+    assert_err!(e, XmodemError::PacketNumber { expected: 3, received: 1 });
+}
+
+#[test]
+fn test_packet_number_wraparound() {
+    let data = [7u8; 128];
+    let packet = |num: u8| {
+        let mut packet = vec![SOH, num, !num];
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(&crc16(&data).to_be_bytes());
+        packet
+    };
+    let feed = |core: &mut XmodemCore, bytes: &[u8]| bytes.iter().fold(Action::Continue, |_, &b| core.handle_byte(b));
+
+    // packet 256 goes out as 0, and 255 is the one before it
+    let mut core = XmodemCore::new(true);
+    core.sequence = 255;
+    assert_err!(feed(&mut core, &packet(255)), Action::Packet(255));
+    assert_err!(feed(&mut core, &packet(255)), Action::Duplicate(255));
+    assert_err!(feed(&mut core, &packet(0)), Action::Packet(0));
+    assert_err!(feed(&mut core, &packet(0)), Action::Duplicate(0));
+    assert_err!(feed(&mut core, &packet(2)[..3]), Action::Reject(XmodemError::PacketNumber { expected: 1, received: 2 }));
+    assert_eq!(core.sequence(), 257);
+
+    // the sender numbers packets the same way
+    let mut script = Script(Cursor::new(vec![CRC, ACK, ACK]), vec![]);
+    let mut xmodem = Xmodem::new(&mut script);
+    xmodem.resume(254);
+    xmodem.write_packet(&data).expect("packet 255 sent");
+    xmodem.write_packet(&data).expect("packet 256 sent");
+    assert_eq!(xmodem.core.sequence, 257);
+    assert_eq!(&script.1[..3], &packet(255)[..3]);
+    assert_eq!(&script.1[133..136], &packet(0)[..3]);
+}
+
+#[test]
+fn test_large_transfer() {
+    // 4 MiB wraps the packet number 128 times
+    let mut seed = 1u32;
+    let input: Vec<u8> = (0..4 << 20).map(|_| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as u8
+    }).collect();
+
+    let (mut tx, rx) = pipe();
+    let data = input.clone();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&data[..], rx));
+    let mut output = Vec::with_capacity(input.len());
+    let received = Xmodem::receive(&mut tx, &mut output).expect("rx okay");
+    let sent = tx_thread.join().expect("tx join okay").expect("tx okay");
+
+    assert!(output == input);
+    assert_eq!((sent.bytes, sent.packets), (4 << 20, 32768));
+    assert_eq!((received.bytes, received.packets), (4 << 20, 32768));
+    assert_eq!((sent.retransmissions, received.retransmissions), (0, 0));
+}
+
+#[test]
+fn test_max_len() {
+    // exactly two packets fit
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&[4u8; 256][..], rx));
+    let mut output = vec![];
+    let received = Xmodem::builder().max_len(256).receive(&mut tx, &mut output).expect("rx okay");
+    assert_eq!((received.bytes, output.len()), (256, 256));
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+
+    // the third is cancelled without being written. The sender's end is
+    // kept open so that both CANs can be written, and the receiver stops
+    // discarding its input when reads time out.
+    let (mut tx, rx) = pipe();
+    tx.set_timeout(Some(Duration::from_millis(100)));
+    let tx_thread = std::thread::spawn(move || {
+        let mut rx = rx;
+        (Xmodem::transmit(&[4u8; 300][..], &mut rx), rx)
+    });
+
+    let mut output = vec![];
+    let e = Xmodem::builder().max_len(256).receive(&mut tx, &mut output).expect_err("too large");
+    assert_err!(e, XmodemError::TooLarge { max_len: 256 });
+    assert_eq!(output.len(), 256);
+    assert!(tx.written().ends_with(&[CAN, CAN]));
+
+    let e = tx_thread.join().expect("tx join okay").0.expect_err("cancelled");
+    assert_err!(e, XmodemError::Cancelled { packets: 3 });
+}
+
+#[test]
+fn test_cancel_reports_progress() {
+    // the receiver takes two packets, then gives up
+    let mut script = Script(Cursor::new(vec![CRC, ACK, ACK, CAN, CAN]), vec![]);
+    let e = Xmodem::transmit(&[6u8; 400][..], &mut script).expect_err("cancelled");
+    assert_err!(e, XmodemError::Cancelled { packets: 2 });
+
+    // counting from where it resumed, so the count can be resumed from
+    let mut script = Script(Cursor::new(vec![CRC, ACK, ACK, CAN, CAN]), vec![]);
+    let e = Xmodem::builder().resume(5).transmit(&[6u8; 400][..], &mut script).expect_err("cancelled");
+    assert_err!(e, XmodemError::Cancelled { packets: 7 });
+
+    // the sender gives up after one packet
+    let mut input = vec![SOH, 1, !1];
+    input.extend_from_slice(&[7; 128]);
+    input.extend_from_slice(&crc16(&[7; 128]).to_be_bytes());
+    input.extend_from_slice(&[CAN, CAN]);
+    let mut output = vec![];
+    let e = Xmodem::receive(Script(Cursor::new(input), vec![]), &mut output).expect_err("cancelled");
+    assert_err!(e, XmodemError::Cancelled { packets: 1 });
+    assert_eq!(output.len(), 128);
+}
+
+#[test]
+fn test_cancel() {
+    let mut packet = [0u8; 128];
+    let mut script = Script(Cursor::new(vec![CRC, ACK, 1, 2, 3]), vec![]);
+    let mut xmodem = Xmodem::new(&mut script);
+    xmodem.write_packet(&packet).expect("first packet okay");
+    assert_eq!((xmodem.core.sequence, xmodem.started, xmodem.core.crc), (2, true, true));
+
+    xmodem.cancel().expect("cancel okay");
+    assert_eq!((xmodem.core.sequence, xmodem.started, xmodem.core.crc), (1, false, false));
+    assert_eq!((xmodem.stats().packets, xmodem.stats().cancels), (1, 1));
+
+    // the leftover input is gone, and the CANs follow the packet
+    let e = xmodem.read_packet(&mut packet).expect_err("nothing left");
+    assert_err!(e, XmodemError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof);
+    assert_eq!(&script.1[3 + 128 + 2..], &[CAN, CAN]);
+
+    // the mode the receiver picked is forgotten, but the configured one stays
+    let mut script = Script(Cursor::new(vec![STREAM]), vec![]);
+    let mut xmodem = Xmodem::builder().escape(true).build(&mut script);
+    xmodem.write_packet(&packet).expect("streamed packet okay");
+    assert_eq!((xmodem.core.streaming, xmodem.core.escape), (true, true));
+
+    xmodem.cancel().expect("cancel okay");
+    assert_eq!((xmodem.core.sequence, xmodem.core.crc), (1, false));
+    assert_eq!((xmodem.core.streaming, xmodem.core.escape), (false, true));
+}
+
+#[test]
+fn test_error_display() {
+    use std::error::Error;
+
+    let e = XmodemError::PacketNumber { expected: 3, received: 1 };
+    assert_eq!(e.to_string(), "expected packet 3, received packet 1");
+    let e = XmodemError::UnexpectedByte { expected: "expected ACK after EOT", received: NAK };
+    assert_eq!(e.to_string(), "expected ACK after EOT, received 0x15");
+    let e = XmodemError::CrcMismatch { expected: 0xCBF43926, computed: 0 };
+    assert_eq!(e.to_string(), "CRC-32 mismatch: sender has 0xcbf43926, receiver computed 0x00000000");
+    assert!(e.source().is_none());
+
+    // transport errors show through
+    let e = XmodemError::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+    assert_eq!(e.to_string(), "slow");
+    let boxed: Box<dyn Error + Send + Sync> = Box::new(XmodemError::RetriesExhausted);
+    assert_eq!(boxed.to_string(), "too many retries");
+}
+
+#[test]
+fn test_error_into_io_error() {
+    let kind = |e: XmodemError| io::Error::from(e).kind();
+    assert_eq!(kind(XmodemError::Cancelled { packets: 0 }), io::ErrorKind::ConnectionAborted);
+    assert_eq!(kind(XmodemError::ChecksumMismatch), io::ErrorKind::Interrupted);
+    assert_eq!(kind(XmodemError::Nak), io::ErrorKind::Interrupted);
+    assert_eq!(kind(XmodemError::PacketNumber { expected: 2, received: 1 }), io::ErrorKind::InvalidData);
+    assert_eq!(kind(XmodemError::RetriesExhausted), io::ErrorKind::BrokenPipe);
+    assert_eq!(kind(XmodemError::BadBuffer), io::ErrorKind::UnexpectedEof);
+    assert_eq!(kind(XmodemError::TooLarge { max_len: 0 }), io::ErrorKind::InvalidData);
+    assert_eq!(kind(XmodemError::CrcMismatch { expected: 0, computed: 1 }), io::ErrorKind::InvalidData);
+
+    // transport errors come back out unchanged
+    let e = XmodemError::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+    assert!(e.is_timeout());
+    assert_eq!(kind(e), io::ErrorKind::TimedOut);
+}
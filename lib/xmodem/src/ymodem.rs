@@ -0,0 +1,267 @@
+//! YMODEM batch transfers.
+//!
+//! YMODEM sends any number of files in one session over the XMODEM-CRC
+//! packet layer. Each file is preceded by packet number 0, a header block
+//! holding the file's name, size, and modification time, and the session
+//! ends with a header whose name is empty:
+//!
+//! ```text
+//! receiver: C           C              C           C
+//! sender:     header 0    data 1..n EOT  header 0    empty header 0
+//! ```
+//!
+//! Since the header carries the exact size, the receiver drops the padding
+//! that fills out the last packet of each file.
+
+use core::fmt::{self, Write};
+use core::str;
+
+use shim::io;
+
+use crate::progress::{self, Progress};
+use crate::read_ext::ReadExt;
+use crate::{Result, Xmodem, XmodemError};
+
+/// The longest file name a `Header` can hold, in bytes. Together with the
+/// size and modification time, it always fits in one 128-byte block.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// The metadata sent in a YMODEM header block.
+#[derive(Clone, Copy)]
+pub struct Header {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    size: Option<u64>,
+    modified: Option<u64>,
+}
+
+impl Header {
+    /// Returns a header for the file `name` of `size` bytes. Fails with
+    /// `BadHeader` if `name` is empty, longer than `MAX_NAME_LEN`, or
+    /// contains a NUL byte.
+    pub fn new(name: &str, size: u64) -> Result<Header> {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() || bytes.len() > MAX_NAME_LEN || bytes.contains(&0) {
+            return Err(XmodemError::BadHeader("invalid YMODEM file name"));
+        }
+
+        let mut header = Header { name: [0; MAX_NAME_LEN], name_len: bytes.len(), size: Some(size), modified: None };
+        header.name[..bytes.len()].copy_from_slice(bytes);
+        Ok(header)
+    }
+
+    /// Sets the modification time, in seconds since the Unix epoch.
+    pub fn with_modified(mut self, modified: u64) -> Header {
+        self.modified = Some(modified);
+        self
+    }
+
+    /// The file's name.
+    pub fn name(&self) -> &str {
+        // only ever built from a `&str` or checked by `parse`
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// The file's size in bytes, if the sender gave one.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// The file's modification time in seconds since the Unix epoch, if the
+    /// sender gave one.
+    pub fn modified(&self) -> Option<u64> {
+        self.modified
+    }
+
+    /// Writes this header into `block` as `name NUL size [mtime]`, with the
+    /// size in decimal and the modification time in octal.
+    pub(crate) fn encode(&self, block: &mut [u8]) {
+        block.iter_mut().for_each(|b| *b = 0);
+        block[..self.name_len].copy_from_slice(&self.name[..self.name_len]);
+
+        let mut fields = BlockWriter { block: &mut block[self.name_len + 1..], pos: 0 };
+        // `MAX_NAME_LEN` leaves room for the longest fields
+        let _ = match (self.size, self.modified) {
+            (Some(size), Some(modified)) => write!(fields, "{} {:o}", size, modified),
+            (Some(size), None) => write!(fields, "{}", size),
+            (None, _) => Ok(()),
+        };
+    }
+
+    /// Parses a header block. Returns `None` for the empty header that ends
+    /// the session.
+    pub(crate) fn parse(block: &[u8]) -> Result<Option<Header>> {
+        let name_len = match block.iter().position(|&b| b == 0) {
+            Some(0) => return Ok(None),
+            Some(len) if len <= MAX_NAME_LEN => len,
+            _ => return Err(XmodemError::BadHeader("YMODEM file name too long")),
+        };
+
+        let name = match str::from_utf8(&block[..name_len]) {
+            Ok(name) => name,
+            Err(_) => return Err(XmodemError::BadHeader("YMODEM file name isn't UTF-8")),
+        };
+
+        let rest = &block[name_len + 1..];
+        let rest = &rest[..rest.iter().position(|&b| b == 0).unwrap_or(rest.len())];
+        let mut fields = match str::from_utf8(rest) {
+            Ok(fields) => fields.split(' ').filter(|f| !f.is_empty()),
+            Err(_) => return Err(XmodemError::BadHeader("invalid YMODEM header")),
+        };
+
+        let size = match fields.next().map(|f| f.parse::<u64>()) {
+            Some(Ok(size)) => Some(size),
+            Some(Err(_)) => return Err(XmodemError::BadHeader("invalid YMODEM file size")),
+            None => None,
+        };
+
+        let mut header = Header::new(name, 0)?;
+        header.size = size;
+        header.modified = fields.next().and_then(|f| u64::from_str_radix(f, 8).ok());
+        Ok(Some(header))
+    }
+}
+
+impl fmt::Debug for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Header")
+            .field("name", &self.name())
+            .field("size", &self.size)
+            .field("modified", &self.modified)
+            .finish()
+    }
+}
+
+/// Formats into a fixed block, failing once it's full.
+struct BlockWriter<'a> {
+    block: &'a mut [u8],
+    pos: usize,
+}
+
+impl Write for BlockWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.pos + s.len();
+        if end > self.block.len() {
+            return Err(fmt::Error);
+        }
+
+        self.block[self.pos..end].copy_from_slice(s.as_bytes());
+        self.pos = end;
+        Ok(())
+    }
+}
+
+/// Implementation of the YMODEM batch protocol.
+pub struct Ymodem;
+
+impl Ymodem {
+    /// Sends each file in `files` to `to`, then ends the session. Returns the
+    /// total number of bytes read from the files.
+    ///
+    /// Each file's data is whatever its reader yields; the receiver relies on
+    /// the header's size to drop padding, so it should match.
+    #[inline]
+    pub fn transmit<I, R, W>(files: I, to: W) -> Result<usize>
+        where I: IntoIterator<Item = (Header, R)>, R: io::Read, W: io::Read + io::Write
+    {
+        Ymodem::transmit_with_progress(files, to, progress::noop)
+    }
+
+    pub fn transmit_with_progress<I, R, W, P>(files: I, to: W, f: P) -> Result<usize>
+        where I: IntoIterator<Item = (Header, R)>, R: io::Read, W: io::Read + io::Write, P: FnMut(Progress)
+    {
+        let mut transmitter = Xmodem::new_with_progress(to, f);
+        let mut packet = [0u8; 128];
+        let mut written = 0;
+
+        for (header, mut data) in files {
+            header.encode(&mut packet);
+            send_header(&mut transmitter, &packet)?;
+
+            // the receiver asks for the data with another `C`
+            transmitter.started = false;
+            loop {
+                let n = data.read_max(&mut packet)?;
+                packet[n..].iter_mut().for_each(|b| *b = 0);
+
+                if n == 0 {
+                    transmitter.write_packet(&[])?;
+                    break;
+                }
+
+                transmitter.write_packet(&packet)?;
+                written += n;
+            }
+        }
+
+        packet.iter_mut().for_each(|b| *b = 0);
+        send_header(&mut transmitter, &packet)?;
+        Ok(written)
+    }
+
+    /// Receives files from `from` until the sender ends the session. For
+    /// each file, `open` is called with its header and returns where to write
+    /// the file's data. Returns the number of files received.
+    ///
+    /// Only as many bytes as the header's size are written; without a size,
+    /// all packets are written whole, padding included.
+    #[inline]
+    pub fn receive<R, F, W>(from: R, open: F) -> Result<usize>
+        where R: io::Read + io::Write, F: FnMut(&Header) -> io::Result<W>, W: io::Write
+    {
+        Ymodem::receive_with_progress(from, open, progress::noop)
+    }
+
+    pub fn receive_with_progress<R, F, W, P>(from: R, mut open: F, f: P) -> Result<usize>
+        where R: io::Read + io::Write, F: FnMut(&Header) -> io::Result<W>, W: io::Write, P: FnMut(Progress)
+    {
+        let mut receiver = Xmodem::new_with_progress(from, f);
+        let mut packet = [0u8; 128];
+        receiver.core.crc = true;
+
+        let mut files = 0;
+        loop {
+            receiver.core.sequence = 0;
+            receiver.started = false;
+            receiver.request_start()?;
+            if receiver.next_packet(&mut packet)? == 0 {
+                let expected = "expected YMODEM header, got EOT";
+                return Err(XmodemError::UnexpectedByte { expected, received: crate::frame::EOT });
+            }
+
+            let header = match Header::parse(&packet)? {
+                Some(header) => header,
+                None => return Ok(files),
+            };
+
+            let mut into = open(&header)?;
+            let mut remaining = header.size;
+            receiver.started = false;
+            receiver.request_start()?;
+            while receiver.next_packet(&mut packet)? != 0 {
+                let n = match remaining {
+                    Some(ref mut remaining) => {
+                        let n = (*remaining).min(128) as usize;
+                        *remaining -= n as u64;
+                        n
+                    }
+                    None => 128,
+                };
+
+                into.write_all(&packet[..n])?;
+            }
+
+            files += 1;
+        }
+    }
+}
+
+/// Sends the header block in `packet` as packet 0, once the receiver asks
+/// for it.
+fn send_header<W, P>(transmitter: &mut Xmodem<W, P>, packet: &[u8]) -> Result<()>
+    where W: io::Read + io::Write, P: FnMut(Progress)
+{
+    transmitter.core.sequence = 0;
+    transmitter.started = false;
+    transmitter.write_packet(packet).map(|_| ())
+}
@@ -0,0 +1,779 @@
+//! ZMODEM transfers.
+//!
+//! Unlike XMODEM, ZMODEM doesn't wait for an acknowledgement after every
+//! packet. The sender streams data in subpackets of up to 1KiB, and the
+//! receiver only speaks up when something goes wrong: it sends `ZRPOS` with
+//! the offset of the first byte it's missing and the sender resumes from
+//! there. The same mechanism resumes interrupted transfers: when offered a
+//! file, the receiver answers with the offset to start at.
+//!
+//! A session looks like this:
+//!
+//! ```text
+//! sender:   ZRQINIT     ZFILE+info       ZDATA+data...ZEOF      ZFIN       OO
+//! receiver:         ZRINIT         ZRPOS                  ZRINIT     ZFIN
+//! ```
+//!
+//! This implementation is compatible with `lrzsz` and supports the subset of
+//! the protocol needed to move files:
+//!
+//!   * headers are sent in hex by the receiver and in binary with a CRC-16
+//!     by the sender; 32-bit CRCs aren't offered or accepted,
+//!   * the sender expects a `ZACK` every `WINDOW` bytes, so a transport with
+//!     blocking reads never has to check for `ZRPOS` while it's writing, and
+//!   * commands (`ZCOMMAND`) and file conversion options are ignored.
+//!
+//! As with XMODEM, recovering from a lost frame relies on the transport's
+//! reads timing out with `TimedOut`.
+
+use core::cmp;
+
+use shim::io::{self, SeekFrom};
+use shim::ioerr;
+
+use crate::progress::{self, Progress};
+use crate::clock::DefaultClock;
+use crate::stats::Tracker;
+use crate::read_ext::ReadExt;
+use crate::frame::{update_crc16, DLE, XOFF, XON};
+use crate::ymodem::Header;
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+
+// frame types
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCHALLENGE: u8 = 14;
+const ZCAN: u8 = 16;
+
+// subpacket terminators, sent after a ZDLE
+/// End of frame; a header follows.
+const ZCRCE: u8 = b'h';
+/// The frame continues.
+const ZCRCG: u8 = b'i';
+/// The frame continues; the receiver sends `ZACK`.
+const ZCRCQ: u8 = b'j';
+/// End of frame; the receiver sends `ZACK`.
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// `ZRINIT` flag: the receiver can send and receive at the same time.
+const CANFDX: u8 = 0x01;
+/// `ZRINIT` flag: the receiver can receive data while writing it out.
+const CANOVIO: u8 = 0x02;
+
+/// `ZFILE` conversion option: binary transfer.
+const ZCBIN: u8 = 1;
+
+/// The largest subpacket sent or accepted.
+pub const SUBPACKET_LEN: usize = 1024;
+
+/// The most data the sender streams before waiting for a `ZACK`.
+const WINDOW: usize = 16 * SUBPACKET_LEN;
+
+/// How many bytes to skip looking for a header before giving up. After a
+/// `ZRPOS`, the rest of the window, escaped, arrives before the next header.
+const MAX_GARBAGE: usize = 4 * WINDOW;
+
+/// How many errors in a row either side tolerates.
+const MAX_ERRORS: usize = 10;
+
+/// A header: a frame type and four bytes of arguments, either flags or a
+/// little-endian file position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Frame {
+    kind: u8,
+    args: [u8; 4],
+}
+
+impl Frame {
+    fn new(kind: u8, args: [u8; 4]) -> Frame {
+        Frame { kind, args }
+    }
+
+    fn at(kind: u8, position: u64) -> Frame {
+        Frame::new(kind, (position as u32).to_le_bytes())
+    }
+
+    fn position(&self) -> u64 {
+        u32::from_le_bytes(self.args) as u64
+    }
+
+    fn bytes(&self) -> [u8; 5] {
+        let [a, b, c, d] = self.args;
+        [self.kind, a, b, c, d]
+    }
+}
+
+/// What `read_escaped` found.
+enum Escaped {
+    Byte(u8),
+    /// A subpacket terminator.
+    End(u8),
+}
+
+fn needs_escape(byte: u8) -> bool {
+    match byte & 0x7F {
+        ZDLE | DLE | XON | XOFF => true,
+        _ => false,
+    }
+}
+
+/// Returns `true` for errors a retry can recover from: corruption and
+/// timeouts.
+fn recoverable(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted => true,
+        _ => false,
+    }
+}
+
+/// Fails if `frame` cancels the session.
+fn check_abort(frame: Frame) -> io::Result<()> {
+    match frame.kind {
+        ZCAN | ZABORT | ZFERR => ioerr!(ConnectionAborted, "transfer aborted by peer"),
+        _ => Ok(()),
+    }
+}
+
+fn hex_digit(c: u8) -> io::Result<u8> {
+    match (c as char).to_digit(16) {
+        Some(d) => Ok(d as u8),
+        None => ioerr!(InvalidData, "bad hex digit in ZMODEM header"),
+    }
+}
+
+/// One end of a ZMODEM session.
+struct Session<T, P> {
+    inner: T,
+    progress: P,
+    tracker: Tracker,
+    packets: u8,
+    /// The most data to send before waiting for a `ZACK`.
+    window: usize,
+    /// Whether the receiver has a limited buffer, in which case each window
+    /// ends the frame and the receiver gets a new `ZDATA`.
+    buffered: bool,
+}
+
+impl<T: io::Read + io::Write, P: FnMut(Progress)> Session<T, P> {
+    fn new(inner: T, progress: P) -> Session<T, P> {
+        Session { inner, progress, tracker: Tracker::new(DefaultClock::default()), packets: 0, window: WINDOW, buffered: false }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Writes `data`, escaping the bytes that would confuse the receiver or
+    /// the line.
+    fn write_escaped(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut buf = [0u8; 512];
+        let mut len = 0;
+        for &byte in data {
+            if len + 2 > buf.len() {
+                self.inner.write_all(&buf[..len])?;
+                len = 0;
+            }
+
+            if needs_escape(byte) {
+                buf[len] = ZDLE;
+                buf[len + 1] = byte ^ 0x40;
+                len += 2;
+            } else {
+                buf[len] = byte;
+                len += 1;
+            }
+        }
+
+        self.inner.write_all(&buf[..len])
+    }
+
+    /// Reads a byte, undoing escaping. Unescaped XON/XOFF are flow control
+    /// noise and skipped. Five CANs in a row cancel the session.
+    fn read_escaped(&mut self) -> io::Result<Escaped> {
+        loop {
+            match self.read_byte()? {
+                ZDLE => break,
+                XON | XOFF | 0x91 | 0x93 => continue,
+                byte => return Ok(Escaped::Byte(byte)),
+            }
+        }
+
+        let mut cans = 1;
+        loop {
+            let byte = match self.read_byte()? {
+                ZDLE => {
+                    cans += 1;
+                    if cans == 5 {
+                        return ioerr!(ConnectionAborted, "received CAN");
+                    }
+                    continue;
+                }
+                XON | XOFF | 0x91 | 0x93 => continue,
+                end @ ZCRCE..=ZCRCW => return Ok(Escaped::End(end)),
+                ZRUB0 => 0x7F,
+                ZRUB1 => 0xFF,
+                byte if byte & 0x60 == 0x40 => byte ^ 0x40,
+                _ => return ioerr!(InvalidData, "bad ZDLE escape"),
+            };
+
+            return Ok(Escaped::Byte(byte));
+        }
+    }
+
+    /// Sends `frame` as a hex header, the form used by the receiver.
+    fn send_hex(&mut self, frame: Frame) -> io::Result<()> {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        let bytes = frame.bytes();
+        let [crc_hi, crc_lo] = update_crc16(0, &bytes).to_be_bytes();
+        let mut out = [0u8; 21];
+        out[..4].copy_from_slice(&[ZPAD, ZPAD, ZDLE, ZHEX]);
+        for (i, &byte) in bytes.iter().chain(&[crc_hi, crc_lo]).enumerate() {
+            out[4 + 2 * i] = HEX[(byte >> 4) as usize];
+            out[5 + 2 * i] = HEX[(byte & 0xF) as usize];
+        }
+
+        // CR, LF with the high bit set, then XON to restart a stopped line;
+        // the sender is waiting for data after ZACK and ZFIN, so skip it
+        out[18..21].copy_from_slice(&[b'\r', 0x8A, XON]);
+        let len = match frame.kind {
+            ZACK | ZFIN => 20,
+            _ => 21,
+        };
+
+        self.inner.write_all(&out[..len])?;
+        self.inner.flush()
+    }
+
+    /// Sends `frame` as a binary header with a CRC-16, the form used by the
+    /// sender.
+    fn send_bin(&mut self, frame: Frame) -> io::Result<()> {
+        let bytes = frame.bytes();
+        let [crc_hi, crc_lo] = update_crc16(0, &bytes).to_be_bytes();
+        self.inner.write_all(&[ZPAD, ZDLE, ZBIN])?;
+        self.write_escaped(&bytes)?;
+        self.write_escaped(&[crc_hi, crc_lo])?;
+        self.inner.flush()
+    }
+
+    /// Sends `data` as a subpacket ending with `end`.
+    fn send_subpacket(&mut self, data: &[u8], end: u8) -> io::Result<()> {
+        let crc = update_crc16(update_crc16(0, data), &[end]);
+        self.write_escaped(data)?;
+        self.inner.write_all(&[ZDLE, end])?;
+        self.write_escaped(&crc.to_be_bytes())?;
+        self.inner.flush()?;
+        self.subpacket_done();
+        Ok(())
+    }
+
+    fn subpacket_done(&mut self) {
+        self.packets = self.packets.wrapping_add(1);
+        (self.progress)(Progress::Packet(self.packets));
+    }
+
+    /// Reports `n` more bytes of file data.
+    fn transferred(&mut self, n: usize) {
+        (self.progress)(Progress::Transferred(self.tracker.transferred(n)));
+    }
+
+    /// Counts data sent again from an earlier position.
+    fn retried(&mut self) {
+        self.tracker.retried();
+        (self.progress)(Progress::NAK);
+    }
+
+    /// Skips to the next header and reads it.
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        let mut garbage = 0;
+        let mut cans = 0;
+        while garbage < MAX_GARBAGE {
+            let byte = self.read_byte()?;
+            if byte != ZPAD {
+                garbage += 1;
+                cans = if byte == ZDLE { cans + 1 } else { 0 };
+                if cans == 5 {
+                    return ioerr!(ConnectionAborted, "received CAN");
+                }
+                continue;
+            }
+
+            cans = 0;
+            let mut byte = self.read_byte()?;
+            while byte == ZPAD {
+                byte = self.read_byte()?;
+            }
+
+            if byte == ZDLE {
+                match self.read_byte()? {
+                    ZBIN => return self.read_bin_header(),
+                    ZHEX => return self.read_hex_header(),
+                    _ => {}
+                }
+            }
+
+            garbage += 1;
+        }
+
+        ioerr!(InvalidData, "no ZMODEM header found")
+    }
+
+    fn read_bin_header(&mut self) -> io::Result<Frame> {
+        let mut buf = [0u8; 7];
+        for byte in buf.iter_mut() {
+            *byte = match self.read_escaped()? {
+                Escaped::Byte(byte) => byte,
+                Escaped::End(_) => return ioerr!(InvalidData, "bad ZMODEM header"),
+            };
+        }
+
+        // the CRC of the data followed by its big-endian CRC is zero
+        if update_crc16(0, &buf) != 0 {
+            return ioerr!(InvalidData, "ZMODEM header CRC mismatch");
+        }
+
+        Ok(Frame::new(buf[0], [buf[1], buf[2], buf[3], buf[4]]))
+    }
+
+    fn read_hex_header(&mut self) -> io::Result<Frame> {
+        let mut buf = [0u8; 7];
+        for byte in buf.iter_mut() {
+            let hi = hex_digit(self.read_byte()?)?;
+            let lo = hex_digit(self.read_byte()?)?;
+            *byte = (hi << 4) | lo;
+        }
+
+        if update_crc16(0, &buf) != 0 {
+            return ioerr!(InvalidData, "ZMODEM header CRC mismatch");
+        }
+
+        // CR, LF; some senders leave out the LF
+        if self.read_byte()? & 0x7F == b'\r' {
+            self.read_byte()?;
+        }
+
+        Ok(Frame::new(buf[0], [buf[1], buf[2], buf[3], buf[4]]))
+    }
+
+    /// Reads a subpacket into `buf`. Returns its length and terminator.
+    fn read_subpacket(&mut self, buf: &mut [u8]) -> io::Result<(usize, u8)> {
+        let mut len = 0;
+        loop {
+            match self.read_escaped()? {
+                Escaped::Byte(_) if len == buf.len() => {
+                    return ioerr!(InvalidData, "ZMODEM subpacket too long");
+                }
+                Escaped::Byte(byte) => {
+                    buf[len] = byte;
+                    len += 1;
+                }
+                Escaped::End(end) => {
+                    let mut crc = [0u8; 2];
+                    for byte in crc.iter_mut() {
+                        *byte = match self.read_escaped()? {
+                            Escaped::Byte(byte) => byte,
+                            Escaped::End(_) => return ioerr!(InvalidData, "bad ZMODEM subpacket"),
+                        };
+                    }
+
+                    if update_crc16(update_crc16(0, &buf[..len]), &[end]) != u16::from_be_bytes(crc) {
+                        return ioerr!(InvalidData, "ZMODEM subpacket CRC mismatch");
+                    }
+
+                    return Ok((len, end));
+                }
+            }
+        }
+    }
+
+    fn send_rinit(&mut self) -> io::Result<()> {
+        self.send_hex(Frame::new(ZRINIT, [0, 0, 0, CANFDX | CANOVIO]))
+    }
+
+    /// Sends `ZRQINIT` until the receiver answers with `ZRINIT`, and sets up
+    /// the window from the receiver's buffer size.
+    fn start(&mut self) -> io::Result<()> {
+        (self.progress)(Progress::Waiting);
+        self.inner.write_all(b"rz\r")?;
+        for _ in 0..MAX_ERRORS {
+            self.send_hex(Frame::at(ZRQINIT, 0))?;
+            match self.read_frame() {
+                Ok(Frame { kind: ZRINIT, args }) => {
+                    let buffer = u16::from_le_bytes([args[0], args[1]]) as usize;
+                    self.buffered = buffer != 0;
+                    if self.buffered {
+                        self.window = cmp::min(buffer, WINDOW);
+                    }
+
+                    self.tracker.start();
+                    (self.progress)(Progress::Started);
+                    return Ok(());
+                }
+                Ok(Frame { kind: ZCHALLENGE, args }) => self.send_hex(Frame::new(ZACK, args))?,
+                Ok(frame) => check_abort(frame)?,
+                Err(ref e) if recoverable(e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        ioerr!(BrokenPipe, "no ZRINIT from receiver")
+    }
+
+    /// Offers `header`'s file and sends `data` from wherever the receiver
+    /// asks for. Returns the number of bytes sent, which is 0 if the
+    /// receiver skips the file.
+    fn send_file<R: io::Read + io::Seek>(&mut self, header: &Header, mut data: R) -> io::Result<usize> {
+        let mut info = [0u8; 128];
+        header.encode(&mut info);
+
+        let mut errors = 0;
+        let start = loop {
+            if errors == MAX_ERRORS {
+                return ioerr!(BrokenPipe, "receiver didn't accept ZFILE");
+            }
+
+            self.send_bin(Frame::new(ZFILE, [0, 0, 0, ZCBIN]))?;
+            self.send_subpacket(&info, ZCRCW)?;
+            match self.read_frame() {
+                Ok(Frame { kind: ZSKIP, .. }) => return Ok(0),
+                Ok(frame) => match frame.kind {
+                    ZRPOS => break frame.position(),
+                    _ => check_abort(frame)?,
+                },
+                Err(ref e) if recoverable(e) => {}
+                Err(e) => return Err(e),
+            }
+
+            errors += 1;
+        };
+
+        let mut packet = [0u8; SUBPACKET_LEN];
+        let mut pos = start;
+        let mut acked = start;
+        errors = 0;
+        'restart: loop {
+            if errors == MAX_ERRORS {
+                return ioerr!(BrokenPipe, "bad transmit");
+            }
+
+            data.seek(SeekFrom::Start(pos))?;
+            self.send_bin(Frame::at(ZDATA, pos))?;
+
+            let mut in_window = 0;
+            loop {
+                let len = cmp::min(SUBPACKET_LEN, self.window - in_window);
+                let n = data.read_max(&mut packet[..len])?;
+                in_window += n;
+                let end = if n < len {
+                    ZCRCE
+                } else if in_window < self.window {
+                    ZCRCG
+                } else if self.buffered {
+                    ZCRCW
+                } else {
+                    ZCRCQ
+                };
+
+                self.send_subpacket(&packet[..n], end)?;
+                self.transferred(n);
+                pos += n as u64;
+                match end {
+                    ZCRCE => break,
+                    ZCRCG => continue,
+                    _ => {}
+                }
+
+                // wait for the receiver to catch up
+                match self.read_frame() {
+                    Ok(frame) => match frame.kind {
+                        ZACK if frame.position() == pos => {
+                            acked = pos;
+                            errors = 0;
+                            in_window = 0;
+                            if end == ZCRCW {
+                                continue 'restart;
+                            }
+                        }
+                        ZACK | ZRPOS => {
+                            pos = frame.position();
+                            errors += 1;
+                            self.retried();
+                            continue 'restart;
+                        }
+                        _ => {
+                            check_abort(frame)?;
+                            pos = acked;
+                            errors += 1;
+                            self.retried();
+                            continue 'restart;
+                        }
+                    },
+                    Err(ref e) if recoverable(e) => {
+                        pos = acked;
+                        errors += 1;
+                        self.retried();
+                        continue 'restart;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            loop {
+                self.send_bin(Frame::at(ZEOF, pos))?;
+                match self.read_frame() {
+                    Ok(frame) => match frame.kind {
+                        ZRINIT => return Ok((pos - start) as usize),
+                        ZRPOS => {
+                            pos = frame.position();
+                            errors += 1;
+                            self.retried();
+                            continue 'restart;
+                        }
+                        _ => check_abort(frame)?,
+                    },
+                    Err(ref e) if recoverable(e) => {}
+                    Err(e) => return Err(e),
+                }
+
+                errors += 1;
+                if errors == MAX_ERRORS {
+                    return ioerr!(BrokenPipe, "receiver didn't acknowledge ZEOF");
+                }
+            }
+        }
+    }
+
+    /// Ends the session.
+    fn finish(&mut self) -> io::Result<()> {
+        for _ in 0..MAX_ERRORS {
+            self.send_hex(Frame::at(ZFIN, 0))?;
+            match self.read_frame() {
+                Ok(Frame { kind: ZFIN, .. }) => {
+                    self.inner.write_all(b"OO")?;
+                    return self.inner.flush();
+                }
+                Ok(frame) => check_abort(frame)?,
+                Err(ref e) if recoverable(e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        ioerr!(BrokenPipe, "receiver didn't end the session")
+    }
+
+    /// Receives a file into `into`, starting at offset `start`. Returns the
+    /// number of bytes received.
+    fn receive_file<W: io::Write>(&mut self, into: &mut W, start: u64, buf: &mut [u8]) -> io::Result<u64> {
+        let mut pos = start;
+        let mut errors = 0;
+        self.send_hex(Frame::at(ZRPOS, pos))?;
+        loop {
+            if errors == MAX_ERRORS {
+                return ioerr!(BrokenPipe, "bad receive");
+            }
+
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(ref e) if recoverable(e) => {
+                    errors += 1;
+                    self.retried();
+                    self.send_hex(Frame::at(ZRPOS, pos))?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            match frame.kind {
+                ZDATA if frame.position() == pos => loop {
+                    match self.read_subpacket(buf) {
+                        Ok((n, end)) => {
+                            into.write_all(&buf[..n])?;
+                            pos += n as u64;
+                            errors = 0;
+                            self.subpacket_done();
+                            self.transferred(n);
+                            if end == ZCRCQ || end == ZCRCW {
+                                self.send_hex(Frame::at(ZACK, pos))?;
+                            }
+
+                            if end == ZCRCE || end == ZCRCW {
+                                break;
+                            }
+                        }
+                        Err(ref e) if recoverable(e) => {
+                            errors += 1;
+                            self.retried();
+                            self.send_hex(Frame::at(ZRPOS, pos))?;
+                            break;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                },
+                ZEOF if frame.position() == pos => return Ok(pos - start),
+                // the sender is repeating itself; the reply is on its way
+                ZFILE => match self.read_subpacket(buf) {
+                    Ok(_) => {}
+                    Err(ref e) if recoverable(e) => {}
+                    Err(e) => return Err(e),
+                },
+                _ => {
+                    check_abort(frame)?;
+                    errors += 1;
+                    self.retried();
+                    self.send_hex(Frame::at(ZRPOS, pos))?;
+                }
+            }
+        }
+    }
+}
+
+/// Implementation of the ZMODEM protocol.
+pub struct Zmodem;
+
+impl Zmodem {
+    /// Sends each file in `files` to `to`, starting each from the offset the
+    /// receiver asks for. Returns the total number of bytes sent.
+    ///
+    /// Files are read from their start to their end, whatever their header's
+    /// size says.
+    #[inline]
+    pub fn transmit<I, R, W>(files: I, to: W) -> io::Result<usize>
+        where I: IntoIterator<Item = (Header, R)>, R: io::Read + io::Seek, W: io::Read + io::Write
+    {
+        Zmodem::transmit_with_progress(files, to, progress::noop)
+    }
+
+    pub fn transmit_with_progress<I, R, W, P>(files: I, to: W, f: P) -> io::Result<usize>
+        where I: IntoIterator<Item = (Header, R)>, R: io::Read + io::Seek, W: io::Read + io::Write,
+              P: FnMut(Progress)
+    {
+        let mut session = Session::new(to, f);
+        session.start()?;
+
+        let mut sent = 0;
+        for (header, data) in files {
+            sent += session.send_file(&header, data)?;
+        }
+
+        session.finish()?;
+        Ok(sent)
+    }
+
+    /// Receives files from `from` until the sender ends the session. Returns
+    /// the number of files received.
+    ///
+    /// For each file offered, `open` is called with its header. It returns
+    /// `None` to skip the file, or where to write it along with how many of
+    /// its bytes are already there; the transfer resumes from that offset.
+    #[inline]
+    pub fn receive<R, F, W>(from: R, open: F) -> io::Result<usize>
+        where R: io::Read + io::Write, F: FnMut(&Header) -> io::Result<Option<(W, u64)>>, W: io::Write
+    {
+        Zmodem::receive_with_progress(from, open, progress::noop)
+    }
+
+    pub fn receive_with_progress<R, F, W, P>(from: R, mut open: F, f: P) -> io::Result<usize>
+        where R: io::Read + io::Write, F: FnMut(&Header) -> io::Result<Option<(W, u64)>>, W: io::Write,
+              P: FnMut(Progress)
+    {
+        let mut session = Session::new(from, f);
+        let mut buf = [0u8; SUBPACKET_LEN];
+        let mut files = 0;
+        let mut errors = 0;
+
+        (session.progress)(Progress::Waiting);
+        session.send_rinit()?;
+        loop {
+            if errors == MAX_ERRORS {
+                return ioerr!(BrokenPipe, "bad receive");
+            }
+
+            let frame = match session.read_frame() {
+                Ok(frame) => frame,
+                Err(ref e) if recoverable(e) => {
+                    errors += 1;
+                    session.send_rinit()?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            match frame.kind {
+                ZFILE => {
+                    let header = match session.read_subpacket(&mut buf) {
+                        Ok((n, _)) => Header::parse(&buf[..n]).map_err(io::Error::from),
+                        Err(e) => Err(e),
+                    };
+
+                    let header = match header {
+                        Ok(Some(header)) => header,
+                        Ok(None) => {
+                            errors += 1;
+                            session.send_hex(Frame::at(ZNAK, 0))?;
+                            continue;
+                        }
+                        Err(ref e) if recoverable(e) => {
+                            errors += 1;
+                            session.send_hex(Frame::at(ZNAK, 0))?;
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
+
+                    if files == 0 {
+                        session.tracker.start();
+                        (session.progress)(Progress::Started);
+                    }
+
+                    match open(&header)? {
+                        Some((mut into, start)) => {
+                            session.receive_file(&mut into, start, &mut buf)?;
+                            files += 1;
+                            session.send_rinit()?;
+                        }
+                        None => session.send_hex(Frame::at(ZSKIP, 0))?,
+                    }
+
+                    errors = 0;
+                }
+                ZSINIT => match session.read_subpacket(&mut buf) {
+                    Ok(_) => session.send_hex(Frame::at(ZACK, 0))?,
+                    Err(ref e) if recoverable(e) => session.send_hex(Frame::at(ZNAK, 0))?,
+                    Err(e) => return Err(e),
+                },
+                ZFIN => {
+                    session.send_hex(Frame::at(ZFIN, 0))?;
+                    // the sender's "over and out"; it doesn't matter if it's lost
+                    let _ = session.inner.read_exact(&mut [0u8; 2]);
+                    return Ok(files);
+                }
+                _ => {
+                    check_abort(frame)?;
+                    if frame.kind != ZRQINIT {
+                        errors += 1;
+                    }
+                    session.send_rinit()?;
+                }
+            }
+        }
+    }
+}